@@ -0,0 +1,118 @@
+//! Registering, logging in and out of the Atris server
+use atris_client_lib::atris_common::authenticate_user::{AuthenticateUserError, AuthenticateUserResponse};
+use atris_client_lib::comms::console::OutputMode;
+use atris_client_lib::comms::{initiator::AtrisInitiator, AtrisConnection};
+use atris_client_lib::profile::{Profile, Profiles, SavedSession, UnlockedProfile};
+use atris_client_lib::{http_auth::AtrisAuth, AtrisAuthClient};
+
+use crate::output::{self, CliEvent};
+use crate::session::{self, current_profile};
+use crate::CliError;
+
+/// A client for `server`, or for the default server
+fn auth_client(server: Option<&str>) -> Result<AtrisAuth, CliError> {
+    Ok(match server {
+        Some(server) => AtrisAuth::with_server(server)?,
+        None => AtrisAuth::new()?,
+    })
+}
+
+pub async fn register(username: &str, server: Option<&str>, mode: OutputMode) -> Result<(), CliError> {
+    let password = output::secret(mode, "Password: ", "password")?;
+    if output::secret(mode, "Password again: ", "password")? != password {
+        return Err("The passwords do not match".into());
+    }
+    auth_client(server)?.create_user(username, &password).await??;
+    mode.emit(&CliEvent::Registered {
+        username: username.to_owned(),
+    });
+    Ok(())
+}
+
+async fn authenticate(
+    client: &AtrisAuth,
+    username: &str,
+    password: &str,
+    mode: OutputMode,
+) -> Result<AuthenticateUserResponse, CliError> {
+    // The server wants an offer to hand to whoever invites this user next, though joining makes a fresh one
+    let initiator = AtrisInitiator::new(AtrisConnection::new().await?).await?;
+    let initiator_string = initiator.encoded_local_description()?;
+    match client.authenticate_user(username, password, &initiator_string).await? {
+        Ok(auth) => Ok(auth),
+        Err(AuthenticateUserError::SecondFactorRequired) => {
+            let code = output::prompt(mode, "Two-factor code or recovery code: ")?;
+            Ok(client
+                .authenticate_user_with_second_factor(username, password, &initiator_string, &code)
+                .await??)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `login <username>` logs in and saves the session in the user's profile, making one locked with a new passphrase
+/// if they have none, and switches to it. With `remember`, the profile unlocks without its passphrase until logout.
+pub async fn login(username: &str, server: Option<String>, remember: bool, mode: OutputMode) -> Result<(), CliError> {
+    let profiles = Profiles::open()?;
+    let mut profile = match profiles.find(username)? {
+        Some(profile) => {
+            let mut profile = session::unlock(&profiles, profile, mode)?;
+            if server.is_some() {
+                profile.profile_mut().server = server;
+            }
+            profile
+        }
+        None => {
+            output::status(
+                mode,
+                format!("Making a profile for {username}, locked with a passphrase on this machine"),
+            );
+            let passphrase = output::secret(mode, "New passphrase: ", "passphrase")?;
+            if output::secret(mode, "New passphrase again: ", "passphrase")? != passphrase {
+                return Err("The passphrases do not match".into());
+            }
+            UnlockedProfile::create(username, server, &passphrase)?
+        }
+    };
+
+    let password = output::secret(mode, "Password: ", "password")?;
+    let auth = authenticate(&profile.profile().auth_client()?, username, &password, mode).await?;
+    profile.set_session(Some(SavedSession {
+        session_id: auth.session_id,
+        ice_servers: auth.ice_servers,
+    }))?;
+    profiles.save(profile.profile())?;
+    profiles.set_current(Some(username))?;
+    if remember {
+        profiles.remember(&profile)?;
+    }
+    mode.emit(&CliEvent::LoggedIn {
+        username: username.to_owned(),
+    });
+    Ok(())
+}
+
+/// `logout` forgets the current profile's session and remembered key, keeping the rest of the profile
+pub fn logout(mode: OutputMode) -> Result<(), CliError> {
+    let profiles = Profiles::open()?;
+    let username = match current_profile()? {
+        Some(mut profile) if profile.is_logged_in() => {
+            profile.log_out();
+            profiles.save(&profile)?;
+            profiles.forget(&profile.username)?;
+            Some(profile.username)
+        }
+        _ => None,
+    };
+    mode.emit(&CliEvent::LoggedOut { username });
+    Ok(())
+}
+
+pub fn whoami(mode: OutputMode) -> Result<(), CliError> {
+    mode.emit(&CliEvent::Whoami {
+        username: current_profile()?
+            .filter(Profile::is_logged_in)
+            .map(|profile| profile.username),
+    });
+    Ok(())
+}
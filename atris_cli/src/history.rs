@@ -0,0 +1,91 @@
+//! Paging through and searching the messages the current profile sent and received in its rooms, and exporting
+//! and importing them
+use std::path::{Path, PathBuf};
+
+use atris_client_lib::comms::console::OutputMode;
+use atris_client_lib::export::{self, ExportFormat};
+use atris_client_lib::history::{EntryId, History};
+
+use crate::output::{self, CliEvent};
+use crate::session::Session;
+use crate::CliError;
+
+/// How many messages `history` shows unless asked for another number
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// `history <room>` shows the last `limit` messages in a room, or the ones before the message `before`
+pub fn history(room_id: u16, before: Option<EntryId>, limit: usize, mode: OutputMode) -> Result<(), CliError> {
+    let session = Session::require(mode)?;
+    let log = session.history()?.conversation(&History::room(room_id))?;
+    mode.emit(&CliEvent::History {
+        conversation: log.name().to_owned(),
+        entries: log.page(before, limit).to_vec(),
+    });
+    Ok(())
+}
+
+/// `search <query>` finds the messages containing every word of `query` in every room
+pub fn search(query: &str, mode: OutputMode) -> Result<(), CliError> {
+    let session = Session::require(mode)?;
+    let hits = session.history()?.search(query)?;
+    mode.emit(&CliEvent::SearchResults {
+        query: query.to_owned(),
+        hits,
+    });
+    Ok(())
+}
+
+/// `export <room> <path>` writes a room's messages and the `files` attached to an archive, as HTML if `path` ends in
+/// `.html` and JSON otherwise, encrypted with a new passphrase if asked
+pub fn export(room_id: u16, path: &Path, files: &[PathBuf], encrypt: bool, mode: OutputMode) -> Result<(), CliError> {
+    let session = Session::require(mode)?;
+    let log = session.history()?.conversation(&History::room(room_id))?;
+    if log.is_empty() && files.is_empty() {
+        return Err(format!("There are no messages in room {room_id} to export").into());
+    }
+    let passphrase = match encrypt {
+        true => {
+            let passphrase = output::secret(mode, "Passphrase to encrypt the export with: ", "passphrase")?;
+            if output::secret(mode, "Passphrase again: ", "passphrase")? != passphrase {
+                return Err("The passphrases do not match".into());
+            }
+            Some(passphrase)
+        }
+        false => None,
+    };
+    let exported = export::export(&log, files)?;
+    export::write(&exported, path, ExportFormat::of(path), passphrase.as_deref())?;
+    mode.emit(&CliEvent::Exported {
+        conversation: exported.conversation,
+        path: path.display().to_string(),
+        messages: exported.messages.len(),
+        files: exported.files.len(),
+        encrypted: encrypt,
+    });
+    Ok(())
+}
+
+/// `import <path>` adds the messages of an exported conversation to the history, as the room `room_id` if given,
+/// saving its files in the download directory
+pub fn import(path: &Path, room_id: Option<u16>, mode: OutputMode) -> Result<(), CliError> {
+    let session = Session::require(mode)?;
+    let archive = export::read(path)?;
+    let passphrase = match archive.is_encrypted() {
+        true => Some(output::secret(
+            mode,
+            "Passphrase the export is encrypted with: ",
+            "passphrase",
+        )?),
+        false => None,
+    };
+    let exported = export::open(archive, passphrase.as_deref())?;
+    let files_dir = session.download_dir().unwrap_or_else(|| PathBuf::from("."));
+    let name = room_id.map(History::room);
+    let imported = export::import(&session.history()?, exported, name.as_deref(), &files_dir)?;
+    mode.emit(&CliEvent::Imported {
+        conversation: imported.conversation,
+        messages: imported.messages,
+        files: imported.files.iter().map(|path| path.display().to_string()).collect(),
+    });
+    Ok(())
+}
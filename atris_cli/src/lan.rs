@@ -0,0 +1,111 @@
+//! Finds other users on the local network and connects to them, without any server
+use atris_client_lib::comms::channels::DEFAULT_CHANNEL_LABEL;
+use atris_client_lib::comms::console::OutputMode;
+use atris_client_lib::comms::discovery::{self, BROWSE_DURATION};
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::lan::{self, LanListener, LanRequest};
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::signal;
+use atris_client_lib::comms::transfer::{self, TRANSFER_CHANNEL_LABEL};
+use atris_client_lib::identity::IdentityKey;
+
+use crate::output::{self, CliEvent};
+use crate::pair::{chat, take_incoming, untrickled_connection};
+use crate::session;
+
+/// `lan [username]` advertises this user, lists the others on the network, and connects to the one picked, or
+/// waits for someone to connect and asks the user whether to accept them. Users with a profile keep their identity
+/// key, and so their fingerprint, between runs.
+pub async fn lan(username: Option<String>, mode: OutputMode) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let username = match username {
+        Some(username) => username,
+        None => session::current_profile()?.ok_or("Usage: lan <username>")?.username,
+    };
+    let identity = session::identity(&username, mode)?.unwrap_or_else(IdentityKey::generate);
+    let fingerprint = identity.public().fingerprint();
+    let listener = LanListener::advertise(identity.clone(), &username).await?;
+    mode.emit(&CliEvent::Advertising {
+        username: username.clone(),
+        fingerprint: fingerprint.clone(),
+    });
+
+    output::status(mode, "Looking for others...");
+    let peers: Vec<_> = discovery::browse(BROWSE_DURATION)
+        .await?
+        .into_iter()
+        .filter(|peer| peer.fingerprint != fingerprint)
+        .collect();
+    for (index, peer) in peers.iter().enumerate() {
+        mode.emit(&CliEvent::Peer {
+            index,
+            username: peer.username.clone(),
+            fingerprint: peer.fingerprint.clone(),
+            address: peer.address.to_string(),
+        });
+    }
+    let prompt = "Enter a number to connect, or wait for someone to connect to you: ";
+    mode.emit(&CliEvent::Prompt {
+        message: prompt.to_owned(),
+    });
+
+    // Lines are read on a thread of their own, so waiting for one does not stop others connecting
+    let (line_sender, mut lines) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(line) = signal::read_in_line() {
+            if line_sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    // Anyone on the network can connect, so whoever does waits for the user to accept them
+    let mut request: Option<LanRequest> = None;
+    let (chat_parts, file_parts, room_key) = loop {
+        tokio::select! {
+            accepted = listener.accept(), if request.is_none() => {
+                let accepted = accepted?;
+                let peer = accepted.peer();
+                mode.emit(&CliEvent::Prompt {
+                    message: format!(
+                        "{} ({}) wants to connect from {}. Enter y to accept, or anything else to turn them away: ",
+                        peer.username, peer.fingerprint, peer.address
+                    ),
+                });
+                request = Some(accepted);
+            }
+            line = lines.recv() => {
+                let line = line.ok_or("No more input")?;
+                if let Some(request) = request.take() {
+                    let peer = request.peer().clone();
+                    if !line.eq_ignore_ascii_case("y") {
+                        output::status(mode, format!("Turned {} ({}) away", peer.username, peer.fingerprint));
+                        mode.emit(&CliEvent::Prompt {
+                            message: prompt.to_owned(),
+                        });
+                        continue;
+                    }
+                    let responder = AtrisResponder::with_connection(untrickled_connection().await?);
+                    let (channels, room_key) = request.answer(responder).await?;
+                    output::status(mode, format!("{} ({}) connected from {}", peer.username, peer.fingerprint, peer.address));
+                    let (chat_parts, file_parts) = take_incoming(channels).await?;
+                    break (chat_parts, file_parts, room_key);
+                }
+                let peer = line
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| peers.get(index))
+                    .ok_or("There is nobody with that number")?;
+                let initiator =
+                    AtrisInitiator::with_channels(untrickled_connection().await?, transfer::chat_and_file_channels())
+                        .await?;
+                output::status(mode, format!("Waiting for {} to accept...", peer.username));
+                let (mut channels, room_key) = lan::connect(peer, &identity, &username, initiator).await?;
+                output::status(mode, format!("Connected to {} ({})", peer.username, peer.fingerprint));
+                let chat_parts = channels.take(DEFAULT_CHANNEL_LABEL).ok_or("No chat channel!")?;
+                let file_parts = channels.take(TRANSFER_CHANNEL_LABEL).ok_or("No file channel!")?;
+                break (chat_parts, file_parts, room_key);
+            }
+        }
+    };
+    mode.emit(&CliEvent::Connected { with: None });
+    chat(chat_parts, file_parts, room_key, None, mode).await
+}
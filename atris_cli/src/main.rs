@@ -1,62 +1,184 @@
-use atris_client_lib::atris_common::authenticate_user::AuthenticateUserResponse;
-use atris_client_lib::comms::AtrisChannel;
-use atris_client_lib::comms::responder::AtrisResponder;
-use atris_client_lib::comms::{initiator::AtrisInitiator, AtrisConnection};
-use atris_client_lib::{http_auth::AtrisAuth, AtrisAuthClient};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use atris_client_lib::comms::console::OutputMode;
+use clap::{Parser, Subcommand};
+
+mod account;
+mod history;
+mod lan;
+mod output;
+mod pair;
+mod profile;
+mod rendezvous;
+mod room;
+mod session;
+mod tui;
+
+/// What every command fails with, printed with its `Display` impl
+pub type CliError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Private, peer to peer chat and file transfer
+#[derive(Parser)]
+#[command(name = "atris", version)]
+struct Cli {
+    /// Report everything as JSON lines on stdout, and take chat commands as JSON lines on stdin, like
+    /// `{"command":"send","text":"hello"}`. Prompts still take a plain line.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create an account on the Atris server
+    Register {
+        username: String,
+        /// The server to register on, like `http://localhost:9000`, instead of the default one
+        #[arg(long)]
+        server: Option<String>,
+    },
+    /// Log in, saving the session in this user's profile, and switch to it
+    Login {
+        username: String,
+        /// The server the account is on, like `http://localhost:9000`, which the profile keeps
+        #[arg(long)]
+        server: Option<String>,
+        /// Unlock the profile without its passphrase until logging out
+        #[arg(long)]
+        remember: bool,
+    },
+    /// Forget the current profile's session, and its passphrase if it was remembered
+    Logout,
+    /// Show who is logged in
+    Whoami,
+    /// Create a room for another user, and wait in it for them to join
+    Invite { user: String },
+    /// Join a room another user invited you to
+    Join { room: u16 },
+    /// Go back to a room you left before the other user got there
+    Chat { room: u16 },
+    /// Send a file to the other user in a room, waiting until they have it
+    SendFile { room: u16, path: PathBuf },
+    /// List the rooms you created or joined
+    Rooms,
+    /// Pair with another user from a shared passphrase, without the server
+    Pair {
+        /// The other user's line, to answer their offer
+        line: Option<String>,
+    },
+    /// Find other users on the local network, without the server
+    Lan {
+        /// The name to advertise, the current profile's by default, whose identity key is used if it has a profile
+        username: Option<String>,
+    },
+    /// Show the messages you sent and received in a room, the latest ones first unless `--before` is given
+    History {
+        room: u16,
+        /// Show the messages before the one with this number
+        #[arg(long)]
+        before: Option<u64>,
+        /// How many messages to show
+        #[arg(long, default_value_t = history::DEFAULT_PAGE_SIZE)]
+        limit: usize,
+    },
+    /// Find the messages containing every word of a query, in every room
+    Search { query: String },
+    /// Export a room's messages to a JSON archive, or an HTML transcript if the path ends in `.html`, to import
+    /// elsewhere
+    Export {
+        room: u16,
+        path: PathBuf,
+        /// Attach a file to the export, which can be given more than once
+        #[arg(long = "file")]
+        files: Vec<PathBuf>,
+        /// Encrypt the export with a passphrase, which importing it asks for
+        #[arg(long)]
+        encrypt: bool,
+    },
+    /// Import an exported conversation into your history, saving its files in your download directory
+    Import {
+        path: PathBuf,
+        /// Import the messages into this room rather than the one they were exported from
+        #[arg(long)]
+        room: Option<u16>,
+    },
+    /// Open the full-screen client, in `room` if given
+    Tui { room: Option<u16> },
+    /// Meet another user at an HTTP endpoint one of you serves, without the server
+    Rendezvous {
+        /// The port to serve on, or the address of the other user's rendezvous
+        port_or_address: String,
+        /// The other user's token, to post an offer to their rendezvous
+        token: Option<String>,
+    },
+    /// List, switch between and remove the saved profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// List the saved profiles
+    List,
+    /// Act as another saved profile
+    Use { username: String },
+    /// Delete a saved profile
+    Remove { username: String },
+    /// Save accepted files in `path`, or in the directory atris runs in without one
+    DownloadDir { path: Option<PathBuf> },
+}
+
+async fn run(command: Command, mode: OutputMode) -> Result<(), CliError> {
+    match command {
+        Command::Register { username, server } => account::register(&username, server.as_deref(), mode).await,
+        Command::Login {
+            username,
+            server,
+            remember,
+        } => account::login(&username, server, remember, mode).await,
+        Command::Logout => account::logout(mode),
+        Command::Whoami => account::whoami(mode),
+        Command::Invite { user } => room::invite(&user, mode).await,
+        Command::Join { room } => room::join(room, mode).await,
+        Command::Chat { room } => room::chat_room(room, mode).await,
+        Command::SendFile { room, path } => room::send_file(room, &path, mode).await,
+        Command::Rooms => room::rooms(mode),
+        Command::History { room, before, limit } => history::history(room, before, limit, mode),
+        Command::Search { query } => history::search(&query, mode),
+        Command::Export {
+            room,
+            path,
+            files,
+            encrypt,
+        } => history::export(room, &path, &files, encrypt, mode),
+        Command::Import { path, room } => history::import(&path, room, mode),
+        Command::Tui { room } => tui::tui(room).await,
+        Command::Pair { line } => pair::pair(line, mode).await,
+        Command::Lan { username } => lan::lan(username, mode).await,
+        Command::Rendezvous { port_or_address, token } => {
+            rendezvous::rendezvous(Some(port_or_address), token, mode).await
+        }
+        Command::Profile(ProfileCommand::List) => profile::list(mode),
+        Command::Profile(ProfileCommand::Use { username }) => profile::switch(&username, mode),
+        Command::Profile(ProfileCommand::Remove { username }) => profile::remove(&username, mode),
+        Command::Profile(ProfileCommand::DownloadDir { path }) => profile::download_dir(path, mode),
+    }
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    async fn for_user(
-        user: &str,
-        pass: &str,
-    ) -> Result<
-        (AtrisInitiator, AtrisAuth, AuthenticateUserResponse),
-        Box<dyn std::error::Error + Send + Sync>,
-    > {
-        let client = AtrisAuth::new()?;
-        client.create_user(user, pass).await?;
-        let initiator = AtrisInitiator::new(AtrisConnection::new().await?).await?;
-        let initiator_string = initiator.encoded_local_description()?;
-        let auth = client
-            .authenticate_user(user, pass, &initiator_string)
-            .await??;
-        Ok((initiator, client, auth))
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mode = if cli.json { OutputMode::Json } else { OutputMode::Human };
+    match run(cli.command, mode).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            match mode {
+                OutputMode::Human => eprintln!("Error: {e}"),
+                OutputMode::Json => mode.emit_error(e),
+            }
+            ExitCode::FAILURE
+        }
     }
-    // Create the client to the authorization server
-    let (terrior_initiator, _terrior, _terrior_session) = for_user("terrior", "password").await?;
-    let (_terrior2_initiator, terrior2, terrior2_session) =
-        for_user("terrior2", "password").await?;
-    let terrior2_responder = AtrisResponder::new().await?;
-    let room = dbg!(
-        terrior2
-            .create_room(terrior2_session.session_id.clone(), "terrior")
-            .await
-    )??;
-    let (terrior2_responder_string, terrior2_channel) = terrior2_responder
-        .into_channel_parts_with::<String>(&room.initiator_string)
-        .await?;
-    let set_room = dbg!(
-        terrior2
-            .set_room_responder(
-                room.room_id,
-                terrior2_session.session_id,
-                "terrior",
-                &terrior2_responder_string
-            )
-            .await
-    )??;
-    let mut terrior_parts = terrior_initiator
-        .into_channel_parts_with::<String>(&terrior2_responder_string)
-        .await?;
-    let mut terrior_channel = AtrisChannel::new(terrior_parts, set_room.room_symmetric_key.as_cipher());
-    let mut terrior2_channel =  AtrisChannel::new(terrior2_channel.await.ok_or("Ew!")?, set_room.room_symmetric_key.as_cipher());
-
-    terrior_channel.send("From terrior".into()).await;
-    dbg!(terrior2_channel.receive().await);
-
-    terrior2_channel.send("From terrior2".into()).await;
-    dbg!(terrior_channel.receive().await);
-
-    dbg!(room.room_id, set_room.room_symmetric_key);
-    Ok(())
 }
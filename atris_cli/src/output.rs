@@ -0,0 +1,278 @@
+//! What the commands report besides the chat itself, as sentences, or as JSON lines with `--json`
+use std::fmt::Display;
+
+use atris_client_lib::comms::console::OutputMode;
+use atris_client_lib::comms::signal;
+use atris_client_lib::history::{HistoryEntry, SearchHit};
+use serde::Serialize;
+
+use crate::session::SavedRoom;
+use crate::CliError;
+
+/// Something a command did, or wants from the user, tagged by its `event` field in JSON
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CliEvent {
+    /// What the command is doing, like waiting for the other user
+    Status {
+        message: String,
+    },
+    /// The command reads the next line of stdin, which is plain text even with `--json`
+    Prompt {
+        message: String,
+    },
+    Registered {
+        username: String,
+    },
+    LoggedIn {
+        username: String,
+    },
+    /// Nobody was logged in if there is no `username`
+    LoggedOut {
+        username: Option<String>,
+    },
+    Whoami {
+        username: Option<String>,
+    },
+    Rooms {
+        rooms: Vec<SavedRoom>,
+    },
+    Invited {
+        room_id: u16,
+        user: String,
+    },
+    /// A line for the user to hand to the other user, like a pairing offer
+    Line {
+        line: String,
+    },
+    Rendezvous {
+        port: u16,
+        token: String,
+    },
+    /// This user is advertised on the local network
+    Advertising {
+        username: String,
+        fingerprint: String,
+    },
+    /// Another user on the local network, to connect to by `index`
+    Peer {
+        index: usize,
+        username: String,
+        fingerprint: String,
+        address: String,
+    },
+    /// Connected to the other user, called `with` if they are known
+    Connected {
+        with: Option<String>,
+    },
+    FileSent {
+        path: String,
+    },
+    Profiles {
+        profiles: Vec<ProfileSummary>,
+    },
+    /// The commands now act as this profile
+    ProfileSwitched {
+        username: String,
+    },
+    ProfileRemoved {
+        username: String,
+    },
+    /// Where the profile saves accepted files, or the directory the client runs in if there is no `path`
+    DownloadDir {
+        username: String,
+        path: Option<String>,
+    },
+    /// A page of a conversation's messages, oldest first
+    History {
+        conversation: String,
+        entries: Vec<HistoryEntry>,
+    },
+    SearchResults {
+        query: String,
+        hits: Vec<SearchHit>,
+    },
+    Exported {
+        conversation: String,
+        path: String,
+        messages: usize,
+        files: usize,
+        encrypted: bool,
+    },
+    /// `messages` counts the ones which were not in the history already, and `files` is where the files were saved
+    Imported {
+        conversation: String,
+        messages: usize,
+        files: Vec<String>,
+    },
+}
+
+/// A saved profile, for `atris profile list`
+#[derive(Debug, Serialize)]
+pub struct ProfileSummary {
+    pub username: String,
+    /// The server the account is on, if it is not the default one
+    pub server: Option<String>,
+    pub logged_in: bool,
+    /// Whether the profile unlocks without its passphrase
+    pub remembered: bool,
+    /// Whether the commands act as this profile
+    pub current: bool,
+}
+impl Display for CliEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliEvent::Status { message } | CliEvent::Prompt { message } => write!(f, "{message}"),
+            CliEvent::Registered { username } => {
+                write!(f, "Registered {username}, log in with `atris login {username}`")
+            }
+            CliEvent::LoggedIn { username } => write!(f, "Logged in as {username}"),
+            CliEvent::LoggedOut {
+                username: Some(username),
+            } => write!(f, "Logged out of {username}"),
+            CliEvent::LoggedOut { username: None } => write!(f, "You were not logged in"),
+            CliEvent::Whoami {
+                username: Some(username),
+            } => write!(f, "{username}"),
+            CliEvent::Whoami { username: None } => write!(f, "Not logged in"),
+            CliEvent::Rooms { rooms } if rooms.is_empty() => {
+                write!(f, "No rooms yet, create one with `atris invite <user>`")
+            }
+            CliEvent::Rooms { rooms } => {
+                let lines: Vec<_> = rooms
+                    .iter()
+                    .map(|room| match (&room.other_user, room.created) {
+                        (Some(other_user), true) => format!("{}\tcreated for {other_user}", room.room_id),
+                        _ => format!("{}\tjoined", room.room_id),
+                    })
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            CliEvent::Invited { room_id, user } => write!(
+                f,
+                "Invited {user} to room {room_id}, ask them to run `atris join {room_id}`\n\
+                 If you stop waiting, come back with `atris chat {room_id}`"
+            ),
+            CliEvent::Line { line } => write!(f, "{line}"),
+            CliEvent::Rendezvous { port, token } => write!(
+                f,
+                "Serving a rendezvous on port {port}\n\
+                 Ask the other user to run `atris rendezvous <your address>:{port} {token}`"
+            ),
+            CliEvent::Advertising { username, fingerprint } => {
+                write!(f, "Advertising {username} with the fingerprint {fingerprint}")
+            }
+            CliEvent::Peer {
+                index,
+                username,
+                fingerprint,
+                address,
+            } => write!(f, "[{index}] {username} ({fingerprint}) at {address}"),
+            CliEvent::Connected { with: Some(with) } => {
+                write!(f, "Connected to {with}! Type to chat, or `/send <path>` to send a file")
+            }
+            CliEvent::Connected { with: None } => {
+                write!(f, "Connected! Type to chat, or `/send <path>` to send a file")
+            }
+            CliEvent::FileSent { path } => write!(f, "Sent {path}"),
+            CliEvent::Profiles { profiles } if profiles.is_empty() => {
+                write!(f, "No profiles yet, make one with `atris login <username>`")
+            }
+            CliEvent::Profiles { profiles } => {
+                let lines: Vec<_> = profiles
+                    .iter()
+                    .map(|profile| {
+                        let mut notes = Vec::new();
+                        if let Some(server) = &profile.server {
+                            notes.push(format!("on {server}"));
+                        }
+                        notes.push(if profile.logged_in { "logged in" } else { "logged out" }.to_owned());
+                        if profile.remembered {
+                            notes.push("remembered".to_owned());
+                        }
+                        let marker = if profile.current { "*" } else { " " };
+                        format!("{marker} {}\t{}", profile.username, notes.join(", "))
+                    })
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            CliEvent::ProfileSwitched { username } => write!(f, "Now acting as {username}"),
+            CliEvent::ProfileRemoved { username } => write!(f, "Removed the profile for {username}"),
+            CliEvent::DownloadDir {
+                username,
+                path: Some(path),
+            } => write!(f, "{username} saves accepted files in {path}"),
+            CliEvent::DownloadDir { username, path: None } => {
+                write!(f, "{username} saves accepted files in the directory atris runs in")
+            }
+            CliEvent::History { conversation, entries } if entries.is_empty() => {
+                write!(f, "No messages in {conversation}")
+            }
+            CliEvent::History { entries, .. } => {
+                let lines: Vec<_> = entries.iter().map(|entry| format!("{}\t{entry}", entry.id)).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            CliEvent::SearchResults { query, hits } if hits.is_empty() => write!(f, "No messages contain '{query}'"),
+            CliEvent::SearchResults { hits, .. } => {
+                let lines: Vec<_> = hits
+                    .iter()
+                    .map(|hit| format!("{}#{}\t{}", hit.conversation, hit.entry.id, hit.entry))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            CliEvent::Exported {
+                conversation,
+                path,
+                messages,
+                files,
+                encrypted,
+            } => {
+                let encrypted = if *encrypted { ", encrypted," } else { "" };
+                write!(
+                    f,
+                    "Exported {messages} messages and {files} files from {conversation}{encrypted} to {path}"
+                )
+            }
+            CliEvent::Imported {
+                conversation,
+                messages,
+                files,
+            } => {
+                write!(f, "Imported {messages} new messages into {conversation}")?;
+                for file in files {
+                    write!(f, "\nSaved {file}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Report what the command is doing
+pub fn status(mode: OutputMode, message: impl Into<String>) {
+    mode.emit(&CliEvent::Status {
+        message: message.into(),
+    });
+}
+
+/// Ask for a line of stdin
+pub fn prompt(mode: OutputMode, message: impl Into<String>) -> Result<String, CliError> {
+    mode.emit(&CliEvent::Prompt {
+        message: message.into(),
+    });
+    Ok(signal::read_in_line()?)
+}
+
+/// Ask for a secret, like the `password`, without echoing it, or as a plain line of stdin for programs driving `--json`
+pub fn secret(mode: OutputMode, message: &str, what: &str) -> Result<String, CliError> {
+    let secret = match mode {
+        OutputMode::Human => {
+            rpassword::prompt_password(message).map_err(|e| format!("Could not read the {what}: {e}"))?
+        }
+        OutputMode::Json => prompt(mode, message)?,
+    };
+    if secret.is_empty() {
+        return Err(format!("The {what} cannot be empty").into());
+    }
+    Ok(secret)
+}
@@ -0,0 +1,114 @@
+//! Pairs with another user by pasting lines to each other, without any server
+use std::time::Duration;
+
+use atris_client_lib::atris_common::message::ChatMessage;
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::channels::{IncomingChannels, DEFAULT_CHANNEL_LABEL};
+use atris_client_lib::comms::console::{Conversation, OutputMode};
+use atris_client_lib::comms::pairing::{self, PairingOffer};
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::transfer::{self, FileTransfers, TransferMessage, TRANSFER_CHANNEL_LABEL};
+use atris_client_lib::comms::{
+    initiator::AtrisInitiator, AtrisChannel, AtrisChannelParts, AtrisConnection, DEFAULT_STUN_SERVER,
+};
+use atris_client_lib::history::ConversationLog;
+
+use crate::output::{self, CliEvent};
+use crate::session;
+
+/// How long the answering end waits for the offering end to connect
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub async fn untrickled_connection() -> Result<AtrisConnection, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(AtrisConnection::builder()
+        .stun_server(DEFAULT_STUN_SERVER)
+        .untrickled()
+        .build()
+        .await?)
+}
+
+fn read_passphrase(mode: OutputMode) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let passphrase = output::prompt(mode, "Passphrase you both agreed on: ")?;
+    if passphrase.is_empty() {
+        return Err("The passphrase cannot be empty".into());
+    }
+    Ok(passphrase)
+}
+
+/// `pair` offers to pair, and `pair <line>` answers the offer in `line`
+pub async fn pair(
+    offer_line: Option<String>,
+    mode: OutputMode,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let passphrase = read_passphrase(mode)?;
+    let (chat_parts, file_parts, room_key) = match offer_line {
+        None => {
+            output::status(mode, "Gathering candidates...");
+            let initiator =
+                AtrisInitiator::with_channels(untrickled_connection().await?, transfer::chat_and_file_channels())
+                    .await?;
+            let offer = PairingOffer::new(initiator, &passphrase);
+            output::status(mode, "Give this line to the other user, and ask them to run `atris pair <line>`:");
+            mode.emit(&CliEvent::Line { line: offer.line()? });
+            let answer = output::prompt(mode, "Paste their answer: ")?;
+            let (mut channels, room_key) = offer.into_channels_with(&answer).await?;
+            let chat = channels.take(DEFAULT_CHANNEL_LABEL).ok_or("No chat channel!")?;
+            let files = channels.take(TRANSFER_CHANNEL_LABEL).ok_or("No file channel!")?;
+            (chat, files, room_key)
+        }
+        Some(offer_line) => {
+            output::status(mode, "Gathering candidates...");
+            let responder = AtrisResponder::with_connection(untrickled_connection().await?);
+            let (answer, channels, room_key) = pairing::answer(responder, &offer_line, &passphrase).await?;
+            output::status(mode, "Give this line back to the other user:");
+            mode.emit(&CliEvent::Line { line: answer });
+            output::status(mode, "Waiting for them to connect...");
+            let channels = tokio::time::timeout(CONNECT_TIMEOUT, channels.confirmed())
+                .await
+                .map_err(|_| "They did not connect in time")??;
+            let (chat, files) = take_incoming(channels).await?;
+            (chat, files, room_key)
+        }
+    };
+    mode.emit(&CliEvent::Connected { with: None });
+    chat(chat_parts, file_parts, room_key, None, mode).await
+}
+
+/// Wait for the chat and file channels the other user's offer opens
+pub async fn take_incoming(
+    mut channels: IncomingChannels,
+) -> Result<(AtrisChannelParts<ChatMessage>, AtrisChannelParts<TransferMessage>), Box<dyn std::error::Error + Send + Sync>>
+{
+    let timed_out = "They did not connect in time";
+    let chat = tokio::time::timeout(CONNECT_TIMEOUT, channels.take(DEFAULT_CHANNEL_LABEL))
+        .await
+        .map_err(|_| timed_out)?
+        .ok_or("No chat channel!")?;
+    let files = tokio::time::timeout(CONNECT_TIMEOUT, channels.take(TRANSFER_CHANNEL_LABEL))
+        .await
+        .map_err(|_| timed_out)?
+        .ok_or("No file channel!")?;
+    Ok((chat, files))
+}
+
+/// Chat over the channels, and send files over the file channel, until the connection closes. Accepted files are
+/// saved in the current profile's download directory, if it has one, and the messages are recorded in `history`
+/// if given.
+pub async fn chat(
+    chat: AtrisChannelParts<ChatMessage>,
+    files: AtrisChannelParts<TransferMessage>,
+    room_key: CipherKey,
+    history: Option<ConversationLog>,
+    mode: OutputMode,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let files = FileTransfers::spawn(AtrisChannel::new(files, room_key.as_cipher()));
+    let mut conversation = Conversation::new(AtrisChannel::new(chat, room_key.as_cipher()), Some(files));
+    if let Some(dir) = session::download_dir() {
+        conversation = conversation.save_files_in(dir);
+    }
+    if let Some(log) = history {
+        conversation = conversation.record_in(log);
+    }
+    conversation.console(mode).await?;
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! Listing, switching between and removing the saved profiles, and changing their preferences
+use std::path::PathBuf;
+
+use atris_client_lib::comms::console::OutputMode;
+use atris_client_lib::profile::Profiles;
+
+use crate::output::{CliEvent, ProfileSummary};
+use crate::session::current_profile;
+use crate::CliError;
+
+/// `profile list` lists the saved profiles, marking the current one
+pub fn list(mode: OutputMode) -> Result<(), CliError> {
+    let profiles = Profiles::open()?;
+    let current = profiles.current()?;
+    let mut summaries = Vec::new();
+    for username in profiles.list()? {
+        let profile = profiles.load(&username)?;
+        summaries.push(ProfileSummary {
+            remembered: profiles.remembered(&username)?.is_some(),
+            current: current.as_deref() == Some(username.as_str()),
+            logged_in: profile.is_logged_in(),
+            server: profile.server,
+            username,
+        });
+    }
+    mode.emit(&CliEvent::Profiles { profiles: summaries });
+    Ok(())
+}
+
+/// `profile use <username>` makes the other commands act as another saved profile
+pub fn switch(username: &str, mode: OutputMode) -> Result<(), CliError> {
+    let profiles = Profiles::open()?;
+    profiles.load(username)?;
+    profiles.set_current(Some(username))?;
+    mode.emit(&CliEvent::ProfileSwitched {
+        username: username.to_owned(),
+    });
+    Ok(())
+}
+
+/// `profile remove <username>` deletes a saved profile, with its keys, session, rooms and history
+pub fn remove(username: &str, mode: OutputMode) -> Result<(), CliError> {
+    Profiles::open()?.remove(username)?;
+    mode.emit(&CliEvent::ProfileRemoved {
+        username: username.to_owned(),
+    });
+    Ok(())
+}
+
+/// `profile download-dir [path]` saves accepted files in `path` from now on, or in the directory atris runs in
+/// without one
+pub fn download_dir(path: Option<PathBuf>, mode: OutputMode) -> Result<(), CliError> {
+    let mut profile = current_profile()?.ok_or("There is no current profile, run `atris login <username>` first")?;
+    let path = match path {
+        Some(path) if !path.is_dir() => return Err(format!("{} is not a directory", path.display()).into()),
+        Some(path) => Some(path.canonicalize()?),
+        None => None,
+    };
+    profile.preferences.download_dir = path.clone();
+    Profiles::open()?.save(&profile)?;
+    mode.emit(&CliEvent::DownloadDir {
+        username: profile.username,
+        path: path.map(|path| path.display().to_string()),
+    });
+    Ok(())
+}
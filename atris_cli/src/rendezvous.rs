@@ -0,0 +1,61 @@
+//! Meets another user at a temporary HTTP endpoint one of them serves, without any server or accounts
+use std::net::SocketAddr;
+
+use atris_client_lib::comms::channels::DEFAULT_CHANNEL_LABEL;
+use atris_client_lib::comms::console::OutputMode;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::signal::{self, RendezvousServer, RendezvousToken};
+use atris_client_lib::comms::transfer::{self, TRANSFER_CHANNEL_LABEL};
+
+use crate::output::{self, CliEvent};
+use crate::pair::{chat, take_incoming, untrickled_connection};
+
+/// `rendezvous <port>` serves a rendezvous and waits for an offer, and `rendezvous <address> <token>` posts one to it
+pub async fn rendezvous(
+    first: Option<String>,
+    token: Option<String>,
+    mode: OutputMode,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let usage = "Usage: rendezvous <port>, or rendezvous <address> <token>";
+    let (chat_parts, file_parts, room_key) = match (first, token) {
+        (Some(port), None) => {
+            let port = port.parse::<u16>().map_err(|_| usage)?;
+            let mut server = RendezvousServer::bind(SocketAddr::from(([0, 0, 0, 0], port))).await?;
+            mode.emit(&CliEvent::Rendezvous {
+                port: server.address().port(),
+                token: server.token().to_string(),
+            });
+            let responder = AtrisResponder::with_connection(untrickled_connection().await?);
+            let pending = server.next_offer().await.ok_or("The rendezvous closed")?;
+            output::status(mode, "Got an offer, gathering candidates...");
+            let (answer, channels) = responder.into_channels_with(pending.offer()).await?;
+            pending.answer(answer);
+            let (chat_parts, file_parts) = take_incoming(channels).await?;
+            (chat_parts, file_parts, server.token().room_key())
+        }
+        (Some(address), Some(token)) => {
+            let token = RendezvousToken::parse(&token)?;
+            output::status(mode, "Gathering candidates...");
+            let initiator = AtrisInitiator::with_channels(
+                untrickled_connection().await?,
+                transfer::chat_and_file_channels(),
+            )
+            .await?;
+            let answer =
+                signal::post_offer(&address, &token, &initiator.compact_local_description()?)
+                    .await?;
+            let mut channels = initiator.into_channels_with(&answer).await?;
+            let chat_parts = channels
+                .take(DEFAULT_CHANNEL_LABEL)
+                .ok_or("No chat channel!")?;
+            let file_parts = channels
+                .take(TRANSFER_CHANNEL_LABEL)
+                .ok_or("No file channel!")?;
+            (chat_parts, file_parts, token.room_key())
+        }
+        _ => return Err(usage.into()),
+    };
+    mode.emit(&CliEvent::Connected { with: None });
+    chat(chat_parts, file_parts, room_key, None, mode).await
+}
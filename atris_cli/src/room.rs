@@ -0,0 +1,173 @@
+//! Inviting other users to rooms, joining them, and chatting and sending files in them
+use std::path::Path;
+
+use atris_client_lib::atris_common::message::ChatMessage;
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::channels::DEFAULT_CHANNEL_LABEL;
+use atris_client_lib::comms::console::{ChatEvent, OutputMode};
+use atris_client_lib::comms::invite::{self, Invitation};
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::transfer::{self, FileTransfers, TransferEvent, TransferMessage, TRANSFER_CHANNEL_LABEL};
+use atris_client_lib::comms::{initiator::AtrisInitiator, AtrisChannel, AtrisChannelParts, AtrisConnection};
+use atris_client_lib::history::History;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::output::{self, CliEvent};
+use crate::pair::{chat, take_incoming};
+use crate::session::{SavedRoom, Session};
+use crate::CliError;
+
+pub type RoomChannels = (AtrisChannelParts<ChatMessage>, AtrisChannelParts<TransferMessage>, CipherKey);
+
+async fn connection(session: &Session) -> Result<AtrisConnection, CliError> {
+    Ok(AtrisConnection::builder()
+        .ice_servers(session.ice_servers())
+        .build()
+        .await?)
+}
+
+/// What [`enter`] waits for in `room`
+pub fn waiting_for(room: &SavedRoom) -> String {
+    let other_user = room.other_user.as_deref().unwrap_or("the other user");
+    if room.created {
+        format!("Waiting for {other_user} to join room {}...", room.room_id)
+    } else {
+        format!("Waiting for {other_user} to let you into room {}...", room.room_id)
+    }
+}
+
+/// Connect to the other user in a room this user created or joined, waiting for them if they are not there yet
+pub async fn enter(session: &Session, room: &SavedRoom) -> Result<RoomChannels, CliError> {
+    let client = session.client()?;
+    let other_user = room.other_user.as_deref().unwrap_or("the other user");
+    if room.created {
+        let invitation = Invitation::existing(client, session.session_id(), room.room_id, other_user);
+        let responder = AtrisResponder::with_connection(connection(session).await?);
+        let (channels, room_key) = invitation.accept(responder).await?;
+        let (chat_parts, file_parts) = take_incoming(channels).await?;
+        Ok((chat_parts, file_parts, room_key))
+    } else {
+        let initiator =
+            AtrisInitiator::with_channels(connection(session).await?, transfer::chat_and_file_channels()).await?;
+        let (mut channels, room_key) = invite::join(client, session.session_id(), room.room_id, initiator).await?;
+        let chat_parts = channels.take(DEFAULT_CHANNEL_LABEL).ok_or("No chat channel!")?;
+        let file_parts = channels.take(TRANSFER_CHANNEL_LABEL).ok_or("No file channel!")?;
+        Ok((chat_parts, file_parts, room_key))
+    }
+}
+
+/// Enter a room and chat in it until the connection closes
+async fn chat_in(session: &Session, room: &SavedRoom, mode: OutputMode) -> Result<(), CliError> {
+    output::status(mode, waiting_for(room));
+    let (chat_parts, file_parts, room_key) = enter(session, room).await?;
+    mode.emit(&CliEvent::Connected {
+        with: room.other_user.clone(),
+    });
+    let history = session.history()?.conversation(&History::room(room.room_id))?;
+    chat(chat_parts, file_parts, room_key, Some(history), mode).await
+}
+
+/// The room `room_id` this user created or joined before
+fn saved_room(session: &Session, room_id: u16) -> Result<SavedRoom, CliError> {
+    session.room(room_id).cloned().ok_or_else(|| {
+        format!("You have not created or joined room {room_id}, see `atris rooms`, or `atris join {room_id}`").into()
+    })
+}
+
+/// `invite <user>` creates a room for `user`, and waits in it for them
+pub async fn invite(other_user: &str, mode: OutputMode) -> Result<(), CliError> {
+    let mut session = Session::require(mode)?;
+    let invitation = Invitation::create(session.client()?, session.session_id(), other_user).await?;
+    let room = SavedRoom {
+        room_id: invitation.room_id(),
+        other_user: Some(other_user.to_owned()),
+        created: true,
+    };
+    session.remember_room(room.clone())?;
+    mode.emit(&CliEvent::Invited {
+        room_id: room.room_id,
+        user: other_user.to_owned(),
+    });
+    chat_in(&session, &room, mode).await
+}
+
+/// `join <room>` joins a room another user invited this user to
+pub async fn join(room_id: u16, mode: OutputMode) -> Result<(), CliError> {
+    let mut session = Session::require(mode)?;
+    let room = match session.room(room_id) {
+        Some(room) if room.created => return Err(format!("You created room {room_id}, use `atris chat {room_id}`").into()),
+        Some(room) => room.clone(),
+        None => {
+            let room = SavedRoom {
+                room_id,
+                other_user: None,
+                created: false,
+            };
+            session.remember_room(room.clone())?;
+            room
+        }
+    };
+    chat_in(&session, &room, mode).await
+}
+
+/// `chat <room>` goes back to a room this user created or joined
+pub async fn chat_room(room_id: u16, mode: OutputMode) -> Result<(), CliError> {
+    let session = Session::require(mode)?;
+    let room = saved_room(&session, room_id)?;
+    chat_in(&session, &room, mode).await
+}
+
+/// `send-file <room> <path>` offers a file in a room, and waits until the other user has it
+pub async fn send_file(room_id: u16, path: &Path, mode: OutputMode) -> Result<(), CliError> {
+    if !path.is_file() {
+        return Err(format!("{} is not a file", path.display()).into());
+    }
+    let session = Session::require(mode)?;
+    let room = saved_room(&session, room_id)?;
+    output::status(mode, waiting_for(&room));
+    let (_chat_parts, file_parts, room_key) = enter(&session, &room).await?;
+
+    let files = FileTransfers::spawn(AtrisChannel::new(file_parts, room_key.as_cipher()));
+    let mut events = files.events();
+    let id = files.send_file(path).await?;
+    mode.emit(&ChatEvent::FileOffered {
+        id,
+        path: path.to_owned(),
+    });
+    output::status(mode, "Waiting for the other user to accept it...");
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Err("The connection closed".into()),
+        };
+        match event {
+            TransferEvent::Accepted { id: accepted } | TransferEvent::Progress { id: accepted, .. } if accepted == id => {
+                mode.emit(&ChatEvent::from(event))
+            }
+            TransferEvent::Completed { id: sent, .. } if sent == id => {
+                mode.emit(&CliEvent::FileSent {
+                    path: path.display().to_string(),
+                });
+                return Ok(());
+            }
+            TransferEvent::Rejected { id: rejected } if rejected == id => {
+                return Err("The other user rejected the file".into())
+            }
+            TransferEvent::Failed { id: failed, reason } if failed == id => return Err(reason.into()),
+            TransferEvent::Cancelled { id: cancelled } if cancelled == id => {
+                return Err("The transfer was cancelled".into())
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `rooms` lists the rooms this user created or joined
+pub fn rooms(mode: OutputMode) -> Result<(), CliError> {
+    let session = Session::require(mode)?;
+    mode.emit(&CliEvent::Rooms {
+        rooms: session.rooms().to_vec(),
+    });
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! The profile `atris login` saved the session in, which the other commands act as
+use std::path::PathBuf;
+
+use atris_client_lib::atris_common::{CipherKey, IceServer};
+use atris_client_lib::comms::console::OutputMode;
+use atris_client_lib::history::History;
+use atris_client_lib::http_auth::AtrisAuth;
+use atris_client_lib::identity::IdentityKey;
+pub use atris_client_lib::profile::SavedRoom;
+use atris_client_lib::profile::{Profile, Profiles, SavedSession, UnlockedProfile};
+
+use crate::output;
+use crate::CliError;
+
+const NOT_LOGGED_IN: &str = "You are not logged in, run `atris login <username>` first";
+
+/// Unlock a profile with its remembered key, or else with a passphrase from the user
+pub fn unlock(profiles: &Profiles, profile: Profile, mode: OutputMode) -> Result<UnlockedProfile, CliError> {
+    if let Some(key) = profiles.remembered(&profile.username)? {
+        return Ok(profile.unlock_with(key)?);
+    }
+    let passphrase = output::secret(mode, &format!("Passphrase for {}: ", profile.username), "passphrase")?;
+    Ok(profile.unlock(&passphrase)?)
+}
+
+/// The current profile, as saved, if there is one
+pub fn current_profile() -> Result<Option<Profile>, CliError> {
+    let profiles = Profiles::open()?;
+    match profiles.current()? {
+        Some(username) => Ok(profiles.find(&username)?),
+        None => Ok(None),
+    }
+}
+
+/// Where the current profile saves accepted files, if it says
+pub fn download_dir() -> Option<PathBuf> {
+    current_profile().ok().flatten()?.preferences.download_dir
+}
+
+/// The identity key of `username`'s profile, if they have one
+pub fn identity(username: &str, mode: OutputMode) -> Result<Option<IdentityKey>, CliError> {
+    let profiles = Profiles::open()?;
+    match profiles.find(username)? {
+        Some(profile) => Ok(Some(unlock(&profiles, profile, mode)?.identity().clone())),
+        None => Ok(None),
+    }
+}
+
+/// The current profile, unlocked, while it is logged in
+#[derive(Clone)]
+pub struct Session {
+    profiles: Profiles,
+    profile: UnlockedProfile,
+    session: SavedSession,
+}
+impl Session {
+    /// The current profile, or an error telling the user to log in
+    pub fn require(mode: OutputMode) -> Result<Self, CliError> {
+        let profile = current_profile()?.filter(Profile::is_logged_in).ok_or(NOT_LOGGED_IN)?;
+        let profiles = Profiles::open()?;
+        let profile = unlock(&profiles, profile, mode)?;
+        let session = profile.session().cloned().ok_or(NOT_LOGGED_IN)?;
+        Ok(Self {
+            profiles,
+            profile,
+            session,
+        })
+    }
+
+    pub fn username(&self) -> &str {
+        self.profile.username()
+    }
+
+    pub fn session_id(&self) -> CipherKey {
+        self.session.session_id.clone()
+    }
+
+    /// The ICE servers handed out at login, with the credentials for the deployment's TURN relay
+    pub fn ice_servers(&self) -> Vec<IceServer> {
+        self.session.ice_servers.clone()
+    }
+
+    /// A client for the server the profile is on
+    pub fn client(&self) -> Result<AtrisAuth, CliError> {
+        Ok(self.profile.profile().auth_client()?)
+    }
+
+    pub fn rooms(&self) -> &[SavedRoom] {
+        &self.profile.profile().rooms
+    }
+
+    pub fn room(&self, room_id: u16) -> Option<&SavedRoom> {
+        self.profile.profile().room(room_id)
+    }
+
+    /// Remember a room, replacing anything remembered under the same number
+    pub fn remember_room(&mut self, room: SavedRoom) -> Result<(), CliError> {
+        self.profile.profile_mut().remember_room(room);
+        Ok(self.profiles.save(self.profile.profile())?)
+    }
+
+    /// The messages this profile sent and received, locked with its key
+    pub fn history(&self) -> Result<History, CliError> {
+        Ok(self.profiles.history(&self.profile)?)
+    }
+
+    /// Where accepted files are saved, if the user said
+    pub fn download_dir(&self) -> Option<PathBuf> {
+        self.profile.profile().preferences.download_dir.clone()
+    }
+}
@@ -0,0 +1,755 @@
+//! A full-screen client, with the rooms on the side, the conversation in a scrollback pane above an input line with
+//! history, file transfers under the conversation, and the connection in a status bar
+use std::collections::BTreeMap;
+use std::io::Stdout;
+
+use atris_client_lib::comms::console::{ChatCommand, ChatEvent, Conversation, OutputMode};
+use atris_client_lib::comms::presence::Presence;
+use atris_client_lib::comms::transfer::{FileTransfers, TransferId};
+use atris_client_lib::comms::AtrisChannel;
+use atris_client_lib::history::{History, HistoryEntry};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tui::backend::CrosstermBackend;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use tui::{Frame, Terminal};
+use unicode_width::UnicodeWidthChar;
+
+use crate::room::{self, RoomChannels};
+use crate::session::{SavedRoom, Session};
+use crate::CliError;
+
+/// The most transfers shown under the conversation at once
+const SHOWN_TRANSFERS: usize = 4;
+/// How many lines `PageUp` and `PageDown` scroll by
+const PAGE: usize = 10;
+/// How many of the messages from before are shown on entering a room
+const EARLIER_MESSAGES: usize = 100;
+
+/// Puts the terminal back the way it was however the client exits
+struct TerminalGuard;
+impl TerminalGuard {
+    fn enter() -> Result<Self, CliError> {
+        enable_raw_mode()?;
+        crossterm::execute!(std::io::stdout(), EnterAlternateScreen)?;
+        Ok(TerminalGuard)
+    }
+}
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = crossterm::execute!(std::io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+/// The line being typed, and the lines typed before
+#[derive(Default)]
+struct InputLine {
+    text: String,
+    /// The cursor as a character index into `text`
+    cursor: usize,
+    history: Vec<String>,
+    /// Which line of the history is shown, if the user went back to one
+    browsing: Option<usize>,
+    /// What was being typed before going back in the history
+    draft: String,
+}
+impl InputLine {
+    fn byte_index(&self) -> usize {
+        self.text
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    fn insert(&mut self, c: char) {
+        let index = self.byte_index();
+        self.text.insert(index, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let index = self.byte_index();
+            self.text.remove(index);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            let index = self.byte_index();
+            self.text.remove(index);
+        }
+    }
+
+    fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    fn end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    fn show(&mut self, text: String) {
+        self.text = text;
+        self.end();
+    }
+
+    /// Go back to the line typed before the one shown
+    fn previous(&mut self) {
+        let index = match self.browsing {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = std::mem::take(&mut self.text);
+                self.history.len() - 1
+            }
+            Some(index) => index.saturating_sub(1),
+        };
+        self.browsing = Some(index);
+        self.show(self.history[index].clone());
+    }
+
+    /// Go forward to the line typed after the one shown, and then back to the draft
+    fn next(&mut self) {
+        let Some(index) = self.browsing else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.browsing = Some(index + 1);
+            self.show(self.history[index + 1].clone());
+        } else {
+            self.browsing = None;
+            let draft = std::mem::take(&mut self.draft);
+            self.show(draft);
+        }
+    }
+
+    /// Take the typed line, remembering it unless it repeats the last one
+    fn submit(&mut self) -> String {
+        let line = std::mem::take(&mut self.text);
+        self.cursor = 0;
+        self.browsing = None;
+        self.draft.clear();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        line
+    }
+}
+
+/// A line of the scrollback
+enum Entry {
+    Incoming(String),
+    Outgoing(String),
+    /// A message from the history, with when it was sent and by whom
+    Earlier(String),
+    Info(String),
+    Error(String),
+}
+impl Entry {
+    fn style(&self) -> (&'static str, Style) {
+        match self {
+            Entry::Incoming(_) => ("them: ", Style::default().fg(Color::Cyan)),
+            Entry::Outgoing(_) => ("you: ", Style::default().fg(Color::Green)),
+            Entry::Earlier(_) => ("", Style::default().fg(Color::Gray)),
+            Entry::Info(_) => ("* ", Style::default().fg(Color::DarkGray)),
+            Entry::Error(_) => ("! ", Style::default().fg(Color::Red)),
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            Entry::Incoming(text)
+            | Entry::Outgoing(text)
+            | Entry::Earlier(text)
+            | Entry::Info(text)
+            | Entry::Error(text) => text,
+        }
+    }
+}
+impl From<&HistoryEntry> for Entry {
+    fn from(entry: &HistoryEntry) -> Self {
+        Entry::Earlier(entry.to_string())
+    }
+}
+
+/// A file transfer, as it is shown under the conversation
+struct TransferView {
+    name: String,
+    bytes: u64,
+    size: u64,
+    status: &'static str,
+}
+
+/// The connection to the room the user picked
+enum Link {
+    None,
+    Connecting(SavedRoom, JoinHandle<Result<RoomChannels, CliError>>),
+    Connected(SavedRoom, Box<Conversation>),
+    Ended(SavedRoom, String),
+}
+
+/// What happened to the [`Link`]
+enum LinkUpdate {
+    Entered(Result<Result<RoomChannels, CliError>, tokio::task::JoinError>),
+    /// Something happened in the room, or it ended for the reason given
+    Event(Result<ChatEvent, String>),
+}
+impl Link {
+    /// Wait for the room to be entered, or for something to happen in it, or forever if there is nothing to wait for
+    async fn next(&mut self) -> LinkUpdate {
+        match self {
+            Link::Connecting(_, connecting) => LinkUpdate::Entered(connecting.await),
+            Link::Connected(_, conversation) => {
+                LinkUpdate::Event(conversation.next_event().await.map_err(|e| e.to_string()))
+            }
+            Link::None | Link::Ended(..) => std::future::pending().await,
+        }
+    }
+}
+
+struct App {
+    session: Session,
+    /// The messages sent and received in every room, unless it could not be opened
+    history: Option<History>,
+    rooms: ListState,
+    /// Whether keys go to the room list rather than the input line
+    picking_room: bool,
+    input: InputLine,
+    scrollback: Vec<Entry>,
+    /// How many lines up from the bottom the scrollback is scrolled
+    scroll: usize,
+    transfers: BTreeMap<TransferId, TransferView>,
+    link: Link,
+    /// What the other user is doing, like `is typing...`
+    presence: Option<String>,
+    reconnecting: bool,
+    quit: bool,
+}
+impl App {
+    fn new(session: Session) -> Self {
+        let mut rooms = ListState::default();
+        rooms.select((!session.rooms().is_empty()).then_some(0));
+        let mut scrollback = vec![Entry::Info(
+            "Pick a room with the arrows and Enter, Tab switches to the input line, Esc quits. \
+             `/search <words>` finds earlier messages"
+                .to_owned(),
+        )];
+        let history = match session.history() {
+            Ok(history) => Some(history),
+            Err(e) => {
+                scrollback.push(Entry::Error(format!("The message history is not kept: {e}")));
+                None
+            }
+        };
+        App {
+            picking_room: true,
+            session,
+            history,
+            rooms,
+            input: InputLine::default(),
+            scrollback,
+            scroll: 0,
+            transfers: BTreeMap::new(),
+            link: Link::None,
+            presence: None,
+            reconnecting: false,
+            quit: false,
+        }
+    }
+
+    fn log(&mut self, entry: Entry) {
+        self.scrollback.push(entry);
+        // Keep what the user scrolled up to in place
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+
+    fn conversation(&mut self) -> Option<&mut Conversation> {
+        match &mut self.link {
+            Link::Connected(_, conversation) => Some(conversation),
+            _ => None,
+        }
+    }
+
+    /// Leave the room the user is in, if any, and connect to `room`
+    fn enter(&mut self, room: SavedRoom) {
+        if let Link::Connecting(_, connecting) = &self.link {
+            connecting.abort();
+        }
+        if let Some(conversation) = self.conversation() {
+            conversation.channel().signal(Presence::Left);
+        }
+        self.transfers.clear();
+        self.presence = None;
+        self.reconnecting = false;
+        self.show_earlier(&room);
+        self.log(Entry::Info(room::waiting_for(&room)));
+        let session = self.session.clone();
+        let entering = room.clone();
+        let connecting = tokio::spawn(async move { room::enter(&session, &entering).await });
+        self.link = Link::Connecting(room, connecting);
+        self.picking_room = false;
+    }
+
+    /// Show the last messages from the history of `room`
+    fn show_earlier(&mut self, room: &SavedRoom) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        match history.conversation(&History::room(room.room_id)) {
+            Ok(log) => {
+                for entry in log.page(None, EARLIER_MESSAGES) {
+                    self.log(Entry::from(entry));
+                }
+            }
+            Err(e) => self.log(Entry::Error(e.to_string())),
+        }
+    }
+
+    /// Show the messages in every room containing every word of `query`
+    fn search(&mut self, query: &str) {
+        let Some(history) = &self.history else {
+            return self.log(Entry::Error("The message history is not kept".to_owned()));
+        };
+        match history.search(query) {
+            Ok(hits) if hits.is_empty() => self.log(Entry::Info(format!("No messages contain '{query}'"))),
+            Ok(hits) => {
+                self.log(Entry::Info(format!("Messages containing '{query}':")));
+                for hit in hits {
+                    self.log(Entry::Earlier(format!("{}: {}", hit.conversation, hit.entry)));
+                }
+            }
+            Err(e) => self.log(Entry::Error(e.to_string())),
+        }
+    }
+
+    async fn connected(&mut self, result: Result<RoomChannels, CliError>) {
+        let Link::Connecting(room, _) = std::mem::replace(&mut self.link, Link::None) else {
+            return;
+        };
+        match result {
+            Ok((chat_parts, file_parts, room_key)) => {
+                let files = FileTransfers::spawn(AtrisChannel::new(file_parts, room_key.as_cipher()));
+                let chat = AtrisChannel::new(chat_parts, room_key.as_cipher());
+                self.log(Entry::Info(
+                    "Connected! Type to chat, or `/send <path>` to send a file".to_owned(),
+                ));
+                let mut conversation = Conversation::new(chat, Some(files));
+                if let Some(dir) = self.session.download_dir() {
+                    conversation = conversation.save_files_in(dir);
+                }
+                if let Some(history) = &self.history {
+                    match history.conversation(&History::room(room.room_id)) {
+                        Ok(log) => conversation = conversation.record_in(log),
+                        Err(e) => self.log(Entry::Error(e.to_string())),
+                    }
+                }
+                if let Err(e) = conversation
+                    .introduce(concat!("atris tui ", env!("CARGO_PKG_VERSION")))
+                    .await
+                {
+                    self.log(Entry::Error(e.to_string()));
+                }
+                self.link = Link::Connected(room, Box::new(conversation));
+            }
+            Err(e) => {
+                self.log(Entry::Error(e.to_string()));
+                self.link = Link::Ended(room, e.to_string());
+            }
+        }
+    }
+
+    fn on_chat_event(&mut self, event: ChatEvent) {
+        match &event {
+            ChatEvent::Message { text } => {
+                self.presence = None;
+                self.log(Entry::Incoming(text.clone()));
+                return;
+            }
+            // Shown in the status bar
+            ChatEvent::Presence { presence } => {
+                self.presence = Some(presence.clone());
+                return;
+            }
+            ChatEvent::Reconnecting => self.reconnecting = true,
+            ChatEvent::Reconnected => self.reconnecting = false,
+            ChatEvent::FileOffered { id, path } => self.track(*id, path.display().to_string(), 0, "offered"),
+            ChatEvent::TransferOffered { id, name, size } => self.track(*id, name.clone(), *size, "offered to you"),
+            ChatEvent::TransferAccepted { id } => self.update(*id, None, "accepted"),
+            ChatEvent::TransferRejected { id } => self.update(*id, None, "rejected"),
+            ChatEvent::TransferProgress { id, bytes, size } => {
+                if let Some(transfer) = self.transfers.get_mut(id) {
+                    transfer.size = *size;
+                }
+                self.update(*id, Some(*bytes), "sending");
+                // Shown as a progress bar
+                return;
+            }
+            ChatEvent::TransferCompleted { id, .. } => {
+                let size = self.transfers.get(id).map_or(0, |transfer| transfer.size);
+                self.update(*id, Some(size), "done");
+            }
+            ChatEvent::TransferFailed { id, .. } => self.update(*id, None, "failed"),
+            ChatEvent::TransferCancelled { id } => self.update(*id, None, "cancelled"),
+            ChatEvent::Connection { .. }
+            | ChatEvent::Hello { .. }
+            | ChatEvent::Unsupported { .. }
+            | ChatEvent::Error { .. } => {}
+        }
+        let entry = match event {
+            ChatEvent::Error { message } => Entry::Error(message),
+            event => Entry::Info(event.to_string()),
+        };
+        self.log(entry);
+    }
+
+    fn track(&mut self, id: TransferId, name: String, size: u64, status: &'static str) {
+        self.transfers.insert(
+            id,
+            TransferView {
+                name,
+                bytes: 0,
+                size,
+                status,
+            },
+        );
+    }
+
+    fn update(&mut self, id: TransferId, bytes: Option<u64>, status: &'static str) {
+        if let Some(transfer) = self.transfers.get_mut(&id) {
+            transfer.bytes = bytes.unwrap_or(transfer.bytes);
+            transfer.status = status;
+        }
+    }
+
+    fn ended(&mut self, reason: String) {
+        if let Link::Connected(room, _) = std::mem::replace(&mut self.link, Link::None) {
+            self.log(Entry::Error(reason.clone()));
+            self.link = Link::Ended(room, reason);
+        }
+        self.presence = None;
+    }
+
+    async fn on_key(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Release {
+            return;
+        }
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Tab | KeyCode::BackTab => self.picking_room = !self.picking_room,
+            KeyCode::PageUp => self.scroll += PAGE,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(PAGE),
+            _ if self.picking_room => self.on_room_key(key.code),
+            _ => self.on_input_key(key.code).await,
+        }
+    }
+
+    fn on_room_key(&mut self, code: KeyCode) {
+        let count = self.session.rooms().len();
+        if count == 0 {
+            return;
+        }
+        let selected = self.rooms.selected().unwrap_or(0);
+        match code {
+            KeyCode::Up => self.rooms.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self.rooms.select(Some((selected + 1).min(count - 1))),
+            KeyCode::Enter => {
+                let room = self.session.rooms()[selected].clone();
+                self.enter(room);
+            }
+            _ => {}
+        }
+    }
+
+    async fn on_input_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char(c) => {
+                self.input.insert(c);
+                if let Some(conversation) = self.conversation() {
+                    conversation.channel().signal(Presence::Typing);
+                }
+            }
+            KeyCode::Backspace => self.input.backspace(),
+            KeyCode::Delete => self.input.delete(),
+            KeyCode::Left => self.input.left(),
+            KeyCode::Right => self.input.right(),
+            KeyCode::Home => self.input.home(),
+            KeyCode::End => self.input.end(),
+            KeyCode::Up => self.input.previous(),
+            KeyCode::Down => self.input.next(),
+            KeyCode::Enter => {
+                let line = self.input.submit();
+                if !line.trim().is_empty() {
+                    self.on_line(line).await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn on_line(&mut self, line: String) {
+        let mut words = line.split_whitespace();
+        if words.next() == Some("/search") {
+            return match words.collect::<Vec<_>>().join(" ") {
+                query if query.is_empty() => self.log(Entry::Error("Usage: /search <words>".to_owned())),
+                query => self.search(&query),
+            };
+        }
+        let command = match ChatCommand::parse(&line) {
+            Ok(command) => command,
+            Err(e) => return self.log(Entry::Error(e.to_string())),
+        };
+        let Some(conversation) = self.conversation() else {
+            return self.log(Entry::Error(
+                "Pick a room to chat in first, Tab goes to the rooms".to_owned(),
+            ));
+        };
+        conversation.channel().signal(Presence::StoppedTyping);
+        let sent = match &command {
+            ChatCommand::Send { text } => Some(text.clone()),
+            _ => None,
+        };
+        match conversation.run(command).await {
+            Ok(event) => {
+                self.scroll = 0;
+                if let Some(text) = sent {
+                    self.log(Entry::Outgoing(text));
+                }
+                if let Some(event) = event {
+                    self.on_chat_event(event);
+                }
+            }
+            Err(e) => self.log(Entry::Error(e.to_string())),
+        }
+    }
+}
+
+/// Split `text` into lines at most `width` columns wide, breaking anywhere
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut used = 0;
+    for c in text.chars() {
+        let c_width = c.width().unwrap_or(0);
+        if used + c_width > width.max(1) && used > 0 {
+            lines.push(String::new());
+            used = 0;
+        }
+        lines.last_mut().expect("a line").push(c);
+        used += c_width;
+    }
+    lines
+}
+
+fn draw(f: &mut Frame<CrosstermBackend<Stdout>>, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3), Constraint::Length(1)])
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(24), Constraint::Min(10)])
+        .split(rows[0]);
+    draw_rooms(f, app, columns[0]);
+
+    let shown_transfers = app.transfers.len().min(SHOWN_TRANSFERS) as u16;
+    let panes = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3),
+            Constraint::Length(if shown_transfers == 0 { 0 } else { shown_transfers + 2 }),
+        ])
+        .split(columns[1]);
+    draw_scrollback(f, app, panes[0]);
+    if shown_transfers > 0 {
+        draw_transfers(f, app, panes[1]);
+    }
+    draw_input(f, app, rows[1]);
+    draw_status(f, app, rows[2]);
+}
+
+fn focused(block: Block, focus: bool) -> Block {
+    match focus {
+        true => block.border_style(Style::default().fg(Color::Yellow)),
+        false => block,
+    }
+}
+
+fn draw_rooms(f: &mut Frame<CrosstermBackend<Stdout>>, app: &mut App, area: Rect) {
+    let items: Vec<_> = app
+        .session
+        .rooms()
+        .iter()
+        .map(|room| {
+            let label = match (&room.other_user, room.created) {
+                (Some(other_user), true) => format!("{} {other_user}", room.room_id),
+                _ => format!("{} joined", room.room_id),
+            };
+            ListItem::new(label)
+        })
+        .collect();
+    let list = List::new(items)
+        .block(focused(
+            Block::default().borders(Borders::ALL).title("Rooms"),
+            app.picking_room,
+        ))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, area, &mut app.rooms);
+}
+
+fn draw_scrollback(f: &mut Frame<CrosstermBackend<Stdout>>, app: &mut App, area: Rect) {
+    let title = match &app.link {
+        Link::None => "Atris".to_owned(),
+        Link::Connecting(room, _) | Link::Connected(room, _) | Link::Ended(room, _) => match &room.other_user {
+            Some(other_user) => format!("Room {} with {other_user}", room.room_id),
+            None => format!("Room {}", room.room_id),
+        },
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    let mut lines = Vec::new();
+    for entry in &app.scrollback {
+        let (prefix, style) = entry.style();
+        let text = format!("{prefix}{}", entry.text());
+        lines.extend(
+            wrap(&text, inner.width as usize)
+                .into_iter()
+                .map(|line| Spans::from(Span::styled(line, style))),
+        );
+    }
+    let height = inner.height as usize;
+    let bottom = lines.len().saturating_sub(height);
+    app.scroll = app.scroll.min(bottom);
+    let top = bottom - app.scroll;
+    let shown: Vec<_> = lines.into_iter().skip(top).take(height).collect();
+    f.render_widget(Paragraph::new(shown).block(block), area);
+}
+
+fn draw_transfers(f: &mut Frame<CrosstermBackend<Stdout>>, app: &App, area: Rect) {
+    const BAR: usize = 20;
+    let items: Vec<_> = app
+        .transfers
+        .iter()
+        .rev()
+        .take(SHOWN_TRANSFERS)
+        .map(|(id, transfer)| {
+            let done = match transfer.size {
+                0 => 0,
+                size => (transfer.bytes.min(size) * BAR as u64 / size) as usize,
+            };
+            ListItem::new(format!(
+                "{id} [{}{}] {}/{} {} {}",
+                "#".repeat(done),
+                "-".repeat(BAR - done),
+                transfer.bytes,
+                transfer.size,
+                transfer.status,
+                transfer.name,
+            ))
+        })
+        .collect();
+    f.render_widget(
+        List::new(items).block(Block::default().borders(Borders::ALL).title("Transfers")),
+        area,
+    );
+}
+
+fn draw_input(f: &mut Frame<CrosstermBackend<Stdout>>, app: &App, area: Rect) {
+    let block = focused(Block::default().borders(Borders::ALL), !app.picking_room);
+    let inner = block.inner(area);
+    // Scroll sideways to keep the cursor in view
+    let before: Vec<char> = app.input.text.chars().take(app.input.cursor).collect();
+    let mut skipped = 0;
+    let mut cursor_x: usize = before.iter().map(|c| c.width().unwrap_or(0)).sum();
+    while cursor_x >= inner.width as usize && skipped < before.len() {
+        cursor_x -= before[skipped].width().unwrap_or(0);
+        skipped += 1;
+    }
+    let shown: String = app.input.text.chars().skip(skipped).collect();
+    f.render_widget(Paragraph::new(shown).block(block), area);
+    if !app.picking_room {
+        f.set_cursor(inner.x + cursor_x as u16, inner.y);
+    }
+}
+
+fn draw_status(f: &mut Frame<CrosstermBackend<Stdout>>, app: &App, area: Rect) {
+    let connection = match &app.link {
+        Link::None => "Not in a room".to_owned(),
+        Link::Connecting(room, _) => room::waiting_for(room),
+        Link::Connected(..) if app.reconnecting => "Reconnecting...".to_owned(),
+        Link::Connected(..) => "Connected".to_owned(),
+        Link::Ended(_, reason) => reason.clone(),
+    };
+    let mut parts = vec![app.session.username().to_owned(), connection];
+    if let Some(presence) = &app.presence {
+        parts.push(format!("Other user {presence}"));
+    }
+    let status =
+        Paragraph::new(format!(" {}", parts.join(" | "))).style(Style::default().bg(Color::Blue).fg(Color::White));
+    f.render_widget(status, area);
+}
+
+/// `tui` opens the full-screen client, entering `room` straight away if given
+pub async fn tui(room: Option<u16>) -> Result<(), CliError> {
+    let session = Session::require(OutputMode::Human)?;
+    let mut app = App::new(session);
+    if let Some(room_id) = room {
+        let room = app.session.room(room_id).cloned().ok_or_else(|| {
+            format!("You have not created or joined room {room_id}, see `atris rooms`, or `atris join {room_id}`")
+        })?;
+        let index = app.session.rooms().iter().position(|saved| saved.room_id == room_id);
+        app.rooms.select(index);
+        app.enter(room);
+    }
+
+    // Reading keys blocks, so it happens on its own thread
+    let (keys, mut key_receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = crossterm::event::read() {
+            if keys.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let _guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+    while !app.quit {
+        terminal.draw(|f| draw(f, &mut app))?;
+        tokio::select! {
+            event = key_receiver.recv() => match event {
+                Some(Event::Key(key)) => app.on_key(key).await,
+                // Redrawn on the way round
+                Some(_) => {}
+                None => app.quit = true,
+            },
+            update = app.link.next() => match update {
+                LinkUpdate::Entered(Ok(result)) => app.connected(result).await,
+                LinkUpdate::Entered(Err(e)) => app.connected(Err(e.into())).await,
+                LinkUpdate::Event(Ok(event)) => app.on_chat_event(event),
+                LinkUpdate::Event(Err(reason)) => app.ended(reason),
+            },
+        }
+    }
+    if let Some(conversation) = app.conversation() {
+        conversation.channel().signal(Presence::Left);
+    }
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::RTCDataChannel;
+
+use super::{AtrisChannelParts, AtrisConnection};
+
+/// The label of the channel opened by [`super::initiator::AtrisInitiator::new`]
+pub const DEFAULT_CHANNEL_LABEL: &str = "data";
+
+/// What happens to messages that are lost on the way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reliability {
+    /// Lost messages are retransmitted until they arrive
+    #[default]
+    Reliable,
+    /// Lost messages are retransmitted at most this many times, then dropped
+    MaxRetransmits(u16),
+    /// Lost messages are retransmitted for at most this many milliseconds, then dropped
+    MaxLifetime(u16),
+}
+
+/// The delivery guarantees of a data channel, which the initiator chooses when opening it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelOptions {
+    /// Whether messages are delivered in the order they were sent
+    pub ordered: bool,
+    pub reliability: Reliability,
+}
+impl Default for ChannelOptions {
+    fn default() -> Self {
+        Self::reliable()
+    }
+}
+impl ChannelOptions {
+    /// Ordered delivery of every message, for chat and control traffic
+    pub fn reliable() -> Self {
+        Self {
+            ordered: true,
+            reliability: Reliability::Reliable,
+        }
+    }
+
+    /// Unordered delivery without retransmissions, for real-time traffic where only the latest message matters
+    pub fn lossy() -> Self {
+        Self {
+            ordered: false,
+            reliability: Reliability::MaxRetransmits(0),
+        }
+    }
+
+    pub(super) fn init(self) -> RTCDataChannelInit {
+        let (max_retransmits, max_packet_life_time) = match self.reliability {
+            Reliability::Reliable => (None, None),
+            Reliability::MaxRetransmits(retransmits) => (Some(retransmits), None),
+            Reliability::MaxLifetime(lifetime) => (None, Some(lifetime)),
+        };
+        RTCDataChannelInit {
+            ordered: Some(self.ordered),
+            max_retransmits,
+            max_packet_life_time,
+            ..Default::default()
+        }
+    }
+}
+
+/// The data channels the initiator opened, taken one at a time as [`AtrisChannelParts`]
+pub struct AtrisDataChannels {
+    connection: Arc<AtrisConnection>,
+    channels: HashMap<String, Arc<RTCDataChannel>>,
+}
+impl AtrisDataChannels {
+    pub(super) fn new(connection: Arc<AtrisConnection>, channels: Vec<Arc<RTCDataChannel>>) -> Self {
+        let channels = channels
+            .into_iter()
+            .map(|channel| (channel.label().to_owned(), channel))
+            .collect();
+        Self {
+            connection,
+            channels,
+        }
+    }
+
+    /// Open another channel on the same connection, which only the responder sees, among its [`IncomingChannels`]
+    pub(super) async fn open(&self, label: &str, options: ChannelOptions) -> Result<(), webrtc::Error> {
+        self.connection.connection.create_data_channel(label, Some(options.init())).await?;
+        Ok(())
+    }
+
+    /// The labels of the channels which have not been taken yet
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.channels.keys().map(String::as_str)
+    }
+
+    /// Take the channel with the given label. This only returns [`Some`] the first time for each label.
+    pub fn take<T>(&mut self, label: &str) -> Option<AtrisChannelParts<T>>
+    where
+        T: Serialize + Send + Sync + 'static,
+        for<'d> T: Deserialize<'d>,
+    {
+        let channel = self.channels.remove(label)?;
+        Some(AtrisChannelParts::new(Arc::clone(&self.connection), channel))
+    }
+}
+
+/// The data channels the initiator opens, as they arrive at the responder
+pub struct IncomingChannels {
+    connection: Arc<AtrisConnection>,
+    receiver: Receiver<Arc<RTCDataChannel>>,
+    /// Channels which have arrived but not been taken yet, oldest first
+    arrived: VecDeque<Arc<RTCDataChannel>>,
+}
+impl IncomingChannels {
+    pub(super) fn new(connection: Arc<AtrisConnection>, receiver: Receiver<Arc<RTCDataChannel>>) -> Self {
+        Self {
+            connection,
+            receiver,
+            arrived: VecDeque::new(),
+        }
+    }
+
+    /// Wait for the next channel which has not been taken yet, returning its label along with it
+    pub async fn next<T>(&mut self) -> Option<(String, AtrisChannelParts<T>)>
+    where
+        T: Serialize + Send + Sync + 'static,
+        for<'d> T: Deserialize<'d>,
+    {
+        let channel = match self.arrived.pop_front() {
+            Some(channel) => channel,
+            None => self.receiver.recv().await?,
+        };
+        Some((
+            channel.label().to_owned(),
+            AtrisChannelParts::new(Arc::clone(&self.connection), channel),
+        ))
+    }
+
+    /// Wait for the channel with the given label, keeping any others that arrive first for later
+    pub async fn take<T>(&mut self, label: &str) -> Option<AtrisChannelParts<T>>
+    where
+        T: Serialize + Send + Sync + 'static,
+        for<'d> T: Deserialize<'d>,
+    {
+        let channel = self.take_where(|arrived| arrived == label).await?;
+        Some(AtrisChannelParts::new(Arc::clone(&self.connection), channel))
+    }
+
+    /// Wait for the first channel whose label `matches`, keeping any others that arrive first for later
+    pub(super) async fn take_where(&mut self, matches: impl Fn(&str) -> bool) -> Option<Arc<RTCDataChannel>> {
+        match self.arrived.iter().position(|channel| matches(channel.label())) {
+            Some(index) => self.arrived.remove(index),
+            None => loop {
+                let channel = self.receiver.recv().await?;
+                if matches(channel.label()) {
+                    break Some(channel);
+                }
+                self.arrived.push_back(channel);
+            },
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Compresses messages before they are encrypted, once both ends have agreed to.
+//!
+//! Each end announces the compression it accepts when its [`super::AtrisChannel`] is created, and only compresses
+//! messages once the other end has accepted it. Small messages are sent as they are, and so are messages whose
+//! start does not compress, which catches images, archives and anything else that is already compressed.
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+
+use super::framing::MAX_MESSAGE_SIZE;
+
+/// Messages smaller than this are never compressed, since the savings would not be worth it
+pub const MIN_COMPRESSED_SIZE: usize = 256;
+/// How much of a message is compressed to decide whether compressing the rest is worth it
+const SAMPLE_SIZE: usize = 4 * 1024;
+/// A message is only compressed if its sample shrinks to at most this fraction of its size
+const MAX_SAMPLE_RATIO: f64 = 0.9;
+
+/// How a message is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Compression {
+    /// Messages are sent as they are
+    None,
+    /// Messages are compressed with DEFLATE, which did best on short texts
+    #[default]
+    Deflate,
+}
+
+/// A serialized message, compressed or not, which is what gets encrypted
+#[derive(Serialize, Deserialize)]
+pub(super) struct Payload {
+    compression: Compression,
+    #[serde(with = "serde_bytes")]
+    bytes: Vec<u8>,
+}
+impl Payload {
+    /// Compress the serialized message with `compression` if that is worth it, recording the outcome in `stats`
+    pub(super) fn new(bytes: Vec<u8>, compression: Compression, stats: &mut CompressionStats) -> Self {
+        stats.messages += 1;
+        let compressed = match compression {
+            Compression::Deflate if worth_compressing(&bytes) => deflate(&bytes).filter(|c| c.len() < bytes.len()),
+            _ => None,
+        };
+        match compressed {
+            Some(compressed) => {
+                stats.compressed_messages += 1;
+                stats.original_bytes += bytes.len() as u64;
+                stats.compressed_bytes += compressed.len() as u64;
+                Self {
+                    compression: Compression::Deflate,
+                    bytes: compressed,
+                }
+            }
+            None => Self {
+                compression: Compression::None,
+                bytes,
+            },
+        }
+    }
+
+    /// The serialized message, decompressed
+    pub(super) fn into_bytes(self) -> std::io::Result<Vec<u8>> {
+        match self.compression {
+            Compression::None => Ok(self.bytes),
+            Compression::Deflate => {
+                // Never inflate past the largest message that could have been sent
+                let mut decoder = DeflateDecoder::new(self.bytes.as_slice()).take(MAX_MESSAGE_SIZE as u64 + 1);
+                let mut bytes = Vec::new();
+                decoder.read_to_end(&mut bytes)?;
+                if bytes.len() > MAX_MESSAGE_SIZE {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Decompressed message is over the limit",
+                    ));
+                }
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+fn worth_compressing(bytes: &[u8]) -> bool {
+    if bytes.len() < MIN_COMPRESSED_SIZE {
+        return false;
+    }
+    let sample = &bytes[..bytes.len().min(SAMPLE_SIZE)];
+    match deflate(sample) {
+        Some(compressed) => (compressed.len() as f64) <= sample.len() as f64 * MAX_SAMPLE_RATIO,
+        None => false,
+    }
+}
+
+fn deflate(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes).ok()?;
+    encoder.finish().ok()
+}
+
+/// How much compression has saved on the messages a channel sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStats {
+    /// Every message sent
+    pub messages: u64,
+    /// The messages which were sent compressed
+    pub compressed_messages: u64,
+    /// The size of the compressed messages before compression
+    pub original_bytes: u64,
+    /// The size of the compressed messages after compression
+    pub compressed_bytes: u64,
+}
+impl CompressionStats {
+    /// The compressed size of the compressed messages as a fraction of their original size, if any were
+    pub fn ratio(&self) -> Option<f64> {
+        match self.original_bytes {
+            0 => None,
+            original => Some(self.compressed_bytes as f64 / original as f64),
+        }
+    }
+}
+
+/// The compression the other end accepts, which is none until it says otherwise
+#[derive(Default)]
+pub(super) struct Accepted {
+    accepted: Mutex<Vec<Compression>>,
+}
+impl Accepted {
+    pub(super) fn set(&self, compression: Vec<Compression>) {
+        if let Ok(mut accepted) = self.accepted.lock() {
+            *accepted = compression;
+        }
+    }
+
+    /// The compression to send with, given the compression this end prefers
+    pub(super) fn negotiate(&self, preferred: Compression) -> Compression {
+        match self.accepted.lock() {
+            Ok(accepted) if accepted.contains(&preferred) => preferred,
+            _ => Compression::None,
+        }
+    }
+}
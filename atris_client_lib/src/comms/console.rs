@@ -0,0 +1,463 @@
+//! Chatting over an [`AtrisChannel`] from a terminal, or from another program through JSON lines.
+//!
+//! In [`OutputMode::Json`], everything that happens is written to stdout as one [`ChatEvent`] object per line,
+//! tagged by its `event` field, and every line read from stdin is a [`ChatCommand`] object tagged by its `command`
+//! field, like `{"command":"send","text":"hello"}`.
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use atris_common::message::{ChatMessage, Control, MessageBody};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{broadcast, watch};
+
+use crate::history::ConversationLog;
+
+use super::delivery::StatusUpdate;
+use super::presence::PresenceReceiver;
+use super::transfer::{self, FileTransfers, TransferEvent, TransferId};
+use super::{AtrisChannel, ChannelState, ConnectionEvent};
+
+/// How a console client writes what happens, and reads what the user wants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Sentences for a person, and `/` commands
+    #[default]
+    Human,
+    /// A JSON object per line in both directions
+    Json,
+}
+impl OutputMode {
+    /// Write `event` to stdout, as a sentence or as a line of JSON
+    pub fn emit<E: Serialize + Display>(self, event: &E) {
+        match self {
+            OutputMode::Human => println!("{event}"),
+            OutputMode::Json => match serde_json::to_string(event) {
+                Ok(line) => println!("{line}"),
+                Err(e) => println!("{}", serde_json::json!({ "event": "error", "message": e.to_string() })),
+            },
+        }
+    }
+
+    /// Write an error as a [`ChatEvent::Error`]
+    pub fn emit_error(self, error: impl Display) {
+        self.emit(&ChatEvent::Error {
+            message: error.to_string(),
+        })
+    }
+
+    /// Read a [`ChatCommand`] from a line the user entered
+    pub fn parse_command(self, line: &str) -> Result<ChatCommand> {
+        match self {
+            OutputMode::Human => ChatCommand::parse(line),
+            OutputMode::Json => serde_json::from_str(line).map_err(|e| anyhow!("Not a valid command: {e}")),
+        }
+    }
+}
+
+/// Something that happened during a chat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// The other user sent a message
+    Message {
+        text: String,
+    },
+    /// The other user said which client they are using
+    Hello {
+        client: String,
+    },
+    /// The other user sent a `kind` of message this version cannot show, probably from a newer version
+    Unsupported {
+        kind: u16,
+    },
+    /// The other user's presence changed, like `is typing...`
+    Presence {
+        presence: String,
+    },
+    /// Something happened to the connection, see [`ConnectionEvent`]
+    Connection {
+        description: String,
+    },
+    /// The connection dropped, and is being restarted
+    Reconnecting,
+    /// The connection came back
+    Reconnected,
+    /// This end offered a file, under the transfer `id`
+    FileOffered {
+        id: TransferId,
+        path: PathBuf,
+    },
+    /// The other user offered a file
+    TransferOffered {
+        id: TransferId,
+        name: String,
+        size: u64,
+    },
+    TransferAccepted {
+        id: TransferId,
+    },
+    TransferRejected {
+        id: TransferId,
+    },
+    TransferProgress {
+        id: TransferId,
+        bytes: u64,
+        size: u64,
+    },
+    /// The file arrived intact, at `path` if this end received it
+    TransferCompleted {
+        id: TransferId,
+        path: Option<PathBuf>,
+    },
+    TransferFailed {
+        id: TransferId,
+        reason: String,
+    },
+    TransferCancelled {
+        id: TransferId,
+    },
+    /// Something went wrong, which the chat carries on from unless the connection closed
+    Error {
+        message: String,
+    },
+}
+impl Display for ChatEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatEvent::Message { text } => write!(f, "From other user: '{text}'"),
+            ChatEvent::Hello { client } => write!(f, "Other user is using {client}"),
+            ChatEvent::Unsupported { kind } => write!(
+                f,
+                "Other user sent a kind of message ({kind}) this version cannot show, updating atris may help"
+            ),
+            ChatEvent::Presence { presence } => write!(f, "Other user {presence}"),
+            ChatEvent::Connection { description } => write!(f, "{description}"),
+            ChatEvent::Reconnecting => write!(f, "Connection dropped, reconnecting..."),
+            ChatEvent::Reconnected => write!(f, "Reconnected"),
+            ChatEvent::FileOffered { id, path } => write!(f, "Offered {} as {id}", path.display()),
+            ChatEvent::TransferOffered { id, name, size } => {
+                write!(f, "File offered: '{name}' ({size} bytes), /accept {id} or /reject {id}")
+            }
+            ChatEvent::TransferAccepted { id } => write!(f, "Transfer {id} accepted"),
+            ChatEvent::TransferRejected { id } => write!(f, "Transfer {id} rejected"),
+            ChatEvent::TransferProgress { id, bytes, size } => write!(f, "Transfer {id}: {bytes}/{size} bytes"),
+            ChatEvent::TransferCompleted { id, path: Some(path) } => {
+                write!(f, "Transfer {id} saved to {}", path.display())
+            }
+            ChatEvent::TransferCompleted { id, path: None } => write!(f, "Transfer {id} completed"),
+            ChatEvent::TransferFailed { id, reason } => write!(f, "Transfer {id} failed: {reason}"),
+            ChatEvent::TransferCancelled { id } => write!(f, "Transfer {id} cancelled"),
+            ChatEvent::Error { message } => write!(f, "{message}"),
+        }
+    }
+}
+impl From<TransferEvent> for ChatEvent {
+    fn from(event: TransferEvent) -> Self {
+        match event {
+            TransferEvent::Offered { id, name, size } => ChatEvent::TransferOffered { id, name, size },
+            TransferEvent::Accepted { id } => ChatEvent::TransferAccepted { id },
+            TransferEvent::Rejected { id } => ChatEvent::TransferRejected { id },
+            TransferEvent::Progress { id, bytes, size } => ChatEvent::TransferProgress { id, bytes, size },
+            TransferEvent::Completed { id, path } => ChatEvent::TransferCompleted { id, path },
+            TransferEvent::Failed { id, reason } => ChatEvent::TransferFailed { id, reason },
+            TransferEvent::Cancelled { id } => ChatEvent::TransferCancelled { id },
+        }
+    }
+}
+
+/// Something the user wants done during a chat
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ChatCommand {
+    /// Send a message
+    Send { text: String },
+    /// Offer a file
+    SendFile { path: PathBuf },
+    /// Accept an offered file, saving it under its own name unless `path` is given
+    Accept {
+        id: TransferId,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// Reject an offered file
+    Reject { id: TransferId },
+    /// Stop a transfer
+    Cancel { id: TransferId },
+}
+impl ChatCommand {
+    /// Read a line a person typed, where anything which is not a `/` command is a message:
+    ///
+    /// - `/send <path>` offers a file
+    /// - `/accept <id> [path]` accepts an offered file, saving it under its own name by default
+    /// - `/reject <id>` rejects an offered file
+    /// - `/cancel <id>` stops a transfer
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        let id = |word: Option<&str>, usage: &str| {
+            word.and_then(|id| id.parse::<TransferId>().ok())
+                .ok_or_else(|| anyhow!("Usage: {usage}"))
+        };
+        match words.next() {
+            Some("/send") => match line["/send".len()..].trim() {
+                "" => Err(anyhow!("Usage: /send <path>")),
+                path => Ok(ChatCommand::SendFile { path: path.into() }),
+            },
+            Some("/accept") => Ok(ChatCommand::Accept {
+                id: id(words.next(), "/accept <id> [path]")?,
+                path: words.next().map(PathBuf::from),
+            }),
+            Some("/reject") => Ok(ChatCommand::Reject {
+                id: id(words.next(), "/reject <id>")?,
+            }),
+            Some("/cancel") => Ok(ChatCommand::Cancel {
+                id: id(words.next(), "/cancel <id>")?,
+            }),
+            _ => Ok(ChatCommand::Send { text: line.to_owned() }),
+        }
+    }
+}
+
+/// A chat over an [`AtrisChannel`], and optionally the file transfers beside it, as the [`ChatEvent`]s that happen
+/// and the [`ChatCommand`]s the user gives, for clients to show however they like
+pub struct Conversation {
+    channel: AtrisChannel<ChatMessage>,
+    files: Option<FileTransfers>,
+    state: watch::Receiver<ChannelState>,
+    events: broadcast::Receiver<ConnectionEvent>,
+    presence: PresenceReceiver,
+    transfer_events: Option<broadcast::Receiver<TransferEvent>>,
+    /// The names of offered files, to save them under by default
+    offered: HashMap<TransferId, String>,
+    /// Where accepted files are saved by default, instead of the working directory
+    download_dir: Option<PathBuf>,
+    /// Where the messages both ways are recorded, and the updates to their delivery status
+    history: Option<(ConversationLog, broadcast::Receiver<StatusUpdate>)>,
+    /// Why recording the last message failed, to tell the user before anything else
+    history_error: Option<String>,
+    /// Why the connection closed, once it has
+    closed: Option<String>,
+}
+impl Conversation {
+    pub fn new(channel: AtrisChannel<ChatMessage>, files: Option<FileTransfers>) -> Self {
+        Conversation {
+            state: channel.state_receiver(),
+            events: channel.events(),
+            presence: channel.presence(),
+            transfer_events: files.as_ref().map(FileTransfers::events),
+            channel,
+            files,
+            offered: HashMap::new(),
+            download_dir: None,
+            history: None,
+            history_error: None,
+            closed: None,
+        }
+    }
+
+    /// Save accepted files in `dir` unless the user says where
+    pub fn save_files_in(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.download_dir = Some(dir.into());
+        self
+    }
+
+    /// Record the messages sent and received in `log`, and keep their delivery status up to date
+    pub fn record_in(mut self, log: ConversationLog) -> Self {
+        self.history = Some((log, self.channel.status_updates()));
+        self
+    }
+
+    /// Where the messages are recorded, if they are
+    pub fn history(&self) -> Option<&ConversationLog> {
+        self.history.as_ref().map(|(log, _)| log)
+    }
+
+    /// The chat channel underneath
+    pub fn channel(&mut self) -> &mut AtrisChannel<ChatMessage> {
+        &mut self.channel
+    }
+
+    /// The next thing that happens, or why the conversation is over.
+    /// Cancelling this loses nothing, so it can be raced against the user's input.
+    pub async fn next_event(&mut self) -> Result<ChatEvent> {
+        if let Some(message) = self.history_error.take() {
+            return Ok(ChatEvent::Error { message });
+        }
+        if let Some(reason) = self.closed.take() {
+            return Err(anyhow!("Connection {reason}"));
+        }
+        loop {
+            tokio::select! {
+                Ok(event) = self.events.recv() => {
+                    if let ConnectionEvent::Closed(reason) = &event {
+                        self.closed = Some(reason.to_string());
+                    }
+                    return Ok(ChatEvent::Connection { description: event.to_string() });
+                },
+                Ok(()) = self.state.changed() => {
+                    let current = *self.state.borrow();
+                    match current {
+                        ChannelState::Reconnecting => return Ok(ChatEvent::Reconnecting),
+                        ChannelState::Reconnected => return Ok(ChatEvent::Reconnected),
+                        ChannelState::Lost => return Err(anyhow!("Connection lost")),
+                        _ => {}
+                    }
+                },
+                Ok(event) = async { self.transfer_events.as_mut().expect("guarded").recv().await },
+                    if self.transfer_events.is_some() =>
+                {
+                    if let TransferEvent::Offered { id, name, .. } = &event {
+                        self.offered.insert(*id, name.clone());
+                    }
+                    return Ok(ChatEvent::from(event));
+                },
+                Some(presence) = self.presence.recv() => {
+                    return Ok(ChatEvent::Presence { presence: presence.to_string() });
+                },
+                Ok(update) = async { self.history.as_mut().expect("guarded").1.recv().await },
+                    if self.history.is_some() =>
+                {
+                    if let Some(Err(e)) = self.history.as_mut().map(|(log, _)| log.update_status(update)) {
+                        return Ok(ChatEvent::Error { message: format!("Could not save the message history: {e}") });
+                    }
+                },
+                Some(atris_common::Result::Ok(message)) = self.channel.receive() => {
+                    if let MessageBody::Text(_) | MessageBody::File { .. } = &message.body {
+                        let recorded = self.history.as_mut().map(|(log, _)| log.record_received(message.body.to_string()));
+                        if let Some(Err(e)) = recorded {
+                            self.history_error = Some(format!("Could not save the message history: {e}"));
+                        }
+                    }
+                    match message.body {
+                        MessageBody::Text(text) => return Ok(ChatEvent::Message { text }),
+                        MessageBody::Control(Control::Hello { client }) => return Ok(ChatEvent::Hello { client }),
+                        MessageBody::Unsupported { kind } => return Ok(ChatEvent::Unsupported { kind }),
+                        // The offer itself arrives as a transfer event
+                        MessageBody::File { .. } => {}
+                    }
+                },
+                else => return Err(anyhow!("Connection closed")),
+            };
+        }
+    }
+
+    /// Tell the other user which `client` this end is using
+    pub async fn introduce(&mut self, client: &str) -> Result<()> {
+        self.channel
+            .send(ChatMessage::hello(client))
+            .await
+            .map(drop)
+            .map_err(|e| anyhow!("{e}"))
+    }
+
+    /// Send `body` and record it, keeping why recording failed for the next event
+    async fn send(&mut self, body: MessageBody) -> Result<()> {
+        let text = body.to_string();
+        let sent = self.channel.send(ChatMessage::new(body)).await;
+        let recorded = match (&mut self.history, &sent) {
+            (Some((log, _)), Ok(id)) => log.record_sent(*id, text).map(drop),
+            (Some((log, _)), Err(_)) => log.record_failed(text).map(drop),
+            (None, _) => Ok(()),
+        };
+        if let Err(e) = recorded {
+            self.history_error = Some(format!("Could not save the message history: {e}"));
+        }
+        sent.map(drop).map_err(|e| anyhow!("{e}"))
+    }
+
+    /// Do what the user asked, returning what happened if it is not a message sent
+    pub async fn run(&mut self, command: ChatCommand) -> Result<Option<ChatEvent>> {
+        let files = match (&command, &self.files) {
+            (ChatCommand::Send { text }, _) => {
+                self.send(MessageBody::Text(text.clone())).await?;
+                return Ok(self.history_error.take().map(|message| ChatEvent::Error { message }));
+            }
+            (_, Some(files)) => files,
+            (_, None) => return Err(anyhow!("This chat cannot transfer files")),
+        };
+        match command {
+            ChatCommand::Send { .. } => {}
+            ChatCommand::SendFile { path } => {
+                let id = files.send_file(&path).await?;
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let size = std::fs::metadata(&path)
+                    .map(|metadata| metadata.len())
+                    .unwrap_or_default();
+                // The offer stands even if it cannot be shown among the messages
+                let _ = self.send(MessageBody::File { name, size }).await;
+                return Ok(Some(ChatEvent::FileOffered { id, path }));
+            }
+            ChatCommand::Accept { id, path } => {
+                let path = match (path, self.offered.get(&id)) {
+                    (Some(path), _) => path,
+                    // Whatever the other user named the file, it stays in the download directory
+                    (None, Some(name)) => {
+                        let name = transfer::local_file_name(name)
+                            .ok_or_else(|| anyhow!("'{name}' cannot be saved as it is, use /accept {id} <path>"))?;
+                        match &self.download_dir {
+                            Some(dir) => dir.join(name),
+                            None => PathBuf::from(name),
+                        }
+                    }
+                    (None, None) => return Err(anyhow!("Usage: /accept <id> <path>")),
+                };
+                files.accept(id, path).await?;
+            }
+            ChatCommand::Reject { id } => files.reject(id)?,
+            ChatCommand::Cancel { id } => files.cancel(id)?,
+        }
+        Ok(None)
+    }
+
+    /// Chat from stdin and stdout until the connection closes. See [`ChatCommand::parse`] for what a person can type.
+    pub async fn console(mut self, mode: OutputMode) -> Result<Infallible> {
+        if let Err(e) = self.introduce(concat!("atris ", env!("CARGO_PKG_VERSION"))).await {
+            mode.emit_error(e);
+        }
+        let mut input = BufReader::new(tokio::io::stdin()).lines();
+        let mut input_open = true;
+
+        loop {
+            tokio::select! {
+                event = self.next_event() => mode.emit(&event?),
+                line = input.next_line(), if input_open => {
+                    let line = match line {
+                        Ok(Some(line)) => line,
+                        // Keep showing what arrives after the input runs out
+                        _ => {
+                            input_open = false;
+                            continue;
+                        }
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let result = match mode.parse_command(&line) {
+                        Ok(command) => self.run(command).await,
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(Some(event)) => mode.emit(&event),
+                        Ok(None) => {}
+                        Err(e) => mode.emit_error(e),
+                    }
+                },
+            };
+        }
+    }
+}
+
+impl AtrisChannel<ChatMessage> {
+    /// Chat over this channel from stdin and stdout until the connection closes, transferring files over `files`
+    /// if there are any. See [`Conversation::console`].
+    pub async fn console(self, files: Option<FileTransfers>, mode: OutputMode) -> Result<Infallible> {
+        Conversation::new(self, files).console(mode).await
+    }
+}
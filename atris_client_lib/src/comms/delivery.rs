@@ -0,0 +1,212 @@
+//! Tracks whether the messages sent on an [`super::AtrisChannel`] reached the other end.
+//!
+//! Every message is sent with an id, which the receiving channel acknowledges as soon as the message arrives. The
+//! application reading the message can then send a read receipt for it with [`super::AtrisChannel::mark_read`].
+//! Acknowledgements and receipts are [`Control`]s, which are encrypted like the messages and skip ahead of them.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+
+use atris_common::{Cipher, Encrypted};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{broadcast, oneshot};
+
+use super::compression::{Accepted, Compression, Payload};
+use super::presence::Presence;
+use super::{DeliveryError, Outgoing, EVENT_CAPACITY};
+
+/// Identifies a message among those sent on the same channel
+pub type MessageId = u64;
+
+/// How far a sent message has got, in the order messages go through them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageStatus {
+    /// Sent, but not acknowledged by the other end yet
+    Pending,
+    /// The other end's channel received the message
+    Delivered,
+    /// The other end's application marked the message as read
+    Read,
+    /// The message could not be sent
+    Failed,
+}
+impl Display for MessageStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageStatus::Pending => write!(f, "Pending"),
+            MessageStatus::Delivered => write!(f, "Delivered"),
+            MessageStatus::Read => write!(f, "Read"),
+            MessageStatus::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+/// A message whose status changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusUpdate {
+    pub id: MessageId,
+    pub status: MessageStatus,
+}
+
+/// What goes over the data channel. Everything is encrypted, so nobody on the path can forge receipts or presence
+/// signals, or turn compression off.
+#[derive(Serialize, Deserialize)]
+pub(super) enum Frame {
+    Message { id: MessageId, body: Encrypted<Payload> },
+    Presence(Encrypted<Presence>),
+    Control(Encrypted<Control>),
+}
+
+/// What the two ends of a channel tell each other about it, rather than through it
+#[derive(Serialize, Deserialize)]
+pub(super) enum Control {
+    Delivered(MessageId),
+    Read(MessageId),
+    /// The compression the sending end accepts, sent once when its channel is created
+    Compression(Vec<Compression>),
+}
+
+/// Sends and opens the [`Control`]s of a channel. They are queued apart from the messages, so acknowledging a message
+/// never waits for this end's own sends, and are sent ahead of them.
+/// Controls can only be sealed and opened once the channel has its cipher, so any received or acknowledged before then
+/// wait for it.
+pub(super) struct Controls {
+    sender: UnboundedSender<Outgoing>,
+    state: Mutex<ControlState>,
+    statuses: Arc<Statuses>,
+    accepted: Arc<Accepted>,
+}
+#[derive(Default)]
+struct ControlState {
+    cipher: Option<Cipher>,
+    unopened: Vec<Encrypted<Control>>,
+    unacknowledged: Vec<MessageId>,
+}
+impl Controls {
+    pub(super) fn new(sender: UnboundedSender<Outgoing>, statuses: Arc<Statuses>, accepted: Arc<Accepted>) -> Self {
+        Self {
+            sender,
+            state: Mutex::new(ControlState::default()),
+            statuses,
+            accepted,
+        }
+    }
+
+    /// Seal and open controls with `cipher` from now on, catching up on those which waited for it
+    pub(super) fn set_cipher(&self, cipher: Cipher) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let mut cipher = state.cipher.insert(cipher).clone();
+        for control in std::mem::take(&mut state.unopened) {
+            let _ = self.open(control, &mut cipher);
+        }
+        for id in std::mem::take(&mut state.unacknowledged) {
+            self.queue(&Control::Delivered(id), &mut cipher);
+        }
+    }
+
+    /// Act on a control from the other end, failing if it was not sealed with this channel's cipher
+    pub(super) fn receive(&self, control: Encrypted<Control>) -> atris_common::Result<()> {
+        let Ok(mut state) = self.state.lock() else {
+            return Ok(());
+        };
+        match state.cipher.clone() {
+            Some(mut cipher) => {
+                drop(state);
+                self.open(control, &mut cipher)
+            }
+            None => {
+                if state.unopened.len() < EVENT_CAPACITY {
+                    state.unopened.push(control);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Acknowledge a message from the other end as soon as it arrives, whether or not the application reads it
+    pub(super) fn acknowledge(&self, id: MessageId) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        match state.cipher.clone() {
+            Some(mut cipher) => {
+                drop(state);
+                self.queue(&Control::Delivered(id), &mut cipher);
+            }
+            None => state.unacknowledged.push(id),
+        }
+    }
+
+    /// Queue a control to be sent, returning a receiver for whether it was
+    pub(super) fn send(&self, control: &Control) -> Option<oneshot::Receiver<Result<(), DeliveryError>>> {
+        let mut cipher = self.state.lock().ok()?.cipher.clone()?;
+        self.queue(control, &mut cipher)
+    }
+
+    fn queue(&self, control: &Control, cipher: &mut Cipher) -> Option<oneshot::Receiver<Result<(), DeliveryError>>> {
+        let frame = Frame::Control(Encrypted::encrypt(control, cipher).ok()?);
+        let message = bincode::serialize(&frame).ok()?;
+        let (sent, result) = oneshot::channel();
+        self.sender.send(Outgoing { message, sent }).ok()?;
+        Some(result)
+    }
+
+    fn open(&self, control: Encrypted<Control>, cipher: &mut Cipher) -> atris_common::Result<()> {
+        match control.decrypt(cipher)? {
+            Control::Delivered(id) => self.statuses.advance(id, MessageStatus::Delivered),
+            Control::Read(id) => self.statuses.advance(id, MessageStatus::Read),
+            Control::Compression(compression) => self.accepted.set(compression),
+        }
+        Ok(())
+    }
+}
+
+/// The statuses of the messages sent on a channel, until they are read or fail
+pub(super) struct Statuses {
+    statuses: Mutex<HashMap<MessageId, MessageStatus>>,
+    updates: broadcast::Sender<StatusUpdate>,
+}
+impl Default for Statuses {
+    fn default() -> Self {
+        Self {
+            statuses: Mutex::new(HashMap::new()),
+            updates: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+}
+impl Statuses {
+    pub(super) fn get(&self, id: MessageId) -> Option<MessageStatus> {
+        self.statuses.lock().ok()?.get(&id).copied()
+    }
+
+    pub(super) fn subscribe(&self) -> broadcast::Receiver<StatusUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Move a message on to `status`. Statuses never go backwards, since acknowledgements and receipts can arrive
+    /// out of order on unordered channels, and a failed message stays failed.
+    /// Messages are forgotten once read or failed, since their status cannot change after that.
+    pub(super) fn advance(&self, id: MessageId, status: MessageStatus) {
+        let Ok(mut statuses) = self.statuses.lock() else {
+            return;
+        };
+        let current = statuses.get(&id).copied();
+        let advanced = match current {
+            // Including messages which were already read or failed
+            None => status == MessageStatus::Pending,
+            Some(current) => status > current,
+        };
+        if advanced {
+            match status {
+                MessageStatus::Read | MessageStatus::Failed => statuses.remove(&id),
+                _ => statuses.insert(id, status),
+            };
+            drop(statuses);
+            let _ = self.updates.send(StatusUpdate { id, status });
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Finds other Atris clients on the local network with multicast DNS service discovery (RFC 6762 and 6763).
+//!
+//! A client advertises an `_atris._tcp.local.` service whose SRV record holds the port it takes signalling on (see
+//! [`super::lan`]) and whose TXT record holds its username and identity key fingerprint. The SRV record points at a
+//! host name of the client's own, whose address records follow the addresses of this host.
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::Result;
+use mdns_sd::{IfKind, ResolvedService, ServiceDaemon, ServiceEvent, ServiceInfo};
+use tokio::time::Instant;
+
+/// The DNS-SD service type Atris clients advertise
+pub const SERVICE_TYPE: &str = "_atris._tcp.local.";
+/// How long a browse waits for answers by default
+pub const BROWSE_DURATION: Duration = Duration::from_secs(3);
+
+/// The version of the TXT record contents
+const TXT_VERSION: &str = "1";
+
+/// Another client found on the local network
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LanPeer {
+    pub username: String,
+    /// The fingerprint of the peer's identity key, see [`crate::identity::PublicIdentity::fingerprint`]
+    pub fingerprint: String,
+    /// Where the peer takes signalling connections
+    pub address: SocketAddr,
+}
+
+/// What a client advertises about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceRecord {
+    pub username: String,
+    pub fingerprint: String,
+    pub port: u16,
+}
+impl ServiceRecord {
+    /// The name of this client's service instance and host, which the fingerprint keeps unique
+    fn instance(&self) -> String {
+        format!("atris-{}", self.fingerprint.replace('-', "").to_ascii_lowercase())
+    }
+
+    fn service(&self) -> Result<ServiceInfo> {
+        let instance = self.instance();
+        let txt = [
+            ("v", TXT_VERSION),
+            ("user", self.username.as_str()),
+            ("fp", self.fingerprint.as_str()),
+        ];
+        let service = ServiceInfo::new(
+            SERVICE_TYPE,
+            &instance,
+            &format!("{instance}.local."),
+            (),
+            self.port,
+            &txt[..],
+        )?;
+        Ok(service.enable_addr_auto())
+    }
+}
+
+/// A daemon which only uses IPv4, as that is all [`super::lan::LanListener`] listens on
+fn daemon() -> Result<ServiceDaemon> {
+    let daemon = ServiceDaemon::new()?;
+    daemon.disable_interface(IfKind::IPv6)?;
+    Ok(daemon)
+}
+
+/// Advertises a [`ServiceRecord`] until it is dropped
+pub struct Advertiser {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+impl Advertiser {
+    /// Announce `record` and answer every query for [`SERVICE_TYPE`] with it
+    pub fn start(record: ServiceRecord) -> Result<Self> {
+        let daemon = daemon()?;
+        let service = record.service()?;
+        let fullname = service.get_fullname().to_owned();
+        daemon.register(service)?;
+        Ok(Self { daemon, fullname })
+    }
+}
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        // Tells the others the service is gone, rather than leaving them to wait for its records to expire
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Ask for every client advertising on the local network, collecting the answers for `duration`.
+/// This includes any advertised by this client itself.
+pub async fn browse(duration: Duration) -> Result<Vec<LanPeer>> {
+    let daemon = daemon()?;
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let deadline = Instant::now() + duration;
+    let mut peers: Vec<LanPeer> = Vec::new();
+    while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, events.recv_async()).await {
+        let ServiceEvent::ServiceResolved(service) = event else {
+            continue;
+        };
+        if let Some(peer) = peer(&service).filter(|peer| !peers.contains(peer)) {
+            peers.push(peer);
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(peers)
+}
+
+/// The client a resolved service describes, unless it is incomplete or not from an Atris client
+fn peer(service: &ResolvedService) -> Option<LanPeer> {
+    if service.get_property_val_str("v") != Some(TXT_VERSION) {
+        return None;
+    }
+    // Another host cannot reach this one's loopback address, so only fall back to it when nothing else is advertised
+    let address = service
+        .get_addresses_v4()
+        .into_iter()
+        .min_by_key(|address| (address.is_loopback(), *address))?;
+    Some(LanPeer {
+        username: service.get_property_val_str("user")?.to_owned(),
+        fingerprint: service.get_property_val_str("fp")?.to_owned(),
+        address: SocketAddr::new(IpAddr::V4(address), service.get_port()),
+    })
+}
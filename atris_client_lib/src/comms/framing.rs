@@ -0,0 +1,195 @@
+//! Splits messages which are too large for a single data channel message into fragments, and puts them back
+//! together on the other end.
+//!
+//! Every fragment starts with a header of three big-endian `u32`s: the id of the message it belongs to, its index
+//! within the message, and how many fragments the message has. The rest of the fragment is a slice of the message.
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Display;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// The most message bytes carried by one fragment, which keeps each data channel message well below the limits
+/// of every implementation
+pub const MAX_FRAGMENT_PAYLOAD: usize = 16 * 1024;
+/// The largest message that is sent or reassembled
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+/// How many messages can be partially received at once. On unordered or lossy channels the fragments of several
+/// messages can be interleaved, or never complete, in which case the oldest partial message is dropped.
+pub const MAX_PARTIAL_MESSAGES: usize = 16;
+/// How many bytes the partial messages can hold between them. Once a fragment would take them over, the oldest other
+/// partial messages are dropped to make room for it.
+pub const MAX_PARTIAL_BYTES: usize = 2 * MAX_MESSAGE_SIZE;
+
+const HEADER_LEN: usize = 12;
+
+/// Split a message into the fragments to send, in order
+pub fn fragment(message_id: u32, message: &[u8]) -> Vec<Bytes> {
+    let mut chunks: Vec<&[u8]> = message.chunks(MAX_FRAGMENT_PAYLOAD).collect();
+    // Empty messages still need a fragment
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+    let count = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut frame = BytesMut::with_capacity(HEADER_LEN + chunk.len());
+            frame.put_u32(message_id);
+            frame.put_u32(index as u32);
+            frame.put_u32(count as u32);
+            frame.put_slice(chunk);
+            frame.freeze()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramingError {
+    /// The fragment is too short to have a header, or its header is inconsistent
+    MalformedFragment,
+    /// The message would be larger than the limit, so its fragments are dropped
+    MessageTooLarge(usize),
+}
+impl Display for FramingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramingError::MalformedFragment => write!(f, "Malformed fragment"),
+            FramingError::MessageTooLarge(size) => {
+                write!(f, "Message of at least {size} bytes is over the limit")
+            }
+        }
+    }
+}
+impl Error for FramingError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// A message which some fragments have arrived for
+struct PartialMessage {
+    fragments: Vec<Option<Bytes>>,
+    received: usize,
+    size: usize,
+}
+
+/// Puts the fragments made by [`fragment`] back together, in whatever order they arrive
+pub struct Reassembler {
+    max_message_size: usize,
+    max_partial_bytes: usize,
+    partial: HashMap<u32, PartialMessage>,
+    /// The bytes held by all the partial messages
+    partial_bytes: usize,
+    /// The ids of the partial messages, oldest first
+    oldest: VecDeque<u32>,
+}
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new(MAX_MESSAGE_SIZE)
+    }
+}
+impl Reassembler {
+    pub fn new(max_message_size: usize) -> Self {
+        Self::with_partial_budget(max_message_size, MAX_PARTIAL_BYTES.max(max_message_size))
+    }
+
+    /// A reassembler whose partial messages hold at most `max_partial_bytes` between them
+    pub fn with_partial_budget(max_message_size: usize, max_partial_bytes: usize) -> Self {
+        Self {
+            max_message_size,
+            max_partial_bytes,
+            partial: HashMap::new(),
+            partial_bytes: 0,
+            oldest: VecDeque::new(),
+        }
+    }
+
+    /// Take in a fragment, returning the message it completes, if any
+    pub fn push(&mut self, fragment: &[u8]) -> Result<Option<Vec<u8>>, FramingError> {
+        if fragment.len() < HEADER_LEN {
+            return Err(FramingError::MalformedFragment);
+        }
+        let (header, payload) = fragment.split_at(HEADER_LEN);
+        let field = |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let (message_id, index, count) = (field(0), field(4) as usize, field(8) as usize);
+        if index >= count {
+            return Err(FramingError::MalformedFragment);
+        }
+        // Every fragment but the last is full, so this is the smallest the message can be
+        let least_size = (count - 1) * MAX_FRAGMENT_PAYLOAD;
+        if least_size > self.max_message_size {
+            return Err(FramingError::MessageTooLarge(least_size));
+        }
+        if count == 1 {
+            return Ok(Some(payload.to_vec()));
+        }
+
+        if !self.partial.contains_key(&message_id) {
+            if self.oldest.len() >= MAX_PARTIAL_MESSAGES {
+                if let Some(dropped) = self.oldest.pop_front() {
+                    self.partial.remove(&dropped);
+                }
+            }
+            self.oldest.push_back(message_id);
+            self.partial.insert(
+                message_id,
+                PartialMessage {
+                    fragments: vec![None; count],
+                    received: 0,
+                    size: 0,
+                },
+            );
+        }
+        let Some(partial) = self.partial.get(&message_id) else {
+            return Ok(None);
+        };
+        if partial.fragments.len() != count {
+            self.forget(message_id);
+            return Err(FramingError::MalformedFragment);
+        }
+        // Duplicates are ignored
+        if partial.fragments[index].is_some() {
+            return Ok(None);
+        }
+        while self.partial_bytes + payload.len() > self.max_partial_bytes {
+            let Some(dropped) = self.oldest.iter().copied().find(|id| *id != message_id) else {
+                let size = self.forget(message_id).map_or(0, |partial| partial.size) + payload.len();
+                return Err(FramingError::MessageTooLarge(size));
+            };
+            self.forget(dropped);
+        }
+        let Some(partial) = self.partial.get_mut(&message_id) else {
+            return Ok(None);
+        };
+        partial.size += payload.len();
+        partial.received += 1;
+        partial.fragments[index] = Some(Bytes::copy_from_slice(payload));
+        self.partial_bytes += payload.len();
+        if partial.size > self.max_message_size {
+            let size = partial.size;
+            self.forget(message_id);
+            return Err(FramingError::MessageTooLarge(size));
+        }
+        if partial.received < count {
+            return Ok(None);
+        }
+
+        let Some(partial) = self.forget(message_id) else {
+            return Ok(None);
+        };
+        let mut message = Vec::with_capacity(partial.size);
+        for fragment in partial.fragments.into_iter().flatten() {
+            message.extend_from_slice(&fragment);
+        }
+        Ok(Some(message))
+    }
+
+    fn forget(&mut self, message_id: u32) -> Option<PartialMessage> {
+        self.oldest.retain(|id| *id != message_id);
+        let partial = self.partial.remove(&message_id)?;
+        self.partial_bytes -= partial.size;
+        Some(partial)
+    }
+}
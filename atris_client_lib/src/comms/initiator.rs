@@ -1,60 +1,69 @@
 use std::sync::Arc;
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
 use serde::{Deserialize, Serialize};
 use webrtc::{
     data_channel::RTCDataChannel, peer_connection::sdp::session_description::RTCSessionDescription,
 };
 
-use super::{signal, AtrisChannelParts};
+use super::channels::{AtrisDataChannels, ChannelOptions, DEFAULT_CHANNEL_LABEL};
+use super::{signal, trickle::IceTrickle, AtrisChannelParts};
 
-use super::{AtrisChannel, AtrisConnection};
+use super::AtrisConnection;
 
 pub struct AtrisInitiator {
     connection: AtrisConnection,
     local_description: RTCSessionDescription,
-    data_channel: Arc<RTCDataChannel>,
+    /// The channels to open, the first of which is used by [`AtrisInitiator::into_channel_parts_with`]
+    data_channels: Vec<Arc<RTCDataChannel>>,
 }
 impl AtrisInitiator {
-    /// Create a new initiator
-    pub async fn new(mut connection: AtrisConnection) -> Result<Self> {
+    /// Create a new initiator, which opens a single reliable channel labelled [`DEFAULT_CHANNEL_LABEL`]
+    pub async fn new(connection: AtrisConnection) -> Result<Self> {
+        Self::with_channels(connection, [(DEFAULT_CHANNEL_LABEL, ChannelOptions::default())]).await
+    }
+
+    /// Create an initiator which opens the given channels, each with its own delivery guarantees.
+    /// The first channel is the one used by [`AtrisInitiator::into_channel_parts_with`].
+    pub async fn with_channels<'a>(
+        mut connection: AtrisConnection,
+        channels: impl IntoIterator<Item = (&'a str, ChannelOptions)>,
+    ) -> Result<Self> {
         let peer_connection = &mut connection.connection;
 
-        // Create a datachannel with label 'data'
-        let data_channel = peer_connection.create_data_channel("data", None).await?;
+        // The channels must exist before the offer, so the offer sets up the transport they share
+        let mut data_channels: Vec<Arc<RTCDataChannel>> = Vec::new();
+        for (label, options) in channels {
+            if data_channels.iter().any(|channel| channel.label() == label) {
+                return Err(anyhow!("Duplicate data channel label '{label}'"));
+            }
+            data_channels.push(peer_connection.create_data_channel(label, Some(options.init())).await?);
+        }
+        if data_channels.is_empty() {
+            return Err(anyhow!("An initiator needs at least one data channel"));
+        }
 
         // Create an offer to send to the browser
         let offer = peer_connection.create_offer(None).await?;
 
-        // Create channel that is blocked until ICE Gathering is complete
-        let mut gather_complete = peer_connection.gathering_complete_promise().await;
-
         // Sets the LocalDescription, and starts our UDP listeners
-        peer_connection.set_local_description(offer).await?;
-
-        // Block until ICE Gathering is complete, disabling trickle ICE
-        // we do this because we only can exchange one signaling message
-        // in a production application you should exchange ICE Candidates via OnICECandidate
-        // -- In understandable words: I think this waits until we get our IP info that we forward to other users --
-        let _ = gather_complete.recv().await;
-
-        // Output the answer in base64 so we can paste it in browser
-        // -- Actually, don't convert to base64 because raw json is smaller in size --
-        if let Some(local_description) = peer_connection.local_description().await {
-            Ok(Self {
-                connection,
-                local_description,
-                data_channel,
-            })
-        } else {
-            println!("");
-            panic!();
-            // Err(anyhow::Error::new())
-        }
+        // The candidates are not in the description, they are trickled to the responder once the room is known,
+        // unless the connection is untrickled
+        let local_description = connection.describe(offer).await?;
+        Ok(Self {
+            connection,
+            local_description,
+            data_channels,
+        })
+    }
+
+    /// Take the handle which trickles this initiator's candidates to the responder, and offers ICE restarts when the connection drops
+    pub fn ice_trickle(&mut self) -> Option<IceTrickle> {
+        self.connection.ice_trickle(true)
     }
 
     pub async fn close(self)->Result<(),webrtc::Error>{
-        self.connection.connection.close().await
+        self.connection.close().await
     }
 
     pub fn encoded_local_description(&self) -> Result<String> {
@@ -62,16 +71,27 @@ impl AtrisInitiator {
         let b64 = signal::encode(&json_str);
         Ok(b64)
     }
-    /// If we created an initiator, feed the responder's response here
-    pub async fn into_channel_parts_with<T>(self, responder_string: &String) -> Result<AtrisChannelParts<T>>
+
+    /// The offer encoded with [`signal::encode_description`], short enough to copy and paste as one line
+    pub fn compact_local_description(&self) -> Result<String> {
+        signal::encode_description(&self.local_description)
+    }
+
+    /// If we created an initiator, feed the responder's response here to get its first channel
+    pub async fn into_channel_parts_with<T>(self, responder_string: &str) -> Result<AtrisChannelParts<T>>
     where
         T: Serialize + Send + Sync + 'static,
         for<'d> T: Deserialize<'d>,
     {
-        let decoded_responder_string = signal::decode(responder_string.as_str())?;
-        // Convert the json input into a useful datatype
-        let responder_description =
-            serde_json::from_str::<RTCSessionDescription>(&decoded_responder_string)?;
+        let label = self.data_channels[0].label().to_owned();
+        let mut channels = self.into_channels_with(responder_string).await?;
+        channels.take(&label).ok_or_else(|| anyhow!("No data channel '{label}'"))
+    }
+
+    /// Feed the responder's response here to get every channel this initiator opens.
+    /// The response can be in either encoding, see [`signal::decode_description`].
+    pub async fn into_channels_with(self, responder_string: &str) -> Result<AtrisDataChannels> {
+        let responder_description = signal::decode_description(responder_string)?;
         // dbg!(&responder_description);
 
         // Apply the answer as the remote description
@@ -80,8 +100,6 @@ impl AtrisInitiator {
             .set_remote_description(responder_description)
             .await?;
 
-        // Convert that channel into an AtrisChannel
-        let channel = AtrisChannelParts::new(self.connection, self.data_channel);
-        Ok(channel)
+        Ok(AtrisDataChannels::new(Arc::new(self.connection), self.data_channels))
     }
 }
@@ -0,0 +1,142 @@
+//! Connects the creator of a room with the user they invited, whenever each of them gets to it.
+//!
+//! The initiator string registered at login is only good while the client which logged in is still running, so
+//! rather than answering it, the creator waits for the invited user to post a fresh [`SignalMessage::Offer`] to the
+//! room when they join. The creator answers that offer through [`AtrisAuthClient::set_room_responder`], which also
+//! hands out the room key, and both ends then trickle their candidates through the room as usual.
+use std::fmt::{Debug, Display};
+
+use anyhow::{anyhow, Result};
+use atris_common::join_room::JoinRoomError;
+use atris_common::signal_room::{SignalMessage, SignalRole};
+use atris_common::CipherKey;
+
+use super::channels::{AtrisDataChannels, IncomingChannels};
+use super::initiator::AtrisInitiator;
+use super::responder::AtrisResponder;
+use super::trickle::{RoomSignaller, Signaller, POLL_INTERVAL};
+use crate::AtrisAuthClient;
+
+/// A room this user created for another user, which is yet to be joined
+pub struct Invitation<C> {
+    client: C,
+    session_id: CipherKey,
+    room_id: u16,
+    other_user: String,
+}
+impl<C> Invitation<C>
+where
+    C: AtrisAuthClient + Clone + Send + Sync + 'static,
+    C::Error: Debug + Display + Send + Sync + 'static,
+{
+    /// Create a room for `other_user`, who can join it with [`join`] once they are told its number
+    pub async fn create(client: C, session_id: CipherKey, other_user: &str) -> Result<Self> {
+        let room = client.create_room(session_id.clone(), other_user).await??;
+        Ok(Self::existing(client, session_id, room.room_id, other_user))
+    }
+
+    /// An invitation to a room this user already created, to wait in it again after leaving before the other user
+    /// joined. A room only connects its two users once.
+    pub fn existing(client: C, session_id: CipherKey, room_id: u16, other_user: &str) -> Self {
+        Self {
+            client,
+            session_id,
+            room_id,
+            other_user: other_user.to_owned(),
+        }
+    }
+
+    pub fn room_id(&self) -> u16 {
+        self.room_id
+    }
+
+    pub fn other_user(&self) -> &str {
+        &self.other_user
+    }
+
+    /// Wait for the invited user to join, answering their offer with `responder`.
+    /// Returns the channels their offer opens as they arrive, and the room key.
+    /// Only offers the server says the invited user posted are answered.
+    pub async fn accept(self, mut responder: AtrisResponder) -> Result<(IncomingChannels, CipherKey)> {
+        let mut signaller = self.signaller(SignalRole::Responder);
+        let offer = loop {
+            // The latest offer, in case they joined a room this user left before answering
+            let offer = signaller
+                .exchange(Vec::new())
+                .await?
+                .into_iter()
+                .rev()
+                .find_map(|signal| match signal {
+                    SignalMessage::Offer(offer) => Some(offer),
+                    _ => None,
+                });
+            match offer {
+                Some(offer) if signaller.sender() == Some(self.other_user.as_str()) => break offer,
+                Some(_) => {
+                    return Err(anyhow!(
+                        "Room {} was joined by {}, not {}",
+                        self.room_id,
+                        signaller.sender().unwrap_or("an unknown user"),
+                        self.other_user
+                    ))
+                }
+                None => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        };
+
+        if let Some(trickle) = responder.ice_trickle() {
+            tokio::spawn(trickle.run(self.signaller(SignalRole::Responder)));
+        }
+        let (answer, channels) = responder.into_channels_with(&offer).await?;
+        let set_room = self
+            .client
+            .set_room_responder(self.room_id, self.session_id.clone(), &self.other_user, &answer)
+            .await??;
+        Ok((channels, set_room.room_symmetric_key))
+    }
+
+    fn signaller(&self, role: SignalRole) -> RoomSignaller<C> {
+        RoomSignaller::new(self.client.clone(), self.session_id.clone(), self.room_id, role)
+    }
+}
+
+/// Join the room `room_id` another user invited this user to, offering with `initiator`.
+/// Waits for the room's creator to answer, then returns the channels and the room key.
+pub async fn join<C>(
+    client: C,
+    session_id: CipherKey,
+    room_id: u16,
+    mut initiator: AtrisInitiator,
+) -> Result<(AtrisDataChannels, CipherKey)>
+where
+    C: AtrisAuthClient + Clone + Send + Sync + 'static,
+    C::Error: Debug + Display + Send + Sync + 'static,
+{
+    // Make sure this user was invited before offering, the room has no data until the creator answers
+    match client.join_room(session_id.clone(), room_id).await? {
+        Ok(_) | Err(JoinRoomError::IncompleteRoom) => {}
+        Err(e) => return Err(e.into()),
+    }
+    let offer = SignalMessage::Offer(initiator.encoded_local_description()?);
+    RoomSignaller::new(client.clone(), session_id.clone(), room_id, SignalRole::Initiator)
+        .exchange(vec![offer])
+        .await?;
+
+    let room = loop {
+        match client.join_room(session_id.clone(), room_id).await? {
+            Ok(room) => break room,
+            Err(JoinRoomError::IncompleteRoom) => tokio::time::sleep(POLL_INTERVAL).await,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let room_data = room
+        .room_data
+        .decrypt(&mut session_id.as_cipher())
+        .map_err(|_| anyhow!("The room was answered for another session, log in again and rejoin it"))?;
+
+    if let Some(trickle) = initiator.ice_trickle() {
+        tokio::spawn(trickle.run(RoomSignaller::new(client, session_id, room_id, SignalRole::Initiator)));
+    }
+    let channels = initiator.into_channels_with(&room_data.responder_string).await?;
+    Ok((channels, room_data.symmetric_key))
+}
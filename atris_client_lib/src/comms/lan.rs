@@ -0,0 +1,304 @@
+//! Connects clients found with [`super::discovery`] by signalling over a direct TCP connection, without the server.
+//!
+//! The two clients run a Noise XX handshake (see <https://noiseprotocol.org/noise.html#interactive-handshake-patterns-fundamental>)
+//! with their identity keys as the static keys, so each learns the other's identity key and knows the other holds it.
+//! The connecting client checks the listening client's key against the fingerprint which was advertised before it
+//! sends its own identity and its offer. The listening client only answers once its user accepts the connecting
+//! client's identity, and sends the room key along with the answer over the encrypted channel the handshake set up.
+//! Both connections should be [untrickled](super::AtrisConnectionBuilder::untrickled), since the signalling
+//! connection is closed once the descriptions are exchanged.
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_common::CipherKey;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use snow::{HandshakeState, TransportState};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio::time::error::Elapsed;
+use tokio::time::timeout;
+
+use super::channels::{AtrisDataChannels, IncomingChannels};
+use super::discovery::{Advertiser, LanPeer, ServiceRecord};
+use super::initiator::AtrisInitiator;
+use super::responder::AtrisResponder;
+use crate::identity::{IdentityKey, PublicIdentity};
+
+/// How long either end waits for the other during the handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the connecting client waits for the other user to accept it once the handshake is done
+pub const ANSWER_TIMEOUT: Duration = Duration::from_secs(120);
+/// The Noise protocol the handshake runs
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+/// Bound into the handshake, so it cannot be mistaken for another protocol's
+const PROLOGUE: &[u8] = b"atris lan signalling 1";
+/// The largest Noise message, which is far more than a description with every candidate needs
+const MAX_MESSAGE_SIZE: usize = 65535;
+
+/// An error from the signalling handshake itself, rather than from connecting
+#[derive(Debug)]
+pub enum LanError {
+    /// The other end sent something which is not part of the handshake
+    UnexpectedMessage,
+    /// The other end's identity key is not the one it advertised
+    FingerprintMismatch { advertised: String, actual: String },
+    /// The other end could not prove it holds the identity key it sent
+    AuthenticationFailed,
+}
+impl std::fmt::Display for LanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LanError::UnexpectedMessage => write!(f, "The other client sent something unexpected"),
+            LanError::FingerprintMismatch { advertised, actual } => write!(
+                f,
+                "The other client advertised the fingerprint {advertised}, but its key has the fingerprint {actual}"
+            ),
+            LanError::AuthenticationFailed => {
+                write!(f, "The other client could not prove it holds the identity key it sent")
+            }
+        }
+    }
+}
+impl std::error::Error for LanError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// The connecting client's payload of the last handshake message, once its identity is hidden from eavesdroppers
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    username: String,
+    /// The connecting client's compact offer, see [`super::signal::encode_description`]
+    offer: String,
+}
+
+/// The listening client's reply once the handshake is done
+#[derive(Serialize, Deserialize)]
+struct Answer {
+    /// The listening client's compact answer
+    description: String,
+    room_key: Vec<u8>,
+}
+
+async fn write_message(stream: &mut TcpStream, message: &[u8]) -> Result<()> {
+    stream.write_u32(message.len() as u32).await?;
+    stream.write_all(message).await?;
+    Ok(())
+}
+
+async fn read_message(stream: &mut TcpStream, wait: Duration) -> Result<Vec<u8>> {
+    timeout(wait, async {
+        let length = stream.read_u32().await? as usize;
+        if length > MAX_MESSAGE_SIZE {
+            return Err(LanError::UnexpectedMessage.into());
+        }
+        let mut message = vec![0; length];
+        stream.read_exact(&mut message).await?;
+        Ok(message)
+    })
+    .await?
+}
+
+/// The start of a handshake with this end's identity key as its static key
+fn noise(identity: &IdentityKey) -> snow::Builder<'_> {
+    snow::Builder::new(NOISE_PARAMS.parse().expect("valid Noise parameters"))
+        .prologue(PROLOGUE)
+        .local_private_key(identity.secret())
+}
+
+/// Send the next handshake message, carrying `payload`
+async fn send_handshake(stream: &mut TcpStream, noise: &mut HandshakeState, payload: &[u8]) -> Result<()> {
+    let mut message = vec![0; MAX_MESSAGE_SIZE];
+    let length = noise.write_message(payload, &mut message)?;
+    write_message(stream, &message[..length]).await
+}
+
+/// Read the next handshake message, returning its payload
+async fn receive_handshake(stream: &mut TcpStream, noise: &mut HandshakeState) -> Result<Vec<u8>> {
+    let message = read_message(stream, HANDSHAKE_TIMEOUT).await?;
+    let mut payload = vec![0; MAX_MESSAGE_SIZE];
+    let length = noise
+        .read_message(&message, &mut payload)
+        .map_err(|_| LanError::AuthenticationFailed)?;
+    payload.truncate(length);
+    Ok(payload)
+}
+
+async fn send_encrypted(
+    stream: &mut TcpStream,
+    transport: &mut TransportState,
+    payload: &impl Serialize,
+) -> Result<()> {
+    let mut message = vec![0; MAX_MESSAGE_SIZE];
+    let length = transport.write_message(&bincode::serialize(payload)?, &mut message)?;
+    write_message(stream, &message[..length]).await
+}
+
+async fn receive_encrypted<T: DeserializeOwned>(stream: &mut TcpStream, transport: &mut TransportState) -> Result<T> {
+    let message = read_message(stream, ANSWER_TIMEOUT).await?;
+    let mut payload = vec![0; MAX_MESSAGE_SIZE];
+    let length = transport
+        .read_message(&message, &mut payload)
+        .map_err(|_| LanError::AuthenticationFailed)?;
+    decode(&payload[..length])
+}
+
+fn decode<T: DeserializeOwned>(payload: &[u8]) -> Result<T> {
+    Ok(bincode::deserialize(payload).map_err(|_| LanError::UnexpectedMessage)?)
+}
+
+/// The identity key the other end proved it holds
+fn remote_identity(noise: &HandshakeState) -> Result<PublicIdentity> {
+    let key = noise.get_remote_static().ok_or(LanError::UnexpectedMessage)?;
+    Ok(PublicIdentity::from_bytes(
+        key.try_into().map_err(|_| LanError::UnexpectedMessage)?,
+    ))
+}
+
+/// A client which connected and proved who it is, waiting for its offer to be answered.
+/// Dropping it turns the client away.
+pub struct LanRequest {
+    stream: TcpStream,
+    transport: TransportState,
+    peer: LanPeer,
+    offer: String,
+}
+impl LanRequest {
+    /// Who connected, where the address is the one they connected from.
+    /// The fingerprint is of the key they proved they hold, which the user should check before answering.
+    pub fn peer(&self) -> &LanPeer {
+        &self.peer
+    }
+
+    /// Answer the client's offer with `responder`, returning the channels the offer opens as they arrive, and the
+    /// room key
+    pub async fn answer(mut self, responder: AtrisResponder) -> Result<(IncomingChannels, CipherKey)> {
+        let (description, channels) = responder.into_channels_with(&self.offer).await?;
+        let room_key = CipherKey::generate();
+        let answer = Answer {
+            description,
+            room_key: room_key.as_ref().to_vec(),
+        };
+        send_encrypted(&mut self.stream, &mut self.transport, &answer).await?;
+        Ok((channels, room_key))
+    }
+}
+
+/// The listening end of the handshake, up to where the connecting client has proved who it is
+async fn confirm(mut stream: TcpStream, address: SocketAddr, identity: IdentityKey) -> Result<LanRequest> {
+    let mut noise = noise(&identity).build_responder()?;
+    // -> e
+    receive_handshake(&mut stream, &mut noise).await?;
+    // <- e, ee, s, es
+    send_handshake(&mut stream, &mut noise, &[]).await?;
+    // -> s, se
+    let hello: Hello = decode(&receive_handshake(&mut stream, &mut noise).await?)?;
+    let peer = LanPeer {
+        username: hello.username,
+        fingerprint: remote_identity(&noise)?.fingerprint(),
+        address,
+    };
+    Ok(LanRequest {
+        stream,
+        transport: noise.into_transport_mode()?,
+        peer,
+        offer: hello.offer,
+    })
+}
+
+/// Takes signalling connections from other clients on the local network, advertising itself until it is dropped
+pub struct LanListener {
+    listener: TcpListener,
+    identity: IdentityKey,
+    /// The handshakes under way, kept between calls to [`LanListener::accept`] so turning one client away does not
+    /// drop the others
+    handshakes: Mutex<JoinSet<Result<Result<LanRequest>, Elapsed>>>,
+    _advertiser: Advertiser,
+}
+impl LanListener {
+    /// Listen on a port of its own, and advertise it along with `username` and `identity`'s fingerprint
+    pub async fn advertise(identity: IdentityKey, username: &str) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let advertiser = Advertiser::start(ServiceRecord {
+            username: username.to_owned(),
+            fingerprint: identity.public().fingerprint(),
+            port: listener.local_addr()?.port(),
+        })?;
+        Ok(Self {
+            listener,
+            identity,
+            handshakes: Mutex::default(),
+            _advertiser: advertiser,
+        })
+    }
+
+    /// The port this listener takes signalling connections on
+    pub fn port(&self) -> Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    /// Wait for the next client to connect and prove who it is, without answering it yet.
+    /// Handshakes run side by side, each for at most [`HANDSHAKE_TIMEOUT`], and those which fail are dropped, so no
+    /// one client can hold up the others. Any client on the network can connect, so check who it is with
+    /// [`LanRequest::peer`] before answering it.
+    pub async fn accept(&self) -> Result<LanRequest> {
+        let mut handshakes = self.handshakes.lock().await;
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (stream, address) = accepted?;
+                    let handshake = confirm(stream, address, self.identity.clone());
+                    handshakes.spawn(timeout(HANDSHAKE_TIMEOUT, handshake));
+                }
+                Some(finished) = handshakes.join_next() => {
+                    if let Ok(Ok(Ok(request))) = finished {
+                        return Ok(request);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Connect to a client found with [`super::discovery::browse`], offering with `initiator`.
+/// Fails with [`LanError::FingerprintMismatch`] if the client is not the one which advertised itself as `peer`.
+pub async fn connect(
+    peer: &LanPeer,
+    identity: &IdentityKey,
+    username: &str,
+    initiator: AtrisInitiator,
+) -> Result<(AtrisDataChannels, CipherKey)> {
+    let mut stream = timeout(HANDSHAKE_TIMEOUT, TcpStream::connect(peer.address)).await??;
+    let mut noise = noise(identity).build_initiator()?;
+    // -> e
+    send_handshake(&mut stream, &mut noise, &[]).await?;
+    // <- e, ee, s, es
+    receive_handshake(&mut stream, &mut noise).await?;
+    let actual = remote_identity(&noise)?.fingerprint();
+    if actual != peer.fingerprint {
+        return Err(LanError::FingerprintMismatch {
+            advertised: peer.fingerprint.clone(),
+            actual,
+        }
+        .into());
+    }
+    // -> s, se
+    let hello = Hello {
+        username: username.to_owned(),
+        offer: initiator.compact_local_description()?,
+    };
+    send_handshake(&mut stream, &mut noise, &bincode::serialize(&hello)?).await?;
+
+    let mut transport = noise.into_transport_mode()?;
+    let answer: Answer = receive_encrypted(&mut stream, &mut transport).await?;
+    if answer.room_key.len() != 32 {
+        return Err(LanError::UnexpectedMessage.into());
+    }
+    let channels = initiator.into_channels_with(&answer.description).await?;
+    Ok((channels, CipherKey::from(answer.room_key.as_slice())))
+}
@@ -1,42 +1,279 @@
 use std::convert::Infallible;
 
-use std::fmt::{Display, Debug};
+use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use atris_common::cipher::KeyInit;
-use atris_common::{CipherKey, Encrypted, Cipher, EncryptionError};
-use tokio::io::AsyncReadExt;
+use atris_common::{Encrypted, Cipher, EncryptionError};
+use atris_common::signal_room::SignalMessage;
+use atris_common::IceServer;
 
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::error::TryRecvError;
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::{unbounded_channel, Receiver, Sender, UnboundedReceiver};
+use tokio::sync::{broadcast, oneshot, watch, Notify};
 use webrtc::data_channel::RTCDataChannel;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::setting_engine::SettingEngine;
 use webrtc::api::APIBuilder;
+use webrtc::ice::mdns::MulticastDnsMode;
 use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidate;
 use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::policy::ice_transport_policy::RTCIceTransportPolicy;
 use webrtc::interceptor::registry::Registry;
 use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 
-pub(self) type Message = String;
-
+pub mod channels;
+pub mod compression;
+pub mod console;
+pub mod delivery;
+pub mod discovery;
+pub mod framing;
 pub mod initiator;
+pub mod invite;
+pub mod lan;
+pub mod pairing;
+pub mod presence;
 pub mod responder;
 pub mod signal;
+pub mod transfer;
+pub mod trickle;
+
+use compression::{Accepted, Compression, CompressionStats, Payload};
+use delivery::{Control, Controls, Frame, MessageId, MessageStatus, Statuses, StatusUpdate};
+use framing::Reassembler;
+use presence::{Presence, PresenceReceiver, SignalLimiter};
+use trickle::IceTrickle;
+
+/// The STUN server used by [`AtrisConnection::new`]
+pub const DEFAULT_STUN_SERVER: &str = "stun:stun.l.google.com:19302"; //consider switching to stun3
+
+/// Configures the STUN and TURN servers an [`AtrisConnection`] uses to find a route to the other client
+/// ```no_run
+/// use atris_client_lib::comms::AtrisConnection;
+/// # async fn build() -> anyhow::Result<()> {
+/// let connection = AtrisConnection::builder()
+///     .stun_server("stun:stun.l.google.com:19302")
+///     .turn_server("turn:turn.example.com:3478", "username", "credential")
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AtrisConnectionBuilder {
+    ice_servers: Vec<IceServer>,
+    relay_only: bool,
+    offline: bool,
+    untrickled: bool,
+}
+impl AtrisConnectionBuilder {
+    /// Add a STUN server, like `stun:stun.l.google.com:19302`
+    pub fn stun_server(self, url: &str) -> Self {
+        self.ice_server(IceServer {
+            urls: vec![url.into()],
+            ..Default::default()
+        })
+    }
+    /// Add a TURN server to relay through, like `turn:turn.example.com:3478?transport=udp`
+    pub fn turn_server(self, url: &str, username: &str, credential: &str) -> Self {
+        self.ice_server(IceServer {
+            urls: vec![url.into()],
+            username: username.into(),
+            credential: credential.into(),
+        })
+    }
+    /// Add a STUN or TURN server
+    pub fn ice_server(mut self, ice_server: IceServer) -> Self {
+        self.ice_servers.push(ice_server);
+        self
+    }
+    /// Add several STUN or TURN servers, like the ones issued in an
+    /// [`AuthenticateUserResponse`](atris_common::authenticate_user::AuthenticateUserResponse)
+    pub fn ice_servers(mut self, ice_servers: impl IntoIterator<Item = IceServer>) -> Self {
+        self.ice_servers.extend(ice_servers);
+        self
+    }
+    /// Only connect through a TURN relay, which hides each client's address from the other
+    pub fn relay_only(mut self, relay_only: bool) -> Self {
+        self.relay_only = relay_only;
+        self
+    }
+    /// Only use the addresses of this machine's own network interfaces, without contacting any STUN or TURN server
+    /// or using mDNS, so clients on the same host or LAN connect without internet access.
+    /// Any servers added to the builder are ignored.
+    /// - Note: loopback addresses are never gathered, so clients on the same host connect through a LAN interface
+    pub fn offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+    /// Put every local candidate in the description instead of trickling them, for when nothing can be signalled
+    /// after the descriptions are exchanged. Taking the description then waits for candidate gathering to finish.
+    pub fn untrickled(mut self) -> Self {
+        self.untrickled = true;
+        self
+    }
+    /// Create the connection
+    pub async fn build(self) -> Result<AtrisConnection> {
+        AtrisConnection::with_config(self).await
+    }
+}
+
+/// The state of the connection underneath an [`AtrisChannel`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    /// The connection has not been established yet
+    Connecting,
+    /// The connection is up
+    Connected,
+    /// The connection dropped and is being restarted, messages sent meanwhile are buffered until it is back
+    Reconnecting,
+    /// The connection is back up after dropping, and the buffered messages have been resent
+    Reconnected,
+    /// The connection dropped for good, or was closed
+    Lost,
+}
+impl ChannelState {
+    /// Whether messages can currently be sent
+    pub fn is_up(self) -> bool {
+        matches!(self, Self::Connected | Self::Reconnected)
+    }
+
+    /// The state after the ICE connection changed to `ice_state`.
+    /// This follows ICE rather than the peer connection, whose state does not come back to connected after an ICE restart.
+    fn after(self, ice_state: RTCIceConnectionState, restartable: bool) -> Self {
+        match ice_state {
+            RTCIceConnectionState::Connected | RTCIceConnectionState::Completed => match self {
+                Self::Connecting => Self::Connected,
+                Self::Reconnecting => Self::Reconnected,
+                other => other,
+            },
+            RTCIceConnectionState::Disconnected if self.is_up() => Self::Reconnecting,
+            // Failed connections can only come back through an ICE restart
+            RTCIceConnectionState::Failed if restartable && self != Self::Lost => Self::Reconnecting,
+            RTCIceConnectionState::Failed | RTCIceConnectionState::Closed => Self::Lost,
+            _ => self,
+        }
+    }
+}
+
+/// How many [`ConnectionEvent`]s are kept for subscribers which have fallen behind
+pub const EVENT_CAPACITY: usize = 64;
+
+/// Sends wait while more than this many bytes are queued in a data channel's SCTP buffer
+pub const HIGH_WATER_MARK: usize = 1024 * 1024;
+/// Waiting sends resume once a data channel's SCTP buffer drains below this many bytes
+pub const LOW_WATER_MARK: usize = 256 * 1024;
+/// How many messages can wait to be sent on each channel
+const OUTGOING_CAPACITY: usize = 20;
+
+/// Something that happened to the connection underneath an [`AtrisChannel`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// The ICE connection changed state
+    IceState(RTCIceConnectionState),
+    /// The peer connection changed state
+    PeerState(RTCPeerConnectionState),
+    /// A data channel opened, and messages can be sent on it
+    DataChannelOpen { label: String },
+    /// A data channel closed, and no more messages will arrive on it
+    DataChannelClose { label: String },
+    /// The connection is gone for good
+    Closed(CloseReason),
+    /// Something went wrong, which the connection carries on from
+    Error(String),
+}
+
+impl std::fmt::Display for ConnectionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionEvent::IceState(state) => write!(f, "ICE Connection State has changed: {state}"),
+            ConnectionEvent::PeerState(state) => write!(f, "Peer Connection State has changed: {state}"),
+            ConnectionEvent::DataChannelOpen { label } => write!(f, "Data channel '{label}' open"),
+            ConnectionEvent::DataChannelClose { label } => write!(f, "Data channel '{label}' closed"),
+            ConnectionEvent::Closed(reason) => write!(f, "Connection closed: {reason}"),
+            ConnectionEvent::Error(e) => write!(f, "Connection error: {e}"),
+        }
+    }
+}
+
+/// Why a connection is gone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// This end closed the connection
+    Local,
+    /// The other end closed the connection
+    Remote,
+    /// The connection dropped and could not be restarted
+    Lost,
+}
+impl std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::Local => write!(f, "closed by this end"),
+            CloseReason::Remote => write!(f, "closed by the other end"),
+            CloseReason::Lost => write!(f, "lost, and could not be restarted"),
+        }
+    }
+}
 
 /// Datatype that handles communication between two clients
 pub struct AtrisConnection {
     connection: Arc<RTCPeerConnection>,
-    done_reciever: Receiver<()>,
+    /// The state of the connection, which is updated as the peer connection changes
+    state: Arc<watch::Sender<ChannelState>>,
+    /// Kept so the state can be updated even while nobody else is watching it
+    state_receiver: watch::Receiver<ChannelState>,
+    /// Whether something will try to restart the connection when it fails, see [`IceTrickle::run`]
+    restartable: Arc<AtomicBool>,
+    /// Whether this end is closing the connection, as opposed to the other end
+    closing: Arc<AtomicBool>,
+    events: broadcast::Sender<ConnectionEvent>,
+    /// The signals for the local candidates, until they are taken by [`AtrisConnection::ice_trickle`]
+    local_signals: Option<UnboundedReceiver<SignalMessage>>,
+    /// Whether the local candidates go in the description, see [`AtrisConnectionBuilder::untrickled`]
+    untrickled: bool,
+}
+impl Drop for AtrisConnection {
+    fn drop(&mut self) {
+        // Close the connection along with the last channel using it, so its trickle stops too
+        if let Result::Ok(runtime) = tokio::runtime::Handle::try_current() {
+            self.closing.store(true, Ordering::SeqCst);
+            let connection = Arc::clone(&self.connection);
+            runtime.spawn(async move {
+                let _ = connection.close().await;
+            });
+        }
+    }
 }
 
 impl AtrisConnection {
+    /// Create a connection which only uses [`DEFAULT_STUN_SERVER`]
     pub async fn new() -> Result<Self> {
+        Self::builder().stun_server(DEFAULT_STUN_SERVER).build().await
+    }
+
+    /// Create a connection which only connects to clients on the same host or LAN, see [`AtrisConnectionBuilder::offline`].
+    /// This needs a network interface other than loopback, even to connect within one host.
+    pub async fn offline() -> Result<Self> {
+        Self::builder().offline().build().await
+    }
+
+    /// Configure the servers of a new connection
+    pub fn builder() -> AtrisConnectionBuilder {
+        AtrisConnectionBuilder::default()
+    }
+
+    async fn with_config(builder: AtrisConnectionBuilder) -> Result<Self> {
         // Create a MediaEngine object to configure the supported codec
         let mut m = MediaEngine::default();
 
@@ -52,60 +289,196 @@ impl AtrisConnection {
         // Use the default set of Interceptors
         registry = register_default_interceptors(registry, &mut m)?;
 
+        // Offline connections should not even query for mDNS candidates
+        let mut setting_engine = SettingEngine::default();
+        if builder.offline {
+            setting_engine.set_ice_multicast_dns_mode(MulticastDnsMode::Disabled);
+        }
+
         // Create the API object with the MediaEngine
         let api = APIBuilder::new()
             .with_media_engine(m)
             .with_interceptor_registry(registry)
+            .with_setting_engine(setting_engine)
             .build();
 
         // Prepare the configuration
         let config = RTCConfiguration {
-            ice_servers: vec![RTCIceServer {
-                urls: vec!["stun:stun.l.google.com:19302".to_owned()], //consider switching to stun3
-                ..Default::default()
-            }],
+            ice_servers: builder
+                .ice_servers
+                .into_iter()
+                .filter(|_| !builder.offline)
+                .map(|ice_server| RTCIceServer {
+                    urls: ice_server.urls,
+                    username: ice_server.username,
+                    credential: ice_server.credential,
+                    ..Default::default()
+                })
+                .collect(),
+            ice_transport_policy: if builder.relay_only {
+                RTCIceTransportPolicy::Relay
+            } else {
+                RTCIceTransportPolicy::All
+            },
             ..Default::default()
         };
 
         // Create a new RTCPeerConnection
         let peer_connection = Arc::new(api.new_peer_connection(config).await?);
 
-        // Generate read and write
-        let (done_tx, done_reciever) = tokio::sync::mpsc::channel::<()>(1);
-        // Set the handler for Peer connection state
+        // Set the handler for ICE connection state
         // This will notify you when the peer has connected/disconnected
+        let (state, state_receiver) = watch::channel(ChannelState::Connecting);
+        let state = Arc::new(state);
+        let restartable = Arc::new(AtomicBool::new(false));
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let closing = Arc::new(AtomicBool::new(false));
+        let handler_state = Arc::clone(&state);
+        let handler_restartable = Arc::clone(&restartable);
+        let handler_events = events.clone();
+        peer_connection.on_ice_connection_state_change(Box::new(
+            move |s: RTCIceConnectionState| {
+                let _ = handler_events.send(ConnectionEvent::IceState(s));
+
+                // The connection may come back from Disconnected by itself, and from Failed through an ICE restart
+                let current = *handler_state.borrow();
+                let next = current.after(s, handler_restartable.load(Ordering::SeqCst));
+                if next != current {
+                    let _ = handler_state.send(next);
+                }
+
+                Box::pin(async {})
+            },
+        ));
+        let handler_state = Arc::clone(&state);
+        let handler_events = events.clone();
         peer_connection.on_peer_connection_state_change(Box::new(
             move |s: RTCPeerConnectionState| {
-                println!("Peer Connection State has changed: {}", s);
-
-                if s == RTCPeerConnectionState::Failed {
-                    // Wait until PeerConnection has had no network activity for 30 seconds or another failure. It may be reconnected using an ICE Restart.
-                    // Use webrtc.PeerConnectionStateDisconnected if you are interested in detecting faster timeout.
-                    // Note that the PeerConnection may come back from PeerConnectionStateDisconnected.
-                    println!("Peer Connection has gone to failed exiting");
-                    let _ = done_tx.try_send(());
+                let _ = handler_events.send(ConnectionEvent::PeerState(s));
+
+                if s == RTCPeerConnectionState::Closed {
+                    let _ = handler_state.send(ChannelState::Lost);
                 }
 
                 Box::pin(async {})
             },
         ));
+
+        // Queue up local candidates as they are gathered, so they can be trickled to the other end
+        let (signal_sender, local_signals) = tokio::sync::mpsc::unbounded_channel();
+        peer_connection.on_ice_candidate(Box::new(move |candidate: Option<RTCIceCandidate>| {
+            let signal = match candidate.map(|c| c.to_json()) {
+                Some(Result::Ok(init)) => serde_json::to_string(&init).ok().map(SignalMessage::Candidate),
+                Some(Err(_)) => None,
+                // Gathering is finished
+                None => Some(SignalMessage::EndOfCandidates),
+            };
+            if let Some(signal) = signal {
+                let _ = signal_sender.send(signal);
+            }
+            Box::pin(async {})
+        }));
         Ok(Self {
             connection: peer_connection,
-            done_reciever,
+            state,
+            state_receiver,
+            restartable,
+            closing,
+            events,
+            local_signals: Some(local_signals),
+            untrickled: builder.untrickled,
+        })
+    }
+
+    /// Set the local description, returning it as the other end should see it.
+    /// Untrickled connections wait for their candidates to be gathered into it first.
+    async fn describe(&self, description: RTCSessionDescription) -> Result<RTCSessionDescription> {
+        // The promise only resolves for gathering which finishes after it is made
+        let mut gathered = match self.untrickled {
+            true => Some(self.connection.gathering_complete_promise().await),
+            false => None,
+        };
+        self.connection.set_local_description(description).await?;
+        if let Some(gathered) = gathered.as_mut() {
+            let _ = gathered.recv().await;
+        }
+        self.connection
+            .local_description()
+            .await
+            .ok_or_else(|| anyhow!("The connection has no local description"))
+    }
+
+    /// Take the handle which trickles this connection's candidates to the other end, and restarts the connection
+    /// when it drops. Only the `offerer` creates the offers restarting the connection.
+    /// This only returns [`Some`] the first time it is called.
+    fn ice_trickle(&mut self, offerer: bool) -> Option<IceTrickle> {
+        Some(IceTrickle {
+            connection: Arc::clone(&self.connection),
+            local_signals: self.local_signals.take()?,
+            offerer,
+            state: Arc::clone(&self.state),
+            restartable: Arc::clone(&self.restartable),
+            closing: Arc::clone(&self.closing),
+            events: self.events.clone(),
         })
     }
+
+    /// The current state of this connection
+    pub fn state(&self) -> ChannelState {
+        *self.state_receiver.borrow()
+    }
+
+    /// Subscribe to the events of this connection from now on
+    pub fn events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Close the connection from this end
+    pub async fn close(&self) -> Result<(), webrtc::Error> {
+        self.closing.store(true, Ordering::SeqCst);
+        let _ = self.events.send(ConnectionEvent::Closed(CloseReason::Local));
+        self.connection.close().await
+    }
 }
 
 pub struct AtrisChannelParts<T> {
-    connection: AtrisConnection,
+    /// Shared by every channel on the connection, which is closed once they are all dropped
+    connection: Arc<AtrisConnection>,
     data_channel: Arc<RTCDataChannel>,
-    sender: Sender<Encrypted<T>>,
-    receiver: Receiver<Encrypted<T>>,
+    /// Serialized frames, which are fragmented as they are sent
+    sender: Sender<Outgoing>,
+    receiver: Receiver<(MessageId, Encrypted<Payload>)>,
+    /// The statuses of the messages sent on this channel, which the other end's acknowledgements update
+    statuses: Arc<Statuses>,
+    /// Acknowledgements, receipts and compression, both ways
+    controls: Arc<Controls>,
+    /// The other end's presence signals, still encrypted
+    presence: broadcast::Sender<Encrypted<Presence>>,
+    /// The compression the other end has said it accepts
+    accepted: Arc<Accepted>,
+    phantom_data: PhantomData<T>,
+}
+
+/// A serialized message waiting to be sent, and where to report whether it was
+struct Outgoing {
+    message: Vec<u8>,
+    sent: oneshot::Sender<Result<(), DeliveryError>>,
+}
+
+/// Why a message could not be handed to the data channel
+enum DeliveryError {
+    Lost,
+    DataChannel(webrtc::Error),
 }
 
 pub struct AtrisChannel<T> {
     atris_channel_internal: AtrisChannelParts<T>,
     cipher: Cipher,
+    next_id: MessageId,
+    limiter: SignalLimiter,
+    /// The compression this end would like to send with
+    compression: Compression,
+    compression_stats: CompressionStats,
     phantom_data:PhantomData<T>
 }
 impl <T> Debug for AtrisChannel<T> {
@@ -118,47 +491,111 @@ where
     T: Serialize + Send + Sync + 'static,
     for<'a> T: Deserialize<'a>,
 {
-    pub fn new(connection: AtrisConnection, data_channel: Arc<RTCDataChannel>) -> Self {
+    pub fn new(connection: Arc<AtrisConnection>, data_channel: Arc<RTCDataChannel>) -> Self {
         // The channel that messages *to* this initiator will use
         let (incoming_sender, incoming_receiver) = tokio::sync::mpsc::channel(20);
         // The channel that messages *from* this initiator will use
-        let (outgoing_sender, mut outgoing_receiver) = tokio::sync::mpsc::channel(20);
+        let (outgoing_sender, mut outgoing_receiver) = tokio::sync::mpsc::channel::<Outgoing>(OUTGOING_CAPACITY);
+        // Controls are few and small, and are never held back by the messages
+        let (control_sender, mut control_receiver) = unbounded_channel::<Outgoing>();
 
         // Register channel opening handling
         let arc_data_channel = Arc::clone(&data_channel);
+        let mut state = connection.state_receiver.clone();
+        let events = connection.events.clone();
         data_channel.on_open(Box::new(move || {
             //THIS IS WHERE THE THINGS ARE GENERATED
-            println!(
-                "Data channel '{}'-'{}' open.",
-                arc_data_channel.label(),
-                arc_data_channel.id()
-            );
+            let _ = events.send(ConnectionEvent::DataChannelOpen {
+                label: arc_data_channel.label().to_owned(),
+            });
             Box::pin(async move {
-                let mut result = Result::<usize>::Ok(0);
-                // Get the next outgoing message from the `outgoing_sender`
-                while let Some(next_message) = outgoing_receiver.recv().await {
-                    // Send the next outgoing message and record the result
-                    if let Result::Ok(msg) = bincode::serialize(&next_message) {
-                        // incoming_sender.blocking_send(&msg);
-                        result = arc_data_channel.send(&msg.into()).await.map_err(Into::into);
-                    }
-                    // unimplemented!("Sending messages");
-                    // result = arc_data_channel.send(next_message).await.map_err(Into::into);
+                let drained = Arc::new(Notify::new());
+                let notify = Arc::clone(&drained);
+                arc_data_channel.set_buffered_amount_low_threshold(LOW_WATER_MARK).await;
+                arc_data_channel
+                    .on_buffered_amount_low(Box::new(move || {
+                        notify.notify_one();
+                        Box::pin(async {})
+                    }))
+                    .await;
+
+                let mut message_id = 0u32;
+                // Get the next control, or else the next outgoing message from the `outgoing_sender`, until every
+                // message sender is dropped
+                loop {
+                    let Outgoing { message, sent } = tokio::select! {
+                        biased;
+                        Some(control) = control_receiver.recv() => control,
+                        outgoing = outgoing_receiver.recv() => match outgoing {
+                            Some(outgoing) => outgoing,
+                            None => break,
+                        },
+                    };
+                    let fragments = framing::fragment(message_id, &message);
+                    message_id = message_id.wrapping_add(1);
+                    let result = deliver(&arc_data_channel, &mut state, &drained, fragments).await;
+                    let _ = sent.send(result);
                 }
             })
         }));
 
+        // Register channel closing handling
+        let label = data_channel.label().to_owned();
+        let events = connection.events.clone();
+        let closing = Arc::clone(&connection.closing);
+        data_channel.on_close(Box::new(move || {
+            let _ = events.send(ConnectionEvent::DataChannelClose {
+                label: label.clone(),
+            });
+            // Unless this end is closing, the other end closed the channel
+            if !closing.load(Ordering::SeqCst) {
+                let _ = events.send(ConnectionEvent::Closed(CloseReason::Remote));
+            }
+            Box::pin(async {})
+        }));
+
         // Register text message handling
-        let _d_label = data_channel.label().to_owned();
         let incoming_sender = Arc::new(incoming_sender);
+        let events = connection.events.clone();
+        let statuses = Arc::new(Statuses::default());
+        let accepted = Arc::new(Accepted::default());
+        let controls = Arc::new(Controls::new(control_sender, Arc::clone(&statuses), Arc::clone(&accepted)));
+        let arc_controls = Arc::clone(&controls);
+        let (presence, _) = broadcast::channel(EVENT_CAPACITY);
+        let presence_sender = presence.clone();
+        let mut reassembler = Reassembler::default();
         data_channel.on_message(Box::new(move |msg: DataChannelMessage| {
-            // let msg_str = String::from_utf8(msg.data.to_vec()).unwrap();
-            // println!("Message from DataChannel '{}': '{}'", d_label, msg_str);
             let incoming_sender = Arc::clone(&incoming_sender);
+            let events = events.clone();
+            let controls = Arc::clone(&arc_controls);
+            let presence = presence_sender.clone();
+            let message = reassembler.push(&msg.data);
             Box::pin(async move {
-                if let Result::Ok(msg) = bincode::deserialize(&msg.data) {
-                    incoming_sender.send(msg).await;
+                let message = match message {
+                    Result::Ok(Some(message)) => message,
+                    Result::Ok(None) => return,
+                    Err(e) => {
+                        let _ = events.send(ConnectionEvent::Error(e.to_string()));
+                        return;
+                    }
                 };
+                match bincode::deserialize::<Frame>(&message) {
+                    Result::Ok(Frame::Message { id, body }) => {
+                        controls.acknowledge(id);
+                        let _ = incoming_sender.send((id, body)).await;
+                    }
+                    Result::Ok(Frame::Presence(signal)) => {
+                        let _ = presence.send(signal);
+                    }
+                    Result::Ok(Frame::Control(control)) => {
+                        if controls.receive(control).is_err() {
+                            let _ = events.send(ConnectionEvent::Error("Unauthenticated control message".to_owned()));
+                        }
+                    }
+                    Err(e) => {
+                        let _ = events.send(ConnectionEvent::Error(format!("Malformed message: {e}")));
+                    }
+                }
             })
         }));
         Self {
@@ -166,14 +603,80 @@ where
             data_channel,
             sender: outgoing_sender,
             receiver: incoming_receiver,
+            statuses,
+            controls,
+            presence,
+            accepted,
+            phantom_data: PhantomData,
+        }
+    }
+}
+
+/// Hand the fragments of a message to the data channel, waiting while its buffer is over [`HIGH_WATER_MARK`].
+/// Fragments are held while the connection is down, and sent once it is back.
+async fn deliver(
+    data_channel: &RTCDataChannel,
+    state: &mut watch::Receiver<ChannelState>,
+    drained: &Notify,
+    fragments: Vec<Bytes>,
+) -> Result<(), DeliveryError> {
+    for fragment in fragments {
+        loop {
+            let current = *state.borrow_and_update();
+            if current == ChannelState::Lost {
+                return Err(DeliveryError::Lost);
+            }
+            let wait_for_drain = current.is_up() && data_channel.buffered_amount().await > HIGH_WATER_MARK;
+            if current.is_up() && !wait_for_drain {
+                match data_channel.send(&fragment).await {
+                    Result::Ok(_) => break,
+                    // The connection may have dropped since its state was checked
+                    Err(_) if !state.borrow().is_up() => continue,
+                    Err(e) => return Err(DeliveryError::DataChannel(e)),
+                }
+            }
+            tokio::select! {
+                _ = drained.notified(), if wait_for_drain => {}
+                changed = state.changed() => {
+                    if changed.is_err() {
+                        return Err(DeliveryError::Lost);
+                    }
+                }
+            }
         }
     }
+    Result::Ok(())
+}
+
+/// Decrypt, decompress and deserialize a received message
+fn open<T>(encrypted: Encrypted<Payload>, cipher: &mut Cipher) -> atris_common::Result<T>
+where
+    for<'a> T: Deserialize<'a>,
+{
+    let bytes = encrypted
+        .decrypt(cipher)?
+        .into_bytes()
+        .map_err(|e| EncryptionError::from(Box::new(bincode::ErrorKind::Io(e))))?;
+    Result::Ok(bincode::deserialize(&bytes)?)
+}
+
+impl<T> AtrisChannelParts<T> {
+    /// The label of the data channel these parts use
+    pub fn label(&self) -> &str {
+        self.data_channel.label()
+    }
 }
 
 #[derive(Debug)]
 pub enum SendError<T>{
     ChannelError(T),
-    EncryptionError(T,EncryptionError)
+    EncryptionError(T,EncryptionError),
+    /// The encrypted message is larger than [`framing::MAX_MESSAGE_SIZE`]
+    TooLarge(T, usize),
+    /// The connection was lost before the message could be sent
+    ConnectionLost(T),
+    /// The data channel refused the message
+    DataChannelError(T, webrtc::Error),
 }
 impl <T:Debug> std::fmt::Display for SendError<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -201,60 +704,184 @@ where
     for<'a> T: Deserialize<'a>
 {
     pub fn new(parts:AtrisChannelParts<T>,cipher:Cipher)->Self{
+        Self::with_compression(parts, cipher, Compression::default())
+    }
+
+    /// Create a channel which compresses the messages it sends with `compression`, once the other end accepts it.
+    /// [`Compression::None`] turns compression off.
+    pub fn with_compression(parts:AtrisChannelParts<T>,cipher:Cipher,compression:Compression)->Self{
+        // Controls skip ahead of the messages, so this is sent before any of them
+        let accepts = match compression {
+            Compression::None => vec![],
+            compression => vec![compression],
+        };
+        parts.controls.set_cipher(cipher.clone());
+        let _ = parts.controls.send(&Control::Compression(accepts));
         Self {
             phantom_data:PhantomData,
             atris_channel_internal:parts,
-            cipher
+            cipher,
+            next_id: 0,
+            limiter: SignalLimiter::default(),
+            compression,
+            compression_stats: CompressionStats::default(),
         }
     }
 
-    pub async fn send(&mut self, t: T) -> Result<(), SendError<T>> {
-        let encrypted = match Encrypted::encrypt(&t, &mut self.cipher){
+    /// Send a message, returning its id once the data channel has taken it. This waits while the channel's buffer
+    /// is full, and while the connection is reconnecting, so a fast sender cannot queue more than the network
+    /// carries. The message is [`MessageStatus::Pending`] until the other end acknowledges it.
+    pub async fn send(&mut self, t: T) -> Result<MessageId, SendError<T>> {
+        let bytes = match bincode::serialize(&t) {
+            Result::Ok(bytes)=>bytes,
+            Err(e)=>return Err(SendError::EncryptionError(t, e.into()))
+        };
+        let compression = self.atris_channel_internal.accepted.negotiate(self.compression);
+        let payload = Payload::new(bytes, compression, &mut self.compression_stats);
+        let encrypted = match Encrypted::encrypt(&payload, &mut self.cipher){
             Result::Ok(s)=>s,
             Err(e)=>return Err(SendError::EncryptionError(t, e))
         };
-        self.atris_channel_internal.sender.send(encrypted).await.map_err(|e|{
-            SendError::ChannelError(t)
-        })
+        let id = self.next_id;
+        let serialized = match bincode::serialize(&Frame::Message { id, body: encrypted }) {
+            Result::Ok(s)=>s,
+            Err(_)=>return Err(SendError::ChannelError(t))
+        };
+        if serialized.len() > framing::MAX_MESSAGE_SIZE {
+            let size = serialized.len();
+            return Err(SendError::TooLarge(t, size));
+        }
+        self.next_id += 1;
+        let statuses = &self.atris_channel_internal.statuses;
+        statuses.advance(id, MessageStatus::Pending);
+        let (sent, result) = oneshot::channel();
+        let outgoing = Outgoing { message: serialized, sent };
+        let result = match self.atris_channel_internal.sender.send(outgoing).await {
+            Result::Ok(()) => result.await,
+            Err(_) => {
+                statuses.advance(id, MessageStatus::Failed);
+                return Err(SendError::ChannelError(t));
+            }
+        };
+        if !matches!(result, Result::Ok(Result::Ok(()))) {
+            statuses.advance(id, MessageStatus::Failed);
+        }
+        match result {
+            Result::Ok(Result::Ok(())) => Result::Ok(id),
+            Result::Ok(Err(DeliveryError::Lost)) => Err(SendError::ConnectionLost(t)),
+            Result::Ok(Err(DeliveryError::DataChannel(e))) => Err(SendError::DataChannelError(t, e)),
+            Err(_) => Err(SendError::ChannelError(t)),
+        }
     }
 
     pub fn try_receive(&mut self) -> Result<T, TryReceiveError> {
-        self.atris_channel_internal.receiver.try_recv()
-        .map_err(TryReceiveError::Emp)
-        .and_then(|e|{
-            e.decrypt(&mut self.cipher).map_err(TryReceiveError::DecryptionError)
-        })
+        self.try_receive_with_id().map(|(_, t)| t)
     }
     pub async fn receive(&mut self) -> Option<atris_common::Result<T>> {
-        Some(self.atris_channel_internal.receiver.recv().await?.decrypt(&mut self.cipher))
+        Some(self.receive_with_id().await?.map(|(_, t)| t))
     }
-}
 
+    /// Like [`Self::try_receive`], along with the id to pass to [`Self::mark_read`]
+    pub fn try_receive_with_id(&mut self) -> Result<(MessageId, T), TryReceiveError> {
+        let (id, encrypted) = self.atris_channel_internal.receiver.try_recv().map_err(TryReceiveError::Emp)?;
+        let t = open(encrypted, &mut self.cipher).map_err(TryReceiveError::DecryptionError)?;
+        Result::Ok((id, t))
+    }
 
+    /// Like [`Self::receive`], along with the id to pass to [`Self::mark_read`]
+    pub async fn receive_with_id(&mut self) -> Option<atris_common::Result<(MessageId, T)>> {
+        let (id, encrypted) = self.atris_channel_internal.receiver.recv().await?;
+        Some(open(encrypted, &mut self.cipher).map(|t| (id, t)))
+    }
 
-impl AtrisChannel<String> {
-    pub async fn io_loop(mut self) -> Result<Infallible> {
-        let mut buffer = [0; 1024];
-        let mut input = tokio::io::stdin();
+    /// Tell the other end that the application has shown it a received message
+    pub async fn mark_read(&self, id: MessageId) -> Result<(), SendError<MessageId>> {
+        let result = self.atris_channel_internal.controls.send(&Control::Read(id));
+        match result {
+            Some(result) => match result.await {
+                Result::Ok(Result::Ok(())) => Result::Ok(()),
+                Result::Ok(Err(DeliveryError::Lost)) => Err(SendError::ConnectionLost(id)),
+                Result::Ok(Err(DeliveryError::DataChannel(e))) => Err(SendError::DataChannelError(id, e)),
+                Err(_) => Err(SendError::ChannelError(id)),
+            },
+            None => Err(SendError::ChannelError(id)),
+        }
+    }
 
-        loop {
-            tokio::select! {
-                Some(atris_common::Result::Ok(incoming_message)) = self.receive() => {
-                    println!("From other user: '{incoming_message}'")
-                },
-                Result::Ok(len) = input.read(&mut buffer) => {
-                    if len > 1 {
-                        if let Result::Ok(msg) = String::from_utf8(Vec::from(buffer)) {
-                            self.send(msg.trim().to_owned()).await?;
-                        }
-                        buffer = [0;1024];
-                    }
-                },
-                else => {
+    /// Signal what the user is doing to the other end, returning whether the signal was sent.
+    /// Signals repeating the previous one within [`presence::SIGNAL_INTERVAL`] are dropped, as are signals made
+    /// while the connection is down or the channel is busy, so this never waits.
+    pub fn signal(&mut self, presence: Presence) -> bool {
+        if !self.state().is_up() || !self.limiter.allow(presence) {
+            return false;
+        }
+        let Result::Ok(encrypted) = Encrypted::encrypt(&presence, &mut self.cipher) else {
+            return false;
+        };
+        let Result::Ok(message) = bincode::serialize(&Frame::Presence(encrypted)) else {
+            return false;
+        };
+        // Nobody waits to hear whether the signal was sent
+        let (sent, _) = oneshot::channel();
+        self.atris_channel_internal.sender.try_send(Outgoing { message, sent }).is_ok()
+    }
 
-                }
+    /// Receive the other end's presence signals from now on
+    pub fn presence(&self) -> PresenceReceiver {
+        PresenceReceiver::new(self.atris_channel_internal.presence.subscribe(), self.cipher.clone())
+    }
 
-            };
-        }
+    /// How much compression has saved on the messages this channel sent
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression_stats
+    }
+
+    /// The status of a message sent on this channel, if it was
+    pub fn status(&self, id: MessageId) -> Option<MessageStatus> {
+        self.atris_channel_internal.statuses.get(id)
+    }
+
+    /// Subscribe to the status changes of the messages sent on this channel from now on
+    pub fn status_updates(&self) -> broadcast::Receiver<StatusUpdate> {
+        self.atris_channel_internal.statuses.subscribe()
+    }
+
+    /// The label of the data channel underneath this channel
+    pub fn label(&self) -> &str {
+        self.atris_channel_internal.label()
+    }
+
+    /// How many bytes have been handed to the data channel but not sent yet
+    pub async fn buffered_amount(&self) -> usize {
+        self.atris_channel_internal.data_channel.buffered_amount().await
+    }
+
+    /// The current state of the connection underneath this channel
+    pub fn state(&self) -> ChannelState {
+        self.atris_channel_internal.connection.state()
+    }
+
+    /// A receiver which is notified whenever the state of the connection underneath this channel changes
+    pub fn state_receiver(&self) -> watch::Receiver<ChannelState> {
+        self.atris_channel_internal.connection.state_receiver.clone()
+    }
+
+    /// Subscribe to the events of the connection underneath this channel from now on
+    pub fn events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.atris_channel_internal.connection.events()
+    }
+}
+
+
+impl AtrisChannel<atris_common::message::ChatMessage> {
+    /// Chat over this channel from stdin and stdout until the connection closes, see [`Self::console`]
+    pub async fn io_loop(self) -> Result<Infallible> {
+        self.console(None, console::OutputMode::Human).await
+    }
+
+    /// Like [`Self::io_loop`], but also transfers files over `files`, see [`console::ChatCommand::parse`] for the
+    /// commands
+    pub async fn io_loop_with_files(self, files: transfer::FileTransfers) -> Result<Infallible> {
+        self.console(Some(files), console::OutputMode::Human).await
     }
 }
@@ -0,0 +1,226 @@
+//! Pairs two clients without a server, from a passphrase the two users share.
+//!
+//! Each user pastes a line from the other: a compact description (see [`super::signal::encode_description`]) followed by a
+//! SPAKE2 message derived from the passphrase. The passphrase itself is never sent, and the room key comes out of the
+//! exchange, so someone who reads or even replaces the lines gets a single guess at the passphrase. The answer also
+//! carries a confirmation, so the offering end catches a wrong passphrase before it connects. The offering end sends
+//! its own confirmation once connected, as the label of a channel of its own, and the answering end only hands out
+//! the channels after checking it.
+//! Both connections should be [untrickled](super::AtrisConnectionBuilder::untrickled), since there is nothing to
+//! trickle candidates through.
+use atris_common::CipherKey;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+
+use anyhow::Result;
+
+use super::channels::{AtrisDataChannels, ChannelOptions, IncomingChannels};
+use super::initiator::AtrisInitiator;
+use super::responder::AtrisResponder;
+
+/// Separates the description from the pairing message in a line, and is in neither of their alphabets
+pub const PAIRING_SEPARATOR: char = '.';
+/// Starts the label of the channel whose label is the offering end's confirmation, which is not among the channels
+/// handed out
+pub const CONFIRMATION_CHANNEL_PREFIX: &str = "pairing confirmation ";
+
+/// The size of a SPAKE2 message, which is a byte for the side it comes from and then a point
+const MESSAGE_SIZE: usize = 33;
+const CONFIRMATION_SIZE: usize = 32;
+
+/// An error from the pairing exchange itself, rather than from connecting
+#[derive(Debug)]
+pub enum PairingError {
+    /// The line is not one made by [`PairingOffer::line`] or [`answer`]
+    MalformedLine,
+    /// The pairing message in the line is not a valid point
+    InvalidMessage,
+    /// The two ends used different passphrases, or the line was tampered with
+    PassphraseMismatch,
+    /// The connection closed before the offering end confirmed the pairing
+    Unconfirmed,
+}
+impl std::fmt::Display for PairingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PairingError::MalformedLine => write!(f, "That is not a pairing line, check it was copied whole"),
+            PairingError::InvalidMessage => write!(f, "The pairing line is corrupted, check it was copied right"),
+            PairingError::PassphraseMismatch => write!(
+                f,
+                "The passphrases do not match, or someone tampered with the pairing lines. Pair again."
+            ),
+            PairingError::Unconfirmed => write!(f, "The other user disconnected before confirming the pairing"),
+        }
+    }
+}
+impl std::error::Error for PairingError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// Which end of the exchange this is, which decides the SPAKE2 role each end plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Offerer,
+    Answerer,
+}
+impl Side {
+    /// What this side proves with its confirmation
+    fn label(self) -> &'static [u8] {
+        match self {
+            Side::Offerer => b"offerer",
+            Side::Answerer => b"answerer",
+        }
+    }
+}
+
+/// One end of a SPAKE2 exchange, along with the message it sends the other end
+struct Exchange {
+    spake: Spake2<Ed25519Group>,
+    message: Vec<u8>,
+}
+impl Exchange {
+    fn start(passphrase: &str, side: Side) -> Self {
+        let password = Password::new(passphrase.as_bytes());
+        let (offerer, answerer) = (
+            Identity::new(b"atris pairing offerer"),
+            Identity::new(b"atris pairing answerer"),
+        );
+        let (spake, message) = match side {
+            Side::Offerer => Spake2::<Ed25519Group>::start_a(&password, &offerer, &answerer),
+            Side::Answerer => Spake2::<Ed25519Group>::start_b(&password, &offerer, &answerer),
+        };
+        Self { spake, message }
+    }
+
+    /// The room key and the confirmation key shared with the end which sent `theirs`, if it knew the passphrase
+    fn finish(self, theirs: &[u8]) -> Result<([u8; 32], [u8; 32]), PairingError> {
+        let shared = self.spake.finish(theirs).map_err(|_| PairingError::InvalidMessage)?;
+        let keys = Sha512::new()
+            .chain_update(b"atris pairing keys")
+            .chain_update(shared)
+            .finalize();
+        let mut room_key = [0; 32];
+        let mut confirmation_key = [0; 32];
+        room_key.copy_from_slice(&keys[..32]);
+        confirmation_key.copy_from_slice(&keys[32..]);
+        Ok((room_key, confirmation_key))
+    }
+}
+
+/// Proves the end on `side` derived the same keys, without revealing them
+fn confirmation(confirmation_key: &[u8; 32], side: Side) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(confirmation_key).expect("HMAC takes keys of any size");
+    mac.update(b"atris pairing confirmation ");
+    mac.update(side.label());
+    mac
+}
+
+/// Split a pasted line into its description and its pairing bytes, ignoring any whitespace
+fn split_line(line: &str) -> Result<(String, Vec<u8>), PairingError> {
+    let line: String = line.split_whitespace().collect();
+    let (description, pairing) = line.rsplit_once(PAIRING_SEPARATOR).ok_or(PairingError::MalformedLine)?;
+    let pairing = BASE32_NOPAD
+        .decode(pairing.to_ascii_uppercase().as_bytes())
+        .map_err(|_| PairingError::MalformedLine)?;
+    Ok((description.to_owned(), pairing))
+}
+
+/// The offering end of a pairing, see [`PairingOffer::line`]
+pub struct PairingOffer {
+    initiator: AtrisInitiator,
+    exchange: Exchange,
+}
+impl PairingOffer {
+    /// Offer to pair `initiator`'s connection with whoever knows `passphrase`
+    pub fn new(initiator: AtrisInitiator, passphrase: &str) -> Self {
+        Self {
+            initiator,
+            exchange: Exchange::start(passphrase, Side::Offerer),
+        }
+    }
+
+    /// The line to give the other user, which fits on one line
+    pub fn line(&self) -> Result<String> {
+        Ok(format!(
+            "{}{PAIRING_SEPARATOR}{}",
+            self.initiator.compact_local_description()?,
+            BASE32_NOPAD.encode(&self.exchange.message)
+        ))
+    }
+
+    /// Feed the other user's answer here to get every channel the offer opens, and the room key derived from the
+    /// passphrase. Fails with [`PairingError::PassphraseMismatch`] without connecting if the passphrases differ.
+    /// Otherwise this end's confirmation is sent as soon as the connection is up.
+    pub async fn into_channels_with(self, answer_line: &str) -> Result<(AtrisDataChannels, CipherKey)> {
+        let (description, pairing) = split_line(answer_line)?;
+        if pairing.len() != MESSAGE_SIZE + CONFIRMATION_SIZE {
+            return Err(PairingError::MalformedLine.into());
+        }
+        let (message, their_confirmation) = pairing.split_at(MESSAGE_SIZE);
+        let (room_key, confirmation_key) = self.exchange.finish(message)?;
+        confirmation(&confirmation_key, Side::Answerer)
+            .verify_slice(their_confirmation)
+            .map_err(|_| PairingError::PassphraseMismatch)?;
+        let channels = self.initiator.into_channels_with(&description).await?;
+
+        let tag = confirmation(&confirmation_key, Side::Offerer).finalize().into_bytes();
+        let label = format!("{CONFIRMATION_CHANNEL_PREFIX}{}", BASE32_NOPAD.encode(&tag));
+        channels.open(&label, ChannelOptions::reliable()).await?;
+        Ok((channels, CipherKey::from(room_key.as_slice())))
+    }
+}
+
+/// The channels an answered offer opens, which are only handed out once the offering end confirms the pairing
+pub struct UnconfirmedChannels {
+    channels: IncomingChannels,
+    confirmation_key: [u8; 32],
+}
+impl UnconfirmedChannels {
+    /// Wait for the offering end to connect and prove it used the same passphrase, then return the channels its offer
+    /// opens as they arrive. Fails with [`PairingError::PassphraseMismatch`] if it did not.
+    pub async fn confirmed(mut self) -> Result<IncomingChannels> {
+        let channel = self
+            .channels
+            .take_where(|label| label.starts_with(CONFIRMATION_CHANNEL_PREFIX))
+            .await
+            .ok_or(PairingError::Unconfirmed)?;
+        let tag = channel
+            .label()
+            .strip_prefix(CONFIRMATION_CHANNEL_PREFIX)
+            .and_then(|tag| BASE32_NOPAD.decode(tag.as_bytes()).ok())
+            .ok_or(PairingError::PassphraseMismatch)?;
+        confirmation(&self.confirmation_key, Side::Offerer)
+            .verify_slice(&tag)
+            .map_err(|_| PairingError::PassphraseMismatch)?;
+        Ok(self.channels)
+    }
+}
+
+/// Answer the other user's offer line with the same `passphrase`, returning the line to give back to them, the
+/// channels their offer opens once it is [confirmed](UnconfirmedChannels::confirmed), and the room key.
+pub async fn answer(
+    responder: AtrisResponder,
+    offer_line: &str,
+    passphrase: &str,
+) -> Result<(String, UnconfirmedChannels, CipherKey)> {
+    let (description, message) = split_line(offer_line)?;
+    if message.len() != MESSAGE_SIZE {
+        return Err(PairingError::MalformedLine.into());
+    }
+    let exchange = Exchange::start(passphrase, Side::Answerer);
+    let mut pairing = exchange.message.clone();
+    let (room_key, confirmation_key) = exchange.finish(&message)?;
+
+    let (answer, channels) = responder.into_channels_with(&description).await?;
+    pairing.extend_from_slice(&confirmation(&confirmation_key, Side::Answerer).finalize().into_bytes());
+    let line = format!("{answer}{PAIRING_SEPARATOR}{}", BASE32_NOPAD.encode(&pairing));
+    let channels = UnconfirmedChannels {
+        channels,
+        confirmation_key,
+    };
+    Ok((line, channels, CipherKey::from(room_key.as_slice())))
+}
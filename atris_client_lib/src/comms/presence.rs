@@ -0,0 +1,101 @@
+//! Ephemeral signals about what the other user is doing, like typing or leaving.
+//!
+//! Signals skip the durable message path: they are not acknowledged, not queued while the connection is down, and
+//! dropped rather than waited for when the channel is busy. They are still encrypted with the room key.
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use atris_common::{Cipher, Encrypted};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+
+/// The same signal is sent at most once in this interval, so that it can be emitted on every keystroke
+pub const SIGNAL_INTERVAL: Duration = Duration::from_secs(2);
+/// A user who has not signalled [`Presence::Typing`] for this long is taken to have stopped typing
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// What a user is doing in the conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Presence {
+    Typing,
+    StoppedTyping,
+    Active,
+    Idle,
+    /// The user left the conversation, and will not send anything else
+    Left,
+}
+impl Display for Presence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Presence::Typing => write!(f, "is typing..."),
+            Presence::StoppedTyping => write!(f, "stopped typing"),
+            Presence::Active => write!(f, "is active"),
+            Presence::Idle => write!(f, "is idle"),
+            Presence::Left => write!(f, "left the conversation"),
+        }
+    }
+}
+
+/// Drops signals which repeat the previous one within [`SIGNAL_INTERVAL`]
+#[derive(Default)]
+pub(super) struct SignalLimiter {
+    last: Option<(Presence, Instant)>,
+}
+impl SignalLimiter {
+    /// Whether `presence` should be sent now, recording it as sent if so
+    pub(super) fn allow(&mut self, presence: Presence) -> bool {
+        let now = Instant::now();
+        let repeated = matches!(self.last, Some((last, at)) if last == presence && now - at < SIGNAL_INTERVAL);
+        if !repeated {
+            self.last = Some((presence, now));
+        }
+        !repeated
+    }
+}
+
+/// Receives the other user's signals, see [`super::AtrisChannel::presence`]
+pub struct PresenceReceiver {
+    receiver: broadcast::Receiver<Encrypted<Presence>>,
+    cipher: Cipher,
+    /// Whether the last signal was [`Presence::Typing`], which times out
+    typing: bool,
+}
+impl PresenceReceiver {
+    pub(super) fn new(receiver: broadcast::Receiver<Encrypted<Presence>>, cipher: Cipher) -> Self {
+        Self {
+            receiver,
+            cipher,
+            typing: false,
+        }
+    }
+
+    /// Wait for the next signal. When the other user has been typing but stops signalling it for
+    /// [`TYPING_TIMEOUT`], this returns [`Presence::StoppedTyping`] on their behalf.
+    /// Returns [`None`] once the channel is closed.
+    pub async fn recv(&mut self) -> Option<Presence> {
+        loop {
+            let next = match self.typing {
+                true => match timeout(TYPING_TIMEOUT, self.receiver.recv()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        self.typing = false;
+                        return Some(Presence::StoppedTyping);
+                    }
+                },
+                false => self.receiver.recv().await,
+            };
+            let encrypted = match next {
+                Ok(encrypted) => encrypted,
+                // Only the latest signal matters
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            // Signals which cannot be decrypted did not come from the other user
+            if let Ok(presence) = encrypted.decrypt(&mut self.cipher) {
+                self.typing = presence == Presence::Typing;
+                return Some(presence);
+            }
+        }
+    }
+}
@@ -7,10 +7,9 @@ use webrtc::data_channel::RTCDataChannel;
 
 use anyhow::{Ok, Result};
 
-use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
-
-use super::{signal, AtrisChannelParts};
-use super::{AtrisChannel, AtrisConnection};
+use super::channels::IncomingChannels;
+use super::{signal, trickle::IceTrickle, AtrisChannelParts};
+use super::AtrisConnection;
 
 pub struct AtrisResponder {
     connection: AtrisConnection,
@@ -23,31 +22,48 @@ impl AtrisResponder {
         Ok(Self { connection })
     }
 
+    /// Create a responder on a connection configured with [`AtrisConnection::builder`]
+    pub fn with_connection(connection: AtrisConnection) -> Self {
+        Self { connection }
+    }
+
+    /// Take the handle which trickles this responder's candidates to the initiator, and answers ICE restarts when the connection drops
+    pub fn ice_trickle(&mut self) -> Option<IceTrickle> {
+        self.connection.ice_trickle(false)
+    }
+
     // pub fn encoded_local_description(&self)->Result<String> {
     //     let json_str = serde_json::to_string(&self.local_description)?;
     //     let b64 = signal::encode(&json_str);
     //     Ok(b64)
     // }
 
-    /// Set the initator's description
+    /// Set the initator's description, returning the answer and the first channel the initiator opens
     pub async fn into_channel_parts_with<T>(
-        mut self,
-        offer_str: &String,
+        self,
+        offer_str: &str,
     ) -> Result<(String, impl Future<Output = Option<AtrisChannelParts<T>>>)>
     where
         T: Serialize + Send + Sync + 'static,
         for<'d> T: Deserialize<'d>,
     {
+        let (answer, mut channels) = self.into_channels_with(offer_str).await?;
+        Ok((answer, async move { channels.next().await.map(|(_, parts)| parts) }))
+    }
+
+    /// Set the initator's description, returning the answer and every channel the initiator opens as it arrives.
+    /// The answer is encoded the same way as the offer, see [`signal::encode_description`].
+    pub async fn into_channels_with(mut self, offer_str: &str) -> Result<(String, IncomingChannels)> {
         let peer_connection = &mut self.connection.connection;
 
-        let (data_channel_sender, mut data_channel_receiver) =
+        let (data_channel_sender, data_channel_receiver) =
             // tokio::sync::mpsc::channel::<Arc<RTCDataChannel>>(1);
             tokio::sync::mpsc::channel::<Arc<RTCDataChannel>>(10);
         let data_channel_sender = Arc::new(data_channel_sender);
 
         // Wait for the offer to be pasted
-        let decoded_offer_str = signal::decode(offer_str.as_str())?;
-        let offer = serde_json::from_str::<RTCSessionDescription>(&decoded_offer_str)?;
+        let offer = signal::decode_description(offer_str)?;
+        let compact = signal::is_compact(offer_str);
         
         // Set the remote SessionDescription
         peer_connection.set_remote_description(offer).await?;
@@ -56,39 +72,23 @@ impl AtrisResponder {
         peer_connection.on_data_channel(Box::new(move |data_channel: Arc<RTCDataChannel>| {
             let data_channel_sender = Arc::clone(&data_channel_sender);
             Box::pin(async move {
-                data_channel_sender.send(data_channel).await;
+                let _ = data_channel_sender.send(data_channel).await;
             })
         }));
 
         // Create an answer
         let answer = peer_connection.create_answer(None).await?;
 
-        // Create channel that is blocked until ICE Gathering is complete
-        let mut gather_complete = peer_connection.gathering_complete_promise().await;
-
         // Sets the LocalDescription, and starts our UDP listeners
-        peer_connection.set_local_description(answer).await?;
-
-        // Block until ICE Gathering is complete, disabling trickle ICE
-        // we do this because we only can exchange one signaling message
-        // in a production application you should exchange ICE Candidates via OnICECandidate
-        let _ = gather_complete.recv().await;
-
-        // Output the answer in base64 so we can paste it in browser
-        // -- no, just print the thing normally --
-        let Some(local_desc) = peer_connection.local_description().await else {
-            panic!("generate local_description failed!");
+        // The candidates are not in the description, they are trickled to the initiator through the room,
+        // unless the connection is untrickled
+        let local_desc = self.connection.describe(answer).await?;
+        let b64 = match compact {
+            true => signal::encode_description(&local_desc)?,
+            false => signal::encode(&serde_json::to_string(&local_desc)?),
         };
-        let json_str = serde_json::to_string(&local_desc)?;
-        let b64 = signal::encode(&json_str);
-
-        Ok((b64, async move {
-            let data_channel = data_channel_receiver.recv().await;
-            match data_channel {
-                Some(data_channel)=>Some(AtrisChannelParts::new(self.connection, data_channel)),
-                _=>None
-            }
-        }))
+
+        Ok((b64, IncomingChannels::new(Arc::new(self.connection), data_channel_receiver)))
     }
 }
 
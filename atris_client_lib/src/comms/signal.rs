@@ -1,71 +1,310 @@
+use std::convert::Infallible;
 use std::fmt::Display;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use anyhow::Result;
-// use hyper::service::{make_service_fn, service_fn};
-// use hyper::{Body, Method, Request, Response, Server, StatusCode};
-
-// lazy_static! {
-//     static ref SDP_CHAN_TX_MUTEX: Arc<Mutex<Option<mpsc::Sender<String>>>> =
-//         Arc::new(Mutex::new(None));
-// }
-
-// HTTP Listener to get sdp
-// async fn remote_handler(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-//     match (req.method(), req.uri().path()) {
-//         // A HTTP handler that processes a SessionDescription given to us from the other WebRTC-rs or Pion process
-//         (&Method::POST, "/sdp") => {
-//             //println!("remote_handler receive from /sdp");
-//             let sdp_str = match std::str::from_utf8(&hyper::body::to_bytes(req.into_body()).await?)
-//             {
-//                 Ok(s) => s.to_owned(),
-//                 Err(err) => panic!("{}", err),
-//             };
-
-//             {
-//                 let sdp_chan_tx = SDP_CHAN_TX_MUTEX.lock().await;
-//                 if let Some(tx) = &*sdp_chan_tx {
-//                     let _ = tx.send(sdp_str).await;
-//                 }
-//             }
-
-//             let mut response = Response::new(Body::empty());
-//             *response.status_mut() = StatusCode::OK;
-//             Ok(response)
-//         }
-//         // Return the 404 Not Found for other routes.
-//         _ => {
-//             let mut not_found = Response::default();
-//             *not_found.status_mut() = StatusCode::NOT_FOUND;
-//             Ok(not_found)
-//         }
-//     }
-// }
-
-/// http_sdp_server starts a HTTP Server that consumes SDPs
-// pub async fn http_sdp_server(port: u16) -> mpsc::Receiver<String> {
-//     let (sdp_chan_tx, sdp_chan_rx) = mpsc::channel::<String>(1);
-//     {
-//         let mut tx = SDP_CHAN_TX_MUTEX.lock().await;
-//         *tx = Some(sdp_chan_tx);
-//     }
-
-//     tokio::spawn(async move {
-//         let addr = SocketAddr::from_str(&format!("0.0.0.0:{}", port)).unwrap();
-//         let service =
-//             make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(remote_handler)) });
-//         let server = Server::bind(&addr).serve(service);
-//         // Run this server for... forever!
-//         if let Err(e) = server.await {
-//             eprintln!("server error: {}", e);
-//         }
-//     });
-
-//     sdp_chan_rx
-// }
+use anyhow::{anyhow, Result};
+use atris_common::CipherKey;
+use bincode::Options;
+use data_encoding::BASE32_NOPAD;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use hmac::{Hmac, Mac};
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, oneshot};
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+/// The path a [`RendezvousServer`] takes offers on
+pub const RENDEZVOUS_PATH: &str = "/sdp";
+/// The header holding the signature of a rendezvous request or response body, see [`RendezvousToken::sign`]
+pub const SIGNATURE_HEADER: &str = "x-atris-signature";
+/// The largest offer a [`RendezvousServer`] reads
+const MAX_OFFER_SIZE: u64 = 64 * 1024;
+
+/// The secret shared out of band between the two ends of a rendezvous, like `ABCD...`.
+///
+/// It is never sent: requests and responses are signed with it instead, so someone watching the plain HTTP exchange
+/// can neither forge an offer or answer nor learn the room key, which is derived from it too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RendezvousToken([u8; 16]);
+impl RendezvousToken {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// Read a token given by the other user, in any case
+    pub fn parse(token: &str) -> Result<Self> {
+        let bytes = BASE32_NOPAD
+            .decode(token.trim().to_ascii_uppercase().as_bytes())
+            .map_err(|_| anyhow!("The rendezvous token is not valid base32"))?;
+        Ok(Self(bytes.try_into().map_err(|_| anyhow!("The rendezvous token has the wrong length"))?))
+    }
+
+    /// The room key for the connection the rendezvous sets up
+    pub fn room_key(&self) -> CipherKey {
+        let key = Sha256::new()
+            .chain_update(b"atris rendezvous room key")
+            .chain_update(self.0)
+            .finalize();
+        CipherKey::from(key.as_slice())
+    }
+
+    fn mac(&self, label: &[u8], body: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes keys of any size");
+        mac.update(label);
+        mac.update(body);
+        mac
+    }
+
+    /// The signature of a request or response `body`, where `label` says which it is
+    pub fn sign(&self, label: &[u8], body: &[u8]) -> String {
+        BASE32_NOPAD.encode(&self.mac(label, body).finalize().into_bytes())
+    }
+
+    /// Whether `signature` is the one [`RendezvousToken::sign`] makes for `body`
+    pub fn verify(&self, label: &[u8], body: &[u8], signature: &str) -> bool {
+        match BASE32_NOPAD.decode(signature.trim().as_bytes()) {
+            Ok(signature) => self.mac(label, body).verify_slice(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+impl Display for RendezvousToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", BASE32_NOPAD.encode(&self.0))
+    }
+}
+
+/// What offers posted to a [`RendezvousServer`] are signed as
+pub const OFFER_LABEL: &[u8] = b"atris rendezvous offer ";
+/// What a [`RendezvousServer`]'s answers are signed as
+pub const ANSWER_LABEL: &[u8] = b"atris rendezvous answer ";
+
+/// An error from the rendezvous exchange itself, rather than from connecting
+#[derive(Debug)]
+pub enum RendezvousError {
+    /// The server rejected the offer's signature, so the token is wrong
+    Unauthorized,
+    /// The server already answered another offer
+    AlreadyAnswered,
+    /// The answer's signature is wrong, so it did not come from whoever has the token
+    ForgedAnswer,
+    /// The server failed some other way
+    Status(u16, String),
+}
+impl Display for RendezvousError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RendezvousError::Unauthorized => write!(f, "The rendezvous token is wrong"),
+            RendezvousError::AlreadyAnswered => write!(f, "The rendezvous already answered someone else"),
+            RendezvousError::ForgedAnswer => write!(f, "The answer did not come from whoever has the token"),
+            RendezvousError::Status(status, reason) => write!(f, "The rendezvous failed ({status}): {reason}"),
+        }
+    }
+}
+impl std::error::Error for RendezvousError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// An offer posted to a [`RendezvousServer`], whose poster waits for the answer
+pub struct PendingOffer {
+    offer: String,
+    reply: oneshot::Sender<std::result::Result<String, String>>,
+}
+impl PendingOffer {
+    pub fn offer(&self) -> &str {
+        &self.offer
+    }
+
+    /// Send the answer back to whoever posted the offer, which closes the rendezvous
+    pub fn answer(self, answer: String) {
+        let _ = self.reply.send(Ok(answer));
+    }
+
+    /// Tell whoever posted the offer that it could not be answered, leaving the rendezvous open
+    pub fn fail(self, reason: impl Display) {
+        let _ = self.reply.send(Err(reason.to_string()));
+    }
+}
+
+/// What the request handlers share, in place of a global
+struct Rendezvous {
+    token: RendezvousToken,
+    offers: mpsc::Sender<PendingOffer>,
+    answered: AtomicBool,
+}
+
+fn respond(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle(rendezvous: Arc<Rendezvous>, request: Request<Body>) -> std::result::Result<Response<Body>, Infallible> {
+    if request.uri().path() != RENDEZVOUS_PATH {
+        return Ok(respond(StatusCode::NOT_FOUND, "Not found"));
+    }
+    if request.method() != Method::POST {
+        return Ok(respond(StatusCode::METHOD_NOT_ALLOWED, "Offers are POSTed"));
+    }
+    if rendezvous.answered.load(Ordering::SeqCst) {
+        return Ok(respond(StatusCode::GONE, "Already answered"));
+    }
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|signature| signature.to_str().ok())
+        .map(str::to_owned);
+    if request.body().size_hint().upper().unwrap_or(u64::MAX) > MAX_OFFER_SIZE {
+        return Ok(respond(StatusCode::PAYLOAD_TOO_LARGE, "The offer is too large"));
+    }
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return Ok(respond(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+    match signature {
+        Some(signature) if rendezvous.token.verify(OFFER_LABEL, &body, &signature) => {}
+        _ => return Ok(respond(StatusCode::UNAUTHORIZED, "Bad signature")),
+    }
+    let Ok(offer) = String::from_utf8(body.to_vec()) else {
+        return Ok(respond(StatusCode::BAD_REQUEST, "The offer is not UTF-8"));
+    };
+
+    let (reply, answer) = oneshot::channel();
+    if rendezvous.offers.send(PendingOffer { offer, reply }).await.is_err() {
+        return Ok(respond(StatusCode::GONE, "The rendezvous is closed"));
+    }
+    match answer.await {
+        Ok(Ok(answer)) => {
+            rendezvous.answered.store(true, Ordering::SeqCst);
+            let signature = rendezvous.token.sign(ANSWER_LABEL, answer.as_bytes());
+            let mut response = respond(StatusCode::OK, answer);
+            if let Ok(signature) = signature.parse() {
+                response.headers_mut().insert(SIGNATURE_HEADER, signature);
+            }
+            Ok(response)
+        }
+        Ok(Err(reason)) => Ok(respond(StatusCode::INTERNAL_SERVER_ERROR, reason)),
+        Err(_) => Ok(respond(StatusCode::GONE, "The rendezvous is closed")),
+    }
+}
+
+/// A temporary HTTP endpoint which takes a signed offer and replies with the answer, for signalling without the
+/// server. It stops serving once it is dropped, and refuses any offer after the first one it answers.
+/// ```no_run
+/// use atris_client_lib::comms::responder::AtrisResponder;
+/// use atris_client_lib::comms::signal::RendezvousServer;
+/// # async fn serve(responder: AtrisResponder) -> anyhow::Result<()> {
+/// let mut server = RendezvousServer::bind(([0, 0, 0, 0], 8080).into()).await?;
+/// println!("Token: {}", server.token());
+/// let offer = server.next_offer().await.expect("open rendezvous");
+/// let (answer, channels) = responder.into_channels_with(offer.offer()).await?;
+/// offer.answer(answer);
+/// let room_key = server.token().room_key();
+/// # Ok(())
+/// # }
+/// ```
+pub struct RendezvousServer {
+    address: SocketAddr,
+    token: RendezvousToken,
+    offers: mpsc::Receiver<PendingOffer>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+impl RendezvousServer {
+    /// Serve on `address` with a new token
+    pub async fn bind(address: SocketAddr) -> Result<Self> {
+        let token = RendezvousToken::generate();
+        let (offers, offer_receiver) = mpsc::channel(1);
+        let rendezvous = Arc::new(Rendezvous {
+            token: token.clone(),
+            offers,
+            answered: AtomicBool::new(false),
+        });
+        let service = make_service_fn(move |_| {
+            let rendezvous = Arc::clone(&rendezvous);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(Arc::clone(&rendezvous), request)))
+            }
+        });
+        let server = Server::try_bind(&address)?.serve(service);
+        let address = server.local_addr();
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = stopped.await;
+        }));
+        Ok(Self {
+            address,
+            token,
+            offers: offer_receiver,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// The address the server is listening on, whose port was picked by the system if it was 0
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The token to give to the other user
+    pub fn token(&self) -> &RendezvousToken {
+        &self.token
+    }
+
+    /// Wait for the next validly signed offer
+    pub async fn next_offer(&mut self) -> Option<PendingOffer> {
+        self.offers.recv().await
+    }
+}
+impl Drop for RendezvousServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Post a signed `offer` to the [`RendezvousServer`] at `address`, returning its answer once its signature is checked
+pub async fn post_offer(address: &str, token: &RendezvousToken, offer: &str) -> Result<String> {
+    let url = match address.starts_with("http://") || address.starts_with("https://") {
+        true => format!("{}{RENDEZVOUS_PATH}", address.trim_end_matches('/')),
+        false => format!("http://{address}{RENDEZVOUS_PATH}"),
+    };
+    let response = reqwest::Client::new()
+        .post(url)
+        .header(SIGNATURE_HEADER, token.sign(OFFER_LABEL, offer.as_bytes()))
+        .body(offer.to_owned())
+        .send()
+        .await?;
+    let status = response.status();
+    let signature = response
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|signature| signature.to_str().ok())
+        .map(str::to_owned);
+    let body = response.text().await?;
+    match status.as_u16() {
+        200 => {}
+        401 => return Err(RendezvousError::Unauthorized.into()),
+        410 => return Err(RendezvousError::AlreadyAnswered.into()),
+        status => return Err(RendezvousError::Status(status, body).into()),
+    }
+    match signature {
+        Some(signature) if token.verify(ANSWER_LABEL, body.as_bytes(), &signature) => Ok(body),
+        _ => Err(RendezvousError::ForgedAnswer.into()),
+    }
+}
 
 pub fn read_in_line() -> Result<String> {
     let mut line = String::new();
-    let num_read = std::io::stdin().read_line(&mut line)?;
+    let _num_read = std::io::stdin().read_line(&mut line)?;
     Ok(line.trim().to_string())
 }
 
@@ -83,9 +322,26 @@ pub fn must_read_stdin() -> Result<String> {
     println!();
     Ok(line)
 }
+/// The longest line [`print_in_chunks`] prints, which is under the 1024 bytes many terminals accept as input
+pub const PRINTED_CHUNK_SIZE: usize = 1023;
+
+/// Print `s` in numbered chunks short enough to paste into a terminal, however long it is
 pub fn print_in_chunks(s: &str) {
-    println!("[0]\n{}", &s[0..1023]);
-    println!("[1]\n{}", &s[1023..]);
+    let mut rest = s;
+    let mut index = 0;
+    loop {
+        let mut end = rest.len().min(PRINTED_CHUNK_SIZE);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, remainder) = rest.split_at(end);
+        println!("[{index}]\n{chunk}");
+        if remainder.is_empty() {
+            break;
+        }
+        rest = remainder;
+        index += 1;
+    }
 }
 
 // Allows compressing offer/answer to bypass terminal input limits.
@@ -117,6 +373,231 @@ pub fn decode(s: &str) -> Result<String> {
 pub fn flush_out<T:Display>(t:&T){
     use std::io::Write;
     let mut out = std::io::stdout();
-    writeln!(out,"{}",t);
-    out.flush();
-}
\ No newline at end of file
+    let _ = writeln!(out,"{}",t);
+    let _ = out.flush();
+}
+/// The first character of every compact description, which base64 JSON never starts with
+const COMPACT_PREFIX: char = 'A';
+/// The version of the compact format, bumped whenever [`CompactDescription`] changes
+const COMPACT_VERSION: u8 = 2;
+/// The SCTP port a description which names none uses (RFC 8841 section 5)
+const DEFAULT_SCTP_PORT: u16 = 5000;
+/// The most a compact description can inflate to, which is far more than any real description needs
+const MAX_COMPACT_SIZE: u64 = 64 * 1024;
+
+/// The DTLS role in the `a=setup` attribute
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum Setup {
+    ActPass,
+    Active,
+    Passive,
+}
+impl Setup {
+    fn parse(role: &str) -> Result<Self> {
+        match role {
+            "actpass" => Ok(Setup::ActPass),
+            "active" => Ok(Setup::Active),
+            "passive" => Ok(Setup::Passive),
+            other => Err(anyhow!("Unknown DTLS setup role '{other}'")),
+        }
+    }
+}
+impl Display for Setup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Setup::ActPass => write!(f, "actpass"),
+            Setup::Active => write!(f, "active"),
+            Setup::Passive => write!(f, "passive"),
+        }
+    }
+}
+
+/// The parts of a data channel description which differ between connections, or which could.
+/// Everything else in the SDP is the same for every Atris connection, and is rebuilt by [`CompactDescription::sdp`].
+#[derive(Debug, Serialize, Deserialize)]
+struct CompactDescription {
+    offer: bool,
+    /// The media id of the data channel section
+    mid: String,
+    sctp_port: u16,
+    /// The largest message the SCTP association takes, if the description limits it
+    max_message_size: Option<u64>,
+    ice_ufrag: String,
+    ice_pwd: String,
+    /// The SHA-256 fingerprint of the DTLS certificate
+    fingerprint: [u8; 32],
+    setup: Setup,
+    /// The candidate attributes, without the `a=` in front, if any were gathered before the description was taken
+    candidates: Vec<String>,
+}
+impl CompactDescription {
+    fn from_sdp(description: &RTCSessionDescription) -> Result<Self> {
+        let offer = match description.sdp_type {
+            RTCSdpType::Offer => true,
+            RTCSdpType::Answer => false,
+            other => return Err(anyhow!("Only offers and answers can be encoded, not {other}")),
+        };
+        let mut media_sections = 0;
+        let mut mid = None;
+        let mut sctp_port = None;
+        let mut max_message_size = None;
+        let mut ice_ufrag = None;
+        let mut ice_pwd = None;
+        let mut fingerprint = None;
+        let mut setup = None;
+        let mut candidates = Vec::new();
+        for line in description.sdp.lines() {
+            let line = line.trim_end();
+            // Only a lone data channel section can be rebuilt
+            if let Some(media) = line.strip_prefix("m=") {
+                if !media.starts_with("application ") || !media.ends_with(" UDP/DTLS/SCTP webrtc-datachannel") {
+                    return Err(anyhow!("Only data channel descriptions can be encoded, not m={media}"));
+                }
+                media_sections += 1;
+                continue;
+            }
+            let Some(attribute) = line.strip_prefix("a=") else {
+                continue;
+            };
+            if let Some(id) = attribute.strip_prefix("mid:") {
+                mid = Some(id.to_owned());
+            } else if let Some(port) = attribute.strip_prefix("sctp-port:") {
+                sctp_port = Some(port.parse()?);
+            } else if let Some(size) = attribute.strip_prefix("max-message-size:") {
+                max_message_size = Some(size.parse()?);
+            } else if let Some(ufrag) = attribute.strip_prefix("ice-ufrag:") {
+                ice_ufrag = Some(ufrag.to_owned());
+            } else if let Some(pwd) = attribute.strip_prefix("ice-pwd:") {
+                ice_pwd = Some(pwd.to_owned());
+            } else if let Some(hex) = attribute.strip_prefix("fingerprint:sha-256 ") {
+                fingerprint = Some(parse_fingerprint(hex)?);
+            } else if let Some(role) = attribute.strip_prefix("setup:") {
+                setup = Some(Setup::parse(role)?);
+            } else if attribute.starts_with("candidate:") {
+                candidates.push(attribute.to_owned());
+            }
+        }
+        if media_sections != 1 {
+            return Err(anyhow!(
+                "Only descriptions with one data channel section can be encoded, not {media_sections}"
+            ));
+        }
+        Ok(Self {
+            offer,
+            mid: mid.ok_or_else(|| anyhow!("The description has no media id"))?,
+            sctp_port: sctp_port.unwrap_or(DEFAULT_SCTP_PORT),
+            max_message_size,
+            ice_ufrag: ice_ufrag.ok_or_else(|| anyhow!("The description has no ICE username fragment"))?,
+            ice_pwd: ice_pwd.ok_or_else(|| anyhow!("The description has no ICE password"))?,
+            fingerprint: fingerprint.ok_or_else(|| anyhow!("The description has no SHA-256 fingerprint"))?,
+            setup: setup.ok_or_else(|| anyhow!("The description has no DTLS setup role"))?,
+            candidates,
+        })
+    }
+
+    /// The full SDP of a data channel description with these parts
+    fn sdp(&self) -> String {
+        let fingerprint = self
+            .fingerprint
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(":");
+        let mut sdp = format!(
+            "v=0\r\n\
+             o=- 0 0 IN IP4 0.0.0.0\r\n\
+             s=-\r\n\
+             t=0 0\r\n\
+             a=fingerprint:sha-256 {fingerprint}\r\n\
+             a=group:BUNDLE {mid}\r\n\
+             m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n\
+             c=IN IP4 0.0.0.0\r\n\
+             a=setup:{}\r\n\
+             a=mid:{mid}\r\n\
+             a=sendrecv\r\n\
+             a=sctp-port:{}\r\n",
+            self.setup,
+            self.sctp_port,
+            mid = self.mid,
+        );
+        if let Some(max_message_size) = self.max_message_size {
+            sdp.push_str(&format!("a=max-message-size:{max_message_size}\r\n"));
+        }
+        sdp.push_str(&format!(
+            "a=ice-ufrag:{}\r\na=ice-pwd:{}\r\n",
+            self.ice_ufrag, self.ice_pwd
+        ));
+        for candidate in &self.candidates {
+            sdp.push_str(&format!("a={candidate}\r\n"));
+        }
+        if !self.candidates.is_empty() {
+            sdp.push_str("a=end-of-candidates\r\n");
+        }
+        sdp
+    }
+}
+
+fn parse_fingerprint(hex: &str) -> Result<[u8; 32]> {
+    let bytes = hex
+        .trim()
+        .split(':')
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("A SHA-256 fingerprint is 32 bytes long"))
+}
+
+/// Encode a description compactly enough to be copied and pasted as one line.
+///
+/// Only the parts of the SDP which differ between connections are kept. They are deflated, followed by a CRC-32 of
+/// themselves so that a description which was copied wrong is caught, and encoded in base32, which survives being
+/// read out or typed in any case.
+pub fn encode_description(description: &RTCSessionDescription) -> Result<String> {
+    let compact = CompactDescription::from_sdp(description)?;
+    let mut encoder = DeflateEncoder::new(vec![COMPACT_VERSION], flate2::Compression::best());
+    // Variable length integers keep the lengths of the short strings to a byte each
+    encoder.write_all(&bincode::DefaultOptions::new().serialize(&compact)?)?;
+    let mut bytes = encoder.finish()?;
+    let checksum = crc32fast::hash(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    Ok(format!("{COMPACT_PREFIX}{}", BASE32_NOPAD.encode(&bytes)))
+}
+
+/// Whether `s` was made by [`encode_description`] rather than [`encode`]
+pub fn is_compact(s: &str) -> bool {
+    s.trim_start().starts_with(|c: char| c.eq_ignore_ascii_case(&COMPACT_PREFIX))
+}
+
+/// Decode a description made by [`encode_description`], or the base64 JSON made by [`encode`].
+/// Whitespace is ignored, so a compact description can be pasted in pieces.
+pub fn decode_description(s: &str) -> Result<RTCSessionDescription> {
+    let s: String = s.split_whitespace().collect();
+    let Some(compact) = s.strip_prefix(|c: char| c.eq_ignore_ascii_case(&COMPACT_PREFIX)) else {
+        return Ok(serde_json::from_str(&decode(&s)?)?);
+    };
+    let bytes = BASE32_NOPAD
+        .decode(compact.to_ascii_uppercase().as_bytes())
+        .map_err(|e| anyhow!("The description is not valid base32: {e}"))?;
+    if bytes.len() < 5 {
+        return Err(anyhow!("The description is too short"));
+    }
+    let (bytes, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(bytes).to_be_bytes() != checksum {
+        return Err(anyhow!("The description's checksum does not match, it was probably copied wrong"));
+    }
+    let (version, deflated) = bytes.split_first().expect("checked above");
+    if *version != COMPACT_VERSION {
+        return Err(anyhow!("The description is from an unsupported version ({version})"));
+    }
+    let mut serialized = Vec::new();
+    DeflateDecoder::new(deflated)
+        .take(MAX_COMPACT_SIZE)
+        .read_to_end(&mut serialized)?;
+    let compact: CompactDescription = bincode::DefaultOptions::new().deserialize(&serialized)?;
+    let description = match compact.offer {
+        true => RTCSessionDescription::offer(compact.sdp())?,
+        false => RTCSessionDescription::answer(compact.sdp())?,
+    };
+    Ok(description)
+}
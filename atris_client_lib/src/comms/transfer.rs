@@ -0,0 +1,621 @@
+//! Sending files over their own data channel, alongside the chat.
+//!
+//! The sender offers a file with its size and SHA-256 hash, and the receiver accepts it into a destination of
+//! its choice. The file is then streamed from disk in chunks, a window of which may be unacknowledged at a time.
+//! Chunks are written to `<destination>.part`, which is checked against the hash and moved into place once
+//! complete. When the connection comes back after dropping, the sender resends everything after the last
+//! acknowledged chunk, and a file offered again later resumes from whatever is in its `.part` file.
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{broadcast, oneshot, watch};
+
+use super::channels::{ChannelOptions, DEFAULT_CHANNEL_LABEL};
+use super::{AtrisChannel, ChannelState, EVENT_CAPACITY};
+
+/// The label of the channel files are transferred on
+pub const TRANSFER_CHANNEL_LABEL: &str = "files";
+/// How many bytes of the file each chunk carries
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// How many chunks may be sent ahead of the last acknowledged one
+pub const WINDOW: u64 = 16;
+
+/// The channels an initiator opens for chatting and transferring files at the same time
+pub fn chat_and_file_channels() -> [(&'static str, ChannelOptions); 2] {
+    [
+        (DEFAULT_CHANNEL_LABEL, ChannelOptions::reliable()),
+        (TRANSFER_CHANNEL_LABEL, ChannelOptions::reliable()),
+    ]
+}
+
+/// Identifies a transfer on both ends
+pub type TransferId = u64;
+
+/// The name to save a file offered as `name` under, which is only its last path component so an offer cannot name a
+/// file outside the directory it is saved in. `None` if that leaves nothing to save it under.
+pub fn local_file_name(name: &str) -> Option<&str> {
+    let name = Path::new(name).file_name()?.to_str()?;
+    (!name.is_empty() && name != "..").then_some(name)
+}
+
+/// What the two ends of a transfer send each other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TransferMessage {
+    /// The sender offers a file
+    Offer {
+        id: TransferId,
+        name: String,
+        size: u64,
+        sha256: [u8; 32],
+    },
+    /// The receiver accepts the file, and already has its first `from_chunk` chunks
+    Accept { id: TransferId, from_chunk: u64 },
+    /// The receiver does not want the file
+    Reject { id: TransferId },
+    /// A piece of the file
+    Chunk {
+        id: TransferId,
+        index: u64,
+        data: Vec<u8>,
+    },
+    /// The receiver has written the first `chunks` chunks
+    Ack { id: TransferId, chunks: u64 },
+    /// The receiver has all the chunks, and checked them against the hash
+    Verified { id: TransferId, ok: bool },
+    /// Either end gave up on the transfer
+    Cancel { id: TransferId },
+}
+
+/// Something that happened to a transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferEvent {
+    /// The other end offers a file, which can be accepted with [`FileTransfers::accept`]
+    Offered {
+        id: TransferId,
+        name: String,
+        size: u64,
+    },
+    /// The other end accepted a file this end offered
+    Accepted { id: TransferId },
+    /// The other end rejected a file this end offered
+    Rejected { id: TransferId },
+    /// `bytes` of the file have arrived at the receiver
+    Progress {
+        id: TransferId,
+        bytes: u64,
+        size: u64,
+    },
+    /// The file arrived intact, at `path` if this end received it
+    Completed {
+        id: TransferId,
+        path: Option<PathBuf>,
+    },
+    /// The transfer could not be finished
+    Failed { id: TransferId, reason: String },
+    /// Either end cancelled the transfer
+    Cancelled { id: TransferId },
+}
+
+enum Command {
+    Send {
+        id: TransferId,
+        name: String,
+        file: File,
+        size: u64,
+        sha256: [u8; 32],
+    },
+    Accept {
+        id: TransferId,
+        destination: PathBuf,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    Reject { id: TransferId },
+    Cancel { id: TransferId },
+}
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Command")
+    }
+}
+
+/// A handle to the transfers running over a channel labelled [`TRANSFER_CHANNEL_LABEL`]
+#[derive(Debug, Clone)]
+pub struct FileTransfers {
+    commands: UnboundedSender<Command>,
+    events: broadcast::Sender<TransferEvent>,
+}
+impl FileTransfers {
+    /// Start transferring files over the channel, until it closes
+    pub fn spawn(channel: AtrisChannel<TransferMessage>) -> Self {
+        let (commands, command_receiver) = unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let state = channel.state_receiver();
+        let transfers = Transfers {
+            channel,
+            events: events.clone(),
+            outgoing: HashMap::new(),
+            incoming: HashMap::new(),
+        };
+        tokio::spawn(transfers.run(command_receiver, state));
+        Self { commands, events }
+    }
+
+    /// Subscribe to the events of every transfer from now on
+    pub fn events(&self) -> broadcast::Receiver<TransferEvent> {
+        self.events.subscribe()
+    }
+
+    /// Offer a file to the other end, which it will only start receiving once accepted
+    pub async fn send_file(&self, path: impl AsRef<Path>) -> Result<TransferId> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("{} has no file name", path.display()))?
+            .to_owned();
+        let mut file = File::open(path).await?;
+        let sha256 = hash(&mut file).await?;
+        let size = file.metadata().await?.len();
+
+        let id = rand::random();
+        self.command(Command::Send {
+            id,
+            name,
+            file,
+            size,
+            sha256,
+        })?;
+        Ok(id)
+    }
+
+    /// Accept a file the other end offered, saving it at `destination`.
+    /// If a `.part` file is left over from an earlier attempt, the transfer picks up where it stopped.
+    pub async fn accept(&self, id: TransferId, destination: impl Into<PathBuf>) -> Result<()> {
+        let (reply, result) = oneshot::channel();
+        self.command(Command::Accept {
+            id,
+            destination: destination.into(),
+            reply,
+        })?;
+        result.await?
+    }
+
+    /// Reject a file the other end offered
+    pub fn reject(&self, id: TransferId) -> Result<()> {
+        self.command(Command::Reject { id })
+    }
+
+    /// Stop a transfer in either direction
+    pub fn cancel(&self, id: TransferId) -> Result<()> {
+        self.command(Command::Cancel { id })
+    }
+
+    fn command(&self, command: Command) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow!("The transfer channel is closed"))
+    }
+}
+
+/// A file this end is sending
+struct Outgoing {
+    file: File,
+    size: u64,
+    /// Whether the other end accepted the file, and chunks can be sent
+    accepted: bool,
+    /// How many chunks the other end has written
+    acked: u64,
+    /// The next chunk to send
+    next: u64,
+}
+
+/// A file this end is receiving
+struct Incoming {
+    size: u64,
+    sha256: [u8; 32],
+    /// Set once the file is accepted
+    receiving: Option<Receiving>,
+}
+struct Receiving {
+    file: File,
+    destination: PathBuf,
+    part: PathBuf,
+    /// How many chunks have been written to the part file
+    written: u64,
+}
+
+/// The task running the transfers, see [`FileTransfers::spawn`]
+struct Transfers {
+    channel: AtrisChannel<TransferMessage>,
+    events: broadcast::Sender<TransferEvent>,
+    outgoing: HashMap<TransferId, Outgoing>,
+    incoming: HashMap<TransferId, Incoming>,
+}
+
+/// What woke the transfer task up
+enum Wake {
+    Command(Option<Command>),
+    Message(Option<atris_common::Result<TransferMessage>>),
+    State(bool),
+}
+
+impl Transfers {
+    async fn run(mut self, mut commands: UnboundedReceiver<Command>, mut state: watch::Receiver<ChannelState>) {
+        loop {
+            if self.send_chunks().await.is_err() {
+                break;
+            }
+            let wake = tokio::select! {
+                command = commands.recv() => Wake::Command(command),
+                message = self.channel.receive() => Wake::Message(message),
+                changed = state.changed() => Wake::State(changed.is_ok()),
+            };
+            let handled = match wake {
+                Wake::Command(Some(command)) => self.command(command).await,
+                Wake::Message(Some(Ok(message))) => self.message(message).await,
+                // Messages which cannot be decrypted are not ours to handle
+                Wake::Message(Some(Err(_))) => Ok(()),
+                Wake::State(true) => {
+                    // Anything after the last acknowledged chunk may not have made it through the outage
+                    if *state.borrow() == ChannelState::Reconnected {
+                        for outgoing in self.outgoing.values_mut() {
+                            outgoing.next = outgoing.acked;
+                        }
+                    }
+                    Ok(())
+                }
+                Wake::Command(None) | Wake::Message(None) | Wake::State(false) => break,
+            };
+            if handled.is_err() {
+                break;
+            }
+        }
+
+        let ids = self.outgoing.keys().chain(self.incoming.keys()).copied().collect::<Vec<_>>();
+        for id in ids {
+            self.emit(TransferEvent::Failed {
+                id,
+                reason: "The connection closed".into(),
+            });
+        }
+    }
+
+    fn emit(&self, event: TransferEvent) {
+        let _ = self.events.send(event);
+    }
+
+    async fn send(&mut self, message: TransferMessage) -> Result<()> {
+        self.channel
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|_| anyhow!("The transfer channel is closed"))
+    }
+
+    /// Send as many chunks as the windows of the accepted files allow
+    async fn send_chunks(&mut self) -> Result<()> {
+        let mut chunks = Vec::new();
+        let mut failed = Vec::new();
+        for (id, outgoing) in &mut self.outgoing {
+            let count = chunk_count(outgoing.size);
+            while outgoing.accepted && outgoing.next < count && outgoing.next < outgoing.acked + WINDOW {
+                match read_chunk(&mut outgoing.file, outgoing.size, outgoing.next).await {
+                    Ok(data) => chunks.push(TransferMessage::Chunk {
+                        id: *id,
+                        index: outgoing.next,
+                        data,
+                    }),
+                    Err(e) => {
+                        failed.push((*id, e));
+                        break;
+                    }
+                }
+                outgoing.next += 1;
+            }
+        }
+        for (id, e) in failed {
+            self.outgoing.remove(&id);
+            self.send(TransferMessage::Cancel { id }).await?;
+            self.emit(TransferEvent::Failed {
+                id,
+                reason: format!("Could not read the file: {e}"),
+            });
+        }
+        for chunk in chunks {
+            self.send(chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn command(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Send {
+                id,
+                name,
+                file,
+                size,
+                sha256,
+            } => {
+                self.outgoing.insert(
+                    id,
+                    Outgoing {
+                        file,
+                        size,
+                        accepted: false,
+                        acked: 0,
+                        next: 0,
+                    },
+                );
+                self.send(TransferMessage::Offer {
+                    id,
+                    name,
+                    size,
+                    sha256,
+                })
+                .await?;
+            }
+            Command::Accept {
+                id,
+                destination,
+                reply,
+            } => {
+                let result = self.start_receiving(id, destination).await;
+                let from_chunk = result.as_ref().ok().copied();
+                let _ = reply.send(result.map(|_| ()));
+                if let Some(from_chunk) = from_chunk {
+                    self.send(TransferMessage::Accept { id, from_chunk }).await?;
+                    self.received(id).await?;
+                }
+            }
+            Command::Reject { id } => {
+                if self.incoming.remove(&id).is_some() {
+                    self.send(TransferMessage::Reject { id }).await?;
+                }
+            }
+            Command::Cancel { id } => {
+                if self.forget(id).await {
+                    self.send(TransferMessage::Cancel { id }).await?;
+                    self.emit(TransferEvent::Cancelled { id });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the part file of an offered transfer, returning how many chunks it already has
+    async fn start_receiving(&mut self, id: TransferId, destination: PathBuf) -> Result<u64> {
+        let incoming = self
+            .incoming
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("No file was offered as {id}"))?;
+        if incoming.receiving.is_some() {
+            return Err(anyhow!("{id} was already accepted"));
+        }
+
+        let part = part_path(&destination);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&part)
+            .await?;
+        // Only whole chunks are kept, anything after them is written again
+        let written = (file.metadata().await?.len() / CHUNK_SIZE as u64).min(chunk_count(incoming.size));
+        file.set_len(written * CHUNK_SIZE as u64).await?;
+        file.seek(SeekFrom::Start(written * CHUNK_SIZE as u64)).await?;
+
+        incoming.receiving = Some(Receiving {
+            file,
+            destination,
+            part,
+            written,
+        });
+        Ok(written)
+    }
+
+    async fn message(&mut self, message: TransferMessage) -> Result<()> {
+        match message {
+            TransferMessage::Offer {
+                id,
+                name,
+                size,
+                sha256,
+            } => {
+                self.incoming.insert(
+                    id,
+                    Incoming {
+                        size,
+                        sha256,
+                        receiving: None,
+                    },
+                );
+                self.emit(TransferEvent::Offered { id, name, size });
+            }
+            TransferMessage::Accept { id, from_chunk } => {
+                if let Some(outgoing) = self.outgoing.get_mut(&id) {
+                    let from_chunk = from_chunk.min(chunk_count(outgoing.size));
+                    outgoing.accepted = true;
+                    outgoing.acked = from_chunk;
+                    outgoing.next = from_chunk;
+                    self.emit(TransferEvent::Accepted { id });
+                }
+            }
+            TransferMessage::Reject { id } => {
+                if self.outgoing.remove(&id).is_some() {
+                    self.emit(TransferEvent::Rejected { id });
+                }
+            }
+            TransferMessage::Chunk { id, index, data } => {
+                let Some(Incoming {
+                    size,
+                    receiving: Some(receiving),
+                    ..
+                }) = self.incoming.get_mut(&id)
+                else {
+                    return Ok(());
+                };
+                // Chunks before the next one are resent after an outage, and were already written
+                if index == receiving.written {
+                    // Checked before writing, so the file never grows past the size that was offered
+                    let written = match chunk_length(*size, index) {
+                        Some(length) if data.len() as u64 == length => receiving
+                            .file
+                            .write_all(&data)
+                            .await
+                            .map_err(|e| format!("Could not write the file: {e}")),
+                        _ => Err(format!("Piece {index} of the file was not the size that was offered")),
+                    };
+                    if let Err(reason) = written {
+                        self.forget(id).await;
+                        self.send(TransferMessage::Cancel { id }).await?;
+                        self.emit(TransferEvent::Failed { id, reason });
+                        return Ok(());
+                    }
+                    receiving.written += 1;
+                }
+                self.received(id).await?;
+            }
+            TransferMessage::Ack { id, chunks } => {
+                if let Some(outgoing) = self.outgoing.get_mut(&id) {
+                    outgoing.acked = outgoing.acked.max(chunks);
+                    let bytes = (outgoing.acked * CHUNK_SIZE as u64).min(outgoing.size);
+                    let size = outgoing.size;
+                    self.emit(TransferEvent::Progress { id, bytes, size });
+                }
+            }
+            TransferMessage::Verified { id, ok } => {
+                if self.outgoing.remove(&id).is_some() {
+                    self.emit(match ok {
+                        true => TransferEvent::Completed { id, path: None },
+                        false => TransferEvent::Failed {
+                            id,
+                            reason: "The file did not arrive intact".into(),
+                        },
+                    });
+                }
+            }
+            TransferMessage::Cancel { id } => {
+                if self.forget(id).await {
+                    self.emit(TransferEvent::Cancelled { id });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Acknowledge what has been written of an incoming file, and finish it once it is complete
+    async fn received(&mut self, id: TransferId) -> Result<()> {
+        let Some(incoming) = self.incoming.get(&id) else {
+            return Ok(());
+        };
+        let Some(receiving) = &incoming.receiving else {
+            return Ok(());
+        };
+        let (written, size) = (receiving.written, incoming.size);
+        self.send(TransferMessage::Ack { id, chunks: written }).await?;
+        self.emit(TransferEvent::Progress {
+            id,
+            bytes: (written * CHUNK_SIZE as u64).min(size),
+            size,
+        });
+        if written < chunk_count(size) {
+            return Ok(());
+        }
+
+        let Some(Incoming {
+            sha256,
+            receiving: Some(mut receiving),
+            ..
+        }) = self.incoming.remove(&id)
+        else {
+            return Ok(());
+        };
+        let verified = async {
+            receiving.file.flush().await?;
+            receiving.file.seek(SeekFrom::Start(0)).await?;
+            Result::<_>::Ok(hash(&mut receiving.file).await? == sha256)
+        }
+        .await;
+        drop(receiving.file);
+        let ok = match verified {
+            Ok(true) => tokio::fs::rename(&receiving.part, &receiving.destination).await.is_ok(),
+            _ => false,
+        };
+        if ok {
+            self.emit(TransferEvent::Completed {
+                id,
+                path: Some(receiving.destination),
+            });
+        } else {
+            let _ = tokio::fs::remove_file(&receiving.part).await;
+            self.emit(TransferEvent::Failed {
+                id,
+                reason: "The file did not arrive intact".into(),
+            });
+        }
+        self.send(TransferMessage::Verified { id, ok }).await
+    }
+
+    /// Drop a transfer in either direction, along with anything it received. Returns whether it existed.
+    async fn forget(&mut self, id: TransferId) -> bool {
+        if self.outgoing.remove(&id).is_some() {
+            return true;
+        }
+        let Some(incoming) = self.incoming.remove(&id) else {
+            return false;
+        };
+        if let Some(receiving) = incoming.receiving {
+            drop(receiving.file);
+            let _ = tokio::fs::remove_file(&receiving.part).await;
+        }
+        true
+    }
+}
+
+/// How many chunks a file of `size` bytes is sent in
+fn chunk_count(size: u64) -> u64 {
+    size.div_ceil(CHUNK_SIZE as u64)
+}
+
+/// How many bytes chunk `index` of a file of `size` bytes carries, which is less than [`CHUNK_SIZE`] for the last one.
+/// `None` if the file has no such chunk.
+fn chunk_length(size: u64, index: u64) -> Option<u64> {
+    (index < chunk_count(size)).then(|| (size - index * CHUNK_SIZE as u64).min(CHUNK_SIZE as u64))
+}
+
+/// Where the chunks of a file are written until it is complete
+fn part_path(destination: &Path) -> PathBuf {
+    let mut part = OsString::from(destination.as_os_str());
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+async fn read_chunk(file: &mut File, size: u64, index: u64) -> std::io::Result<Vec<u8>> {
+    let offset = index * CHUNK_SIZE as u64;
+    let mut data = vec![0; (size - offset).min(CHUNK_SIZE as u64) as usize];
+    file.seek(SeekFrom::Start(offset)).await?;
+    file.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// The SHA-256 of the rest of the file
+async fn hash(file: &mut File) -> std::io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(hasher.finalize().into());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
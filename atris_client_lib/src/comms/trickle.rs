@@ -0,0 +1,363 @@
+use std::fmt::{Debug, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use atris_common::{
+    signal_room::{SignalMessage, SignalRole},
+    CipherKey,
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
+use webrtc::ice_transport::ice_gathering_state::RTCIceGatheringState;
+use webrtc::peer_connection::offer_answer_options::RTCOfferOptions;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+
+use super::{ChannelState, CloseReason, ConnectionEvent};
+use crate::AtrisAuthClient;
+
+/// How long to wait between two exchanges with the signaling server
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long to keep exchanging candidates before giving up on the connection
+pub const TRICKLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a dropped connection gets to come back by itself before ICE is restarted
+pub const RESTART_GRACE: Duration = Duration::from_secs(3);
+/// How long to keep exchanging candidates for a single restart before trying again
+pub const RESTART_TIMEOUT: Duration = Duration::from_secs(20);
+/// How many times to restart ICE before the connection is considered lost
+pub const MAX_RESTART_ATTEMPTS: u32 = 3;
+
+/// Something that can pass signaling messages to the other end of a connection
+#[async_trait::async_trait]
+pub trait Signaller {
+    /// Publish the outgoing messages, and return any messages from the other end which have not been returned before
+    async fn exchange(&mut self, outgoing: Vec<SignalMessage>) -> Result<Vec<SignalMessage>>;
+}
+
+/// A [`Signaller`] which passes messages through a room's queues on the Atris authentication server
+pub struct RoomSignaller<C> {
+    client: C,
+    session_id: CipherKey,
+    room_id: u16,
+    role: SignalRole,
+    /// How many messages have been received from the other end so far
+    received: u32,
+    /// The user at the other end, as the server last said
+    sender: Option<String>,
+}
+impl<C: AtrisAuthClient> RoomSignaller<C> {
+    pub fn new(client: C, session_id: CipherKey, room_id: u16, role: SignalRole) -> Self {
+        Self {
+            client,
+            session_id,
+            room_id,
+            role,
+            received: 0,
+            sender: None,
+        }
+    }
+
+    /// The user who published the messages received from the other end, once the server has said.
+    /// The server only lets the room's creator signal as the responder, and the user it was created for as the
+    /// initiator.
+    pub fn sender(&self) -> Option<&str> {
+        self.sender.as_deref()
+    }
+}
+#[async_trait::async_trait]
+impl<C> Signaller for RoomSignaller<C>
+where
+    C: AtrisAuthClient + Send + Sync,
+    C::Error: Debug + Display + Send + Sync + 'static,
+{
+    async fn exchange(&mut self, outgoing: Vec<SignalMessage>) -> Result<Vec<SignalMessage>> {
+        let response = self
+            .client
+            .signal_room(
+                self.session_id.clone(),
+                self.room_id,
+                self.role,
+                outgoing,
+                self.received,
+            )
+            .await??;
+        self.received += response.messages.len() as u32;
+        self.sender = response.sender;
+        Ok(response.messages)
+    }
+}
+
+/// A [`Signaller`] which passes messages straight to its partner in the same process, for connecting two local
+/// connections in tests. Only built with the `test-util` feature.
+#[cfg(feature = "test-util")]
+pub struct LocalSignaller {
+    sender: tokio::sync::mpsc::UnboundedSender<SignalMessage>,
+    receiver: UnboundedReceiver<SignalMessage>,
+}
+#[cfg(feature = "test-util")]
+impl LocalSignaller {
+    /// Create two signallers which pass messages to each other
+    pub fn pair() -> (Self, Self) {
+        let (a_sender, a_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (b_sender, b_receiver) = tokio::sync::mpsc::unbounded_channel();
+        (
+            Self {
+                sender: a_sender,
+                receiver: b_receiver,
+            },
+            Self {
+                sender: b_sender,
+                receiver: a_receiver,
+            },
+        )
+    }
+}
+#[cfg(feature = "test-util")]
+#[async_trait::async_trait]
+impl Signaller for LocalSignaller {
+    async fn exchange(&mut self, outgoing: Vec<SignalMessage>) -> Result<Vec<SignalMessage>> {
+        for signal in outgoing {
+            // The partner may have already finished
+            let _ = self.sender.send(signal);
+        }
+        let mut incoming = Vec::new();
+        while let Ok(signal) = self.receiver.try_recv() {
+            incoming.push(signal);
+        }
+        Ok(incoming)
+    }
+}
+
+/// Exchanges ICE candidates with the other end of a connection as they are gathered, rather than waiting for
+/// gathering to finish before the descriptions are exchanged. Once connected, it watches the connection and
+/// restarts ICE through the same signaller when it drops.
+pub struct IceTrickle {
+    pub(super) connection: Arc<RTCPeerConnection>,
+    /// The signals generated by the local connection's candidate gathering
+    pub(super) local_signals: UnboundedReceiver<SignalMessage>,
+    /// Whether this end creates the offers restarting the connection, the other end answers them
+    pub(super) offerer: bool,
+    pub(super) state: Arc<watch::Sender<ChannelState>>,
+    pub(super) restartable: Arc<AtomicBool>,
+    pub(super) closing: Arc<AtomicBool>,
+    pub(super) events: broadcast::Sender<ConnectionEvent>,
+}
+impl IceTrickle {
+    /// Exchange candidates through the signaller until the connection is established, then keep it up by
+    /// restarting ICE whenever it drops, until it is closed or cannot be restarted.
+    /// This is meant to be spawned as soon as the room the connection belongs to is known.
+    pub async fn run<S: Signaller>(mut self, mut signaller: S) -> Result<()> {
+        let deadline = Instant::now() + TRICKLE_TIMEOUT;
+        if let Err(e) = self.exchange_until_connected(&mut signaller, Vec::new(), None, deadline).await {
+            self.give_up().await;
+            return Err(e);
+        }
+        self.restartable.store(true, Ordering::SeqCst);
+
+        let mut state = self.state.subscribe();
+        loop {
+            let current = *state.borrow_and_update();
+            match current {
+                ChannelState::Lost => return Ok(()),
+                ChannelState::Reconnecting => {}
+                _ => {
+                    if state.changed().await.is_err() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            }
+
+            // Give the connection a chance to come back by itself before restarting it
+            tokio::time::sleep(RESTART_GRACE).await;
+            if self.ice_connected() {
+                continue;
+            }
+            if let Err(e) = self.restart(&mut signaller).await {
+                self.give_up().await;
+                return Err(e);
+            }
+        }
+    }
+
+    /// Restart ICE and exchange the new candidates until the connection is back
+    async fn restart<S: Signaller>(&mut self, signaller: &mut S) -> Result<()> {
+        let mut last_error = anyhow!("Connection was not restarted");
+        for attempt in 1..=MAX_RESTART_ATTEMPTS {
+            let deadline = Instant::now() + RESTART_TIMEOUT;
+            // Candidates from the previous generation are of no use to the other end anymore
+            while self.local_signals.try_recv().is_ok() {}
+
+            let result = if self.offerer {
+                match self.restart_offer(deadline).await {
+                    Ok(offer) => {
+                        let expected = Some(RemoteDescription::Answer);
+                        self.exchange_until_connected(signaller, vec![offer], expected, deadline).await
+                    }
+                    Err(e) => Err(e),
+                }
+            } else {
+                let expected = Some(RemoteDescription::Offer);
+                self.exchange_until_connected(signaller, Vec::new(), expected, deadline).await
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if self.connection.connection_state() == RTCPeerConnectionState::Closed {
+                        return Err(e);
+                    }
+                    let _ = self.events.send(ConnectionEvent::Error(format!(
+                        "Restart attempt {attempt} failed: {e}"
+                    )));
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Create and apply an offer restarting ICE, returning the signal passing it to the other end
+    async fn restart_offer(&self, deadline: Instant) -> Result<SignalMessage> {
+        // ICE cannot be restarted while candidates are still being gathered
+        while self.connection.ice_gathering_state() == RTCIceGatheringState::Gathering {
+            if Instant::now() > deadline {
+                return Err(anyhow!("Timed out waiting for candidate gathering to finish"));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        let offer = self
+            .connection
+            .create_offer(Some(RTCOfferOptions {
+                ice_restart: true,
+                ..Default::default()
+            }))
+            .await?;
+        let json = serde_json::to_string(&offer)?;
+        self.connection.set_local_description(offer).await?;
+        Ok(SignalMessage::RestartOffer(json))
+    }
+
+    /// Exchange signals through the signaller until the connection is established, fails, or the deadline passes.
+    /// During a restart, `expected` is the description the other end is yet to send.
+    async fn exchange_until_connected<S: Signaller>(
+        &mut self,
+        signaller: &mut S,
+        mut outgoing: Vec<SignalMessage>,
+        mut expected: Option<RemoteDescription>,
+        deadline: Instant,
+    ) -> Result<()> {
+        // Remote candidates which arrived before the remote description was set
+        let mut pending = Vec::new();
+        let mut local_done = false;
+        let mut remote_done = false;
+
+        loop {
+            if self.ice_connected() {
+                return Ok(());
+            }
+            match self.connection.connection_state() {
+                RTCPeerConnectionState::Closed => {
+                    return Err(anyhow!("Connection closed while exchanging candidates"))
+                }
+                // A restart can bring back a failed connection, the first connection cannot
+                RTCPeerConnectionState::Failed if expected.is_none() => {
+                    return Err(anyhow!("Connection failed while exchanging candidates"))
+                }
+                _ => {}
+            }
+            if Instant::now() > deadline {
+                return Err(anyhow!("Timed out exchanging candidates"));
+            }
+
+            while let Ok(signal) = self.local_signals.try_recv() {
+                local_done |= signal == SignalMessage::EndOfCandidates;
+                outgoing.push(signal);
+            }
+            // Once both ends are done gathering there is nothing left to do but wait for the connection
+            if expected.is_some()
+                || !(local_done && remote_done && outgoing.is_empty() && pending.is_empty())
+            {
+                pending.extend(signaller.exchange(std::mem::take(&mut outgoing)).await?);
+            }
+
+            if self.connection.remote_description().await.is_some() {
+                for signal in std::mem::take(&mut pending) {
+                    match signal {
+                        SignalMessage::Candidate(json) => {
+                            let Ok(candidate) = serde_json::from_str::<RTCIceCandidateInit>(&json)
+                            else {
+                                let _ = self.events.send(ConnectionEvent::Error(format!(
+                                    "Ignoring malformed candidate: {json}"
+                                )));
+                                continue;
+                            };
+                            if let Err(e) = self.connection.add_ice_candidate(candidate).await {
+                                let _ = self.events.send(ConnectionEvent::Error(format!(
+                                    "Failed to add remote candidate: {e}"
+                                )));
+                            }
+                        }
+                        SignalMessage::EndOfCandidates => remote_done = true,
+                        SignalMessage::RestartOffer(json) if !self.offerer => {
+                            let offer = serde_json::from_str::<RTCSessionDescription>(&json)?;
+                            self.connection.set_remote_description(offer).await?;
+                            let answer = self.connection.create_answer(None).await?;
+                            let answer_json = serde_json::to_string(&answer)?;
+                            // Candidates gathered for the answer must only follow it
+                            while self.local_signals.try_recv().is_ok() {}
+                            self.connection.set_local_description(answer).await?;
+                            outgoing.push(SignalMessage::RestartAnswer(answer_json));
+                            (local_done, remote_done) = (false, false);
+                            expected = expected.filter(|e| *e != RemoteDescription::Offer);
+                        }
+                        SignalMessage::RestartAnswer(json) if self.offerer => {
+                            let answer = serde_json::from_str::<RTCSessionDescription>(&json)?;
+                            self.connection.set_remote_description(answer).await?;
+                            remote_done = false;
+                            expected = expected.filter(|e| *e != RemoteDescription::Answer);
+                        }
+                        SignalMessage::RestartOffer(_) | SignalMessage::RestartAnswer(_) => {
+                            let _ = self.events.send(ConnectionEvent::Error(
+                                "Ignoring a restart description meant for the other end".into(),
+                            ));
+                        }
+                        // The offer was answered before the candidates were trickled, see `super::invite`
+                        SignalMessage::Offer(_) => {}
+                    }
+                }
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Whether ICE has found a working path to the other end, which it does again after a restart
+    fn ice_connected(&self) -> bool {
+        matches!(
+            self.connection.ice_connection_state(),
+            RTCIceConnectionState::Connected | RTCIceConnectionState::Completed
+        )
+    }
+
+    /// Mark the connection as lost for good, and close it
+    async fn give_up(&self) {
+        self.restartable.store(false, Ordering::SeqCst);
+        self.closing.store(true, Ordering::SeqCst);
+        let _ = self.state.send(ChannelState::Lost);
+        let _ = self.events.send(ConnectionEvent::Closed(CloseReason::Lost));
+        let _ = self.connection.close().await;
+    }
+}
+
+/// The description the other end has to send before a restart can complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RemoteDescription {
+    Offer,
+    Answer,
+}
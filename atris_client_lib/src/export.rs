@@ -0,0 +1,350 @@
+//! Exporting conversations from the [`History`] to archives in the format of [`atris_common::export`], and
+//! importing them again, on this machine or another.
+//!
+//! An archive is written as JSON, or as an HTML transcript to read in a browser with the JSON inside it, so either
+//! can be imported. With a passphrase, the conversation and its files are encrypted with a key derived from it,
+//! like a profile's, and an HTML transcript only says how to import it.
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use atris_common::export::{
+    ConversationExport, ExportArchive, ExportedDirection, ExportedFile, ExportedMessage, ExportedStatus, EXPORT_VERSION,
+};
+use atris_common::CipherKey;
+use rand::RngCore;
+use serde::Deserialize;
+
+use crate::comms::delivery::MessageStatus;
+use crate::history::{self, ConversationLog, Direction, History, HistoryEntry, HistoryError};
+use crate::profile::ProfileKey;
+
+/// How many random bytes the passphrase of an encrypted archive is salted with
+const SALT_SIZE: usize = 16;
+/// What the archive inside an HTML transcript starts after
+const HTML_ARCHIVE_START: &str = r#"<script type="application/json" id="atris-export">"#;
+/// What the archive inside an HTML transcript ends before
+const HTML_ARCHIVE_END: &str = "</script>";
+
+/// An error exporting or importing a conversation
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    /// An archive which could not be written, or read
+    Format(serde_json::Error),
+    /// A file which is not an exported conversation
+    NotAnExport,
+    /// An archive written by a newer version of Atris
+    UnsupportedVersion(u32),
+    /// The archive is encrypted, and no passphrase was given
+    PassphraseRequired,
+    WrongPassphrase,
+    /// The conversation could not be encrypted
+    Encryption,
+    History(HistoryError),
+}
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Io(err) => write!(f, "Could not read or write the export: {err}"),
+            ExportError::Format(err) => write!(f, "The export is corrupt: {err}"),
+            ExportError::NotAnExport => write!(f, "This is not an exported conversation"),
+            ExportError::UnsupportedVersion(version) => write!(
+                f,
+                "The export is in version {version} of the format, and this version of Atris only reads up to \
+                 {EXPORT_VERSION}"
+            ),
+            ExportError::PassphraseRequired => write!(f, "The export is encrypted, a passphrase is needed"),
+            ExportError::WrongPassphrase => write!(f, "The passphrase does not decrypt the export"),
+            ExportError::Encryption => write!(f, "Could not encrypt the export"),
+            ExportError::History(err) => write!(f, "{err}"),
+        }
+    }
+}
+impl std::error::Error for ExportError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+impl From<io::Error> for ExportError {
+    fn from(err: io::Error) -> Self {
+        ExportError::Io(err)
+    }
+}
+impl From<serde_json::Error> for ExportError {
+    fn from(err: serde_json::Error) -> Self {
+        ExportError::Format(err)
+    }
+}
+impl From<HistoryError> for ExportError {
+    fn from(err: HistoryError) -> Self {
+        ExportError::History(err)
+    }
+}
+pub type Result<T> = std::result::Result<T, ExportError>;
+
+/// How an archive is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    /// A transcript to read in a browser, which can still be imported
+    Html,
+}
+impl ExportFormat {
+    /// The format a file name asks for, HTML for `.html` and `.htm` and JSON otherwise
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm") => {
+                ExportFormat::Html
+            }
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+impl From<Direction> for ExportedDirection {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Sent => ExportedDirection::Sent,
+            Direction::Received => ExportedDirection::Received,
+        }
+    }
+}
+impl From<ExportedDirection> for Direction {
+    fn from(direction: ExportedDirection) -> Self {
+        match direction {
+            ExportedDirection::Sent => Direction::Sent,
+            ExportedDirection::Received => Direction::Received,
+        }
+    }
+}
+impl From<MessageStatus> for ExportedStatus {
+    fn from(status: MessageStatus) -> Self {
+        match status {
+            MessageStatus::Pending => ExportedStatus::Pending,
+            MessageStatus::Delivered => ExportedStatus::Delivered,
+            MessageStatus::Read => ExportedStatus::Read,
+            MessageStatus::Failed => ExportedStatus::Failed,
+        }
+    }
+}
+impl From<ExportedStatus> for MessageStatus {
+    fn from(status: ExportedStatus) -> Self {
+        match status {
+            ExportedStatus::Pending => MessageStatus::Pending,
+            ExportedStatus::Delivered => MessageStatus::Delivered,
+            ExportedStatus::Read => MessageStatus::Read,
+            ExportedStatus::Failed => MessageStatus::Failed,
+        }
+    }
+}
+impl From<&HistoryEntry> for ExportedMessage {
+    fn from(entry: &HistoryEntry) -> Self {
+        ExportedMessage {
+            timestamp: entry.timestamp,
+            direction: entry.direction.into(),
+            text: entry.text.clone(),
+            status: entry.status.into(),
+        }
+    }
+}
+/// An entry to import, whose id is given when it is recorded
+impl From<ExportedMessage> for HistoryEntry {
+    fn from(message: ExportedMessage) -> Self {
+        HistoryEntry {
+            id: 0,
+            timestamp: message.timestamp,
+            direction: message.direction.into(),
+            text: message.text,
+            status: message.status.into(),
+        }
+    }
+}
+
+/// Every message of `log`, with the `files` attached
+pub fn export(log: &ConversationLog, files: &[PathBuf]) -> Result<ConversationExport> {
+    let mut attached = Vec::new();
+    for path in files {
+        attached.push(ExportedFile {
+            name: file_name(path),
+            data: fs::read(path)?,
+        });
+    }
+    Ok(ConversationExport {
+        conversation: log.name().to_owned(),
+        exported_at: history::now(),
+        messages: log.entries().iter().map(ExportedMessage::from).collect(),
+        files: attached,
+    })
+}
+
+/// The last part of `path`, or `file` if it has none
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(|| "file".to_owned(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Write `export` to `path` in `format`, encrypted with `passphrase` if one is given
+pub fn write(export: &ConversationExport, path: &Path, format: ExportFormat, passphrase: Option<&str>) -> Result<()> {
+    let archive = match passphrase {
+        Some(passphrase) => {
+            let mut salt = [0; SALT_SIZE];
+            rand::thread_rng().fill_bytes(&mut salt);
+            let key = derive(passphrase, &salt)?;
+            ExportArchive::encrypted(export, &key, &salt).map_err(|_| ExportError::Encryption)?
+        }
+        None => ExportArchive::plain(export.clone()),
+    };
+    let contents = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&archive)?,
+        ExportFormat::Html => html(export, &archive)?,
+    };
+    Ok(fs::write(path, contents)?)
+}
+
+fn derive(passphrase: &str, salt: &[u8]) -> Result<CipherKey> {
+    let key = ProfileKey::derive(passphrase, salt).map_err(|_| ExportError::Encryption)?;
+    Ok(CipherKey::from(key.as_bytes()))
+}
+
+/// Read the archive at `path`, written as JSON or HTML by [`write`]
+pub fn read(path: &Path) -> Result<ExportArchive> {
+    #[derive(Deserialize)]
+    struct Versioned {
+        version: u32,
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let json = match contents.trim_start().starts_with('{') {
+        true => contents.as_str(),
+        false => {
+            let start = contents.find(HTML_ARCHIVE_START).ok_or(ExportError::NotAnExport)? + HTML_ARCHIVE_START.len();
+            let end = contents[start..]
+                .find(HTML_ARCHIVE_END)
+                .ok_or(ExportError::NotAnExport)?;
+            &contents[start..start + end]
+        }
+    };
+    // The version is checked first, as a newer archive may not read as this version's
+    let Versioned { version } = serde_json::from_str(json).map_err(|_| ExportError::NotAnExport)?;
+    if version > EXPORT_VERSION {
+        return Err(ExportError::UnsupportedVersion(version));
+    }
+    Ok(serde_json::from_str(json)?)
+}
+
+/// The conversation in `archive`, decrypted with `passphrase` if it is encrypted
+pub fn open(archive: ExportArchive, passphrase: Option<&str>) -> Result<ConversationExport> {
+    let key = match (archive.salt(), passphrase) {
+        (Some(salt), Some(passphrase)) => derive(passphrase, salt)?,
+        (Some(_), None) => return Err(ExportError::PassphraseRequired),
+        // Plain archives need no key, so any will do
+        (None, _) => CipherKey::generate(),
+    };
+    archive.decrypt(&key).map_err(|_| ExportError::WrongPassphrase)
+}
+
+/// What [`import`] did
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub conversation: String,
+    /// How many of the messages were not in the history already
+    pub messages: usize,
+    /// Where the attached files were saved
+    pub files: Vec<PathBuf>,
+}
+
+/// Import `export` into `history`, as the conversation called `name` or else the one it was exported from, saving
+/// the attached files in `files_dir` without replacing any there
+pub fn import(history: &History, export: ConversationExport, name: Option<&str>, files_dir: &Path) -> Result<Imported> {
+    let conversation = name.unwrap_or(&export.conversation).to_owned();
+    let mut log = history.conversation(&conversation)?;
+    let messages = log.import(export.messages.into_iter().map(HistoryEntry::from))?;
+    let mut files = Vec::new();
+    if !export.files.is_empty() {
+        fs::create_dir_all(files_dir)?;
+    }
+    for file in export.files {
+        let path = unused_path(&files_dir.join(file_name(Path::new(&file.name))));
+        fs::write(&path, file.data)?;
+        files.push(path);
+    }
+    Ok(Imported {
+        conversation,
+        messages,
+        files,
+    })
+}
+
+/// `path`, or the first of `name (1).ext`, `name (2).ext` and so on beside it which is not taken
+fn unused_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()));
+    let mut candidate = path.to_owned();
+    let mut copy = 0;
+    while candidate.exists() {
+        copy += 1;
+        candidate = path.with_file_name(format!("{stem} ({copy}){}", extension.as_deref().unwrap_or_default()));
+    }
+    candidate
+}
+
+/// An HTML transcript of `export`, with `archive` inside it to import, unless it is encrypted
+fn html(export: &ConversationExport, archive: &ExportArchive) -> Result<String> {
+    let mut body = String::new();
+    if archive.is_encrypted() {
+        body.push_str("<p>This conversation is encrypted. Import it into Atris with its passphrase to read it.</p>\n");
+    } else {
+        for message in &export.messages {
+            let class = match message.direction {
+                ExportedDirection::Sent => "sent",
+                ExportedDirection::Received => "received",
+            };
+            let line = HistoryEntry::from(message.clone()).to_string();
+            body.push_str(&format!("<p class=\"{class}\">{}</p>\n", escape(&line)));
+        }
+        if !export.files.is_empty() {
+            body.push_str("<h2>Files</h2>\n<ul>\n");
+            for file in &export.files {
+                body.push_str(&format!(
+                    "<li>{} ({} bytes)</li>\n",
+                    escape(&file.name),
+                    file.data.len()
+                ));
+            }
+            body.push_str("</ul>\n");
+        }
+    }
+    // `<` only appears in the JSON's strings, where it can be escaped so nothing in it ends the script
+    let json = serde_json::to_string(archive)?.replace('<', "\\u003c");
+    let title = escape(&export.conversation);
+    Ok(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 40em; margin: auto; }}\n\
+         .sent {{ text-align: right; }}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n\
+         <p>Exported from Atris at {} UTC</p>\n{body}{HTML_ARCHIVE_START}{json}{HTML_ARCHIVE_END}\n</body>\n</html>\n",
+        history::utc(export.exported_at)
+    ))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,429 @@
+//! The messages of each conversation, kept on disk encrypted with the profile's key, to page through and search
+//!
+//! Each conversation is a log file of records, appended as messages are sent and received and as their delivery
+//! statuses change. Every record is encrypted on its own, so appending never rewrites what is already there. The
+//! files are named by a keyed hash of the conversation's name, so they do not say who the user talks to either.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use atris_common::Encrypted;
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::comms::delivery::{MessageId, MessageStatus, StatusUpdate};
+use crate::profile::ProfileKey;
+
+/// How many bytes of the keyed hash of a conversation's name its file is named after
+const FILE_NAME_SIZE: usize = 16;
+/// The extension of the conversations' log files
+const LOG_EXTENSION: &str = "log";
+
+/// An error reading or writing the history
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(io::Error),
+    /// A record which could not be read, or decrypted with the profile's key
+    Corrupt,
+    /// A record which could not be encrypted
+    Encryption,
+}
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::Io(err) => write!(f, "Could not read or write the history: {err}"),
+            HistoryError::Corrupt => write!(f, "The history is corrupt, or belongs to another profile"),
+            HistoryError::Encryption => write!(f, "Could not encrypt the history"),
+        }
+    }
+}
+impl std::error::Error for HistoryError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+impl From<io::Error> for HistoryError {
+    fn from(err: io::Error) -> Self {
+        HistoryError::Io(err)
+    }
+}
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+/// The position of an entry in its conversation, counting from 0
+pub type EntryId = u64;
+
+/// Who sent a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A message in a conversation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: EntryId,
+    /// When the message was sent or received, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub text: String,
+    /// How far a sent message got, and [`MessageStatus::Delivered`] for received ones
+    pub status: MessageStatus,
+}
+impl HistoryEntry {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp)
+    }
+
+    /// Whether the text contains every word of `query`, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let text = self.text.to_lowercase();
+        let mut words = query.split_whitespace().peekable();
+        words.peek().is_some() && words.all(|word| text.contains(&word.to_lowercase()))
+    }
+}
+impl Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let time = utc(self.timestamp);
+        match self.direction {
+            Direction::Sent => write!(f, "[{time}] You: {} ({})", self.text, self.status),
+            Direction::Received => write!(f, "[{time}] Them: {}", self.text),
+        }
+    }
+}
+
+/// `timestamp` as a UTC date and time, like `2022-11-05 14:03`
+pub(crate) fn utc(timestamp: u64) -> String {
+    let minutes = timestamp / 60_000;
+    let (hour, minute) = (minutes / 60 % 24, minutes % 60);
+    // Days since the epoch to a civil date, from Howard Hinnant's `civil_from_days`
+    let days = minutes / (60 * 24) + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}")
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// What a conversation's log file is made of
+#[derive(Serialize, Deserialize)]
+enum Record {
+    /// The first record, naming the conversation
+    Name(String),
+    Entry(HistoryEntry),
+    Status {
+        id: EntryId,
+        status: MessageStatus,
+    },
+}
+
+/// A message found by [`History::search`]
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub conversation: String,
+    pub entry: HistoryEntry,
+}
+
+/// The history of one profile's conversations
+#[derive(Clone)]
+pub struct History {
+    dir: PathBuf,
+    key: ProfileKey,
+}
+impl History {
+    /// The history kept in `dir`, encrypted with `key`
+    pub fn at(dir: impl Into<PathBuf>, key: ProfileKey) -> Self {
+        Self { dir: dir.into(), key }
+    }
+
+    /// The name of the conversation in a server room, the same in every client
+    pub fn room(room_id: u16) -> String {
+        format!("room {room_id}")
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.as_bytes()).expect("HMAC takes keys of any size");
+        mac.update(name.as_bytes());
+        let hash = mac.finalize().into_bytes();
+        let file_name = HEXLOWER.encode(&hash[..FILE_NAME_SIZE]);
+        self.dir.join(file_name).with_extension(LOG_EXTENSION)
+    }
+
+    /// The conversation called `name`, which is empty until something is recorded in it.
+    /// Opening it only reads the file: a record cut short, by a crash or by another process still writing it, is
+    /// skipped, and only dropped from the file when something is next recorded.
+    pub fn conversation(&self, name: &str) -> Result<ConversationLog> {
+        let path = self.path(name);
+        let (records, complete) = read_records(&path, &self.key)?;
+        let mut log = ConversationLog {
+            name: name.to_owned(),
+            path,
+            key: self.key.clone(),
+            entries: Vec::new(),
+            sent: HashMap::new(),
+            complete,
+        };
+        for record in records {
+            log.apply(record);
+        }
+        Ok(log)
+    }
+
+    /// The names of the conversations with anything in them, in order
+    pub fn conversations(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == LOG_EXTENSION) {
+                if let Some(Record::Name(name)) = read_records(&path, &self.key)?.0.into_iter().next() {
+                    names.push(name);
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// The messages in every conversation containing every word of `query`, ignoring case, oldest first in each
+    pub fn search(&self, query: &str) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        for name in self.conversations()? {
+            let log = self.conversation(&name)?;
+            hits.extend(log.search(query).into_iter().map(|entry| SearchHit {
+                conversation: name.clone(),
+                entry: entry.clone(),
+            }));
+        }
+        Ok(hits)
+    }
+}
+
+/// Read every complete record of a log file, stopping at one cut short by a crash while it was written.
+/// Also returns how many bytes the complete records take up.
+fn read_records(path: &Path, key: &ProfileKey) -> Result<(Vec<Record>, u64)> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(err) => return Err(err.into()),
+    };
+    let mut records = Vec::new();
+    let mut rest = &bytes[..];
+    while rest.len() >= 4 {
+        let length = u32::from_be_bytes(rest[..4].try_into().expect("4 bytes")) as usize;
+        let Some(record) = rest.get(4..4 + length) else {
+            break;
+        };
+        let encrypted: Encrypted<Record> = bincode::deserialize(record).map_err(|_| HistoryError::Corrupt)?;
+        records.push(
+            encrypted
+                .decrypt(&mut key.as_cipher())
+                .map_err(|_| HistoryError::Corrupt)?,
+        );
+        rest = &rest[4 + length..];
+    }
+    Ok((records, (bytes.len() - rest.len()) as u64))
+}
+
+/// How many bytes at the start of a log file hold complete records, reading on from `start`, which is known to end
+/// a complete record. Only reads the records' lengths.
+fn complete_length(file: &mut File, start: u64) -> io::Result<u64> {
+    let length = file.metadata()?.len();
+    let mut complete = start;
+    let mut header = [0; 4];
+    while complete + 4 <= length {
+        file.seek(SeekFrom::Start(complete))?;
+        file.read_exact(&mut header)?;
+        let end = complete + 4 + u32::from_be_bytes(header) as u64;
+        if end > length {
+            break;
+        }
+        complete = end;
+    }
+    Ok(complete)
+}
+
+/// The messages of one conversation, recording new ones as they are sent and received
+pub struct ConversationLog {
+    name: String,
+    path: PathBuf,
+    key: ProfileKey,
+    entries: Vec<HistoryEntry>,
+    /// The entries of the messages sent on the current channel, to update as the other end acknowledges them
+    sent: HashMap<MessageId, EntryId>,
+    /// How many bytes at the start of the file were complete records when last read or written
+    complete: u64,
+}
+impl ConversationLog {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Every message, oldest first
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Name(_) => {}
+            Record::Entry(entry) => self.entries.push(entry),
+            Record::Status { id, status } => {
+                if let Some(entry) = self.entries.get_mut(id as usize) {
+                    entry.status = status;
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, record: Record) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        // Other processes append to the same file, so only drop a record cut short while none of them is writing
+        file.lock()?;
+        let length = file.metadata()?.len();
+        let start = if length < self.complete { 0 } else { self.complete };
+        let complete = complete_length(&mut file, start)?;
+        if complete < length {
+            file.set_len(complete)?;
+        }
+
+        let mut records = Vec::new();
+        // Including a file a crash cut short while its first record was written
+        if complete == 0 {
+            records.push(Record::Name(self.name.clone()));
+        }
+        records.push(record);
+
+        let mut bytes = Vec::new();
+        for record in &records {
+            let encrypted =
+                Encrypted::encrypt(record, &mut self.key.as_cipher()).map_err(|_| HistoryError::Encryption)?;
+            let encoded = bincode::serialize(&encrypted).map_err(|_| HistoryError::Encryption)?;
+            bytes.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&encoded);
+        }
+        // One write, so a crash cuts off at most the last record
+        file.write_all(&bytes)?;
+        self.complete = complete + bytes.len() as u64;
+        for record in records {
+            self.apply(record);
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, direction: Direction, text: String, status: MessageStatus) -> Result<EntryId> {
+        let id = self.entries.len() as EntryId;
+        self.append(Record::Entry(HistoryEntry {
+            id,
+            timestamp: now(),
+            direction,
+            text,
+            status,
+        }))?;
+        Ok(id)
+    }
+
+    /// Record messages from elsewhere, like an imported export, after the ones already here. Their ids are
+    /// replaced, and the ones already recorded at the same time, both ways, with the same text are skipped, so
+    /// importing twice changes nothing. Returns how many were recorded.
+    pub fn import(&mut self, entries: impl IntoIterator<Item = HistoryEntry>) -> Result<usize> {
+        let mut imported = 0;
+        for mut entry in entries {
+            let recorded = self.entries.iter().any(|existing| {
+                (existing.timestamp, existing.direction, &existing.text)
+                    == (entry.timestamp, entry.direction, &entry.text)
+            });
+            if !recorded {
+                entry.id = self.entries.len() as EntryId;
+                self.append(Record::Entry(entry))?;
+                imported += 1;
+            }
+        }
+        Ok(imported)
+    }
+
+    /// Record a message sent with `message_id` on the current channel, pending until it is acknowledged
+    pub fn record_sent(&mut self, message_id: MessageId, text: impl Into<String>) -> Result<EntryId> {
+        let id = self.record(Direction::Sent, text.into(), MessageStatus::Pending)?;
+        self.sent.insert(message_id, id);
+        Ok(id)
+    }
+
+    /// Record a message which could not be sent
+    pub fn record_failed(&mut self, text: impl Into<String>) -> Result<EntryId> {
+        self.record(Direction::Sent, text.into(), MessageStatus::Failed)
+    }
+
+    pub fn record_received(&mut self, text: impl Into<String>) -> Result<EntryId> {
+        self.record(Direction::Received, text.into(), MessageStatus::Delivered)
+    }
+
+    /// Record how far a message sent on the current channel got
+    pub fn update_status(&mut self, update: StatusUpdate) -> Result<()> {
+        let Some(&id) = self.sent.get(&update.id) else {
+            return Ok(());
+        };
+        if self.entries[id as usize].status == update.status {
+            return Ok(());
+        }
+        self.append(Record::Status {
+            id,
+            status: update.status,
+        })
+    }
+
+    /// Up to `limit` messages from before the entry `before`, or from the end without it, oldest first
+    pub fn page(&self, before: Option<EntryId>, limit: usize) -> &[HistoryEntry] {
+        let end = before.map_or(self.entries.len(), |before| (before as usize).min(self.entries.len()));
+        &self.entries[end.saturating_sub(limit)..end]
+    }
+
+    /// The messages containing every word of `query`, ignoring case, oldest first
+    pub fn search(&self, query: &str) -> Vec<&HistoryEntry> {
+        self.entries.iter().filter(|entry| entry.matches(query)).collect()
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use async_trait::async_trait;
 
 use reqwest::Response;
@@ -5,12 +7,30 @@ use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{AtrisAuthClient, InvocationResult};
 
+#[cfg(feature = "local")]
 macro_rules! local_url {
     ($function_name:literal) => {
-        concat!("http://localhost:9000/lambda-url/", $function_name, "/")
+        FunctionUrl::new(
+            $function_name,
+            concat!("http://localhost:9000/lambda-url/", $function_name, "/"),
+        )
     };
 }
 
+/// Where [`AtrisAuth`] sends the requests for one of the server's functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionUrl {
+    /// The name of the function, like `create_user`
+    pub name: &'static str,
+    /// The URL of the function on the default server
+    pub url: &'static str,
+}
+impl FunctionUrl {
+    pub const fn new(name: &'static str, url: &'static str) -> Self {
+        Self { name, url }
+    }
+}
+
 /// The API of the Atris authentication server, implemented using http requests.
 /// This bundles all of the functions necessary for user creation and authentication, as well as initiating the key exchange.
 ///
@@ -27,9 +47,12 @@ macro_rules! local_url {
 ///     Ok(())
 /// }
 /// ```
+#[derive(Clone)]
 pub struct AtrisAuth {
     /// The http client that this client will use for API calls
     client: reqwest::Client,
+    /// The server to send requests to instead of the default one, see [`AtrisAuth::with_server`]
+    server: Option<String>,
 }
 
 impl AtrisAuth {
@@ -38,13 +61,34 @@ impl AtrisAuth {
         let client = reqwest::Client::builder()
             .user_agent("atris_client_lib")
             .build()?;
-        Ok(Self { client })
+        Ok(Self { client, server: None })
+    }
+
+    /// Create an [`AtrisAuth`] for another deployment of the server, like `http://localhost:9000`.
+    /// Each function is requested at `<server>/lambda-url/<function name>/`, the way `cargo lambda watch` serves them.
+    pub fn with_server(server: &str) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            server: Some(server.trim_end_matches('/').to_owned()),
+            ..Self::new()?
+        })
+    }
+
+    /// The server this client sends requests to, if it is not the default one
+    pub fn server(&self) -> Option<&str> {
+        self.server.as_deref()
+    }
+
+    fn url(&self, function: FunctionUrl) -> Cow<'static, str> {
+        match &self.server {
+            Some(server) => Cow::Owned(format!("{server}/lambda-url/{}/", function.name)),
+            None => Cow::Borrowed(function.url),
+        }
     }
 }
 #[async_trait]
 impl AtrisAuthClient for AtrisAuth {
     type Error = reqwest::Error;
-    type FunctionIdentifier = &'static str;
+    type FunctionIdentifier = FunctionUrl;
 
     type BaseResponse = Response;
 
@@ -59,30 +103,46 @@ impl AtrisAuthClient for AtrisAuth {
     const JOIN_ROOM_FN: Self::FunctionIdentifier = local_url!("join_room");
     #[cfg(feature = "local")]
     const SET_ROOM_RESPONDER_FN: Self::FunctionIdentifier = local_url!("set_room_responder");
+    #[cfg(feature = "local")]
+    const ENROLL_TOTP_FN: Self::FunctionIdentifier = local_url!("enroll_totp");
+    #[cfg(feature = "local")]
+    const CONFIRM_TOTP_FN: Self::FunctionIdentifier = local_url!("confirm_totp");
+    #[cfg(feature = "local")]
+    const SIGNAL_ROOM_FN: Self::FunctionIdentifier = local_url!("signal_room");
 
     #[cfg(not(feature = "local"))]
-    const CREATE_USER_FN: Self::FunctionIdentifier =
-        "https://6mfd7yxy3tibberkbtkmjbhnsu0wevek.lambda-url.us-west-2.on.aws";
+    const CREATE_USER_FN: Self::FunctionIdentifier = FunctionUrl::new(
+        "create_user",
+        "https://6mfd7yxy3tibberkbtkmjbhnsu0wevek.lambda-url.us-west-2.on.aws",
+    );
+    #[cfg(not(feature = "local"))]
+    const AUTHENTICATE_USER_FN: Self::FunctionIdentifier = FunctionUrl::new(
+        "authenticate_user",
+        "https://y46vbul2oe7qumkca6rkyi7k7a0aixvu.lambda-url.us-west-2.on.aws/",
+    );
+    #[cfg(not(feature = "local"))]
+    const CREATE_ROOM_FN: Self::FunctionIdentifier = FunctionUrl::new("create_room", "https:///");
+    #[cfg(not(feature = "local"))]
+    const JOIN_ROOM_FN: Self::FunctionIdentifier = FunctionUrl::new("join_room", "https:///");
     #[cfg(not(feature = "local"))]
-    const AUTHENTICATE_USER_FN: Self::FunctionIdentifier =
-        "https://y46vbul2oe7qumkca6rkyi7k7a0aixvu.lambda-url.us-west-2.on.aws/";
+    const SET_ROOM_RESPONDER_FN: Self::FunctionIdentifier = FunctionUrl::new("set_room_responder", "https:///");
     #[cfg(not(feature = "local"))]
-    const CREATE_ROOM_FN: Self::FunctionIdentifier = "https:///";
+    const ENROLL_TOTP_FN: Self::FunctionIdentifier = FunctionUrl::new("enroll_totp", "https:///");
     #[cfg(not(feature = "local"))]
-    const JOIN_ROOM_FN: Self::FunctionIdentifier = "https:///";
+    const CONFIRM_TOTP_FN: Self::FunctionIdentifier = FunctionUrl::new("confirm_totp", "https:///");
     #[cfg(not(feature = "local"))]
-    const SET_ROOM_RESPONDER_FN: Self::FunctionIdentifier = "https:///";
+    const SIGNAL_ROOM_FN: Self::FunctionIdentifier = FunctionUrl::new("signal_room", "https:///");
 
     async fn invoke_lambda<'s, P: Serialize + Sync, R: DeserializeOwned>(
         &'s self,
-        lambda_function_url: Self::FunctionIdentifier,
+        lambda_function: Self::FunctionIdentifier,
         payload: &'s P,
     ) -> InvocationResult<R, Self::Error> {
         // Get the payload as a `String`
         // dbg!(lambda_function_url);
         Ok(self
             .client
-            .post(lambda_function_url)
+            .post(self.url(lambda_function).as_ref())
             .json(payload)
             .send()
             .await?
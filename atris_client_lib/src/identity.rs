@@ -0,0 +1,69 @@
+//! Long-lived keys which identify a user to the clients they talk to directly, without a server vouching for them.
+//! They are X25519 keys, which [`crate::comms::lan`] uses as the static keys of its Noise handshake.
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use data_encoding::BASE32_NOPAD;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// How many bytes of the public key's hash a fingerprint shows, which is plenty to tell keys apart by eye
+const FINGERPRINT_SIZE: usize = 10;
+
+/// A user's identity key pair
+#[derive(Clone)]
+pub struct IdentityKey {
+    secret: [u8; 32],
+    public: PublicIdentity,
+}
+impl IdentityKey {
+    pub fn generate() -> Self {
+        let mut secret = [0; 32];
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        Self::from_bytes(secret)
+    }
+
+    /// Restore an identity key from [`IdentityKey::to_bytes`]
+    pub fn from_bytes(secret: [u8; 32]) -> Self {
+        let public = PublicIdentity(MontgomeryPoint::mul_base_clamped(secret).to_bytes());
+        Self { secret, public }
+    }
+
+    /// The secret key, to be stored somewhere safe
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.secret
+    }
+
+    pub fn public(&self) -> &PublicIdentity {
+        &self.public
+    }
+
+    /// The secret key, as the private key of a Noise handshake
+    pub(crate) fn secret(&self) -> &[u8; 32] {
+        &self.secret
+    }
+}
+
+/// The public half of an [`IdentityKey`], which is safe to give to anyone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicIdentity([u8; 32]);
+impl PublicIdentity {
+    /// Read a public identity sent by another client
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// A short, human-comparable digest of the key, like `ABCD-EFGH-IJKL-MNOP`
+    pub fn fingerprint(&self) -> String {
+        let hash = Sha256::digest(self.0);
+        BASE32_NOPAD
+            .encode(&hash[..FINGERPRINT_SIZE])
+            .as_bytes()
+            .chunks(4)
+            .map(|group| String::from_utf8_lossy(group).into_owned())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+}
@@ -2,12 +2,17 @@ use std::fmt::{Debug, Display};
 
 use atris_common::{
     authenticate_user::{AuthenticateUserError, AuthenticateUserRequest, AuthenticateUserResponse},
+    confirm_totp::{ConfirmTotpError, ConfirmTotpRequest, ConfirmTotpResponse},
     create_room::{CreateRoomError, CreateRoomRequest, CreateRoomResponse},
     create_user::{CreateUserError, CreateUserRequest, CreateUserResponse},
+    enroll_totp::{EnrollTotpError, EnrollTotpRequest, EnrollTotpResponse},
     join_room::{JoinRoomError, JoinRoomRequest, JoinRoomResponse},
     set_room_responder::{
         SetRoomResponderError, SetRoomResponderRequest, SetRoomResponderResponse,
     },
+    signal_room::{
+        SignalMessage, SignalRole, SignalRoomError, SignalRoomRequest, SignalRoomResponse,
+    },
     CipherKey,
 };
 
@@ -16,7 +21,11 @@ use serde::{de::DeserializeOwned, Serialize};
 pub use atris_common;
 
 pub mod comms;
+pub mod export;
+pub mod history;
 pub mod http_auth;
+pub mod identity;
+pub mod profile;
 pub mod sdk_auth;
 
 /// An error resulting from invoking an Atris Lambda function
@@ -36,12 +45,19 @@ impl<E> From<E> for InvocationError<E> {
         InvocationError::ImplementationError(err)
     }
 }
-impl<E: Debug> Display for InvocationError<E> {
+impl<E: Display> Display for InvocationError<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(&self, f)
+        match self {
+            Self::ImplementationError(err) => write!(f, "Could not reach the Atris server: {err}"),
+            Self::SerializationError(err) => write!(f, "Failed to write the request to the Atris server: {err}"),
+            Self::DeserializationError(err) => {
+                write!(f, "The Atris server sent a response which could not be read: {err}")
+            }
+            Self::NoResponse => write!(f, "The Atris server did not respond"),
+        }
     }
 }
-impl<E: Debug> std::error::Error for InvocationError<E> {
+impl<E: Debug + Display> std::error::Error for InvocationError<E> {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         None
     }
@@ -85,6 +101,12 @@ pub trait AtrisAuthClient {
     const SET_ROOM_RESPONDER_FN: Self::FunctionIdentifier;
     /// The identifier for the JoinRoom Lambda function
     const JOIN_ROOM_FN: Self::FunctionIdentifier;
+    /// The identifier for the EnrollTotp Lambda function
+    const ENROLL_TOTP_FN: Self::FunctionIdentifier;
+    /// The identifier for the ConfirmTotp Lambda function
+    const CONFIRM_TOTP_FN: Self::FunctionIdentifier;
+    /// The identifier for the SignalRoom Lambda function
+    const SIGNAL_ROOM_FN: Self::FunctionIdentifier;
 
     /// Invoke a lambda function with the given input and output types
     async fn invoke_lambda<'s, P: Serialize + Sync, R: DeserializeOwned>(
@@ -159,6 +181,8 @@ pub trait AtrisAuthClient {
         .await
     }
     /// Send the response to authenticate a user on the authentication server
+    /// - If the user has two-factor authentication enabled, this returns [`AuthenticateUserError::SecondFactorRequired`],
+    /// and the login should be retried with [`AtrisAuthClient::authenticate_user_with_second_factor`]
     async fn authenticate_user(
         &self,
         username: &str,
@@ -172,6 +196,71 @@ pub trait AtrisAuthClient {
                 username: username.into(),
                 password_attempt: password_attempt.into(),
                 initiator: initiator.into(),
+                second_factor: None,
+            },
+        )
+        .await
+    }
+    /// Send the response to authenticate a user with a TOTP code or recovery code on the authentication server
+    async fn authenticate_user_with_second_factor(
+        &self,
+        username: &str,
+        password_attempt: &str,
+        initiator: &str,
+        second_factor: &str,
+    ) -> InvocationResult<Result<AuthenticateUserResponse, AuthenticateUserError>, Self::Error>
+    {
+        self.invoke_lambda(
+            Self::AUTHENTICATE_USER_FN,
+            &AuthenticateUserRequest {
+                username: username.into(),
+                password_attempt: password_attempt.into(),
+                initiator: initiator.into(),
+                second_factor: Some(second_factor.into()),
+            },
+        )
+        .await
+    }
+    /// Send the response to start TOTP enrollment on the authentication server
+    async fn enroll_totp(
+        &self,
+        session_id: CipherKey,
+    ) -> InvocationResult<Result<EnrollTotpResponse, EnrollTotpError>, Self::Error> {
+        self.invoke_lambda(Self::ENROLL_TOTP_FN, &EnrollTotpRequest { session_id })
+            .await
+    }
+    /// Send the response to confirm TOTP enrollment on the authentication server
+    async fn confirm_totp(
+        &self,
+        session_id: CipherKey,
+        code: &str,
+    ) -> InvocationResult<Result<ConfirmTotpResponse, ConfirmTotpError>, Self::Error> {
+        self.invoke_lambda(
+            Self::CONFIRM_TOTP_FN,
+            &ConfirmTotpRequest {
+                session_id,
+                code: code.into(),
+            },
+        )
+        .await
+    }
+    /// Send the response to publish signaling messages for a room, and fetch the ones the other end published, on the authentication server
+    async fn signal_room(
+        &self,
+        session_id: CipherKey,
+        room_id: u16,
+        role: SignalRole,
+        messages: Vec<SignalMessage>,
+        received: u32,
+    ) -> InvocationResult<Result<SignalRoomResponse, SignalRoomError>, Self::Error> {
+        self.invoke_lambda(
+            Self::SIGNAL_ROOM_FN,
+            &SignalRoomRequest {
+                session_id,
+                room_id,
+                role,
+                messages,
+                received,
             },
         )
         .await
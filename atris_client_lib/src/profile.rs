@@ -0,0 +1,481 @@
+//! Profiles, which keep an account's server, username, identity key, session and preferences between runs
+//!
+//! Each profile is a JSON file under the platform's config directory. Its secrets, the identity key and the session,
+//! are encrypted with a [`ProfileKey`] derived from a local passphrase with Argon2, which never leaves this machine.
+//! "Remember me" saves the derived key next to the profiles, so it unlocks without the passphrase until forgotten.
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use atris_common::{Cipher, CipherKey, Encrypted, IceServer};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::history::History;
+use crate::http_auth::AtrisAuth;
+use crate::identity::IdentityKey;
+
+/// The directory under the platform's config directory everything is kept in
+const CONFIG_DIR: &str = "atris";
+/// The directory the profiles are kept in, one `<username>.json` each
+const PROFILES_DIR: &str = "profiles";
+/// The directory remembered keys are kept in, one `<username>.key` each
+const KEYS_DIR: &str = "keys";
+/// The directory the profiles' histories are kept in, one directory each
+const HISTORY_DIR: &str = "history";
+/// The file naming the profile the clients use unless told otherwise
+const CURRENT_FILE: &str = "current";
+/// How many random bytes the passphrase is salted with
+const SALT_SIZE: usize = 16;
+
+/// An error loading, unlocking or saving a profile
+#[derive(Debug)]
+pub enum ProfileError {
+    /// The platform has no config directory to keep profiles in
+    NoConfigDir,
+    /// A username which cannot name a profile file
+    InvalidName(String),
+    /// There is no profile for this username
+    NotFound(String),
+    /// The passphrase, or the remembered key, does not unlock the profile
+    WrongPassphrase,
+    Io(io::Error),
+    /// A profile file which could not be read or written
+    Format(serde_json::Error),
+    /// A secret which could not be encrypted or decrypted
+    Encryption,
+}
+impl std::fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileError::NoConfigDir => write!(f, "There is no config directory to keep profiles in"),
+            ProfileError::InvalidName(name) => write!(f, "'{name}' cannot name a profile"),
+            ProfileError::NotFound(name) => write!(f, "There is no profile for {name}"),
+            ProfileError::WrongPassphrase => write!(f, "The passphrase does not unlock this profile"),
+            ProfileError::Io(err) => write!(f, "Could not read or write the profile: {err}"),
+            ProfileError::Format(err) => write!(f, "The profile is corrupt: {err}"),
+            ProfileError::Encryption => write!(f, "Could not encrypt the profile's secrets"),
+        }
+    }
+}
+impl std::error::Error for ProfileError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+impl From<io::Error> for ProfileError {
+    fn from(err: io::Error) -> Self {
+        ProfileError::Io(err)
+    }
+}
+impl From<serde_json::Error> for ProfileError {
+    fn from(err: serde_json::Error) -> Self {
+        ProfileError::Format(err)
+    }
+}
+pub type Result<T> = std::result::Result<T, ProfileError>;
+
+/// The key a profile's secrets are encrypted with, derived from its passphrase
+#[derive(Clone)]
+pub struct ProfileKey(CipherKey);
+impl ProfileKey {
+    /// Derive the key for `passphrase` with Argon2, using the profile's `salt`
+    pub fn derive(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut bytes = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut bytes)
+            .map_err(|_| ProfileError::Encryption)?;
+        Ok(Self(CipherKey::from(&bytes[..])))
+    }
+
+    pub fn as_cipher(&self) -> Cipher {
+        self.0.as_cipher()
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+/// How the user wants the clients to behave
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preferences {
+    /// Where accepted files are saved, instead of the directory the client was started in
+    #[serde(default)]
+    pub download_dir: Option<PathBuf>,
+}
+
+/// A room this user created or joined, remembered to go back to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedRoom {
+    pub room_id: u16,
+    /// Who the room is with, which the user joining a room is not told
+    pub other_user: Option<String>,
+    /// Whether this user created the room, and so waits in it for the other user
+    pub created: bool,
+}
+
+/// What logging in to the server handed out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub session_id: CipherKey,
+    /// The ICE servers handed out at login, with the credentials for the deployment's TURN relay
+    pub ice_servers: Vec<IceServer>,
+}
+
+/// A profile as it is saved, with its secrets still encrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub username: String,
+    /// The server the account is on, if it is not the default one, see [`AtrisAuth::with_server`]
+    #[serde(default)]
+    pub server: Option<String>,
+    #[serde(default)]
+    pub preferences: Preferences,
+    #[serde(default)]
+    pub rooms: Vec<SavedRoom>,
+    #[serde(with = "serde_bytes")]
+    salt: Vec<u8>,
+    /// The secret identity key, which also proves the passphrase is right when it decrypts
+    identity: Encrypted<[u8; 32]>,
+    session: Option<Encrypted<SavedSession>>,
+}
+impl Profile {
+    /// Decrypt the profile's secrets with its passphrase
+    pub fn unlock(self, passphrase: &str) -> Result<UnlockedProfile> {
+        let key = ProfileKey::derive(passphrase, &self.salt)?;
+        self.unlock_with(key)
+    }
+
+    /// Decrypt the profile's secrets with a key derived before, like a remembered one
+    pub fn unlock_with(self, key: ProfileKey) -> Result<UnlockedProfile> {
+        let identity = self
+            .identity
+            .clone()
+            .decrypt(&mut key.as_cipher())
+            .map_err(|_| ProfileError::WrongPassphrase)?;
+        let session = match self.session.clone() {
+            Some(session) => Some(
+                session
+                    .decrypt(&mut key.as_cipher())
+                    .map_err(|_| ProfileError::WrongPassphrase)?,
+            ),
+            None => None,
+        };
+        Ok(UnlockedProfile {
+            profile: self,
+            key,
+            identity: IdentityKey::from_bytes(identity),
+            session,
+        })
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Forget the session, which needs no passphrase
+    pub fn log_out(&mut self) {
+        self.session = None;
+    }
+
+    /// A client for the server the account is on
+    pub fn auth_client(&self) -> std::result::Result<AtrisAuth, reqwest::Error> {
+        match &self.server {
+            Some(server) => AtrisAuth::with_server(server),
+            None => AtrisAuth::new(),
+        }
+    }
+
+    /// Remember a room, replacing anything remembered under the same number
+    pub fn remember_room(&mut self, room: SavedRoom) {
+        self.rooms.retain(|saved| saved.room_id != room.room_id);
+        self.rooms.push(room);
+    }
+
+    pub fn room(&self, room_id: u16) -> Option<&SavedRoom> {
+        self.rooms.iter().find(|room| room.room_id == room_id)
+    }
+}
+
+/// A profile with its secrets decrypted
+#[derive(Clone)]
+pub struct UnlockedProfile {
+    profile: Profile,
+    key: ProfileKey,
+    identity: IdentityKey,
+    session: Option<SavedSession>,
+}
+impl std::fmt::Debug for UnlockedProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The secrets stay out of logs
+        f.debug_struct("UnlockedProfile")
+            .field("username", &self.profile.username)
+            .finish_non_exhaustive()
+    }
+}
+impl UnlockedProfile {
+    /// A new profile for `username`, with a fresh identity key, locked with `passphrase`
+    pub fn create(username: &str, server: Option<String>, passphrase: &str) -> Result<Self> {
+        validate_name(username)?;
+        let mut salt = vec![0; SALT_SIZE];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let key = ProfileKey::derive(passphrase, &salt)?;
+        let identity = IdentityKey::generate();
+        let profile = Profile {
+            username: username.to_owned(),
+            server,
+            preferences: Preferences::default(),
+            rooms: Vec::new(),
+            salt,
+            identity: Encrypted::encrypt(&identity.to_bytes(), &mut key.as_cipher())
+                .map_err(|_| ProfileError::Encryption)?,
+            session: None,
+        };
+        Ok(Self {
+            profile,
+            key,
+            identity,
+            session: None,
+        })
+    }
+
+    /// The profile as it is saved
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// The profile's unencrypted parts, like its preferences and rooms
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.profile
+    }
+
+    pub fn username(&self) -> &str {
+        &self.profile.username
+    }
+
+    pub fn key(&self) -> &ProfileKey {
+        &self.key
+    }
+
+    pub fn identity(&self) -> &IdentityKey {
+        &self.identity
+    }
+
+    /// The session from the last login, if the user has not logged out since
+    pub fn session(&self) -> Option<&SavedSession> {
+        self.session.as_ref()
+    }
+
+    /// Replace the session, encrypting it into the profile
+    pub fn set_session(&mut self, session: Option<SavedSession>) -> Result<()> {
+        self.profile.session = match &session {
+            Some(session) => {
+                Some(Encrypted::encrypt(session, &mut self.key.as_cipher()).map_err(|_| ProfileError::Encryption)?)
+            }
+            None => None,
+        };
+        self.session = session;
+        Ok(())
+    }
+}
+
+/// A username which is safe to name files after
+fn validate_name(username: &str) -> Result<()> {
+    if username.is_empty() || username.starts_with('.') || username.contains(['/', '\\', '\0']) {
+        return Err(ProfileError::InvalidName(username.to_owned()));
+    }
+    Ok(())
+}
+
+/// Write a file only this user can read. The contents go to a temporary file next to it first, which then replaces
+/// it, so a crash or a full disk partway through leaves the old file as it was.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let mut temporary_name = std::ffi::OsString::from(".");
+    temporary_name.push(path.file_name().unwrap_or_default());
+    temporary_name.push(format!(".{}.tmp", std::process::id()));
+    let temporary = dir.join(temporary_name);
+    // Left behind by a crash of an earlier process with the same id
+    remove_if_exists(&temporary)?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options.open(&temporary).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(err) = written.and_then(|()| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+        return Err(err.into());
+    }
+    // Make the rename itself last
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Remove a file, if it is there
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The directory profiles are kept in
+#[derive(Debug, Clone)]
+pub struct Profiles {
+    dir: PathBuf,
+}
+impl Profiles {
+    /// The profiles under the platform's config directory
+    pub fn open() -> Result<Self> {
+        let dir = dirs::config_dir().ok_or(ProfileError::NoConfigDir)?;
+        Ok(Self::at(dir.join(CONFIG_DIR)))
+    }
+
+    /// The profiles kept in `dir`
+    pub fn at(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn profile_path(&self, username: &str) -> Result<PathBuf> {
+        validate_name(username)?;
+        Ok(self.dir.join(PROFILES_DIR).join(format!("{username}.json")))
+    }
+
+    fn key_path(&self, username: &str) -> Result<PathBuf> {
+        validate_name(username)?;
+        Ok(self.dir.join(KEYS_DIR).join(format!("{username}.key")))
+    }
+
+    /// The usernames with a profile, in order
+    pub fn list(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(self.dir.join(PROFILES_DIR)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut usernames = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                if let Some(username) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    usernames.push(username.to_owned());
+                }
+            }
+        }
+        usernames.sort();
+        Ok(usernames)
+    }
+
+    /// The profile for `username`, if there is one
+    pub fn find(&self, username: &str) -> Result<Option<Profile>> {
+        match fs::read(self.profile_path(username)?) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The profile for `username`
+    pub fn load(&self, username: &str) -> Result<Profile> {
+        self.find(username)?
+            .ok_or_else(|| ProfileError::NotFound(username.to_owned()))
+    }
+
+    pub fn save(&self, profile: &Profile) -> Result<()> {
+        write_private(
+            &self.profile_path(&profile.username)?,
+            &serde_json::to_vec_pretty(profile)?,
+        )
+    }
+
+    /// Delete the profile for `username`, with its remembered key and its history, which nothing else can decrypt
+    pub fn remove(&self, username: &str) -> Result<()> {
+        if self.find(username)?.is_none() {
+            return Err(ProfileError::NotFound(username.to_owned()));
+        }
+        remove_if_exists(&self.profile_path(username)?)?;
+        self.forget(username)?;
+        match fs::remove_dir_all(self.dir.join(HISTORY_DIR).join(username)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        if self.current()?.as_deref() == Some(username) {
+            self.set_current(None)?;
+        }
+        Ok(())
+    }
+
+    /// The username of the profile the clients use unless told otherwise
+    pub fn current(&self) -> Result<Option<String>> {
+        match fs::read_to_string(self.dir.join(CURRENT_FILE)) {
+            Ok(username) if username.trim().is_empty() => Ok(None),
+            Ok(username) => Ok(Some(username.trim().to_owned())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Switch to the profile for `username`, or to none
+    pub fn set_current(&self, username: Option<&str>) -> Result<()> {
+        let path = self.dir.join(CURRENT_FILE);
+        match username {
+            Some(username) => {
+                validate_name(username)?;
+                fs::create_dir_all(&self.dir)?;
+                fs::write(path, username)?;
+                Ok(())
+            }
+            None => remove_if_exists(&path),
+        }
+    }
+
+    /// Save the profile's key, so it unlocks without the passphrase. Anyone who can read the key file can read
+    /// the profile's secrets, so it is only readable by this user.
+    pub fn remember(&self, profile: &UnlockedProfile) -> Result<()> {
+        write_private(&self.key_path(profile.username())?, profile.key.0.as_ref())
+    }
+
+    /// Delete the remembered key for `username`, if there is one
+    pub fn forget(&self, username: &str) -> Result<()> {
+        remove_if_exists(&self.key_path(username)?)
+    }
+
+    /// The remembered key for `username`, if there is one
+    pub fn remembered(&self, username: &str) -> Result<Option<ProfileKey>> {
+        match fs::read(self.key_path(username)?) {
+            Ok(bytes) if bytes.len() == 32 => Ok(Some(ProfileKey(CipherKey::from(&bytes[..])))),
+            Ok(_) => Err(ProfileError::WrongPassphrase),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The history of the profile's conversations, encrypted with its key
+    pub fn history(&self, profile: &UnlockedProfile) -> Result<History> {
+        validate_name(profile.username())?;
+        Ok(History::at(
+            self.dir.join(HISTORY_DIR).join(profile.username()),
+            profile.key.clone(),
+        ))
+    }
+
+    /// The profile for `username` unlocked with its remembered key, if it has one
+    pub fn unlock_remembered(&self, username: &str) -> Result<Option<UnlockedProfile>> {
+        match self.remembered(username)? {
+            Some(key) => Ok(Some(self.load(username)?.unlock_with(key)?)),
+            None => Ok(None),
+        }
+    }
+}
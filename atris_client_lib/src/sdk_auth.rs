@@ -53,6 +53,9 @@ impl AtrisAuthClient for AtrisAuthSDK {
     const CREATE_ROOM_FN: Self::FunctionIdentifier = "CreateRoom";
     const SET_ROOM_RESPONDER_FN: Self::FunctionIdentifier = "SetRoomResponder";
     const JOIN_ROOM_FN: Self::FunctionIdentifier = "JoinRoom";
+    const ENROLL_TOTP_FN: Self::FunctionIdentifier = "EnrollTotp";
+    const CONFIRM_TOTP_FN: Self::FunctionIdentifier = "ConfirmTotp";
+    const SIGNAL_ROOM_FN: Self::FunctionIdentifier = "SignalRoom";
     async fn invoke_lambda<'s, P: Serialize + Sync, R: DeserializeOwned>(
         &'s self,
         lambda_function_name: Self::FunctionIdentifier,
@@ -75,7 +78,7 @@ impl AtrisAuthClient for AtrisAuthSDK {
             .payload()
             .ok_or(InvocationError::NoResponse)?
             .as_ref()
-            .into_iter()
+            .iter()
             .map(|c| char::from(*c))
             .collect::<String>();
         serde_json::from_str(&serialized_response).map_err(InvocationError::DeserializationError)
@@ -0,0 +1,368 @@
+//! End to end tests of [`AtrisChannel`] between two offline connections in the same process
+
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::trickle::LocalSignaller;
+use atris_client_lib::comms::channels::ChannelOptions;
+use atris_client_lib::comms::compression::Compression;
+use atris_client_lib::comms::delivery::{MessageStatus, StatusUpdate};
+use atris_client_lib::comms::presence::Presence;
+use atris_client_lib::comms::{
+    framing, AtrisChannel, AtrisChannelParts, AtrisConnection, CloseReason, ConnectionEvent, SendError,
+    HIGH_WATER_MARK,
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::timeout;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Trickle the candidates of an initiator and a responder to each other
+fn spawn_trickles(initiator: &mut AtrisInitiator, responder: &mut AtrisResponder) {
+    let (initiator_signaller, responder_signaller) = LocalSignaller::pair();
+    let initiator_trickle = initiator.ice_trickle().expect("fresh initiator");
+    let responder_trickle = responder.ice_trickle().expect("fresh responder");
+    // The trickles keep watching the connections for as long as they are up
+    tokio::spawn(initiator_trickle.run(initiator_signaller));
+    tokio::spawn(responder_trickle.run(responder_signaller));
+}
+
+/// Connect an initiator and a responder over host candidates only, returning the initiator's and responder's parts
+async fn connected_parts() -> Result<(AtrisChannelParts<String>, AtrisChannelParts<String>)> {
+    connected_parts_of().await
+}
+
+/// Like [`connected_parts`], for channels carrying something other than strings
+async fn connected_parts_of<T>() -> Result<(AtrisChannelParts<T>, AtrisChannelParts<T>)>
+where
+    T: Serialize + Send + Sync + 'static,
+    for<'d> T: Deserialize<'d>,
+{
+    let mut initiator = AtrisInitiator::new(AtrisConnection::offline().await?).await?;
+    let mut responder = AtrisResponder::with_connection(AtrisConnection::offline().await?);
+    spawn_trickles(&mut initiator, &mut responder);
+
+    let (answer, responder_parts) = responder
+        .into_channel_parts_with(&initiator.encoded_local_description()?)
+        .await?;
+    let initiator_parts = initiator.into_channel_parts_with(&answer).await?;
+    let responder_parts = timeout(CONNECT_TIMEOUT, responder_parts)
+        .await?
+        .expect("responder data channel");
+    Ok((initiator_parts, responder_parts))
+}
+
+#[tokio::test]
+async fn messages_arrive_in_order_both_ways() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+
+    for i in 0..10 {
+        initiator.send(format!("to responder {i}")).await?;
+    }
+    for i in 0..10 {
+        let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+        assert_eq!(received.expect("open channel").ok(), Some(format!("to responder {i}")));
+    }
+
+    responder.send("to initiator".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, initiator.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("to initiator"));
+
+    // Nothing else was sent
+    assert!(initiator.try_receive().is_err());
+    assert!(responder.try_receive().is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn large_messages_are_fragmented_transparently() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+
+    // Far larger than a single data channel message can be
+    let large: String = (0..1024 * 1024).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+    initiator.send(large.clone()).await?;
+    initiator.send("after".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+    assert!(received.expect("open channel").ok() == Some(large));
+    let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("after"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn wrong_room_key_cannot_decrypt() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let mut initiator = AtrisChannel::new(initiator_parts, CipherKey::generate().as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, CipherKey::generate().as_cipher());
+
+    initiator.send("secret".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+    assert!(received.expect("open channel").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn remote_close_is_reported() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+    let mut events = initiator.events();
+
+    drop(responder);
+    let closed = timeout(CONNECT_TIMEOUT, async {
+        loop {
+            match events.recv().await? {
+                ConnectionEvent::Closed(reason) => return Result::<_>::Ok(reason),
+                _ => continue,
+            }
+        }
+    })
+    .await??;
+    assert_eq!(closed, CloseReason::Remote);
+    Ok(())
+}
+
+#[tokio::test]
+async fn messages_are_acknowledged_then_read() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+    let mut updates = initiator.status_updates();
+
+    let first = initiator.send("first".into()).await?;
+    let second = initiator.send("second".into()).await?;
+    assert_ne!(first, second);
+
+    // Delivery is acknowledged without the application reading anything
+    let mut delivered = Vec::new();
+    while delivered.len() < 2 {
+        let update = timeout(CONNECT_TIMEOUT, updates.recv()).await??;
+        match update.status {
+            MessageStatus::Pending => {}
+            MessageStatus::Delivered => delivered.push(update.id),
+            status => panic!("{} went straight to {status}", update.id),
+        }
+    }
+    assert_eq!(delivered, [first, second]);
+    assert_eq!(initiator.status(second), Some(MessageStatus::Delivered));
+
+    let received = timeout(CONNECT_TIMEOUT, responder.receive_with_id()).await?;
+    let (id, message) = received.expect("open channel").expect("decryptable message");
+    assert_eq!((id, message.as_str()), (first, "first"));
+    responder.mark_read(id).await?;
+    let update = timeout(CONNECT_TIMEOUT, updates.recv()).await??;
+    assert_eq!(
+        update,
+        StatusUpdate {
+            id: first,
+            status: MessageStatus::Read,
+        }
+    );
+    // Read messages are forgotten, as nothing can happen to them anymore
+    assert_eq!(initiator.status(first), None);
+    assert_eq!(initiator.status(second), Some(MessageStatus::Delivered));
+    Ok(())
+}
+
+#[tokio::test]
+async fn receipts_under_another_room_key_are_refused() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let mut initiator = AtrisChannel::new(initiator_parts, CipherKey::generate().as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, CipherKey::generate().as_cipher());
+    let mut events = initiator.events();
+
+    // The responder acknowledges what it cannot read, but the initiator cannot open the acknowledgement either
+    let id = initiator.send("secret".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder.receive_with_id()).await?;
+    assert!(received.expect("open channel").is_err());
+    responder.mark_read(id).await?;
+    // Its compression, the acknowledgement and the read receipt
+    let refused = timeout(CONNECT_TIMEOUT, async {
+        let mut refused = 0;
+        while refused < 3 {
+            if let ConnectionEvent::Error(_) = events.recv().await? {
+                refused += 1;
+            }
+        }
+        Result::<_>::Ok(refused)
+    })
+    .await??;
+    assert_eq!(refused, 3);
+    assert_eq!(initiator.status(id), Some(MessageStatus::Pending));
+    Ok(())
+}
+
+#[tokio::test]
+async fn presence_signals_skip_the_message_path() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+    let mut presence = responder.presence();
+    let mut updates = initiator.status_updates();
+
+    assert!(initiator.signal(Presence::Typing));
+    // Repeats are dropped until the interval has passed, so a signal can be made on every keystroke
+    assert!(!initiator.signal(Presence::Typing));
+    assert!(initiator.signal(Presence::StoppedTyping));
+    assert!(initiator.signal(Presence::Left));
+
+    for expected in [Presence::Typing, Presence::StoppedTyping, Presence::Left] {
+        assert_eq!(timeout(CONNECT_TIMEOUT, presence.recv()).await?, Some(expected));
+    }
+    // Signals are neither received as messages nor acknowledged
+    initiator.send("after".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("after"));
+    assert_eq!(timeout(CONNECT_TIMEOUT, updates.recv()).await??.status, MessageStatus::Pending);
+    assert_eq!(timeout(CONNECT_TIMEOUT, updates.recv()).await??.status, MessageStatus::Delivered);
+    Ok(())
+}
+
+#[tokio::test]
+async fn compressible_messages_are_compressed_once_agreed() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts_of::<Vec<u8>>().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+
+    // The responder said what it accepts before sending this
+    responder.send(b"hello".to_vec()).await?;
+    timeout(CONNECT_TIMEOUT, initiator.receive()).await?.expect("open channel").expect("decryptable");
+
+    let text = b"the same few words, over and over again. ".repeat(1000);
+    let random: Vec<u8> = (0..64 * 1024).map(|_| rand::random()).collect();
+    for message in [b"short".to_vec(), text, random] {
+        initiator.send(message.clone()).await?;
+        let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+        assert_eq!(received.expect("open channel").ok(), Some(message));
+    }
+
+    // Only the text was worth compressing
+    let stats = initiator.compression_stats();
+    assert_eq!((stats.messages, stats.compressed_messages), (3, 1));
+    assert!(stats.ratio().expect("compressed message") < 0.1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn compression_is_not_sent_to_an_end_which_turned_it_off() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::with_compression(responder_parts, room_key.as_cipher(), Compression::None);
+
+    responder.send("hello".into()).await?;
+    timeout(CONNECT_TIMEOUT, initiator.receive()).await?.expect("open channel").expect("decryptable");
+
+    let text = "the same few words, over and over again. ".repeat(1000);
+    initiator.send(text.clone()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+    assert_eq!(received.expect("open channel").ok(), Some(text));
+    assert_eq!(initiator.compression_stats().compressed_messages, 0);
+    assert_eq!(initiator.compression_stats().ratio(), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn sends_wait_for_the_buffer_to_drain() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+
+    let large: String = "x".repeat(256 * 1024);
+    let receiving = tokio::spawn(async move {
+        for _ in 0..8 {
+            assert!(responder.receive().await.expect("open channel").is_ok());
+        }
+    });
+    for _ in 0..8 {
+        timeout(CONNECT_TIMEOUT, initiator.send(large.clone())).await??;
+        // At most one fragment goes in once the buffer is full
+        assert!(initiator.buffered_amount().await <= HIGH_WATER_MARK + 2 * framing::MAX_FRAGMENT_PAYLOAD);
+    }
+    timeout(CONNECT_TIMEOUT, receiving).await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn send_failures_are_returned() -> Result<()> {
+    let (initiator_parts, responder_parts) = connected_parts().await?;
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+    let mut events = initiator.events();
+
+    drop(responder);
+    timeout(CONNECT_TIMEOUT, async {
+        while !matches!(events.recv().await?, ConnectionEvent::DataChannelClose { .. }) {}
+        Result::<_>::Ok(())
+    })
+    .await??;
+    match timeout(CONNECT_TIMEOUT, initiator.send("too late".into())).await? {
+        Err(SendError::DataChannelError(message, _) | SendError::ConnectionLost(message)) => {
+            assert_eq!(message, "too late")
+        }
+        other => panic!("expected the send to fail, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn named_channels_keep_their_messages_apart() -> Result<()> {
+    let mut initiator = AtrisInitiator::with_channels(
+        AtrisConnection::offline().await?,
+        [("chat", ChannelOptions::reliable()), ("live", ChannelOptions::lossy())],
+    )
+    .await?;
+    let mut responder = AtrisResponder::with_connection(AtrisConnection::offline().await?);
+    spawn_trickles(&mut initiator, &mut responder);
+
+    let (answer, mut incoming) = responder
+        .into_channels_with(&initiator.encoded_local_description()?)
+        .await?;
+    let mut initiator_channels = initiator.into_channels_with(&answer).await?;
+    // Taken in the opposite order they were opened in
+    let responder_live = timeout(CONNECT_TIMEOUT, incoming.take::<String>("live"))
+        .await?
+        .expect("live channel");
+    let responder_chat = timeout(CONNECT_TIMEOUT, incoming.take::<String>("chat"))
+        .await?
+        .expect("chat channel");
+
+    let room_key = CipherKey::generate();
+    let mut initiator_chat = AtrisChannel::new(
+        initiator_channels.take::<String>("chat").expect("chat channel"),
+        room_key.as_cipher(),
+    );
+    let mut initiator_live = AtrisChannel::new(
+        initiator_channels.take::<String>("live").expect("live channel"),
+        room_key.as_cipher(),
+    );
+    assert!(initiator_channels.take::<String>("chat").is_none());
+    let mut responder_chat = AtrisChannel::new(responder_chat, room_key.as_cipher());
+    let mut responder_live = AtrisChannel::new(responder_live, room_key.as_cipher());
+    assert_eq!(responder_live.label(), "live");
+
+    initiator_chat.send("on chat".into()).await?;
+    initiator_live.send("on live".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder_live.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("on live"));
+    let received = timeout(CONNECT_TIMEOUT, responder_chat.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("on chat"));
+    assert!(responder_chat.try_receive().is_err());
+    assert!(responder_live.try_receive().is_err());
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! Fixtures shared between the test files of this crate, which each use only some of them
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use atris_client_lib::comms::AtrisConnection;
+use atris_client_lib::history::History;
+use atris_client_lib::profile::ProfileKey;
+use tempfile::TempDir;
+
+/// An offline connection which puts every candidate in its description, for exchanges with no way to trickle them
+pub async fn untrickled() -> Result<AtrisConnection> {
+    AtrisConnection::builder().offline().untrickled().build().await
+}
+
+/// A fresh directory of its own, which is removed when it is dropped, even if the test fails
+pub fn temporary_dir() -> Result<TempDir> {
+    Ok(tempfile::Builder::new().prefix("atris-").tempdir()?)
+}
+
+/// A history in a fresh temporary directory, which has room for other files beside the history
+pub fn temporary_history(passphrase: &str) -> Result<(History, TempDir)> {
+    let dir = temporary_dir()?;
+    let key = ProfileKey::derive(passphrase, &[7; 16])?;
+    Ok((History::at(history_dir(&dir), key), dir))
+}
+
+/// Where [`temporary_history`] keeps the history in its directory
+pub fn history_dir(dir: &TempDir) -> PathBuf {
+    dir.path().join("history")
+}
@@ -0,0 +1,351 @@
+//! Tests of the commands and events [`console`] reads and writes, for people and in JSON lines, and of
+//! [`Conversation`]s between two offline connections in the same process, and the history they record
+
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::message::{ChatMessage, MessageBody};
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::channels::DEFAULT_CHANNEL_LABEL;
+use atris_client_lib::comms::console::{ChatCommand, ChatEvent, Conversation, OutputMode};
+use atris_client_lib::comms::delivery::MessageStatus;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::transfer::{self, FileTransfers, TransferEvent, TransferMessage, TRANSFER_CHANNEL_LABEL};
+use atris_client_lib::comms::trickle::LocalSignaller;
+use atris_client_lib::comms::{AtrisChannel, AtrisChannelParts, AtrisConnection};
+use atris_client_lib::history::Direction;
+use serde_json::json;
+use tokio::time::timeout;
+
+use common::{temporary_dir, temporary_history};
+
+const EVENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[test]
+fn typed_lines_are_messages_unless_they_are_commands() -> Result<()> {
+    assert_eq!(
+        ChatCommand::parse("  hello there ")?,
+        ChatCommand::Send {
+            text: "hello there".to_owned()
+        }
+    );
+    assert_eq!(
+        ChatCommand::parse("/send my file.txt")?,
+        ChatCommand::SendFile {
+            path: "my file.txt".into()
+        }
+    );
+    assert_eq!(
+        ChatCommand::parse("/accept 3")?,
+        ChatCommand::Accept { id: 3, path: None }
+    );
+    assert_eq!(
+        ChatCommand::parse("/accept 3 saved.txt")?,
+        ChatCommand::Accept {
+            id: 3,
+            path: Some("saved.txt".into())
+        }
+    );
+    assert_eq!(ChatCommand::parse("/reject 4")?, ChatCommand::Reject { id: 4 });
+    assert_eq!(ChatCommand::parse("/cancel 5")?, ChatCommand::Cancel { id: 5 });
+    Ok(())
+}
+
+#[test]
+fn malformed_commands_explain_their_usage() {
+    for (line, usage) in [
+        ("/send", "Usage: /send <path>"),
+        ("/accept", "Usage: /accept <id> [path]"),
+        ("/reject first", "Usage: /reject <id>"),
+        ("/cancel", "Usage: /cancel <id>"),
+    ] {
+        let error = ChatCommand::parse(line).expect_err("a malformed command");
+        assert_eq!(error.to_string(), usage);
+    }
+}
+
+#[test]
+fn json_commands_are_tagged_by_their_command() -> Result<()> {
+    let mode = OutputMode::Json;
+    assert_eq!(
+        mode.parse_command(r#"{"command":"send","text":"/send is not a command here"}"#)?,
+        ChatCommand::Send {
+            text: "/send is not a command here".to_owned()
+        }
+    );
+    assert_eq!(
+        mode.parse_command(r#"{"command":"send_file","path":"a.txt"}"#)?,
+        ChatCommand::SendFile { path: "a.txt".into() }
+    );
+    assert_eq!(
+        mode.parse_command(r#"{"command":"accept","id":7}"#)?,
+        ChatCommand::Accept { id: 7, path: None }
+    );
+    assert!(mode.parse_command("hello").is_err());
+    assert!(mode.parse_command(r#"{"command":"shout","text":"hi"}"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn events_are_tagged_by_their_event() -> Result<()> {
+    let message = ChatEvent::Message { text: "hi".to_owned() };
+    assert_eq!(
+        serde_json::to_value(&message)?,
+        json!({ "event": "message", "text": "hi" })
+    );
+    assert_eq!(message.to_string(), "From other user: 'hi'");
+
+    let progress = ChatEvent::from(TransferEvent::Progress {
+        id: 2,
+        bytes: 10,
+        size: 20,
+    });
+    assert_eq!(
+        serde_json::to_value(&progress)?,
+        json!({ "event": "transfer_progress", "id": 2, "bytes": 10, "size": 20 })
+    );
+    assert_eq!(progress.to_string(), "Transfer 2: 10/20 bytes");
+
+    assert_eq!(
+        serde_json::to_value(ChatEvent::Reconnecting)?,
+        json!({ "event": "reconnecting" })
+    );
+    Ok(())
+}
+
+/// The chat and file channels of one end
+type ConversationParts = (AtrisChannelParts<ChatMessage>, AtrisChannelParts<TransferMessage>);
+
+/// Connect two offline connections with chat and file channels, returning the offering and answering ends and the
+/// key they share
+async fn connected_parts() -> Result<(ConversationParts, ConversationParts, CipherKey)> {
+    let mut initiator =
+        AtrisInitiator::with_channels(AtrisConnection::offline().await?, transfer::chat_and_file_channels()).await?;
+    let mut responder = AtrisResponder::with_connection(AtrisConnection::offline().await?);
+    let (initiator_signaller, responder_signaller) = LocalSignaller::pair();
+    tokio::spawn(
+        initiator
+            .ice_trickle()
+            .expect("fresh initiator")
+            .run(initiator_signaller),
+    );
+    tokio::spawn(
+        responder
+            .ice_trickle()
+            .expect("fresh responder")
+            .run(responder_signaller),
+    );
+
+    let (answer, mut incoming) = responder
+        .into_channels_with(&initiator.encoded_local_description()?)
+        .await?;
+    let mut channels = initiator.into_channels_with(&answer).await?;
+    let offering = (
+        channels.take(DEFAULT_CHANNEL_LABEL).expect("chat channel"),
+        channels.take(TRANSFER_CHANNEL_LABEL).expect("file channel"),
+    );
+    let answering = (
+        timeout(EVENT_TIMEOUT, incoming.take(DEFAULT_CHANNEL_LABEL))
+            .await?
+            .expect("chat channel"),
+        timeout(EVENT_TIMEOUT, incoming.take(TRANSFER_CHANNEL_LABEL))
+            .await?
+            .expect("file channel"),
+    );
+    Ok((offering, answering, CipherKey::generate()))
+}
+
+fn conversation((chat, files): ConversationParts, room_key: &CipherKey) -> Conversation {
+    Conversation::new(
+        AtrisChannel::new(chat, room_key.as_cipher()),
+        Some(FileTransfers::spawn(AtrisChannel::new(files, room_key.as_cipher()))),
+    )
+}
+
+/// Connect two offline connections with chat and file channels, returning the offering and answering ends
+async fn connected_conversations() -> Result<(Conversation, Conversation)> {
+    let (offering, answering, room_key) = connected_parts().await?;
+    Ok((conversation(offering, &room_key), conversation(answering, &room_key)))
+}
+
+/// Wait for the first event `wanted` picks out, skipping the others
+async fn wait_for<T>(conversation: &mut Conversation, mut wanted: impl FnMut(ChatEvent) -> Option<T>) -> Result<T> {
+    loop {
+        if let Some(found) = wanted(timeout(EVENT_TIMEOUT, conversation.next_event()).await??) {
+            return Ok(found);
+        }
+    }
+}
+
+#[tokio::test]
+async fn conversations_carry_messages_and_files() -> Result<()> {
+    let (mut alice, mut bob) = connected_conversations().await?;
+    let sent = alice.run(ChatCommand::parse("hello bob")?).await?;
+    assert_eq!(sent, None);
+    let text = wait_for(&mut bob, |event| match event {
+        ChatEvent::Message { text } => Some(text),
+        _ => None,
+    })
+    .await?;
+    assert_eq!(text, "hello bob");
+
+    let dir = temporary_dir()?;
+    let source = dir.path().join("notes.txt");
+    std::fs::write(&source, b"some notes")?;
+    let offered = alice.run(ChatCommand::SendFile { path: source.clone() }).await?;
+    let Some(ChatEvent::FileOffered { id, path }) = offered else {
+        panic!("the file was not offered: {offered:?}");
+    };
+    assert_eq!(path, source);
+
+    let (offered_id, name) = wait_for(&mut bob, |event| match event {
+        ChatEvent::TransferOffered { id, name, .. } => Some((id, name)),
+        _ => None,
+    })
+    .await?;
+    assert_eq!((offered_id, name.as_str()), (id, "notes.txt"));
+    let destination = dir.path().join("received.txt");
+    bob.run(ChatCommand::parse(&format!("/accept {id} {}", destination.display()))?)
+        .await?;
+    wait_for(&mut bob, |event| match event {
+        ChatEvent::TransferCompleted { id: completed, .. } => (completed == id).then_some(()),
+        _ => None,
+    })
+    .await?;
+    assert_eq!(std::fs::read(&destination)?, b"some notes");
+    Ok(())
+}
+
+#[tokio::test]
+async fn conversations_record_what_is_sent_and_received() -> Result<()> {
+    let (history, _dir) = temporary_history("hunter2")?;
+    let (alice, bob) = connected_conversations().await?;
+    let mut alice = alice.record_in(history.conversation("alice's")?);
+    let mut bob = bob.record_in(history.conversation("bob's")?);
+
+    alice.run(ChatCommand::parse("hello bob")?).await?;
+    wait_for(&mut bob, |event| match event {
+        ChatEvent::Message { text } => Some(text),
+        _ => None,
+    })
+    .await?;
+    let received = bob.history().expect("recording").entries();
+    assert_eq!(received.len(), 1);
+    assert_eq!(
+        (received[0].direction, received[0].text.as_str()),
+        (Direction::Received, "hello bob")
+    );
+
+    // Status updates are recorded without being events, so keep asking until the acknowledgement arrives
+    let delivered = async {
+        loop {
+            let _ = timeout(Duration::from_millis(100), alice.next_event()).await;
+            let sent = &alice.history().expect("recording").entries()[0];
+            if sent.status != MessageStatus::Pending {
+                return sent.clone();
+            }
+        }
+    };
+    let sent = timeout(EVENT_TIMEOUT, delivered).await?;
+    assert_eq!((sent.direction, sent.text.as_str()), (Direction::Sent, "hello bob"));
+    assert_eq!(history.conversation("alice's")?.entries()[0].status, sent.status);
+    Ok(())
+}
+
+#[tokio::test]
+async fn conversations_introduce_clients_and_flag_newer_messages() -> Result<()> {
+    let (mut alice, mut bob) = connected_conversations().await?;
+    alice.introduce("atris gui 0.1.0").await?;
+    let client = wait_for(&mut bob, |event| match event {
+        ChatEvent::Hello { client } => Some(client),
+        _ => None,
+    })
+    .await?;
+    assert_eq!(client, "atris gui 0.1.0");
+
+    // As a newer client would send a kind this one has never heard of
+    alice
+        .channel()
+        .send(ChatMessage::new(MessageBody::Unsupported { kind: 42 }))
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    alice.run(ChatCommand::parse("still there?")?).await?;
+    let kind = wait_for(&mut bob, |event| match event {
+        ChatEvent::Unsupported { kind } => Some(kind),
+        ChatEvent::Message { text } => panic!("{text:?} arrived before the notice"),
+        _ => None,
+    })
+    .await?;
+    assert_eq!(kind, 42);
+    let text = wait_for(&mut bob, |event| match event {
+        ChatEvent::Message { text } => Some(text),
+        _ => None,
+    })
+    .await?;
+    assert_eq!(text, "still there?");
+    Ok(())
+}
+
+#[tokio::test]
+async fn offered_names_cannot_save_files_outside_the_download_directory() -> Result<()> {
+    let dir = temporary_dir()?;
+    let downloads = dir.path().join("downloads");
+    std::fs::create_dir_all(&downloads)?;
+    let ((_alice_chat, alice_files), answering, room_key) = connected_parts().await?;
+    // Alice offers files under names of her own making, rather than their own
+    let mut alice_files = AtrisChannel::<TransferMessage>::new(alice_files, room_key.as_cipher());
+    let mut bob = conversation(answering, &room_key).save_files_in(&downloads);
+
+    let absolute = dir.path().join("absolute.txt").display().to_string();
+    let offers = [
+        (1, "../escaped.txt", "escaped.txt"),
+        (2, absolute.as_str(), "absolute.txt"),
+    ];
+    for (id, name, saved_as) in offers {
+        alice_files
+            .send(TransferMessage::Offer {
+                id,
+                name: name.to_owned(),
+                size: 5,
+                sha256: [0; 32],
+            })
+            .await?;
+        wait_for(&mut bob, |event| match event {
+            ChatEvent::TransferOffered { id: offered, .. } => (offered == id).then_some(()),
+            _ => None,
+        })
+        .await?;
+        bob.run(ChatCommand::parse(&format!("/accept {id}"))?).await?;
+        // Accepting starts the part file, next to where the file will be
+        assert!(
+            downloads.join(format!("{saved_as}.part")).exists(),
+            "{name} was not saved in the downloads"
+        );
+        assert!(
+            !dir.path().join(format!("{saved_as}.part")).exists(),
+            "{name} was saved outside the downloads"
+        );
+    }
+
+    // Nothing is left of some names, so they need a path
+    alice_files
+        .send(TransferMessage::Offer {
+            id: 3,
+            name: "..".to_owned(),
+            size: 5,
+            sha256: [0; 32],
+        })
+        .await?;
+    wait_for(&mut bob, |event| match event {
+        ChatEvent::TransferOffered { id, .. } => (id == 3).then_some(()),
+        _ => None,
+    })
+    .await?;
+    assert!(bob.run(ChatCommand::parse("/accept 3")?).await.is_err());
+    assert_eq!(transfer::local_file_name(""), None);
+    assert_eq!(transfer::local_file_name("/"), None);
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Tests of exporting conversations from a [`History`] to JSON and HTML archives, encrypted or not, and importing
+//! them into another
+
+mod common;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::export::{ExportArchive, ExportContents};
+use atris_client_lib::export::{self, ExportError, ExportFormat};
+use atris_client_lib::history::{Direction, History};
+use tempfile::TempDir;
+
+use common::temporary_history;
+
+/// A history with a conversation in it, with a file to attach
+fn conversation() -> Result<(History, TempDir, PathBuf)> {
+    let (history, dir) = temporary_history("hunter2")?;
+    let mut log = history.conversation(&History::room(7))?;
+    log.record_sent(1, "see the <attached> notes & reply")?;
+    log.record_received("got them")?;
+    let notes = dir.path().join("notes.txt");
+    std::fs::write(&notes, b"some notes")?;
+    Ok((history, dir, notes))
+}
+
+#[test]
+fn exports_import_into_another_history() -> Result<()> {
+    let (history, dir, notes) = conversation()?;
+    let exported = export::export(&history.conversation(&History::room(7))?, &[notes])?;
+    for name in ["room-7.json", "room-7.html"] {
+        let path = dir.path().join(name);
+        export::write(&exported, &path, ExportFormat::of(&path), None)?;
+
+        let (other, other_dir) = temporary_history("another passphrase")?;
+        let archive = export::read(&path)?;
+        assert!(!archive.is_encrypted());
+        let imported = export::import(
+            &other,
+            export::open(archive, None)?,
+            None,
+            &other_dir.path().join("files"),
+        )?;
+        assert_eq!((imported.conversation.as_str(), imported.messages), ("room 7", 2));
+        assert_eq!(imported.files.len(), 1);
+        assert_eq!(std::fs::read(&imported.files[0])?, b"some notes");
+
+        let log = other.conversation(&History::room(7))?;
+        let entries: Vec<_> = log
+            .entries()
+            .iter()
+            .map(|entry| (entry.direction, entry.text.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (Direction::Sent, "see the <attached> notes & reply"),
+                (Direction::Received, "got them")
+            ]
+        );
+        assert_eq!(
+            log.entries()[0].timestamp,
+            history.conversation(&History::room(7))?.entries()[0].timestamp
+        );
+
+        // Importing again adds no messages, and keeps the file already saved
+        let again = export::import(
+            &other,
+            export::open(export::read(&path)?, None)?,
+            None,
+            &other_dir.path().join("files"),
+        )?;
+        assert_eq!(again.messages, 0);
+        assert_ne!(again.files, imported.files);
+        assert_eq!(other.conversation(&History::room(7))?.len(), 2);
+    }
+
+    let transcript = std::fs::read_to_string(dir.path().join("room-7.html"))?;
+    assert!(transcript.contains("see the &lt;attached&gt; notes &amp; reply"));
+    assert!(!transcript.contains("<attached>"));
+    Ok(())
+}
+
+#[test]
+fn encrypted_exports_need_their_passphrase() -> Result<()> {
+    let (history, dir, notes) = conversation()?;
+    let exported = export::export(&history.conversation(&History::room(7))?, &[notes])?;
+    for name in ["room-7.json", "room-7.html"] {
+        let path = dir.path().join(name);
+        export::write(&exported, &path, ExportFormat::of(&path), Some("correct horse"))?;
+        let written = std::fs::read_to_string(&path)?;
+        assert!(!written.contains("got them") && !written.contains("notes.txt"));
+
+        assert!(export::read(&path)?.is_encrypted());
+        let missing = export::open(export::read(&path)?, None);
+        assert!(matches!(missing, Err(ExportError::PassphraseRequired)));
+        let wrong = export::open(export::read(&path)?, Some("wrong horse"));
+        assert!(matches!(wrong, Err(ExportError::WrongPassphrase)));
+        assert_eq!(export::open(export::read(&path)?, Some("correct horse"))?, exported);
+    }
+    Ok(())
+}
+
+#[test]
+fn newer_and_foreign_files_are_refused() -> Result<()> {
+    let (_, dir) = temporary_history("hunter2")?;
+    let newer = dir.path().join("newer.json");
+    std::fs::write(&newer, r#"{"version":99,"kind":"something_new"}"#)?;
+    assert!(matches!(export::read(&newer), Err(ExportError::UnsupportedVersion(99))));
+    let foreign = dir.path().join("page.html");
+    std::fs::write(&foreign, "<html><body>Hello</body></html>")?;
+    assert!(matches!(export::read(&foreign), Err(ExportError::NotAnExport)));
+    Ok(())
+}
+
+#[test]
+fn tampered_encrypted_exports_are_refused() -> Result<()> {
+    let (history, dir, notes) = conversation()?;
+    let exported = export::export(&history.conversation(&History::room(7))?, &[notes])?;
+    let path = dir.path().join("room-7.json");
+    export::write(&exported, &path, ExportFormat::Json, Some("correct horse"))?;
+    let tampered = |tamper: fn(&mut Vec<u8>)| -> Result<ExportArchive> {
+        let mut archive = export::read(&path)?;
+        if let ExportContents::Encrypted { export, .. } = &mut archive.contents {
+            tamper(export);
+        }
+        Ok(archive)
+    };
+
+    // The bincode of the encrypted export starts with the length of its nonce, then the nonce
+    let short_nonce = tampered(|export| {
+        export[..8].copy_from_slice(&11u64.to_le_bytes());
+        export.remove(8);
+    })?;
+    let result = export::open(short_nonce, Some("correct horse"));
+    assert!(matches!(result, Err(ExportError::WrongPassphrase)));
+    let garbage = tampered(|export| *export = vec![1, 2, 3])?;
+    let result = export::open(garbage, Some("correct horse"));
+    assert!(matches!(result, Err(ExportError::WrongPassphrase)));
+    Ok(())
+}
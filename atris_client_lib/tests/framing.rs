@@ -0,0 +1,94 @@
+//! Fragmenting messages and putting them back together
+
+use atris_client_lib::comms::framing::{
+    fragment, FramingError, Reassembler, MAX_FRAGMENT_PAYLOAD, MAX_MESSAGE_SIZE, MAX_PARTIAL_MESSAGES,
+};
+
+fn message(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn small_and_empty_messages_are_one_fragment() {
+    let mut reassembler = Reassembler::default();
+    for message in [Vec::new(), message(10), message(MAX_FRAGMENT_PAYLOAD)] {
+        let fragments = fragment(7, &message);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(reassembler.push(&fragments[0]), Ok(Some(message)));
+    }
+}
+
+#[test]
+fn fragments_reassemble_in_any_order() {
+    let message = message(3 * MAX_FRAGMENT_PAYLOAD + 5);
+    let fragments = fragment(1, &message);
+    assert_eq!(fragments.len(), 4);
+
+    let mut reassembler = Reassembler::default();
+    for index in [2, 0, 3] {
+        assert_eq!(reassembler.push(&fragments[index]), Ok(None));
+    }
+    // Duplicates are ignored
+    assert_eq!(reassembler.push(&fragments[0]), Ok(None));
+    assert_eq!(reassembler.push(&fragments[1]), Ok(Some(message)));
+}
+
+#[test]
+fn interleaved_messages_reassemble_separately() {
+    let (first, second) = (message(2 * MAX_FRAGMENT_PAYLOAD), message(MAX_FRAGMENT_PAYLOAD + 1));
+    let (first_fragments, second_fragments) = (fragment(1, &first), fragment(2, &second));
+
+    let mut reassembler = Reassembler::default();
+    assert_eq!(reassembler.push(&first_fragments[0]), Ok(None));
+    assert_eq!(reassembler.push(&second_fragments[0]), Ok(None));
+    assert_eq!(reassembler.push(&second_fragments[1]), Ok(Some(second)));
+    assert_eq!(reassembler.push(&first_fragments[1]), Ok(Some(first)));
+}
+
+#[test]
+fn oversized_messages_are_rejected() {
+    let message = message(4 * MAX_FRAGMENT_PAYLOAD);
+    let fragments = fragment(1, &message);
+
+    let mut reassembler = Reassembler::new(2 * MAX_FRAGMENT_PAYLOAD);
+    assert!(matches!(
+        reassembler.push(&fragments[0]),
+        Err(FramingError::MessageTooLarge(_))
+    ));
+    assert_eq!(reassembler.push(&[0; 4]), Err(FramingError::MalformedFragment));
+}
+
+#[test]
+fn oldest_partial_message_is_dropped() {
+    let message = message(2 * MAX_FRAGMENT_PAYLOAD);
+    let mut reassembler = Reassembler::default();
+    // Start one more message than can be held, which pushes out the first
+    for id in 0..=MAX_PARTIAL_MESSAGES as u32 {
+        assert_eq!(reassembler.push(&fragment(id, &message)[0]), Ok(None));
+    }
+    assert_eq!(reassembler.push(&fragment(1, &message)[1]), Ok(Some(message.clone())));
+    assert_eq!(reassembler.push(&fragment(0, &message)[1]), Ok(None));
+}
+
+#[test]
+fn oldest_partial_messages_are_dropped_to_stay_within_the_byte_budget() {
+    let message = message(2 * MAX_FRAGMENT_PAYLOAD);
+    // Room for four fragments, so the fifth message pushes out the first
+    let mut reassembler = Reassembler::with_partial_budget(MAX_MESSAGE_SIZE, 4 * MAX_FRAGMENT_PAYLOAD);
+    for id in 0..5 {
+        assert_eq!(reassembler.push(&fragment(id, &message)[0]), Ok(None));
+    }
+    // The last fragment of a message needs room too, which pushes out the next oldest
+    assert_eq!(reassembler.push(&fragment(1, &message)[1]), Ok(Some(message.clone())));
+    assert_eq!(reassembler.push(&fragment(3, &message)[1]), Ok(Some(message.clone())));
+    assert_eq!(reassembler.push(&fragment(0, &message)[1]), Ok(None));
+    assert_eq!(reassembler.push(&fragment(2, &message)[1]), Ok(None));
+
+    // A message which cannot fit on its own is rejected
+    let mut reassembler = Reassembler::with_partial_budget(MAX_MESSAGE_SIZE, MAX_FRAGMENT_PAYLOAD);
+    assert_eq!(reassembler.push(&fragment(0, &message)[0]), Ok(None));
+    assert!(matches!(
+        reassembler.push(&fragment(0, &message)[1]),
+        Err(FramingError::MessageTooLarge(_))
+    ));
+}
@@ -0,0 +1,197 @@
+//! Tests of recording, reopening, paging through and searching the [`History`] in a directory of its own
+
+mod common;
+
+use std::io::Write;
+
+use anyhow::Result;
+use atris_client_lib::comms::delivery::{MessageStatus, StatusUpdate};
+use atris_client_lib::history::{Direction, History, HistoryEntry, HistoryError};
+use atris_client_lib::profile::ProfileKey;
+
+use common::{history_dir, temporary_history};
+
+fn texts(page: &[HistoryEntry]) -> Vec<&str> {
+    page.iter().map(|entry| entry.text.as_str()).collect()
+}
+
+#[test]
+fn conversations_are_kept_across_reopening() -> Result<()> {
+    let (history, _dir) = temporary_history("hunter2")?;
+    let mut log = history.conversation(&History::room(7))?;
+    assert!(log.is_empty());
+    log.record_sent(1, "hello bob")?;
+    log.record_received("hi alice")?;
+    log.record_failed("are you there?")?;
+
+    let reopened = history.conversation(&History::room(7))?;
+    assert_eq!(reopened.name(), "room 7");
+    let entries: Vec<_> = reopened
+        .entries()
+        .iter()
+        .map(|entry| (entry.id, entry.direction, entry.text.as_str(), entry.status))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            (0, Direction::Sent, "hello bob", MessageStatus::Pending),
+            (1, Direction::Received, "hi alice", MessageStatus::Delivered),
+            (2, Direction::Sent, "are you there?", MessageStatus::Failed),
+        ]
+    );
+    assert!(history.conversation(&History::room(8))?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn delivery_statuses_are_kept() -> Result<()> {
+    let (history, _dir) = temporary_history("hunter2")?;
+    let mut log = history.conversation("bob")?;
+    log.record_sent(41, "first")?;
+    log.record_sent(42, "second")?;
+    for status in [MessageStatus::Delivered, MessageStatus::Read] {
+        log.update_status(StatusUpdate { id: 42, status })?;
+    }
+    // Messages sent on another channel are not this log's to update
+    log.update_status(StatusUpdate {
+        id: 99,
+        status: MessageStatus::Read,
+    })?;
+
+    let statuses: Vec<_> = history
+        .conversation("bob")?
+        .entries()
+        .iter()
+        .map(|entry| entry.status)
+        .collect();
+    assert_eq!(statuses, [MessageStatus::Pending, MessageStatus::Read]);
+    Ok(())
+}
+
+#[test]
+fn conversations_are_paged_from_the_end() -> Result<()> {
+    let (history, _dir) = temporary_history("hunter2")?;
+    let mut log = history.conversation("bob")?;
+    for i in 0..10 {
+        log.record_received(format!("message {i}"))?;
+    }
+    let last = log.page(None, 4);
+    assert_eq!(texts(last), ["message 6", "message 7", "message 8", "message 9"]);
+    let before = log.page(Some(last[0].id), 4);
+    assert_eq!(texts(before), ["message 2", "message 3", "message 4", "message 5"]);
+    let first = log.page(Some(before[0].id), 4);
+    assert_eq!(texts(first), ["message 0", "message 1"]);
+    assert!(log.page(Some(0), 4).is_empty());
+    Ok(())
+}
+
+#[test]
+fn every_conversation_is_searched() -> Result<()> {
+    let (history, _dir) = temporary_history("hunter2")?;
+    history.conversation("bob")?.record_received("Lunch at noon?")?;
+    history
+        .conversation("carol")?
+        .record_sent(1, "lunch is at NOON tomorrow")?;
+    history.conversation("carol")?.record_sent(2, "dinner at noon")?;
+
+    let mut hits: Vec<_> = history
+        .search("noon lunch")?
+        .into_iter()
+        .map(|hit| (hit.conversation, hit.entry.text))
+        .collect();
+    hits.sort();
+    assert_eq!(
+        hits,
+        [
+            ("bob".to_owned(), "Lunch at noon?".to_owned()),
+            ("carol".to_owned(), "lunch is at NOON tomorrow".to_owned()),
+        ]
+    );
+    assert!(history.search("   ")?.is_empty());
+    let mut conversations = history.conversations()?;
+    conversations.sort();
+    assert_eq!(conversations, ["bob", "carol"]);
+    Ok(())
+}
+
+#[test]
+fn history_is_unreadable_without_the_key() -> Result<()> {
+    let (history, dir) = temporary_history("hunter2")?;
+    history.conversation("bob")?.record_sent(1, "the secret plan")?;
+    for file in std::fs::read_dir(history_dir(&dir))? {
+        let path = file?.path();
+        let name = path.file_name().expect("a file name").to_string_lossy().into_owned();
+        assert!(
+            !name.contains("bob"),
+            "the file is named after the conversation: {name}"
+        );
+        let bytes = std::fs::read(&path)?;
+        assert!(!String::from_utf8_lossy(&bytes).contains("secret"));
+    }
+
+    let key = ProfileKey::derive("hunter3", &[7; 16])?;
+    let stolen = History::at(history_dir(&dir), key);
+    assert!(stolen.conversation("bob")?.is_empty());
+    assert!(matches!(stolen.conversations(), Err(HistoryError::Corrupt)));
+    Ok(())
+}
+
+#[test]
+fn records_cut_short_by_a_crash_are_written_over() -> Result<()> {
+    let (history, dir) = temporary_history("hunter2")?;
+    let mut log = history.conversation("bob")?;
+    log.record_received("first")?;
+    log.record_received("second")?;
+    let path = std::fs::read_dir(history_dir(&dir))?
+        .next()
+        .expect("the log file")?
+        .path();
+    let torn = |length: u64| -> Result<()> {
+        std::fs::OpenOptions::new().write(true).open(&path)?.set_len(length)?;
+        Ok(())
+    };
+
+    // The crash cuts the second record short, and the next message is recorded after the first
+    torn(std::fs::metadata(&path)?.len() - 3)?;
+    let mut reopened = history.conversation("bob")?;
+    assert_eq!(texts(reopened.entries()), ["first"]);
+    reopened.record_received("third")?;
+    assert_eq!(texts(history.conversation("bob")?.entries()), ["first", "third"]);
+
+    // Even when it cuts short the record naming the conversation
+    torn(3)?;
+    let mut reopened = history.conversation("bob")?;
+    assert!(reopened.is_empty());
+    reopened.record_received("fourth")?;
+    assert_eq!(texts(history.conversation("bob")?.entries()), ["fourth"]);
+    assert_eq!(history.conversations()?, ["bob"]);
+    Ok(())
+}
+
+#[test]
+fn reading_leaves_a_record_still_being_written_alone() -> Result<()> {
+    let (history, dir) = temporary_history("hunter2")?;
+    let mut log = history.conversation("bob")?;
+    log.record_received("first")?;
+    let path = std::fs::read_dir(history_dir(&dir))?
+        .next()
+        .expect("the log file")?
+        .path();
+    let first = std::fs::metadata(&path)?.len() as usize;
+    log.record_received("second")?;
+    let bytes = std::fs::read(&path)?;
+
+    // Another process has only written part of the second record when this one reads the conversation
+    std::fs::write(&path, &bytes[..first + 5])?;
+    assert_eq!(texts(history.conversation("bob")?.entries()), ["first"]);
+    assert_eq!(history.search("first")?.len(), 1);
+    assert_eq!(std::fs::metadata(&path)?.len() as usize, first + 5);
+
+    // So the rest of it still lands after the part already written
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)?
+        .write_all(&bytes[first + 5..])?;
+    assert_eq!(texts(history.conversation("bob")?.entries()), ["first", "second"]);
+    Ok(())
+}
@@ -0,0 +1,318 @@
+//! End to end tests of [`invite`] between clients in the same process, through an in-memory stand-in for the
+//! authentication server
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::create_room::{CreateRoomError, CreateRoomRequest, CreateRoomResponse};
+use atris_client_lib::atris_common::join_room::{JoinRoomError, JoinRoomRequest, JoinRoomResponse};
+use atris_client_lib::atris_common::set_room_responder::{
+    SetRoomResponderError, SetRoomResponderRequest, SetRoomResponderResponse,
+};
+use atris_client_lib::atris_common::signal_room::{
+    SignalMessage, SignalRole, SignalRoomError, SignalRoomRequest, SignalRoomResponse,
+};
+use atris_client_lib::atris_common::{CipherKey, Encrypted, RoomData};
+use atris_client_lib::comms::channels::DEFAULT_CHANNEL_LABEL;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::invite::{self, Invitation};
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::trickle::{RoomSignaller, Signaller};
+use atris_client_lib::comms::{AtrisChannel, AtrisConnection};
+use atris_client_lib::{AtrisAuthClient, InvocationError, InvocationResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::time::timeout;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+struct Room {
+    creator: String,
+    invited: String,
+    data: Option<Encrypted<RoomData>>,
+    initiator_signals: Vec<SignalMessage>,
+    responder_signals: Vec<SignalMessage>,
+}
+
+/// Sessions and rooms kept the way the authentication server keeps them
+#[derive(Default)]
+struct State {
+    /// The usernames by session, in the order they logged in
+    sessions: Vec<(CipherKey, String)>,
+    rooms: HashMap<u16, Room>,
+}
+impl State {
+    fn username(&self, session_id: &CipherKey) -> Option<String> {
+        self.sessions
+            .iter()
+            .find(|(id, _)| id.as_ref() == session_id.as_ref())
+            .map(|(_, username)| username.clone())
+    }
+
+    /// The latest session of `username`
+    fn session(&self, username: &str) -> Option<CipherKey> {
+        self.sessions.iter().rev().find(|(_, name)| name == username).map(|(id, _)| id.clone())
+    }
+
+    fn create_room(&mut self, request: CreateRoomRequest) -> Result<CreateRoomResponse, CreateRoomError> {
+        let creator = self
+            .username(&request.session_id)
+            .ok_or(CreateRoomError::InvalidSessionId(request.session_id))?;
+        self.session(&request.other_user_name)
+            .ok_or(CreateRoomError::NoSessionForUser(request.other_user_name.clone()))?;
+        let room_id = self.rooms.len() as u16;
+        let room = Room {
+            creator,
+            invited: request.other_user_name,
+            data: None,
+            initiator_signals: Vec::new(),
+            responder_signals: Vec::new(),
+        };
+        self.rooms.insert(room_id, room);
+        Ok(CreateRoomResponse {
+            room_id,
+            // Stale by the time anyone could answer it
+            initiator_string: String::new(),
+        })
+    }
+
+    fn set_room_responder(
+        &mut self,
+        request: SetRoomResponderRequest,
+    ) -> Result<SetRoomResponderResponse, SetRoomResponderError> {
+        let username = self
+            .username(&request.session_id)
+            .ok_or(SetRoomResponderError::InvalidSessionId(request.session_id))?;
+        let other_session = self
+            .session(&request.other_user_name)
+            .ok_or(SetRoomResponderError::NoSessionForUser(request.other_user_name.clone()))?;
+        let room = self
+            .rooms
+            .get_mut(&request.room_id)
+            .ok_or(SetRoomResponderError::InvalidRoomId(request.room_id))?;
+        if room.creator != username {
+            return Err(SetRoomResponderError::NotRoomCreator(username));
+        }
+        if room.invited != request.other_user_name {
+            return Err(SetRoomResponderError::NotInvitedUser(request.other_user_name));
+        }
+        let room_symmetric_key = CipherKey::generate();
+        let room_data = RoomData {
+            responder_string: request.responder_string,
+            symmetric_key: room_symmetric_key.clone(),
+        };
+        room.data = Some(
+            Encrypted::encrypt(&room_data, &mut other_session.as_cipher())
+                .map_err(|_| SetRoomResponderError::EncryptionError)?,
+        );
+        Ok(SetRoomResponderResponse { room_symmetric_key })
+    }
+
+    fn join_room(&mut self, request: JoinRoomRequest) -> Result<JoinRoomResponse, JoinRoomError> {
+        let username = self
+            .username(&request.session_id)
+            .ok_or(JoinRoomError::InvalidSessionId(request.session_id))?;
+        let room = self
+            .rooms
+            .get(&request.room_id)
+            .ok_or(JoinRoomError::NonexistentRoomId(request.room_id))?;
+        if room.creator != username && room.invited != username {
+            return Err(JoinRoomError::NotInRoom(request.room_id));
+        }
+        let room_data = room.data.clone().ok_or(JoinRoomError::IncompleteRoom)?;
+        Ok(JoinRoomResponse { room_data })
+    }
+
+    fn signal_room(&mut self, request: SignalRoomRequest) -> Result<SignalRoomResponse, SignalRoomError> {
+        let username = self
+            .username(&request.session_id)
+            .ok_or(SignalRoomError::InvalidSessionId(request.session_id))?;
+        let room = self
+            .rooms
+            .get_mut(&request.room_id)
+            .ok_or(SignalRoomError::NonexistentRoomId(request.room_id))?;
+        // The invited user signals as the initiator, and the creator as the responder
+        let (member, sender, own, other) = match request.role {
+            SignalRole::Initiator => (
+                &room.invited,
+                &room.creator,
+                &mut room.initiator_signals,
+                &room.responder_signals,
+            ),
+            SignalRole::Responder => (
+                &room.creator,
+                &room.invited,
+                &mut room.responder_signals,
+                &room.initiator_signals,
+            ),
+        };
+        if *member != username {
+            return Err(SignalRoomError::NotInRoom(request.room_id));
+        }
+        own.extend(request.messages);
+        Ok(SignalRoomResponse {
+            messages: other.iter().skip(request.received as usize).cloned().collect(),
+            sender: Some(sender.clone()),
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+struct MockServer(Arc<Mutex<State>>);
+impl MockServer {
+    fn log_in(&self, username: &str) -> CipherKey {
+        let session_id = CipherKey::generate();
+        let mut state = self.0.lock().expect("unpoisoned state");
+        state.sessions.push((session_id.clone(), username.to_owned()));
+        session_id
+    }
+}
+
+fn handle<P: DeserializeOwned, R: Serialize, E: Serialize>(
+    payload: serde_json::Value,
+    handler: impl FnOnce(P) -> Result<R, E>,
+) -> serde_json::Value {
+    let request = serde_json::from_value(payload).expect("a request of the function's type");
+    serde_json::to_value(handler(request)).expect("a serializable response")
+}
+
+#[async_trait::async_trait]
+impl AtrisAuthClient for MockServer {
+    type FunctionIdentifier = &'static str;
+    type BaseResponse = ();
+    type Error = String;
+
+    const CREATE_USER_FN: Self::FunctionIdentifier = "create_user";
+    const AUTHENTICATE_USER_FN: Self::FunctionIdentifier = "authenticate_user";
+    const CREATE_ROOM_FN: Self::FunctionIdentifier = "create_room";
+    const SET_ROOM_RESPONDER_FN: Self::FunctionIdentifier = "set_room_responder";
+    const JOIN_ROOM_FN: Self::FunctionIdentifier = "join_room";
+    const ENROLL_TOTP_FN: Self::FunctionIdentifier = "enroll_totp";
+    const CONFIRM_TOTP_FN: Self::FunctionIdentifier = "confirm_totp";
+    const SIGNAL_ROOM_FN: Self::FunctionIdentifier = "signal_room";
+
+    async fn invoke_lambda<'s, P: Serialize + Sync, R: DeserializeOwned>(
+        &'s self,
+        lambda_function_name: Self::FunctionIdentifier,
+        payload: &'s P,
+    ) -> InvocationResult<R, Self::Error> {
+        let payload = serde_json::to_value(payload).map_err(InvocationError::SerializationError)?;
+        let response = {
+            let mut state = self.0.lock().expect("unpoisoned state");
+            match lambda_function_name {
+                "create_room" => handle(payload, |request| state.create_room(request)),
+                "set_room_responder" => handle(payload, |request| state.set_room_responder(request)),
+                "join_room" => handle(payload, |request| state.join_room(request)),
+                "signal_room" => handle(payload, |request| state.signal_room(request)),
+                name => return Err(InvocationError::ImplementationError(format!("{name} is not mocked"))),
+            }
+        };
+        serde_json::from_value(response).map_err(InvocationError::DeserializationError)
+    }
+}
+
+async fn initiator() -> Result<AtrisInitiator> {
+    AtrisInitiator::new(AtrisConnection::offline().await?).await
+}
+
+async fn responder() -> Result<AtrisResponder> {
+    Ok(AtrisResponder::with_connection(AtrisConnection::offline().await?))
+}
+
+#[tokio::test]
+async fn invited_users_join_with_a_fresh_offer() -> Result<()> {
+    let server = MockServer::default();
+    let alice = server.log_in("alice");
+    let bob = server.log_in("bob");
+
+    let invitation = Invitation::create(server.clone(), alice, "bob").await?;
+    let room_id = invitation.room_id();
+    assert_eq!(invitation.other_user(), "bob");
+    // Bob only joins once alice is waiting, long after the offer he made at login is gone
+    let (accepted, joined) = tokio::join!(
+        invitation.accept(responder().await?),
+        invite::join(server.clone(), bob, room_id, initiator().await?)
+    );
+    let (mut incoming, alice_key) = accepted?;
+    let (mut channels, bob_key) = joined?;
+    assert_eq!(alice_key.as_ref(), bob_key.as_ref());
+
+    let bob_parts = channels.take(DEFAULT_CHANNEL_LABEL).expect("chat channel");
+    let alice_parts = timeout(CONNECT_TIMEOUT, incoming.take(DEFAULT_CHANNEL_LABEL))
+        .await?
+        .expect("chat channel");
+    let mut bob_channel = AtrisChannel::<String>::new(bob_parts, bob_key.as_cipher());
+    let mut alice_channel = AtrisChannel::<String>::new(alice_parts, alice_key.as_cipher());
+    bob_channel.send("joined".to_owned()).await?;
+    let received = timeout(CONNECT_TIMEOUT, alice_channel.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("joined"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn rooms_are_joined_before_their_creator_waits_in_them() -> Result<()> {
+    let server = MockServer::default();
+    let alice = server.log_in("alice");
+    let bob = server.log_in("bob");
+    let room_id = Invitation::create(server.clone(), alice.clone(), "bob").await?.room_id();
+
+    // Bob joins first, and alice comes back to the room later
+    let joining = tokio::spawn(invite::join(server.clone(), bob, room_id, initiator().await?));
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let invitation = Invitation::existing(server.clone(), alice, room_id, "bob");
+    let (_incoming, alice_key) = invitation.accept(responder().await?).await?;
+    let (_channels, bob_key) = timeout(CONNECT_TIMEOUT, joining).await???;
+    assert_eq!(alice_key.as_ref(), bob_key.as_ref());
+    Ok(())
+}
+
+#[tokio::test]
+async fn inviting_users_who_never_logged_in_fails() -> Result<()> {
+    let server = MockServer::default();
+    let alice = server.log_in("alice");
+    let error = Invitation::create(server, alice, "nobody").await.err().expect("unknown user");
+    assert!(matches!(error.downcast_ref(), Some(CreateRoomError::NoSessionForUser(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_invited_users_can_join_or_signal_in_a_room() -> Result<()> {
+    let server = MockServer::default();
+    let alice = server.log_in("alice");
+    server.log_in("bob");
+    let mallory = server.log_in("mallory");
+    let room_id = Invitation::create(server.clone(), alice, "bob").await?.room_id();
+
+    let error = invite::join(server.clone(), mallory.clone(), room_id, initiator().await?)
+        .await
+        .err()
+        .expect("mallory was not invited");
+    assert!(matches!(error.downcast_ref(), Some(JoinRoomError::NotInRoom(_))));
+    // Nor can they offer, or answer, around joining
+    for role in [SignalRole::Initiator, SignalRole::Responder] {
+        let error = RoomSignaller::new(server.clone(), mallory.clone(), room_id, role)
+            .exchange(vec![SignalMessage::Offer("mallory's offer".to_owned())])
+            .await
+            .expect_err("mallory was not invited");
+        assert!(matches!(error.downcast_ref(), Some(SignalRoomError::NotInRoom(_))));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn offers_are_only_answered_from_the_invited_user() -> Result<()> {
+    let server = MockServer::default();
+    let alice = server.log_in("alice");
+    let bob = server.log_in("bob");
+    let room_id = Invitation::create(server.clone(), alice.clone(), "bob").await?.room_id();
+
+    // Alice waits in the room for someone else than who it was created for
+    let joining = tokio::spawn(invite::join(server.clone(), bob, room_id, initiator().await?));
+    let invitation = Invitation::existing(server.clone(), alice, room_id, "carol");
+    let accepted = timeout(CONNECT_TIMEOUT, invitation.accept(responder().await?)).await?;
+    assert!(accepted.is_err(), "bob's offer was answered for carol");
+    joining.abort();
+    Ok(())
+}
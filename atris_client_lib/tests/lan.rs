@@ -0,0 +1,188 @@
+//! End to end tests of [`discovery`] and [`lan`] between clients in the same process
+
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::channels::{IncomingChannels, DEFAULT_CHANNEL_LABEL};
+use atris_client_lib::comms::discovery::{self, LanPeer};
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::lan::{self, LanError, LanListener};
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::AtrisChannel;
+use atris_client_lib::identity::IdentityKey;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use common::untrickled;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+const BROWSE_DURATION: Duration = Duration::from_secs(1);
+
+/// Browse for the client advertising `identity`, which other tests may be advertising alongside
+async fn find(identity: &IdentityKey) -> Result<LanPeer> {
+    let fingerprint = identity.public().fingerprint();
+    let peers = discovery::browse(BROWSE_DURATION).await?;
+    Ok(peers
+        .into_iter()
+        .find(|peer| peer.fingerprint == fingerprint)
+        .expect("advertised client"))
+}
+
+/// Answer the next client with `responder`, checking it is the one holding `expected` as a user would
+async fn accept_from(
+    listener: &LanListener,
+    expected: &IdentityKey,
+    responder: AtrisResponder,
+) -> Result<(LanPeer, IncomingChannels, CipherKey)> {
+    let request = listener.accept().await?;
+    let peer = request.peer().clone();
+    assert_eq!(peer.fingerprint, expected.public().fingerprint());
+    let (channels, room_key) = request.answer(responder).await?;
+    Ok((peer, channels, room_key))
+}
+
+#[tokio::test]
+async fn advertised_clients_are_found_by_browsing() -> Result<()> {
+    let identity = IdentityKey::generate();
+    let listener = LanListener::advertise(identity.clone(), "alice").await?;
+
+    let peer = find(&identity).await?;
+    assert_eq!(peer.username, "alice");
+    assert_eq!(peer.address.port(), listener.port()?);
+
+    // Clients stop being found once they stop advertising
+    drop(listener);
+    let fingerprint = identity.public().fingerprint();
+    let peers = discovery::browse(BROWSE_DURATION).await?;
+    assert!(!peers.iter().any(|peer| peer.fingerprint == fingerprint));
+    Ok(())
+}
+
+#[tokio::test]
+async fn discovered_clients_connect_with_the_same_room_key() -> Result<()> {
+    let alice = IdentityKey::generate();
+    let bob = IdentityKey::generate();
+    let listener = LanListener::advertise(alice.clone(), "alice").await?;
+    let peer = find(&alice).await?;
+
+    let initiator = AtrisInitiator::new(untrickled().await?).await?;
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let (connected, accepted) = tokio::join!(
+        lan::connect(&peer, &bob, "bob", initiator),
+        accept_from(&listener, &bob, responder)
+    );
+    let (mut channels, bob_key) = connected?;
+    let (from, mut incoming, alice_key) = accepted?;
+    assert_eq!(bob_key.as_ref(), alice_key.as_ref());
+    assert_eq!(from.username, "bob");
+    assert_eq!(from.fingerprint, bob.public().fingerprint());
+
+    let bob_parts = channels.take(DEFAULT_CHANNEL_LABEL).expect("chat channel");
+    let alice_parts = timeout(CONNECT_TIMEOUT, incoming.take(DEFAULT_CHANNEL_LABEL))
+        .await?
+        .expect("chat channel");
+    let mut bob_channel = AtrisChannel::<String>::new(bob_parts, bob_key.as_cipher());
+    let mut alice_channel = AtrisChannel::<String>::new(alice_parts, alice_key.as_cipher());
+    bob_channel.send("found you".to_owned()).await?;
+    let received = timeout(CONNECT_TIMEOUT, alice_channel.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("found you"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn silent_clients_do_not_hold_up_others() -> Result<()> {
+    let alice = IdentityKey::generate();
+    let bob = IdentityKey::generate();
+    let listener = LanListener::advertise(alice.clone(), "alice").await?;
+    let peer = find(&alice).await?;
+    // Connects first and then never sends its hello
+    let _silent = TcpStream::connect(peer.address).await?;
+
+    let initiator = AtrisInitiator::new(untrickled().await?).await?;
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    // Well within the handshake timeout, so bob is not just let in once the silent client times out
+    let (connected, accepted) = timeout(CONNECT_TIMEOUT, async {
+        tokio::join!(
+            lan::connect(&peer, &bob, "bob", initiator),
+            accept_from(&listener, &bob, responder)
+        )
+    })
+    .await?;
+    connected?;
+    let (from, _, _) = accepted?;
+    assert_eq!(from.username, "bob");
+    Ok(())
+}
+
+#[tokio::test]
+async fn clients_with_another_identity_are_rejected() -> Result<()> {
+    let alice = IdentityKey::generate();
+    let listener = LanListener::advertise(alice.clone(), "alice").await?;
+    let mut peer = find(&alice).await?;
+    // Someone else answering where alice was advertised
+    peer.fingerprint = IdentityKey::generate().public().fingerprint();
+
+    let bob = IdentityKey::generate();
+    let initiator = AtrisInitiator::new(untrickled().await?).await?;
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let (connected, _) = tokio::join!(
+        lan::connect(&peer, &bob, "bob", initiator),
+        timeout(CONNECT_TIMEOUT, accept_from(&listener, &bob, responder))
+    );
+    let error = connected.err().expect("mismatched fingerprint");
+    assert!(matches!(
+        error.downcast_ref(),
+        Some(LanError::FingerprintMismatch { .. })
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn clients_turned_away_are_never_answered() -> Result<()> {
+    let alice = IdentityKey::generate();
+    let bob = IdentityKey::generate();
+    let mallory = IdentityKey::generate();
+    let listener = LanListener::advertise(alice.clone(), "alice").await?;
+    let peer = find(&alice).await?;
+
+    // Mallory gets in first, claiming to be bob, and alice sees it is not bob's key
+    let initiator = AtrisInitiator::new(untrickled().await?).await?;
+    let (connected, request) = tokio::join!(lan::connect(&peer, &mallory, "bob", initiator), async {
+        let request = listener.accept().await?;
+        assert_eq!(request.peer().username, "bob");
+        assert_eq!(request.peer().fingerprint, mallory.public().fingerprint());
+        drop(request);
+        anyhow::Ok(())
+    });
+    request?;
+    assert!(connected.is_err());
+
+    // The listener still takes bob afterwards
+    let initiator = AtrisInitiator::new(untrickled().await?).await?;
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let (connected, accepted) = timeout(CONNECT_TIMEOUT, async {
+        tokio::join!(
+            lan::connect(&peer, &bob, "bob", initiator),
+            accept_from(&listener, &bob, responder)
+        )
+    })
+    .await?;
+    let (_, bob_key) = connected?;
+    let (_, _, alice_key) = accepted?;
+    assert_eq!(bob_key.as_ref(), alice_key.as_ref());
+    Ok(())
+}
+
+#[test]
+fn identity_keys_round_trip_and_fingerprint_stably() {
+    let identity = IdentityKey::generate();
+    let restored = IdentityKey::from_bytes(identity.to_bytes());
+    assert_eq!(restored.public(), identity.public());
+    let fingerprint = identity.public().fingerprint();
+    assert_eq!(fingerprint, restored.public().fingerprint());
+    assert_eq!(fingerprint.len(), 19);
+    assert_ne!(fingerprint, IdentityKey::generate().public().fingerprint());
+}
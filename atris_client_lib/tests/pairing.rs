@@ -0,0 +1,130 @@
+//! End to end tests of [`pairing`] between two untrickled offline connections in the same process
+
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::comms::channels::{ChannelOptions, DEFAULT_CHANNEL_LABEL};
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::pairing::{
+    self, PairingError, PairingOffer, CONFIRMATION_CHANNEL_PREFIX, PAIRING_SEPARATOR,
+};
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::AtrisChannel;
+use data_encoding::BASE32_NOPAD;
+use tokio::time::timeout;
+
+use common::untrickled;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Offer with `offer_passphrase` and answer with `answer_passphrase`, returning the answer line alongside
+async fn exchange(offer_passphrase: &str, answer_passphrase: &str) -> Result<(PairingOffer, String)> {
+    let offer = PairingOffer::new(AtrisInitiator::new(untrickled().await?).await?, offer_passphrase);
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let (answer, _channels, _) = pairing::answer(responder, &offer.line()?, answer_passphrase).await?;
+    Ok((offer, answer))
+}
+
+fn is_mismatch(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref(), Some(PairingError::PassphraseMismatch))
+}
+
+#[tokio::test]
+async fn same_passphrases_pair_without_trickling() -> Result<()> {
+    let offer = PairingOffer::new(AtrisInitiator::new(untrickled().await?).await?, "correct horse");
+    let line = offer.line()?;
+    assert!(!line.contains(char::is_whitespace));
+    assert!(line.len() < 1024);
+
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let (answer, unconfirmed, answer_key) = pairing::answer(responder, &line, "correct horse").await?;
+    let (mut channels, offer_key) = offer.into_channels_with(&answer).await?;
+    assert_eq!(offer_key.as_ref(), answer_key.as_ref());
+    let mut incoming = timeout(CONNECT_TIMEOUT, unconfirmed.confirmed()).await??;
+
+    // No candidates were trickled, so they must have been in the lines
+    let offer_parts = channels.take(DEFAULT_CHANNEL_LABEL).expect("chat channel");
+    let answer_parts = timeout(CONNECT_TIMEOUT, incoming.take(DEFAULT_CHANNEL_LABEL))
+        .await?
+        .expect("chat channel");
+    let mut offerer = AtrisChannel::<String>::new(offer_parts, offer_key.as_cipher());
+    let mut answerer = AtrisChannel::<String>::new(answer_parts, answer_key.as_cipher());
+    offerer.send("paired".to_owned()).await?;
+    let received = timeout(CONNECT_TIMEOUT, answerer.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("paired"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn different_passphrases_are_caught_before_connecting() -> Result<()> {
+    let (offer, answer) = exchange("correct horse", "battery staple").await?;
+    let error = offer
+        .into_channels_with(&answer)
+        .await
+        .err()
+        .expect("mismatched passphrases");
+    assert!(is_mismatch(&error));
+    Ok(())
+}
+
+#[tokio::test]
+async fn answers_are_only_confirmed_by_an_offerer_with_the_same_passphrase() -> Result<()> {
+    // Someone who replaced the offer line with their own, guessing the passphrase, and connects regardless
+    let guess = PairingOffer::new(AtrisInitiator::new(untrickled().await?).await?, "guess").line()?;
+    let (_, guessed_pairing) = guess.rsplit_once(PAIRING_SEPARATOR).expect("pairing line");
+    let forged = format!("{CONFIRMATION_CHANNEL_PREFIX}{}", BASE32_NOPAD.encode(&[0; 32]));
+    let channels = [
+        (DEFAULT_CHANNEL_LABEL, ChannelOptions::reliable()),
+        (forged.as_str(), ChannelOptions::reliable()),
+    ];
+    let impostor = AtrisInitiator::with_channels(untrickled().await?, channels).await?;
+    let line = format!(
+        "{}{PAIRING_SEPARATOR}{guessed_pairing}",
+        impostor.compact_local_description()?
+    );
+
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let (answer, unconfirmed, _) = pairing::answer(responder, &line, "correct horse").await?;
+    let (description, _) = answer.rsplit_once(PAIRING_SEPARATOR).expect("pairing line");
+    let _channels = impostor.into_channels_with(description).await?;
+
+    let error = timeout(CONNECT_TIMEOUT, unconfirmed.confirmed())
+        .await?
+        .err()
+        .expect("unconfirmed pairing");
+    assert!(is_mismatch(&error));
+    Ok(())
+}
+
+#[tokio::test]
+async fn tampered_answers_are_caught() -> Result<()> {
+    let (offer, answer) = exchange("correct horse", "correct horse").await?;
+    // Replace the answer's pairing message with one from an exchange with another passphrase
+    let (_, other) = exchange("guess", "guess").await?;
+    let (description, _) = answer.rsplit_once(PAIRING_SEPARATOR).expect("pairing line");
+    let (_, other_pairing) = other.rsplit_once(PAIRING_SEPARATOR).expect("pairing line");
+    let tampered = format!("{description}{PAIRING_SEPARATOR}{other_pairing}");
+
+    let error = offer
+        .into_channels_with(&tampered)
+        .await
+        .err()
+        .expect("tampered answer");
+    assert!(is_mismatch(&error));
+    Ok(())
+}
+
+#[tokio::test]
+async fn lines_which_are_not_pairing_lines_are_rejected() -> Result<()> {
+    let initiator = AtrisInitiator::new(untrickled().await?).await?;
+    let description = initiator.compact_local_description()?;
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let error = pairing::answer(responder, &description, "correct horse")
+        .await
+        .err()
+        .expect("line without a pairing message");
+    assert!(matches!(error.downcast_ref(), Some(PairingError::MalformedLine)));
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Tests of saving, unlocking, remembering and switching between [`Profiles`] in a directory of their own
+
+mod common;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::{CipherKey, IceServer};
+use atris_client_lib::profile::{ProfileError, Profiles, SavedRoom, SavedSession, UnlockedProfile};
+use tempfile::TempDir;
+
+use common::temporary_dir;
+
+/// Profiles in a fresh temporary directory, which is removed when it is dropped
+fn temporary_profiles() -> Result<(Profiles, TempDir)> {
+    let dir = temporary_dir()?;
+    Ok((Profiles::at(dir.path()), dir))
+}
+
+fn session() -> SavedSession {
+    SavedSession {
+        session_id: CipherKey::generate(),
+        ice_servers: vec![IceServer {
+            urls: vec!["turn:turn.example.com:3478".to_owned()],
+            username: "user".to_owned(),
+            credential: "secret".to_owned(),
+        }],
+    }
+}
+
+#[test]
+fn profiles_keep_their_secrets_behind_the_passphrase() -> Result<()> {
+    let (profiles, dir) = temporary_profiles()?;
+    let mut alice = UnlockedProfile::create("alice", Some("http://localhost:9000".to_owned()), "hunter2")?;
+    let saved_session = session();
+    alice.set_session(Some(saved_session.clone()))?;
+    alice.profile_mut().remember_room(SavedRoom {
+        room_id: 7,
+        other_user: Some("bob".to_owned()),
+        created: true,
+    });
+    profiles.save(alice.profile())?;
+
+    let saved = std::fs::read_to_string(dir.path().join("profiles").join("alice.json"))?;
+    assert!(!saved.contains("secret"), "the session is saved in the clear: {saved}");
+
+    let loaded = profiles.load("alice")?;
+    assert_eq!(loaded.server.as_deref(), Some("http://localhost:9000"));
+    assert_eq!(loaded.room(7).and_then(|room| room.other_user.as_deref()), Some("bob"));
+    assert!(loaded.is_logged_in());
+    let unlocked = loaded.unlock("hunter2")?;
+    assert_eq!(unlocked.identity().public(), alice.identity().public());
+    let unlocked_session = unlocked.session().expect("a saved session");
+    assert_eq!(unlocked_session.session_id.as_ref(), saved_session.session_id.as_ref());
+    assert_eq!(unlocked_session.ice_servers, saved_session.ice_servers);
+
+    let wrong = profiles.load("alice")?.unlock("hunter3");
+    assert!(matches!(wrong, Err(ProfileError::WrongPassphrase)));
+    Ok(())
+}
+
+#[test]
+fn remembered_profiles_unlock_until_forgotten() -> Result<()> {
+    let (profiles, _dir) = temporary_profiles()?;
+    let alice = UnlockedProfile::create("alice", None, "hunter2")?;
+    profiles.save(alice.profile())?;
+    assert!(profiles.unlock_remembered("alice")?.is_none());
+
+    profiles.remember(&alice)?;
+    let remembered = profiles.unlock_remembered("alice")?.expect("a remembered profile");
+    assert_eq!(remembered.identity().public(), alice.identity().public());
+
+    profiles.forget("alice")?;
+    assert!(profiles.unlock_remembered("alice")?.is_none());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn saved_profiles_and_keys_are_only_readable_by_this_user() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = |path: &PathBuf| -> Result<u32> { Ok(std::fs::metadata(path)?.permissions().mode() & 0o777) };
+    let (profiles, dir) = temporary_profiles()?;
+    let alice = UnlockedProfile::create("alice", None, "hunter2")?;
+    profiles.save(alice.profile())?;
+    profiles.remember(&alice)?;
+    let profile = dir.path().join("profiles").join("alice.json");
+    let key = dir.path().join("keys").join("alice.key");
+    assert_eq!(mode(&profile)?, 0o600);
+    assert_eq!(mode(&key)?, 0o600);
+
+    // Files left readable by an older version are narrowed when they are written again
+    std::fs::set_permissions(&profile, std::fs::Permissions::from_mode(0o644))?;
+    std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o644))?;
+    profiles.save(alice.profile())?;
+    profiles.remember(&alice)?;
+    assert_eq!(mode(&profile)?, 0o600);
+    assert_eq!(mode(&key)?, 0o600);
+
+    // They are written through temporary files, none of which are left behind
+    assert_eq!(std::fs::read_dir(dir.path().join("profiles"))?.count(), 1);
+    assert_eq!(std::fs::read_dir(dir.path().join("keys"))?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn logging_out_needs_no_passphrase() -> Result<()> {
+    let (profiles, _dir) = temporary_profiles()?;
+    let mut alice = UnlockedProfile::create("alice", None, "hunter2")?;
+    alice.set_session(Some(session()))?;
+    profiles.save(alice.profile())?;
+
+    let mut loaded = profiles.load("alice")?;
+    loaded.log_out();
+    profiles.save(&loaded)?;
+    let unlocked = profiles.load("alice")?.unlock("hunter2")?;
+    assert!(unlocked.session().is_none());
+    assert_eq!(unlocked.identity().public(), alice.identity().public());
+    Ok(())
+}
+
+#[test]
+fn profiles_can_be_listed_switched_between_and_removed() -> Result<()> {
+    let (profiles, _dir) = temporary_profiles()?;
+    assert!(profiles.list()?.is_empty());
+    assert_eq!(profiles.current()?, None);
+    for username in ["bob", "alice"] {
+        profiles.save(UnlockedProfile::create(username, None, "passphrase")?.profile())?;
+    }
+    assert_eq!(profiles.list()?, ["alice", "bob"]);
+
+    profiles.set_current(Some("bob"))?;
+    assert_eq!(profiles.current()?.as_deref(), Some("bob"));
+    profiles.remove("bob")?;
+    assert_eq!(profiles.list()?, ["alice"]);
+    assert_eq!(profiles.current()?, None);
+    assert!(matches!(profiles.load("bob"), Err(ProfileError::NotFound(_))));
+    Ok(())
+}
+
+#[test]
+fn usernames_which_are_not_file_names_are_refused() -> Result<()> {
+    let (profiles, _dir) = temporary_profiles()?;
+    for username in ["", "../alice", "a/b", ".hidden"] {
+        assert!(matches!(profiles.find(username), Err(ProfileError::InvalidName(_))));
+    }
+    Ok(())
+}
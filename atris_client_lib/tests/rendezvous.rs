@@ -0,0 +1,141 @@
+//! End to end tests of the rendezvous endpoint in [`signal`] between clients in the same process
+
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::comms::channels::DEFAULT_CHANNEL_LABEL;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::signal::{
+    self, RendezvousError, RendezvousServer, RendezvousToken, ANSWER_LABEL, OFFER_LABEL, RENDEZVOUS_PATH,
+    SIGNATURE_HEADER,
+};
+use atris_client_lib::comms::AtrisChannel;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+use common::untrickled;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+async fn bind() -> Result<RendezvousServer> {
+    RendezvousServer::bind(([127, 0, 0, 1], 0).into()).await
+}
+
+/// Answer every offer the server takes with `answer`
+fn answer_with(mut server: RendezvousServer, answer: &'static str) -> SocketAddr {
+    let address = server.address();
+    tokio::spawn(async move {
+        while let Some(offer) = server.next_offer().await {
+            offer.answer(answer.to_owned());
+        }
+    });
+    address
+}
+
+#[tokio::test]
+async fn posted_offers_are_answered_and_connect() -> Result<()> {
+    let mut server = bind().await?;
+    let address = server.address().to_string();
+    let token = RendezvousToken::parse(&server.token().to_string().to_lowercase())?;
+
+    let initiator = AtrisInitiator::new(untrickled().await?).await?;
+    let offer = initiator.compact_local_description()?;
+    let responder = AtrisResponder::with_connection(untrickled().await?);
+    let answering = async {
+        let pending = server.next_offer().await.expect("open rendezvous");
+        let (answer, incoming) = responder.into_channels_with(pending.offer()).await?;
+        pending.answer(answer);
+        Ok::<_, anyhow::Error>(incoming)
+    };
+    let (answer, incoming) = tokio::join!(signal::post_offer(&address, &token, &offer), answering);
+    let mut incoming = incoming?;
+    let mut channels = initiator.into_channels_with(&answer?).await?;
+
+    let room_key = token.room_key();
+    assert_eq!(room_key.as_ref(), server.token().room_key().as_ref());
+    let offer_parts = channels.take(DEFAULT_CHANNEL_LABEL).expect("chat channel");
+    let answer_parts = timeout(CONNECT_TIMEOUT, incoming.take(DEFAULT_CHANNEL_LABEL))
+        .await?
+        .expect("chat channel");
+    let mut offerer = AtrisChannel::<String>::new(offer_parts, room_key.as_cipher());
+    let mut answerer = AtrisChannel::<String>::new(answer_parts, server.token().room_key().as_cipher());
+    offerer.send("met".to_owned()).await?;
+    let received = timeout(CONNECT_TIMEOUT, answerer.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("met"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn offers_with_the_wrong_token_are_refused() -> Result<()> {
+    let address = answer_with(bind().await?, "answer").to_string();
+    let error = signal::post_offer(&address, &RendezvousToken::generate(), "offer")
+        .await
+        .expect_err("wrong token");
+    assert!(matches!(error.downcast_ref(), Some(RendezvousError::Unauthorized)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_the_first_offer_is_answered() -> Result<()> {
+    let server = bind().await?;
+    let token = server.token().clone();
+    let address = answer_with(server, "answer").to_string();
+    assert_eq!(signal::post_offer(&address, &token, "offer").await?, "answer");
+    let error = signal::post_offer(&address, &token, "another offer")
+        .await
+        .expect_err("answered rendezvous");
+    assert!(matches!(error.downcast_ref(), Some(RendezvousError::AlreadyAnswered)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn offers_which_are_not_utf8_are_bad_requests() -> Result<()> {
+    let server = bind().await?;
+    let token = server.token().clone();
+    let address = answer_with(server, "answer");
+    let body = vec![0xff, 0xfe, 0xfd];
+    let signature = token.sign(OFFER_LABEL, &body);
+    let response = reqwest::Client::new()
+        .post(format!("http://{address}{RENDEZVOUS_PATH}"))
+        .header(SIGNATURE_HEADER, signature)
+        .body(body)
+        .send()
+        .await?;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The server is still there for a valid offer
+    assert_eq!(signal::post_offer(&address.to_string(), &token, "offer").await?, "answer");
+    Ok(())
+}
+
+#[tokio::test]
+async fn answers_signed_with_another_token_are_rejected() -> Result<()> {
+    // Something standing in for the server which answers without knowing the token
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    let forger = RendezvousToken::generate();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request = [0; 4096];
+        let _ = stream.read(&mut request).await?;
+        let answer = "forged answer";
+        let signature = forger.sign(ANSWER_LABEL, answer.as_bytes());
+        let response = format!(
+            "HTTP/1.1 200 OK\r\n{SIGNATURE_HEADER}: {signature}\r\ncontent-length: {}\r\n\r\n{answer}",
+            answer.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        Ok::<_, std::io::Error>(())
+    });
+
+    let error = signal::post_offer(&address, &RendezvousToken::generate(), "offer")
+        .await
+        .expect_err("forged answer");
+    assert!(matches!(error.downcast_ref(), Some(RendezvousError::ForgedAnswer)));
+    Ok(())
+}
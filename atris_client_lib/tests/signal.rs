@@ -0,0 +1,155 @@
+//! Tests of the compact description encoding in [`signal`]
+
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::signal;
+use atris_client_lib::comms::trickle::LocalSignaller;
+use atris_client_lib::comms::{AtrisChannel, AtrisConnection};
+use tokio::time::timeout;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The attributes of an SDP which the compact encoding keeps
+fn kept_attributes(sdp: &str) -> Vec<&str> {
+    sdp.lines()
+        .filter(|line| {
+            [
+                "a=group:",
+                "a=mid:",
+                "a=sctp-port:",
+                "a=max-message-size:",
+                "a=ice-ufrag:",
+                "a=ice-pwd:",
+                "a=fingerprint:",
+                "a=setup:",
+                "a=candidate:",
+            ]
+            .iter()
+            .any(|kept| line.starts_with(kept))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn compact_descriptions_fit_on_one_line() -> Result<()> {
+    let initiator = AtrisInitiator::new(AtrisConnection::offline().await?).await?;
+    let compact = initiator.compact_local_description()?;
+    let json = initiator.encoded_local_description()?;
+
+    assert!(!compact.contains(char::is_whitespace));
+    assert!(compact.len() < signal::PRINTED_CHUNK_SIZE);
+    assert!(compact.len() * 3 < json.len());
+    assert!(signal::is_compact(&compact));
+    assert!(!signal::is_compact(&json));
+
+    let original = signal::decode_description(&json)?;
+    let decoded = signal::decode_description(&compact)?;
+    assert_eq!(decoded.sdp_type, original.sdp_type);
+    assert_eq!(kept_attributes(&decoded.sdp), kept_attributes(&original.sdp));
+    // Pasting it in pieces, or in the wrong case, still works
+    let (start, end) = compact.split_at(compact.len() / 2);
+    let pasted = format!("{start}\n{}", end.to_ascii_lowercase());
+    assert_eq!(signal::decode_description(&pasted)?.sdp, decoded.sdp);
+    Ok(())
+}
+
+#[tokio::test]
+async fn candidates_survive_the_compact_encoding() -> Result<()> {
+    let initiator = AtrisInitiator::new(AtrisConnection::offline().await?).await?;
+    let original = signal::decode_description(&initiator.encoded_local_description()?)?;
+    let candidate = "a=candidate:167090039 1 udp 2130706431 192.168.1.20 50000 typ host";
+    let with_candidate = original.sdp.replace("a=ice-pwd:", &format!("{candidate}\r\na=ice-pwd:"));
+    let described = RTCSessionDescription::offer(with_candidate)?;
+
+    let decoded = signal::decode_description(&signal::encode_description(&described)?)?;
+    assert!(decoded.sdp.lines().any(|line| line == candidate));
+    assert!(decoded.sdp.lines().any(|line| line == "a=end-of-candidates"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn data_channel_parameters_survive_the_compact_encoding() -> Result<()> {
+    let initiator = AtrisInitiator::new(AtrisConnection::offline().await?).await?;
+    let original = signal::decode_description(&initiator.encoded_local_description()?)?;
+    let mut changed = Vec::new();
+    for line in original.sdp.lines() {
+        match line {
+            _ if line.starts_with("a=group:BUNDLE ") => changed.push("a=group:BUNDLE data".to_owned()),
+            _ if line.starts_with("a=mid:") => changed.push("a=mid:data".to_owned()),
+            _ if line.starts_with("a=sctp-port:") => {
+                changed.push("a=sctp-port:5001".to_owned());
+                changed.push("a=max-message-size:1073741823".to_owned());
+            }
+            _ if line.starts_with("a=max-message-size:") => {}
+            _ => changed.push(line.to_owned()),
+        }
+    }
+    let described = RTCSessionDescription::offer(changed.join("\r\n") + "\r\n")?;
+
+    let decoded = signal::decode_description(&signal::encode_description(&described)?)?;
+    assert_eq!(kept_attributes(&decoded.sdp), kept_attributes(&described.sdp));
+    Ok(())
+}
+
+#[tokio::test]
+async fn descriptions_with_other_media_are_not_encoded() -> Result<()> {
+    let initiator = AtrisInitiator::new(AtrisConnection::offline().await?).await?;
+    let original = signal::decode_description(&initiator.encoded_local_description()?)?;
+    let with_audio = format!("{}m=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=mid:1\r\n", original.sdp);
+    let described = RTCSessionDescription::offer(with_audio)?;
+    assert!(signal::encode_description(&described).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrupted_descriptions_are_rejected() -> Result<()> {
+    let initiator = AtrisInitiator::new(AtrisConnection::offline().await?).await?;
+    let compact = initiator.compact_local_description()?;
+    let mut corrupted = compact.into_bytes();
+    let middle = corrupted.len() / 2;
+    corrupted[middle] = if corrupted[middle] == b'A' { b'B' } else { b'A' };
+    let corrupted = String::from_utf8(corrupted)?;
+
+    let error = signal::decode_description(&corrupted).expect_err("corrupted description");
+    assert!(error.to_string().contains("checksum"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn channels_connect_over_compact_descriptions() -> Result<()> {
+    let mut initiator = AtrisInitiator::new(AtrisConnection::offline().await?).await?;
+    let mut responder = AtrisResponder::with_connection(AtrisConnection::offline().await?);
+    let (initiator_signaller, responder_signaller) = LocalSignaller::pair();
+    tokio::spawn(initiator.ice_trickle().expect("fresh initiator").run(initiator_signaller));
+    tokio::spawn(responder.ice_trickle().expect("fresh responder").run(responder_signaller));
+
+    let (answer, responder_parts) = responder
+        .into_channel_parts_with::<String>(&initiator.compact_local_description()?)
+        .await?;
+    // The answer comes back in the encoding the offer went out in
+    assert!(signal::is_compact(&answer));
+    let initiator_parts = initiator.into_channel_parts_with::<String>(&answer).await?;
+    let responder_parts = timeout(CONNECT_TIMEOUT, responder_parts)
+        .await?
+        .expect("responder data channel");
+
+    let room_key = CipherKey::generate();
+    let mut initiator = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder = AtrisChannel::new(responder_parts, room_key.as_cipher());
+    initiator.send("over a compact offer".to_owned()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("over a compact offer"));
+    Ok(())
+}
+
+#[test]
+fn strings_of_any_length_print_in_chunks() {
+    signal::print_in_chunks("short");
+    signal::print_in_chunks("");
+    signal::print_in_chunks(&"é".repeat(signal::PRINTED_CHUNK_SIZE));
+}
@@ -0,0 +1,260 @@
+//! End to end tests of [`FileTransfers`] between two offline connections in the same process
+
+mod common;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::transfer::{
+    self, FileTransfers, TransferEvent, TransferId, TransferMessage, CHUNK_SIZE, TRANSFER_CHANNEL_LABEL,
+};
+use atris_client_lib::comms::trickle::LocalSignaller;
+use atris_client_lib::comms::{AtrisChannel, AtrisConnection};
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+
+use common::temporary_dir;
+
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connect two offline connections with a file channel, returning the sender's and receiver's ends of it
+async fn connected_channels() -> Result<(AtrisChannel<TransferMessage>, AtrisChannel<TransferMessage>)> {
+    let mut initiator =
+        AtrisInitiator::with_channels(AtrisConnection::offline().await?, transfer::chat_and_file_channels()).await?;
+    let mut responder = AtrisResponder::with_connection(AtrisConnection::offline().await?);
+    let (initiator_signaller, responder_signaller) = LocalSignaller::pair();
+    tokio::spawn(initiator.ice_trickle().expect("fresh initiator").run(initiator_signaller));
+    tokio::spawn(responder.ice_trickle().expect("fresh responder").run(responder_signaller));
+
+    let (answer, mut incoming) = responder
+        .into_channels_with(&initiator.encoded_local_description()?)
+        .await?;
+    let mut channels = initiator.into_channels_with(&answer).await?;
+    let sender_parts = channels.take(TRANSFER_CHANNEL_LABEL).expect("file channel");
+    let receiver_parts = timeout(TRANSFER_TIMEOUT, incoming.take(TRANSFER_CHANNEL_LABEL))
+        .await?
+        .expect("file channel");
+
+    let room_key = CipherKey::generate();
+    Ok((
+        AtrisChannel::new(sender_parts, room_key.as_cipher()),
+        AtrisChannel::new(receiver_parts, room_key.as_cipher()),
+    ))
+}
+
+/// The sender's and receiver's transfers over a new file channel
+async fn connected_transfers() -> Result<(FileTransfers, FileTransfers)> {
+    let (sender, receiver) = connected_channels().await?;
+    Ok((FileTransfers::spawn(sender), FileTransfers::spawn(receiver)))
+}
+
+/// A file a bit larger than a whole window of chunks, with contents that differ between chunks
+fn contents() -> Vec<u8> {
+    (0..(transfer::WINDOW as usize + 3) * CHUNK_SIZE + 1234)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+async fn next_event(events: &mut broadcast::Receiver<TransferEvent>) -> Result<TransferEvent> {
+    Ok(timeout(TRANSFER_TIMEOUT, events.recv()).await??)
+}
+
+/// Wait for the transfer to finish, one way or another, skipping its progress
+async fn outcome(events: &mut broadcast::Receiver<TransferEvent>) -> Result<TransferEvent> {
+    loop {
+        match next_event(events).await? {
+            TransferEvent::Progress { .. } | TransferEvent::Accepted { .. } => {}
+            event => return Ok(event),
+        }
+    }
+}
+
+/// Offer `source`, waiting for the offer to arrive
+async fn offer(
+    sender: &FileTransfers,
+    receiver_events: &mut broadcast::Receiver<TransferEvent>,
+    source: &PathBuf,
+) -> Result<TransferId> {
+    let id = sender.send_file(source).await?;
+    let offered = next_event(receiver_events).await?;
+    assert_eq!(
+        offered,
+        TransferEvent::Offered {
+            id,
+            name: "source.bin".into(),
+            size: contents().len() as u64,
+        }
+    );
+    Ok(id)
+}
+
+#[tokio::test]
+async fn files_arrive_intact() -> Result<()> {
+    let dir = temporary_dir()?;
+    let source = dir.path().join("source.bin");
+    let destination = dir.path().join("destination.bin");
+    std::fs::write(&source, contents())?;
+    let (sender, receiver) = connected_transfers().await?;
+    let mut sender_events = sender.events();
+    let mut receiver_events = receiver.events();
+
+    let id = offer(&sender, &mut receiver_events, &source).await?;
+    receiver.accept(id, &destination).await?;
+
+    assert_eq!(
+        outcome(&mut receiver_events).await?,
+        TransferEvent::Completed {
+            id,
+            path: Some(destination.clone()),
+        }
+    );
+    assert_eq!(
+        outcome(&mut sender_events).await?,
+        TransferEvent::Completed { id, path: None }
+    );
+    assert_eq!(std::fs::read(&destination)?, contents());
+    assert!(!dir.path().join("destination.bin.part").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfers_resume_from_the_part_file() -> Result<()> {
+    let dir = temporary_dir()?;
+    let source = dir.path().join("source.bin");
+    let destination = dir.path().join("destination.bin");
+    std::fs::write(&source, contents())?;
+    // Two whole chunks from an earlier attempt, and part of a third which is written again
+    std::fs::write(
+        dir.path().join("destination.bin.part"),
+        &contents()[..2 * CHUNK_SIZE + 100],
+    )?;
+    let (sender, receiver) = connected_transfers().await?;
+    let mut receiver_events = receiver.events();
+
+    let id = offer(&sender, &mut receiver_events, &source).await?;
+    receiver.accept(id, &destination).await?;
+
+    assert_eq!(
+        next_event(&mut receiver_events).await?,
+        TransferEvent::Progress {
+            id,
+            bytes: 2 * CHUNK_SIZE as u64,
+            size: contents().len() as u64,
+        }
+    );
+    assert_eq!(
+        outcome(&mut receiver_events).await?,
+        TransferEvent::Completed {
+            id,
+            path: Some(destination.clone()),
+        }
+    );
+    assert_eq!(std::fs::read(&destination)?, contents());
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrupted_files_fail_verification() -> Result<()> {
+    let dir = temporary_dir()?;
+    let source = dir.path().join("source.bin");
+    let destination = dir.path().join("destination.bin");
+    std::fs::write(&source, contents())?;
+    // A chunk which is not what the sender has, and is kept rather than sent again
+    std::fs::write(dir.path().join("destination.bin.part"), vec![0; CHUNK_SIZE])?;
+    let (sender, receiver) = connected_transfers().await?;
+    let mut sender_events = sender.events();
+    let mut receiver_events = receiver.events();
+
+    let id = offer(&sender, &mut receiver_events, &source).await?;
+    receiver.accept(id, &destination).await?;
+
+    assert!(matches!(
+        outcome(&mut receiver_events).await?,
+        TransferEvent::Failed { id: failed, .. } if failed == id
+    ));
+    assert!(matches!(
+        outcome(&mut sender_events).await?,
+        TransferEvent::Failed { id: failed, .. } if failed == id
+    ));
+    assert!(!destination.exists());
+    assert!(!dir.path().join("destination.bin.part").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejected_and_cancelled_transfers_are_reported() -> Result<()> {
+    let dir = temporary_dir()?;
+    let source = dir.path().join("source.bin");
+    std::fs::write(&source, contents())?;
+    let (sender, receiver) = connected_transfers().await?;
+    let mut sender_events = sender.events();
+    let mut receiver_events = receiver.events();
+
+    let rejected = offer(&sender, &mut receiver_events, &source).await?;
+    receiver.reject(rejected)?;
+    assert_eq!(
+        next_event(&mut sender_events).await?,
+        TransferEvent::Rejected { id: rejected }
+    );
+
+    let cancelled = offer(&sender, &mut receiver_events, &source).await?;
+    sender.cancel(cancelled)?;
+    assert_eq!(
+        next_event(&mut sender_events).await?,
+        TransferEvent::Cancelled { id: cancelled }
+    );
+    assert_eq!(
+        next_event(&mut receiver_events).await?,
+        TransferEvent::Cancelled { id: cancelled }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn chunks_larger_than_offered_cancel_the_transfer() -> Result<()> {
+    let dir = temporary_dir()?;
+    let destination = dir.path().join("destination.bin");
+    let (mut sender, receiver) = connected_channels().await?;
+    let receiver = FileTransfers::spawn(receiver);
+    let mut receiver_events = receiver.events();
+
+    // A sender which offers ten bytes and then sends more than that
+    sender
+        .send(TransferMessage::Offer {
+            id: 1,
+            name: "source.bin".into(),
+            size: 10,
+            sha256: [0; 32],
+        })
+        .await?;
+    next_event(&mut receiver_events).await?;
+    receiver.accept(1, &destination).await?;
+    sender
+        .send(TransferMessage::Chunk {
+            id: 1,
+            index: 0,
+            data: vec![0; CHUNK_SIZE],
+        })
+        .await?;
+
+    assert!(matches!(
+        outcome(&mut receiver_events).await?,
+        TransferEvent::Failed { id: 1, .. }
+    ));
+    loop {
+        match timeout(TRANSFER_TIMEOUT, sender.receive())
+            .await?
+            .expect("open channel")
+        {
+            Ok(TransferMessage::Cancel { id: 1 }) => break,
+            Ok(TransferMessage::Accept { .. } | TransferMessage::Ack { .. }) => {}
+            message => panic!("unexpected {message:?}"),
+        }
+    }
+    assert!(!dir.path().join("destination.bin.part").exists());
+    Ok(())
+}
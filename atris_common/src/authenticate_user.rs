@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt::Display;
 
-use crate::CipherKey;
+use crate::{CipherKey, IceServer};
 
 /// A request to authenticate a user on the atris auth server. The server will respond with a Result<AuthenticateUserResponse,AuthenticateUserError>
 #[derive(Deserialize, Serialize, Debug)]
@@ -14,6 +14,9 @@ pub struct AuthenticateUserRequest {
     pub password_attempt: String,
     /// The initiator WebRTC string, which we pass to other users
     pub initiator: String,
+    /// The current TOTP code or an unused recovery code, for users with two-factor authentication enabled
+    #[serde(default)]
+    pub second_factor: Option<String>,
 }
 
 /// A successful response to a [`AuthenticateUserRequest`] on the atris auth server.
@@ -21,6 +24,10 @@ pub struct AuthenticateUserRequest {
 #[derive(Deserialize, Serialize, Debug,Clone)]
 pub struct AuthenticateUserResponse {
     pub session_id: CipherKey,
+    /// The ICE servers the client should use for this session, including short-lived credentials for the
+    /// deployment's own TURN relay if it has one
+    #[serde(default)]
+    pub ice_servers: Vec<IceServer>,
 }
 
 /// A response to a [`AuthenticateUserRequest`] on the atris auth server. For success response, see [`AuthenticateUserResponse`]
@@ -36,6 +43,12 @@ pub enum AuthenticateUserError {
     DatabaseRead,
     /// Failed to write to the databse
     DatabaseWrite,
+    /// The user has two-factor authentication enabled, but no code was provided
+    SecondFactorRequired,
+    /// The code provided matched neither the current TOTP code nor an unused recovery code
+    WrongSecondFactor,
+    /// The session id generated for this login was already in use
+    DuplicateSession,
 }
 impl Display for AuthenticateUserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -58,6 +71,18 @@ impl Display for AuthenticateUserError {
             Self::DatabaseWrite => {
                 write!(f, "Failed to write network information to the database")
             }
+            Self::SecondFactorRequired => {
+                write!(f, "A two-factor authentication code is required")
+            }
+            Self::WrongSecondFactor => {
+                write!(f, "The two-factor authentication code provided does not match")
+            }
+            Self::DuplicateSession => {
+                write!(
+                    f,
+                    "The new session clashed with an existing one, try logging in again"
+                )
+            }
         }
     }
 }
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::CipherKey;
+
+/// A request to finish TOTP enrollment by proving the authenticator app produces matching codes.
+/// The server will respond with a Result<ConfirmTotpResponse,ConfirmTotpError>
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ConfirmTotpRequest {
+    /// The session of the user enrolling
+    pub session_id: CipherKey,
+    /// The code currently shown by the authenticator app
+    pub code: String,
+}
+
+/// A successful response to a [`ConfirmTotpRequest`] on the atris auth server.
+///  - For error response, see [`ConfirmTotpError`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ConfirmTotpResponse {
+    /// Single-use codes that can stand in for a TOTP code. These are only ever shown once.
+    pub recovery_codes: Vec<String>,
+}
+
+/// A response to a [`ConfirmTotpRequest`] on the atris auth server. For success response, see [`ConfirmTotpResponse`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum ConfirmTotpError {
+    /// The session provided does not exist
+    InvalidSessionId(CipherKey),
+    /// The user has not started enrollment with an [`EnrollTotpRequest`](crate::enroll_totp::EnrollTotpRequest)
+    NotEnrolled,
+    /// The user already has two-factor authentication enabled
+    AlreadyEnabled,
+    /// The code provided does not match the pending secret
+    WrongCode,
+    /// The hashing function failed to hash the recovery codes
+    HashError,
+    /// Failed to read the user record from the database
+    DatabaseReadError,
+    /// Failed to write to the database
+    DatabaseWriteError,
+}
+impl Display for ConfirmTotpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSessionId(s) => {
+                write!(f, "Session {s:?} does not exist.")
+            }
+            Self::NotEnrolled => {
+                write!(f, "Two-factor authentication enrollment has not been started")
+            }
+            Self::AlreadyEnabled => {
+                write!(f, "Two-factor authentication is already enabled")
+            }
+            Self::WrongCode => {
+                write!(f, "The code provided does not match")
+            }
+            Self::HashError => {
+                write!(f, "Error creating recovery code hashes")
+            }
+            Self::DatabaseReadError => {
+                write!(f, "Failed to read from the database")
+            }
+            Self::DatabaseWriteError => {
+                write!(f, "Failed to write to the database")
+            }
+        }
+    }
+}
+impl Error for ConfirmTotpError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
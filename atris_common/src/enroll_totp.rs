@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::CipherKey;
+
+/// A request to start enrolling the logged in user in TOTP two-factor authentication.
+/// The server will respond with a Result<EnrollTotpResponse,EnrollTotpError>
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EnrollTotpRequest {
+    /// The session of the user enrolling
+    pub session_id: CipherKey,
+}
+
+/// A successful response to a [`EnrollTotpRequest`] on the atris auth server.
+///  - For error response, see [`EnrollTotpError`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EnrollTotpResponse {
+    /// The `otpauth://` URI to load into an authenticator app
+    pub provisioning_uri: String,
+}
+
+/// A response to a [`EnrollTotpRequest`] on the atris auth server. For success response, see [`EnrollTotpResponse`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum EnrollTotpError {
+    /// The session provided does not exist
+    InvalidSessionId(CipherKey),
+    /// The user already has two-factor authentication enabled
+    AlreadyEnabled,
+    /// Failed to read the user record from the database
+    DatabaseReadError,
+    /// Failed to write the pending secret to the database
+    DatabaseWriteError,
+}
+impl Display for EnrollTotpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSessionId(s) => {
+                write!(f, "Session {s:?} does not exist.")
+            }
+            Self::AlreadyEnabled => {
+                write!(f, "Two-factor authentication is already enabled")
+            }
+            Self::DatabaseReadError => {
+                write!(f, "Failed to read from the database")
+            }
+            Self::DatabaseWriteError => {
+                write!(f, "Failed to write to the database")
+            }
+        }
+    }
+}
+impl Error for EnrollTotpError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
@@ -0,0 +1,145 @@
+//! The archive a conversation is exported to, to keep it or import it on another machine.
+//!
+//! The format is versioned by [`EXPORT_VERSION`], which is bumped whenever it changes, so importers can refuse
+//! archives newer than they understand. It has its own types rather than the clients' history types, so those can
+//! change without changing what is written to disk. In JSON, bytes are written as base64.
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{CipherKey, Encrypted};
+
+/// The version of the format exports are written in
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Who sent an exported message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportedDirection {
+    Sent,
+    Received,
+}
+
+/// How far an exported message got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportedStatus {
+    Pending,
+    Delivered,
+    Read,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    /// When the message was sent or received, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub direction: ExportedDirection,
+    pub text: String,
+    pub status: ExportedStatus,
+}
+
+/// A file attached to an export
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedFile {
+    pub name: String,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// A conversation's messages, oldest first, and the files attached to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversationExport {
+    pub conversation: String,
+    /// When the conversation was exported, in milliseconds since the Unix epoch
+    pub exported_at: u64,
+    pub messages: Vec<ExportedMessage>,
+    #[serde(default)]
+    pub files: Vec<ExportedFile>,
+}
+
+/// What an archive holds, tagged by its `kind` field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportContents {
+    Plain {
+        export: ConversationExport,
+    },
+    /// Encrypted with a key derived from a passphrase and `salt`
+    Encrypted {
+        #[serde(with = "base64_bytes")]
+        salt: Vec<u8>,
+        /// The bincode of the [`Encrypted`] export
+        #[serde(with = "base64_bytes")]
+        export: Vec<u8>,
+    },
+}
+
+/// An exported conversation, as it is written to disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportArchive {
+    pub version: u32,
+    #[serde(flatten)]
+    pub contents: ExportContents,
+}
+impl ExportArchive {
+    /// An archive anyone can read
+    pub fn plain(export: ConversationExport) -> Self {
+        Self {
+            version: EXPORT_VERSION,
+            contents: ExportContents::Plain { export },
+        }
+    }
+
+    /// An archive only readable with `key`, which was derived with `salt`
+    pub fn encrypted(export: &ConversationExport, key: &CipherKey, salt: &[u8]) -> crate::Result<Self> {
+        let encrypted = Encrypted::encrypt(export, &mut key.as_cipher())?;
+        Ok(Self {
+            version: EXPORT_VERSION,
+            contents: ExportContents::Encrypted {
+                salt: salt.to_vec(),
+                export: bincode::serialize(&encrypted)?,
+            },
+        })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self.contents, ExportContents::Encrypted { .. })
+    }
+
+    /// The salt to derive the key of an encrypted archive with
+    pub fn salt(&self) -> Option<&[u8]> {
+        match &self.contents {
+            ExportContents::Plain { .. } => None,
+            ExportContents::Encrypted { salt, .. } => Some(salt),
+        }
+    }
+
+    /// The exported conversation, decrypted with `key` if the archive is encrypted
+    pub fn decrypt(self, key: &CipherKey) -> crate::Result<ConversationExport> {
+        match self.contents {
+            ExportContents::Plain { export } => Ok(export),
+            ExportContents::Encrypted { export, .. } => {
+                let encrypted: Encrypted<ConversationExport> = bincode::deserialize(&export)?;
+                encrypted.decrypt(&mut key.as_cipher())
+            }
+        }
+    }
+}
+
+/// Bytes as base64 in human readable formats like JSON, and as they are in binary ones like bincode
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        match serializer.is_human_readable() {
+            true => serializer.serialize_str(&base64::encode(bytes)),
+            false => serializer.serialize_bytes(bytes),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match deserializer.is_human_readable() {
+            true => base64::decode(String::deserialize(deserializer)?).map_err(D::Error::custom),
+            false => serde_bytes::ByteBuf::deserialize(deserializer).map(serde_bytes::ByteBuf::into_vec),
+        }
+    }
+}
@@ -17,6 +17,8 @@ pub struct JoinRoomResponse {
 pub enum JoinRoomError {
     InvalidSessionId(CipherKey),
     NonexistentRoomId(u16),
+    /// The requester is neither the room's creator nor the user it was created for
+    NotInRoom(u16),
     IncompleteRoom,
     DatabaseReadError,
 }
@@ -32,6 +34,9 @@ impl Display for JoinRoomError {
             Self::NonexistentRoomId(room_id) => {
                 write!(f, "RoomID '{}' does not exist", room_id)
             }
+            Self::NotInRoom(room_id) => {
+                write!(f, "You were not invited to room '{}'", room_id)
+            }
             Self::DatabaseReadError => {
                 write!(f, "Failed to read from the database")
             }
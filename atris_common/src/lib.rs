@@ -6,13 +6,19 @@ use chacha20poly1305::{
     AeadCore, ChaCha20Poly1305, Nonce,
 };
 use cipher::KeyInit;
-use serde::{de::Visitor, ser::SerializeSeq, Deserialize, Serialize};
+use serde::{de::Visitor, Deserialize, Serialize};
 
 pub mod authenticate_user;
+pub mod confirm_totp;
 pub mod create_room;
 pub mod create_user;
+pub mod enroll_totp;
+pub mod export;
 pub mod join_room;
+pub mod message;
 pub mod set_room_responder;
+pub mod signal_room;
+pub mod turn;
 
 pub type Cipher = ChaCha20Poly1305;
 #[derive(Debug, Clone)]
@@ -33,7 +39,7 @@ impl From<cipher::Key> for CipherKey {
 }
 impl From<&[u8]> for CipherKey {
     fn from(bytes: &[u8]) -> Self {
-        CipherKey(cipher::Key::clone_from_slice(&bytes))
+        CipherKey(cipher::Key::clone_from_slice(bytes))
     }
 }
 impl AsRef<[u8]> for CipherKey {
@@ -85,14 +91,14 @@ impl<'de> Visitor<'de> for CipherKeyVisitor {
     where
         E: serde::de::Error,
     {
-        Ok(CipherKey(cipher::Key::clone_from_slice(&v)))
+        Ok(CipherKey(cipher::Key::clone_from_slice(v)))
     }
 
     fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> std::result::Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        Ok(CipherKey(cipher::Key::clone_from_slice(&v)))
+        Ok(CipherKey(cipher::Key::clone_from_slice(v)))
     }
 
     fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Self::Value, E>
@@ -120,6 +126,19 @@ pub struct RoomData {
     pub symmetric_key: CipherKey,
 }
 
+/// A STUN or TURN server which clients can use to find a route to each other
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct IceServer {
+    /// The urls of the server, like `stun:stun.l.google.com:19302` or `turn:turn.example.com:3478?transport=udp`
+    pub urls: Vec<String>,
+    /// The username to authenticate to a TURN server with, empty for STUN servers
+    #[serde(default)]
+    pub username: String,
+    /// The credential to authenticate to a TURN server with, empty for STUN servers
+    #[serde(default)]
+    pub credential: String,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Encrypted<T> {
     #[serde(with = "serde_bytes")]
@@ -161,10 +180,14 @@ impl<T: Serialize + for<'de> Deserialize<'de>> Encrypted<T> {
         })
     }
 
+    /// Fails, rather than panicking, on values which were not encrypted by [`Self::encrypt`], like those read from
+    /// a tampered file
     pub fn decrypt(self, cipher: &mut ChaCha20Poly1305) -> self::Result<T> {
-        // let nonce_new: Nonce = (&c.nonce).into_iter().cloned().collect();
-        let nonce: Nonce = self.nonce.into_iter().collect();
-        let value_bytes: Vec<u8> = cipher.decrypt(&nonce, self.cipher_bytes.as_ref())?;
+        if self.nonce.len() != Nonce::default().len() {
+            return Err(aead::Error.into());
+        }
+        let nonce = Nonce::from_slice(&self.nonce);
+        let value_bytes: Vec<u8> = cipher.decrypt(nonce, self.cipher_bytes.as_ref())?;
         let value = bincode::deserialize(&value_bytes)?;
         Ok(value)
     }
@@ -185,4 +208,4 @@ impl Display for AtrisError {
     }
 }
 /// The region for Atris on Lambda
-pub const REGION: &'static str = "us-west-2";
+pub const REGION: &str = "us-west-2";
@@ -0,0 +1,156 @@
+//! The messages chat clients send each other over their message channel, whichever client they are, so the CLI and
+//! the GUI can talk to each other.
+//!
+//! Every [`ChatMessage`] carries the [`PROTOCOL_VERSION`] it was written in, an id and a timestamp around its
+//! [`MessageBody`]. On the wire the body is the number of its kind followed by its own bincode, so a kind added by a
+//! newer client arrives as [`MessageBody::Unsupported`] instead of failing to decode the whole message. Bincode ignores
+//! trailing bytes, so newer versions may also add fields to the end of an existing kind.
+use std::fmt::Display;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use serde::{Deserialize, Serialize};
+
+/// The version of the message schema this client writes
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Identifies a chat message among those of its sender
+pub type ChatMessageId = u64;
+
+/// A message between two chat clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "WireMessage", from = "WireMessage")]
+pub struct ChatMessage {
+    /// The [`PROTOCOL_VERSION`] of the sender
+    pub version: u16,
+    pub id: ChatMessageId,
+    /// When the message was written, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub body: MessageBody,
+}
+impl ChatMessage {
+    /// A message written now, with a random id
+    pub fn new(body: MessageBody) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            version: PROTOCOL_VERSION,
+            id: OsRng.next_u64(),
+            timestamp,
+            body,
+        }
+    }
+
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(MessageBody::Text(text.into()))
+    }
+
+    /// Tells the other side which client is on this one
+    pub fn hello(client: impl Into<String>) -> Self {
+        Self::new(MessageBody::Control(Control::Hello { client: client.into() }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageBody {
+    Text(String),
+    /// A file offered on the file channel, so it shows among the messages
+    File {
+        name: String,
+        size: u64,
+    },
+    Control(Control),
+    /// A message of a `kind` from a newer version this one does not know, or could not read
+    Unsupported {
+        kind: u16,
+    },
+}
+impl MessageBody {
+    const TEXT: u16 = 0;
+    const FILE: u16 = 1;
+    const CONTROL: u16 = 2;
+
+    /// The number of the kind of the body, as it is sent
+    pub fn kind(&self) -> u16 {
+        match self {
+            MessageBody::Text(_) => Self::TEXT,
+            MessageBody::File { .. } => Self::FILE,
+            MessageBody::Control(_) => Self::CONTROL,
+            MessageBody::Unsupported { kind } => *kind,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let encoded = match self {
+            MessageBody::Text(text) => bincode::serialize(text),
+            MessageBody::File { name, size } => bincode::serialize(&(name, size)),
+            MessageBody::Control(control) => bincode::serialize(control),
+            MessageBody::Unsupported { .. } => Ok(Vec::new()),
+        };
+        encoded.expect("bincode can write any body to memory")
+    }
+
+    fn decode(kind: u16, bytes: &[u8]) -> Self {
+        let decoded = match kind {
+            Self::TEXT => bincode::deserialize(bytes).map(MessageBody::Text),
+            Self::FILE => bincode::deserialize(bytes).map(|(name, size)| MessageBody::File { name, size }),
+            Self::CONTROL => bincode::deserialize(bytes).map(MessageBody::Control),
+            _ => return MessageBody::Unsupported { kind },
+        };
+        decoded.unwrap_or(MessageBody::Unsupported { kind })
+    }
+}
+impl Display for MessageBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageBody::Text(text) => write!(f, "{text}"),
+            MessageBody::File { name, size } => write!(f, "Shared the file '{name}' ({size} bytes)"),
+            MessageBody::Control(Control::Hello { client }) => write!(f, "Is using {client}"),
+            MessageBody::Unsupported { kind } => write!(
+                f,
+                "Sent a kind of message ({kind}) this version of Atris cannot show, updating may help"
+            ),
+        }
+    }
+}
+
+/// Messages about the conversation rather than in it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Control {
+    /// Which client, and version of it, the sender is using
+    Hello { client: String },
+}
+
+/// A [`ChatMessage`] as it is sent, with its body left encoded
+#[derive(Serialize, Deserialize)]
+struct WireMessage {
+    version: u16,
+    id: ChatMessageId,
+    timestamp: u64,
+    kind: u16,
+    #[serde(with = "serde_bytes")]
+    body: Vec<u8>,
+}
+impl From<ChatMessage> for WireMessage {
+    fn from(message: ChatMessage) -> Self {
+        Self {
+            version: message.version,
+            id: message.id,
+            timestamp: message.timestamp,
+            kind: message.body.kind(),
+            body: message.body.encode(),
+        }
+    }
+}
+impl From<WireMessage> for ChatMessage {
+    fn from(wire: WireMessage) -> Self {
+        Self {
+            version: wire.version,
+            id: wire.id,
+            timestamp: wire.timestamp,
+            body: MessageBody::decode(wire.kind, &wire.body),
+        }
+    }
+}
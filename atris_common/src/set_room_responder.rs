@@ -23,6 +23,8 @@ pub enum SetRoomResponderError {
     InvalidRoomId(u16),
     DatabaseWriteError,
     NotRoomCreator(String),
+    /// The room was created for another user than the one it is being answered for
+    NotInvitedUser(String),
     InvalidSessionId(CipherKey),
     NoSessionForUser(String),
 }
@@ -50,6 +52,9 @@ impl Display for SetRoomResponderError {
                     "User {u:?} did not create this room and, as such, cannot set the responder."
                 )
             }
+            Self::NotInvitedUser(u) => {
+                write!(f, "This room was not created for user {u:?}.")
+            }
             Self::NoSessionForUser(u) => {
                 write!(f, "No session found for user {u}.")
             }
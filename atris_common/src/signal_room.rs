@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::CipherKey;
+
+/// The most signaling messages one end of a room can publish, which is enough for dozens of ICE restarts
+pub const MAX_SIGNALS: usize = 512;
+
+/// Which end of a room's connection a signaling message comes from
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalRole {
+    /// The user who joined the room, whose WebRTC initiator was registered at login
+    Initiator,
+    /// The user who created the room and answered the initiator
+    Responder,
+}
+impl SignalRole {
+    /// The role at the other end of the connection
+    pub fn other(self) -> Self {
+        match self {
+            Self::Initiator => Self::Responder,
+            Self::Responder => Self::Initiator,
+        }
+    }
+}
+
+/// A message passed between the two ends of a room while their connection is being set up
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum SignalMessage {
+    /// A newly gathered local ICE candidate, as the JSON of an `RTCIceCandidateInit`
+    Candidate(String),
+    /// The sender has finished gathering candidates
+    EndOfCandidates,
+    /// An offer restarting ICE after the connection dropped, as the JSON of an `RTCSessionDescription`
+    RestartOffer(String),
+    /// The answer to a [`SignalMessage::RestartOffer`], as the JSON of an `RTCSessionDescription`
+    RestartAnswer(String),
+    /// The initiator's offer, made when it joined rather than at login, for the room's creator to answer.
+    /// It is encoded like the initiator string given at login.
+    Offer(String),
+}
+
+/// A request to publish signaling messages for a room and fetch the ones published by the other end.
+/// The server will respond with a Result<SignalRoomResponse,SignalRoomError>
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SignalRoomRequest {
+    pub session_id: CipherKey,
+    pub room_id: u16,
+    /// The end of the connection the requester is
+    pub role: SignalRole,
+    /// The messages to append to the requester's queue
+    pub messages: Vec<SignalMessage>,
+    /// How many messages from the other end's queue the requester has already received
+    pub received: u32,
+}
+
+/// A successful response to a [`SignalRoomRequest`] on the atris auth server.
+///  - For error response, see [`SignalRoomError`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SignalRoomResponse {
+    /// The messages from the other end's queue which the requester has not received yet
+    pub messages: Vec<SignalMessage>,
+    /// The user who published the other end's queue, the only one who can.
+    /// `None` from servers which did not say, or rooms from before the invited user was recorded.
+    #[serde(default)]
+    pub sender: Option<String>,
+}
+
+/// A response to a [`SignalRoomRequest`] on the atris auth server. For success response, see [`SignalRoomResponse`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum SignalRoomError {
+    InvalidSessionId(CipherKey),
+    NonexistentRoomId(u16),
+    /// The requester is not the user of the room at the end they signalled as
+    NotInRoom(u16),
+    /// The requester's end of the room already holds [`MAX_SIGNALS`] messages, or the request had more
+    TooManySignals,
+    SerializationError,
+    DatabaseReadError,
+    DatabaseWriteError,
+}
+impl Display for SignalRoomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSessionId(s) => {
+                write!(f, "Session {s:?} does not exist.")
+            }
+            Self::NonexistentRoomId(room_id) => {
+                write!(f, "RoomID '{}' does not exist", room_id)
+            }
+            Self::NotInRoom(room_id) => {
+                write!(f, "You cannot signal in room '{}' as that end", room_id)
+            }
+            Self::TooManySignals => {
+                write!(f, "Too many signaling messages, at most {MAX_SIGNALS} can be published")
+            }
+            Self::SerializationError => {
+                write!(f, "Error serializing signaling messages")
+            }
+            Self::DatabaseReadError => {
+                write!(f, "Failed to read from the database")
+            }
+            Self::DatabaseWriteError => {
+                write!(f, "Failed to write to the database")
+            }
+        }
+    }
+}
+impl Error for SignalRoomError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
@@ -0,0 +1,25 @@
+//! The short-lived TURN credentials the auth server issues and the relay checks, as described in
+//! draft-uberti-behave-turn-rest (the scheme coturn calls `use-auth-secret`)
+//!
+//! The username is `<expiry unix time>:<atris username>`, and the credential is the base64 HMAC-SHA1 of that username,
+//! keyed with the secret shared between the auth server and the relay.
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+/// The TURN username for `username` which expires at `expiry` (a unix time)
+pub fn username_for(username: &str, expiry: u64) -> String {
+    format!("{expiry}:{username}")
+}
+
+/// The unix time the given TURN username expires at, if it is well formed
+pub fn expiry_of(turn_username: &str) -> Option<u64> {
+    // The expiry comes first, the atris username (if any) after a colon
+    turn_username.split(':').next()?.parse().ok()
+}
+
+/// The credential for the given TURN username, which is the base64 HMAC-SHA1 of the username
+pub fn credential_for(secret: &str, turn_username: &str) -> Option<String> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(turn_username.as_bytes());
+    Some(base64::encode(mac.finalize().into_bytes()))
+}
@@ -0,0 +1,57 @@
+//! Tests of the [`ChatMessage`] schema every client speaks, and of how it copes with messages from newer versions
+
+use anyhow::Result;
+use atris_common::message::{ChatMessage, Control, MessageBody, PROTOCOL_VERSION};
+
+/// The bincode of a message as a client of `version` would send it, with a `kind` and `body` of its choosing
+fn wire(version: u16, kind: u16, body: Vec<u8>) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(&(version, 7u64, 1_000u64, kind, body))?)
+}
+
+#[test]
+fn every_kind_of_message_round_trips() -> Result<()> {
+    let bodies = [
+        MessageBody::Text("hello".to_owned()),
+        MessageBody::File {
+            name: "notes.txt".to_owned(),
+            size: 10,
+        },
+        MessageBody::Control(Control::Hello {
+            client: "atris 0.1.0".to_owned(),
+        }),
+    ];
+    for body in bodies {
+        let message = ChatMessage::new(body);
+        assert_eq!(message.version, PROTOCOL_VERSION);
+        let decoded: ChatMessage = bincode::deserialize(&bincode::serialize(&message)?)?;
+        assert_eq!(decoded, message);
+    }
+    assert_ne!(ChatMessage::text("a").id, ChatMessage::text("a").id);
+    Ok(())
+}
+
+#[test]
+fn newer_kinds_are_unsupported_rather_than_unreadable() -> Result<()> {
+    let message: ChatMessage = bincode::deserialize(&wire(2, 99, b"a sticker".to_vec())?)?;
+    assert_eq!((message.version, message.id, message.timestamp), (2, 7, 1_000));
+    assert_eq!(message.body, MessageBody::Unsupported { kind: 99 });
+
+    // A kind this version knows, but with a body it cannot read, like a new kind of control message
+    let control: ChatMessage = bincode::deserialize(&wire(2, 2, bincode::serialize(&7u32)?)?)?;
+    assert_eq!(control.body, MessageBody::Unsupported { kind: 2 });
+    Ok(())
+}
+
+#[test]
+fn fields_added_to_the_end_of_a_kind_are_ignored() -> Result<()> {
+    let body = bincode::serialize(&("notes.txt", 10u64, "a checksum from a newer version"))?;
+    let message: ChatMessage = bincode::deserialize(&wire(2, 1, body)?)?;
+    assert_eq!(
+        message.body,
+        MessageBody::File {
+            name: "notes.txt".to_owned(),
+            size: 10
+        }
+    );
+    Ok(())
+}
@@ -1,9 +1,11 @@
-use atris_client_lib::{http_auth::AtrisAuth, comms::{initiator::AtrisInitiator, AtrisConnection}, atris_common::{authenticate_user::{AuthenticateUserError, AuthenticateUserResponse}, create_user::{CreateUserRequest, CreateUserError, CreateUserResponse}, CipherKey, create_room::{CreateRoomResponse, CreateRoomError}, join_room::{JoinRoomError, JoinRoomResponse}, set_room_responder::{SetRoomResponderResponse, SetRoomResponderError}}, AtrisAuthClient, InvocationError};
-use iced_native::Debug;
+use crate::Session;
+use atris_client_lib::{http_auth::AtrisAuth, comms::{channels::{AtrisDataChannels, IncomingChannels}, initiator::AtrisInitiator, invite::{self, Invitation}, responder::AtrisResponder, trickle::RoomSignaller, transfer, AtrisConnection}, atris_common::{signal_room::SignalRole, authenticate_user::{AuthenticateUserError, AuthenticateUserResponse}, create_user::{CreateUserError, CreateUserResponse}, CipherKey, create_room::{CreateRoomResponse, CreateRoomError}, join_room::{JoinRoomError, JoinRoomResponse}, set_room_responder::{SetRoomResponderResponse, SetRoomResponderError}}, AtrisAuthClient, InvocationError};
 
 pub struct AtrisClient {
     server_client: AtrisAuth,
-    pub(crate) initiator:AtrisInitiator,
+    /// Only offered at login, which the server asks for. Rooms are connected from fresh connections instead, which
+    /// use the ICE servers issued at login.
+    initiator:AtrisInitiator,
 }
 impl std::fmt::Debug for AtrisClient {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -13,7 +15,15 @@ impl std::fmt::Debug for AtrisClient {
 
 type AtrisError = reqwest::Error;
 
+fn server_client(server: Option<&str>) -> Result<AtrisAuth, AtrisError> {
+    match server {
+        Some(server) => AtrisAuth::with_server(server),
+        None => AtrisAuth::new(),
+    }
+}
+
 #[derive(Debug,Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum ClientError {
     ConnectionError,
     InitiatorError,
@@ -27,12 +37,12 @@ pub enum ClientError {
     SetRoomResponderError(SetRoomResponderError),
 }
 impl From<AtrisError> for ClientError {
-    fn from(err: AtrisError) -> Self {
+    fn from(_err: AtrisError) -> Self {
         Self::RequestError("Error making a request".into())
     }
 }
 impl From<InvocationError<AtrisError>> for ClientError {
-    fn from(err: InvocationError<AtrisError>) -> Self {
+    fn from(_err: InvocationError<AtrisError>) -> Self {
         Self::InvocationError("Error invoking lambda".into())
     }
 }
@@ -65,12 +75,20 @@ impl From<SetRoomResponderError> for ClientError {
 
 
 impl AtrisClient {
-    pub async fn new()->Result<Self,ClientError> {
+    /// A client for `server`, or for the default server
+    pub async fn new(server: Option<String>)->Result<Self,ClientError> {
         Ok(Self {
-            initiator: AtrisInitiator::new(AtrisConnection::new().await.map_err(|_|ClientError::ConnectionError)?).await.map_err(|_|ClientError::InitiatorError)?,
-            server_client: AtrisAuth::new()?
+            initiator: AtrisInitiator::with_channels(AtrisConnection::new().await.map_err(|_|ClientError::ConnectionError)?, transfer::chat_and_file_channels()).await.map_err(|_|ClientError::InitiatorError)?,
+            server_client: server_client(server.as_deref())?
         })
     }
+    /// Talk to `server` from now on, or to the default server, if it is not the one already
+    pub fn set_server(&mut self, server: Option<&str>)->Result<(),ClientError> {
+        if self.server_client.server() != server {
+            self.server_client = server_client(server)?;
+        }
+        Ok(())
+    }
     pub async fn create_user(&self, user: &str,pass: &str,) -> Result<CreateUserResponse,ClientError> {
         self.server_client.create_user(user, pass).await?.map_err(|e|e.into())
     }
@@ -81,15 +99,43 @@ impl AtrisClient {
     pub async fn join_room(&self, session_id: CipherKey,room_id: u16) -> Result<JoinRoomResponse,ClientError> {
         self.server_client.join_room(session_id, room_id).await?.map_err(|e|e.into())
     }
-    pub async fn login(&self, user: &str,pass: &str,) -> Result<AuthenticateUserResponse,ClientError> {
+    pub async fn login(&self, user: &str,pass: &str,second_factor: Option<&str>) -> Result<AuthenticateUserResponse,ClientError> {
         let initiator_string = self.initiator.encoded_local_description().map_err(|_|ClientError::EncodingError)?;
         println!("Authenticating");
-        let auth = self.server_client
-            .authenticate_user(user, pass, &initiator_string)
-            .await??;
+        let auth = match second_factor {
+            Some(code) => self.server_client
+                .authenticate_user_with_second_factor(user, pass, &initiator_string, code)
+                .await??,
+            None => self.server_client
+                .authenticate_user(user, pass, &initiator_string)
+                .await??,
+        };
         println!("Authenticated");
         Ok(auth)
     }
+    /// A connection using the ICE servers issued with `session`
+    async fn connection(session: &Session) -> Result<AtrisConnection, String> {
+        AtrisConnection::builder().ice_servers(session.1.clone()).build().await.map_err(|e|e.to_string())
+    }
+    /// Wait in the room `room_id` this user created for `other_user` to join, and answer them
+    pub async fn accept(&self, session: Session, room_id: u16, other_user: &str) -> Result<(IncomingChannels, CipherKey), String> {
+        let responder = AtrisResponder::with_connection(Self::connection(&session).await?);
+        Invitation::existing(self.server_client.clone(), session.0, room_id, other_user)
+            .accept(responder)
+            .await
+            .map_err(|e|e.to_string())
+    }
+    /// Join the room `room_id` another user invited this user to, and wait for them to answer
+    pub async fn join(&self, session: Session, room_id: u16) -> Result<(AtrisDataChannels, CipherKey), String> {
+        let initiator = AtrisInitiator::with_channels(Self::connection(&session).await?, transfer::chat_and_file_channels())
+            .await
+            .map_err(|e|e.to_string())?;
+        invite::join(self.server_client.clone(), session.0, room_id, initiator).await.map_err(|e|e.to_string())
+    }
+    /// A signaller which trickles candidates through the given room
+    pub fn room_signaller(&self, session_id: CipherKey, room_id: u16, role: SignalRole) -> RoomSignaller<AtrisAuth> {
+        RoomSignaller::new(self.server_client.clone(), session_id, room_id, role)
+    }
     pub async fn set_room_responder(
         &self,
         room_id: u16,
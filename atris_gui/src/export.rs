@@ -0,0 +1,41 @@
+//! Exporting the rooms' messages to archives, and importing them, for the buttons on the home page
+use std::path::{Path, PathBuf};
+
+use atris_client_lib::export::{self, ExportFormat};
+use atris_client_lib::history::History;
+
+/// Export room `room_id` to `path`, encrypted with `passphrase` unless it is empty, saying what was done
+pub fn export_room(history: &History, room_id: u16, path: &Path, passphrase: &str) -> Result<String, String> {
+    let log = history
+        .conversation(&History::room(room_id))
+        .map_err(|e| e.to_string())?;
+    if log.is_empty() {
+        return Err(format!("There are no messages in room {room_id} to export"));
+    }
+    let exported = export::export(&log, &[]).map_err(|e| e.to_string())?;
+    let passphrase = (!passphrase.is_empty()).then_some(passphrase);
+    export::write(&exported, path, ExportFormat::of(path), passphrase).map_err(|e| e.to_string())?;
+    Ok(format!(
+        "Exported {} messages to {}",
+        exported.messages.len(),
+        path.display()
+    ))
+}
+
+/// Import the archive at `path`, decrypting it with `passphrase` if it is encrypted and saving its files in
+/// `files_dir`, saying what was done
+pub fn import(history: &History, path: &Path, passphrase: &str, files_dir: Option<PathBuf>) -> Result<String, String> {
+    let archive = export::read(path).map_err(|e| e.to_string())?;
+    if archive.is_encrypted() && passphrase.is_empty() {
+        return Err("The export is encrypted, enter its passphrase first".into());
+    }
+    let exported = export::open(archive, Some(passphrase)).map_err(|e| e.to_string())?;
+    let files_dir = files_dir.unwrap_or_else(|| PathBuf::from("."));
+    let imported = export::import(history, exported, None, &files_dir).map_err(|e| e.to_string())?;
+    Ok(format!(
+        "Imported {} new messages into {} and {} files",
+        imported.messages,
+        imported.conversation,
+        imported.files.len()
+    ))
+}
@@ -1,29 +1,37 @@
-use std::borrow::Borrow;
-use std::cell::RefCell;
-use std::env::home_dir;
-use std::ffi::OsStr;
-use std::path::{PathBuf, Path};
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc};
 use std::vec;
 
-use atris_client_lib::atris_common::create_room::{CreateRoomResponse, CreateRoomError};
-use atris_client_lib::atris_common::join_room::JoinRoomResponse;
-use atris_client_lib::atris_common::{CipherKey};
-use atris_client_lib::atris_common::authenticate_user::AuthenticateUserResponse;
-use atris_client_lib::comms::AtrisChannel;
-use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::atris_common::create_room::CreateRoomResponse;
+use atris_client_lib::atris_common::message::{ChatMessage, Control, MessageBody};
+use atris_client_lib::atris_common::{CipherKey, IceServer};
+use atris_client_lib::atris_common::authenticate_user::{AuthenticateUserError, AuthenticateUserResponse};
+use atris_client_lib::comms::{AtrisChannel, ChannelState, ConnectionEvent};
+use atris_client_lib::comms::channels::DEFAULT_CHANNEL_LABEL;
+use atris_client_lib::comms::delivery::{MessageId, MessageStatus, StatusUpdate};
+use atris_client_lib::comms::presence::{Presence, PresenceReceiver};
+use atris_client_lib::comms::transfer::{FileTransfers, TransferEvent, TransferId, TRANSFER_CHANNEL_LABEL};
+use atris_client_lib::history::{ConversationLog, Direction, EntryId, History, HistoryEntry, HistoryError, SearchHit};
+use atris_client_lib::profile::UnlockedProfile;
 use client::{AtrisClient};
 use iced::alignment::Horizontal;
-use iced::{executor, Subscription, subscription};
+use iced::{executor, window, Event, Subscription, subscription};
+use iced::futures::future;
+use tokio::sync::{broadcast, watch};
 use iced::futures::lock::Mutex;
-use iced::widget::{button, container, text,text_input, Column, radio, Row};
+use iced::widget::{button, checkbox, container, pick_list, text,text_input, Column, radio, Row};
 use iced::{
     Alignment, Application, Command, Element, Length, Settings,
     Theme,
 };
 
 mod client;
+mod export;
+mod profile;
+
+/// How many messages from before are shown at a time
+const HISTORY_PAGE: usize = 50;
 
 #[derive(Debug,Clone, Copy,PartialEq, Eq)]
 pub enum LoginMode {
@@ -31,7 +39,15 @@ pub enum LoginMode {
     LoginUser
 }
 
-pub struct Session(CipherKey);
+/// The session id, and the ICE servers issued with it
+pub struct Session(CipherKey, Vec<IceServer>);
+impl Session {
+    /// The session saved in a logged in profile
+    fn of(profile: &UnlockedProfile) -> Option<Self> {
+        let session = profile.session()?;
+        Some(Session(session.session_id.clone(), session.ice_servers.clone()))
+    }
+}
 
 pub enum Atris {
     CreatingClient,
@@ -40,16 +56,33 @@ pub enum Atris {
         atris_client: Arc<AtrisClient>,
         username:String,
         password:String,
+        /// The passphrase the profile is locked with on this machine, which a remembered profile does not need
+        passphrase:String,
+        remember_me:bool,
+        /// The usernames of the saved profiles, to switch between
+        profiles:Vec<String>,
         login_select: LoginMode,
         error_message: Option<String>,
+        /// The two-factor code being entered, once the server has asked for one
+        second_factor: Option<String>,
         // Maybe this should also be create?
     },
     LoggingIn,
     Home {
         atris_client: Arc<AtrisClient>,
+        username:String,
         session:Session,
+        /// The messages sent and received in every room, if the profile's history could be opened
+        history:Option<History>,
         other_user:String,
         room_id:String,
+        search:String,
+        /// What the last search found, if there was one
+        search_results:Option<Vec<SearchHit>>,
+        /// The passphrase to encrypt exports with, or decrypt imports with, if there is one
+        export_passphrase:String,
+        /// What happened to the last export or import
+        export_status:Option<String>,
     },
     CreateRoomError {
         other_user:String
@@ -57,62 +90,103 @@ pub enum Atris {
     MesageWaitingFailed(String),
     MessageWaitingPage {
         room_id:u16,
-        other_user:Option<String>
+        other_user:Option<String>,
+        history:Option<History>,
     },
     MessagePage {
         room_id:u16,
+        other_user:String,
+        /// Whether the other user is typing
+        other_typing:bool,
+        /// The last thing the other user signalled about themselves, other than typing
+        other_presence:Option<Presence>,
         messages: Vec<AtrisMessage>,
+        /// Where the messages are recorded, if the profile's history could be opened
+        log: Option<ConversationLog>,
+        /// The oldest message from the history shown, to load the ones before it
+        earliest: Option<EntryId>,
+        /// The statuses of the sent messages, as the other end acknowledges them
+        statuses: HashMap<MessageId, MessageStatus>,
         current_message:String,
-        message_channel: Arc<Mutex<AtrisChannel<AtrisMessageData>>>
+        message_channel: Arc<Mutex<AtrisChannel<ChatMessage>>>,
+        channel_state: ChannelState,
+        // The last notable thing that happened to the connection
+        connection_status: Option<String>,
+        files: FileTransfers,
+        transfers: Vec<Transfer>,
     }
     // 
 }
 #[derive(Debug,Clone,serde::Serialize,serde::Deserialize)]
 pub enum AtrisMessage {
-    Sent(String),
-    Received(String)
+    Sent(MessageId, String),
+    Received(String),
+    /// A message from the history, sent or received before the room was entered
+    Earlier(HistoryEntry),
 }
 
-#[derive(Debug,Clone,serde::Serialize,serde::Deserialize)]
-pub enum AtrisMessageData {
-    Text(String),
-    File{
-        #[serde(with = "serde_bytes")]
-        data:Vec<u8>,
-        name:String,
-    }
+/// A file being sent or received, as shown under the messages
+#[derive(Debug,Clone)]
+pub struct Transfer {
+    id: TransferId,
+    name: String,
+    status: String,
+    /// Whether the transfer is still going, and can be cancelled
+    active: bool,
 }
-// pub type AtrisMessageData = String;
 
 #[derive(Debug,Clone)]
 pub enum Message {
     // LoginPage
     UpdateUsername(String),
     UpdatePassword(String),
-    ClientCreated(Result<Arc<AtrisClient>,()>),
-    LoginComplete(Result<AuthenticateUserResponse,String>,Arc<AtrisClient>,LoginMode),
+    UpdatePassphrase(String),
+    UpdateSecondFactor(String),
+    PickProfile(String),
+    RememberMe(bool),
+    /// The client, and the profile to skip the login page with if it is remembered and logged in
+    ClientCreated(Result<Arc<AtrisClient>,()>,Option<UnlockedProfile>),
+    LoginComplete(Result<UnlockedProfile,String>,Arc<AtrisClient>,LoginMode),
+    /// The client, username, password, passphrase and whether to remember the profile, to finish logging in with
+    SecondFactorRequired(Arc<AtrisClient>,String,String,String,bool),
     LoginSelector(LoginMode),
+    LogOut,
+    SwitchProfile,
     
     UpdateOtherUser(String),
     UpdateRoomId(String),
+    UpdateSearch(String),
+    Search,
+    UpdateExportPassphrase(String),
+    ExportRoom,
+    ImportConversation,
 
     RoomWaitingFailed(String),
 
     CreateRoom,
     CreateRoomFinished((Result<CreateRoomResponse, client::ClientError>,String)),
     JoinRoom,
-    JoinRoomFinished(u16,Result<JoinRoomResponse, client::ClientError>),
 
-    MessageChannelReceived(Arc<Mutex<AtrisChannel<AtrisMessageData>>>),
-    ReceiveMessage(AtrisMessageData),
+    MessageChannelReceived(Arc<Mutex<AtrisChannel<ChatMessage>>>,FileTransfers),
+    ReceiveMessage(MessageId, ChatMessage),
     ReceiveMessageFailed,
+    ChannelStateChanged(ChannelState),
+    ConnectionEvent(ConnectionEvent),
+    PresenceChanged(Presence),
+    WindowFocused(bool),
 
     SendMessage,
-    MessageSent(String),
+    MessageSent(MessageId, String),
+    MessageStatusChanged(StatusUpdate),
+    MessageSendFailed(String,String),
 
     UpdateCurrentMessage(String),
     SendFile, //includes the local directory of the file to send
     ActualSendFile(PathBuf),
+    FileOffered(Result<(TransferId,String,u64),String>),
+    TransferEvent(TransferEvent),
+    CancelTransfer(TransferId),
+    LoadEarlier,
 
     // RoomCreated(Result<AuthenticateUserResponse,String>,Arc<AtrisClient>),
     SubmitUserInfo,
@@ -121,18 +195,21 @@ pub enum Message {
     Nop
 }
 
-async fn wait_for_next_message_actually(channel:&Arc<Mutex<AtrisChannel<AtrisMessageData>>>)->Message {
-    dbg!("Waiting for lock to receive");
-    let mut lock = channel.lock().await;
-    dbg!("Got lock to receive");
-    let msg = lock.receive().await.and_then(|r|dbg!(r).ok()).map(Message::ReceiveMessage).unwrap_or(Message::ReceiveMessageFailed);
-    drop(lock);
-    msg
-}
-// fn wait_for_next_message(channel:Arc<Mutex<AtrisChannel<AtrisMessageData>>>) -> Command<Message> {
+// fn wait_for_next_message(channel:Arc<Mutex<AtrisChannel<ChatMessage>>>) -> Command<Message> {
 //     Command::perform(wait_for_next_message_actually(channel), |a|a)
 // }
 
+impl Atris {
+    /// The login page, filled in with the current profile
+    fn login_page(atris_client: Arc<AtrisClient>, error_message: Option<String>) -> Self {
+        let (profiles, current) = profile::saved();
+        Self::Login { atris_client, username: current.unwrap_or_default(), password: "".into(), passphrase: "".into(), remember_me: false, profiles, login_select: LoginMode::LoginUser, error_message, second_factor: None }
+    }
+    fn home(atris_client: Arc<AtrisClient>, profile: &UnlockedProfile, session: Session) -> Self {
+        Self::Home { atris_client, username: profile.username().into(), session, history: profile::history(profile), room_id: "".into(), other_user: "".into(), search: "".into(), search_results: None, export_passphrase: "".into(), export_status: None }
+    }
+}
+
 impl Application for Atris {
     type Message = Message;
     type Theme = Theme;
@@ -152,14 +229,84 @@ impl Application for Atris {
     }
 
     fn subscription(&self) -> iced::Subscription<Self::Message> {
-        if let Self::MessagePage { message_channel,.. } = self {
-            subscription::unfold((), message_channel.clone(), |channel|async move {
+        if let Self::MessagePage { message_channel,files,.. } = self {
+            let messages = subscription::unfold((), message_channel.clone(), |channel|async move {
                 let msg = {
                     let mut lock = channel.lock().await;
-                    lock.try_receive().ok().map(Message::ReceiveMessage)
+                    lock.try_receive_with_id().ok().map(|(id, m)|Message::ReceiveMessage(id, m))
                 };
                 (msg,channel)
-            })
+            });
+            // Reports the current state first, then every change to it
+            let states = subscription::unfold("channel_state", (message_channel.clone(), None::<watch::Receiver<ChannelState>>), |(channel, receiver)|async move {
+                let receiver = match receiver {
+                    Some(mut receiver) => {
+                        if receiver.changed().await.is_err() {
+                            // The connection is gone, and so is any further change
+                            future::pending::<()>().await;
+                        }
+                        receiver
+                    }
+                    None => channel.lock().await.state_receiver(),
+                };
+                let state = *receiver.borrow();
+                (Some(Message::ChannelStateChanged(state)),(channel, Some(receiver)))
+            });
+            let events = subscription::unfold("connection_events", (message_channel.clone(), None::<broadcast::Receiver<ConnectionEvent>>), |(channel, receiver)|async move {
+                let mut receiver = match receiver {
+                    Some(receiver) => receiver,
+                    None => channel.lock().await.events(),
+                };
+                let event = loop {
+                    match receiver.recv().await {
+                        Ok(event) => break event,
+                        // Only the latest events matter to the status
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => future::pending().await,
+                    }
+                };
+                (Some(Message::ConnectionEvent(event)),(channel, Some(receiver)))
+            });
+            let transfers = subscription::unfold("transfer_events", files.events(), |mut receiver|async move {
+                let event = loop {
+                    match receiver.recv().await {
+                        Ok(event) => break event,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => future::pending().await,
+                    }
+                };
+                (Some(Message::TransferEvent(event)),receiver)
+            });
+            let statuses = subscription::unfold("message_statuses", (message_channel.clone(), None::<broadcast::Receiver<StatusUpdate>>), |(channel, receiver)|async move {
+                let mut receiver = match receiver {
+                    Some(receiver) => receiver,
+                    None => channel.lock().await.status_updates(),
+                };
+                let update = loop {
+                    match receiver.recv().await {
+                        Ok(update) => break update,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => future::pending().await,
+                    }
+                };
+                (Some(Message::MessageStatusChanged(update)),(channel, Some(receiver)))
+            });
+            let presence = subscription::unfold("presence", (message_channel.clone(), None::<PresenceReceiver>), |(channel, receiver)|async move {
+                let mut receiver = match receiver {
+                    Some(receiver) => receiver,
+                    None => channel.lock().await.presence(),
+                };
+                match receiver.recv().await {
+                    Some(presence) => (Some(Message::PresenceChanged(presence)),(channel, Some(receiver))),
+                    None => future::pending().await,
+                }
+            });
+            let focus = subscription::events_with(|event, _|match event {
+                Event::Window(window::Event::Focused) => Some(Message::WindowFocused(true)),
+                Event::Window(window::Event::Unfocused) => Some(Message::WindowFocused(false)),
+                _ => None,
+            });
+            Subscription::batch([messages, states, events, transfers, statuses, presence, focus])
         }else{
             Subscription::none()
         }
@@ -168,14 +315,29 @@ impl Application for Atris {
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
         if let Message::CreateClient = message {
             return Command::perform(async {
-                (AtrisClient::new().await).map(Arc::new).map_err(|_|{})
-            },Message::ClientCreated);
+                let resumed = profile::resume();
+                let server = match &resumed {
+                    Some(resumed) => resumed.profile().server.clone(),
+                    None => profile::saved().1.and_then(|current|profile::server(&current)),
+                };
+                let client = (AtrisClient::new(server).await).map(Arc::new).map_err(|_|{});
+                Message::ClientCreated(client, resumed)
+            },|a|a);
         }else if let Message::Nop = message {
             return Command::none()
         };
         match self {
-            Self::Home { atris_client, session, other_user, room_id } =>{
+            Self::Home { atris_client, username, session, history, other_user, room_id, search, search_results, export_passphrase, export_status } =>{
                 match message {
+                    Message::LogOut => {
+                        let error_message = profile::log_out(username).err();
+                        *self = Self::login_page(Arc::clone(atris_client), error_message);
+                        Command::none()
+                    },
+                    Message::SwitchProfile => {
+                        *self = Self::login_page(Arc::clone(atris_client), None);
+                        Command::none()
+                    },
                     Message::UpdateOtherUser(u)=>{
                         *other_user = u;
                         Command::none()
@@ -184,6 +346,49 @@ impl Application for Atris {
                         *room_id = r;
                         Command::none()
                     },
+                    Message::UpdateSearch(s)=>{
+                        *search = s;
+                        Command::none()
+                    },
+                    Message::Search=>{
+                        *search_results = history.as_ref().map(|history|history.search(search).unwrap_or_default());
+                        Command::none()
+                    },
+                    Message::UpdateExportPassphrase(p)=>{
+                        *export_passphrase = p;
+                        Command::none()
+                    },
+                    Message::ExportRoom=>{
+                        let (Some(history), Ok(room)) = (history, room_id.parse::<u16>()) else {
+                            *export_status = Some("Enter the id of a room to export".into());
+                            return Command::none();
+                        };
+                        let filepath = native_dialog::FileDialog::new()
+                            .set_location("~/Documents")
+                            .set_filename(&format!("room-{room}.html"))
+                            .add_filter("HTML transcript", &["html"])
+                            .add_filter("JSON archive", &["json"])
+                            .show_save_single_file();
+                        if let Ok(Some(path)) = filepath {
+                            *export_status = Some(export::export_room(history, room, &path, export_passphrase).unwrap_or_else(|e|e));
+                        }
+                        Command::none()
+                    },
+                    Message::ImportConversation=>{
+                        let Some(history) = history else {
+                            *export_status = Some("The message history could not be opened".into());
+                            return Command::none();
+                        };
+                        let filepath = native_dialog::FileDialog::new()
+                            .set_location("~/Documents")
+                            .add_filter("Exported conversation", &["html","json"])
+                            .show_open_single_file();
+                        if let Ok(Some(path)) = filepath {
+                            let files_dir = profile::download_dir(username);
+                            *export_status = Some(export::import(history, &path, export_passphrase, files_dir).unwrap_or_else(|e|e));
+                        }
+                        Command::none()
+                    },
                     Message::CreateRoom => {
                         let atris_client = Arc::clone(atris_client);
                         let other_user = other_user.clone();
@@ -195,39 +400,24 @@ impl Application for Atris {
                     Message::CreateRoomFinished((r,other_user)) => {
                         match r {
                             Ok(room)=>{
-                                let Self::Home { atris_client, session, other_user, room_id }  = std::mem::replace(self,Self::MessageWaitingPage {room_id:room.room_id,other_user:Some(other_user.clone()) }) else {
+                                let history = history.clone();
+                                let Self::Home { atris_client, session, other_user, .. }  = std::mem::replace(self,Self::MessageWaitingPage {room_id:room.room_id,other_user:Some(other_user.clone()),history }) else {
                                     unreachable!()
                                 };
-                                let atris_client =Arc::new(atris_client);
                                 Command::perform(async move {
-                                    let atris_responder = match AtrisResponder::new().await {
-                                        Ok(responder)=>{
-                                            match responder
-                                            .into_channel_parts_with::<AtrisMessageData>(&room.initiator_string)
-                                            .await { 
-                                                Ok((responder_string, channel_future))=>{
-                                                    Ok((atris_client
-                                                        .set_room_responder(
-                                                            room.room_id,
-                                                            session.0,
-                                                            other_user.clone(),
-                                                            responder_string
-                                                        ).await,channel_future.await))
-                                                },
-                                                Err(e)=>Err(e),
-                                            }
-                                        }
-                                        Err(e)=>Err(e)
+                                    let (mut channels, room_key) = match atris_client.accept(session, room.room_id, &other_user).await {
+                                        Ok(accepted) => accepted,
+                                        Err(e) => return Message::RoomWaitingFailed(e),
                                     };
-                                    if let Ok((Ok(set_room_response),Some(channel))) = atris_responder {
-                                        let channel = AtrisChannel::new(channel, set_room_response.room_symmetric_key.as_cipher());
-                                        Message::MessageChannelReceived(Arc::new(Mutex::new(channel)))
-                                    }else{
-                                        Message::RoomWaitingFailed("A".into())
-                                    }
+                                    let (Some(channel), Some(file_channel)) = (channels.take(DEFAULT_CHANNEL_LABEL).await, channels.take(TRANSFER_CHANNEL_LABEL).await) else {
+                                        return Message::RoomWaitingFailed("The other user did not open the expected channels".into());
+                                    };
+                                    let channel = AtrisChannel::new(channel, room_key.as_cipher());
+                                    let files = FileTransfers::spawn(AtrisChannel::new(file_channel, room_key.as_cipher()));
+                                    Message::MessageChannelReceived(Arc::new(Mutex::new(channel)),files)
                                 }, |a|a)
                             }
-                            Err(e) => {
+                            Err(_e) => {
                                 *self = Self::CreateRoomError { other_user };
                                 Command::none()
                             },
@@ -235,47 +425,31 @@ impl Application for Atris {
                     },
                     
                     Message::JoinRoom => {
-                        let room_id = room_id.parse::<u16>();
-                        let session = session.0.clone();
-                        let atris_client = Arc::clone(atris_client);
-                        if let Ok(room_id)=room_id {
-                            Command::perform(async move {
-                                atris_client.join_room(session, room_id).await
-                            },move |r|Message::JoinRoomFinished(room_id,r))
-                        } else {
-                            Command::none()
-                        }
-                    },
-                    Message::JoinRoomFinished(room_id, r) => {
-                        if let Ok(join_room_response)=r {
-                            println!("Decrypting");
-                            let room_data = join_room_response.room_data.decrypt(&mut session.0.as_cipher()).unwrap();
-                            println!("Done decrupting, swapping");
-                            let Self::Home { atris_client, session, other_user, room_id } = std::mem::replace(self,Self::MessageWaitingPage {room_id,other_user:None }) else {
+                        if let Ok(room_id)=room_id.parse::<u16>() {
+                            let history = history.clone();
+                            let Self::Home { atris_client, session, .. } = std::mem::replace(self,Self::MessageWaitingPage {room_id,other_user:None,history }) else {
                                 unreachable!()
                             };
-                            println!("Done swapping, unwrapping");
-                            if let Ok(c) = Arc::try_unwrap(atris_client) {
-                                Command::perform(async move {
-                                    println!("Done unwrapping, Making parts");
-                                    let parts = c.initiator.into_channel_parts_with::<AtrisMessageData>(&room_data.responder_string).await.unwrap();
-                                    println!("Making channel");
-                                    let channel = AtrisChannel::new(parts, room_data.symmetric_key.as_cipher());
-                                    println!("Done!");
-                                    Message::MessageChannelReceived(Arc::new(Mutex::new(channel)))
-                                },|a|a)
-                            }else {
-                                println!("Too many client havers!");
-                                Command::none()
-                            }
-                        }else{
+                            Command::perform(async move {
+                                let (mut channels, room_key) = match atris_client.join(session, room_id).await {
+                                    Ok(joined) => joined,
+                                    Err(e) => return Message::RoomWaitingFailed(e),
+                                };
+                                let (Some(parts), Some(file_parts)) = (channels.take(DEFAULT_CHANNEL_LABEL), channels.take(TRANSFER_CHANNEL_LABEL)) else {
+                                    return Message::RoomWaitingFailed("The other user did not open the expected channels".into());
+                                };
+                                let channel = AtrisChannel::new(parts, room_key.as_cipher());
+                                let files = FileTransfers::spawn(AtrisChannel::new(file_parts, room_key.as_cipher()));
+                                Message::MessageChannelReceived(Arc::new(Mutex::new(channel)),files)
+                            },|a|a)
+                        } else {
                             Command::none()
                         }
                     },
                     _=>unreachable!()
                 }
             }
-            Self::Login { username, password, login_select,.. } => {
+            Self::Login { username, password, passphrase, remember_me, login_select, second_factor,.. } => {
                 match message {
                     Message::UpdateUsername(s) => {
                         *username = s;
@@ -285,30 +459,73 @@ impl Application for Atris {
                         *password = s;
                         Command::none()
                     },
+                    Message::UpdatePassphrase(s) => {
+                        *passphrase = s;
+                        Command::none()
+                    },
+                    Message::PickProfile(s) => {
+                        *username = s;
+                        *login_select = LoginMode::LoginUser;
+                        Command::none()
+                    },
+                    Message::RememberMe(b) => {
+                        *remember_me = b;
+                        Command::none()
+                    },
+                    Message::UpdateSecondFactor(s) => {
+                        *second_factor = Some(s);
+                        Command::none()
+                    },
                     Message::SubmitUserInfo => {
-                        println!("hi, {}, whose password is {}", username, password);
-                        let Self::Login { atris_client, username, password,login_select, .. } = std::mem::replace(self,Self::LoggingIn) else {
+                        let Self::Login { mut atris_client, username, password,passphrase,remember_me,login_select,second_factor, .. } = std::mem::replace(self,Self::LoggingIn) else {
                             unreachable!()
                         };
                         Command::perform(async move {
+                            let unlocked = match profile::unlock(&username, &passphrase) {
+                                Ok(unlocked) => unlocked,
+                                Err(e) => return Message::LoginComplete(Err(e),atris_client,login_select),
+                            };
+                            // The account is on the server the profile says
+                            let switched = match Arc::get_mut(&mut atris_client) {
+                                Some(client) => client.set_server(unlocked.profile().server.as_deref()).map_err(|e|format!("{e:?}")),
+                                None => Err("The client is busy, try again".into()),
+                            };
+                            if let Err(e) = switched {
+                                return Message::LoginComplete(Err(e),atris_client,login_select);
+                            }
+                            let saved = |res: Result<AuthenticateUserResponse,String>| {
+                                res.and_then(|auth|profile::save_login(unlocked, auth, remember_me))
+                            };
+                            if let Some(code) = second_factor {
+                                let res = atris_client.login(&username, &password, Some(&code)).await.map_err(|e|{
+                                    format!("{e:?}")
+                                });
+                                return Message::LoginComplete(saved(res),atris_client,login_select);
+                            }
                             let res = if login_select == LoginMode::CreateUser {
                                 let create_user_response = atris_client.create_user(&username.clone(), &password.clone()).await.map_err(|e|{
                                     format!("{e:?}")
                                 });
                                 match create_user_response {
                                     Ok(_)=>{
-                                        atris_client.login(&username.clone(), &password.clone()).await.map_err(|e|{
+                                        atris_client.login(&username.clone(), &password.clone(), None).await.map_err(|e|{
                                             format!("{e:?}")
                                         })
                                     }
                                     Err(s)=>Err(s)
                                 }
                             } else {
-                                atris_client.login(&username.clone(), &password.clone()).await.map_err(|e|{
-                                    format!("{e:?}")
-                                })
+                                match atris_client.login(&username.clone(), &password.clone(), None).await {
+                                    // Ask for the code, keeping the credentials already entered
+                                    Err(client::ClientError::AuthenticateUserError(AuthenticateUserError::SecondFactorRequired))=>{
+                                        return Message::SecondFactorRequired(atris_client,username,password,passphrase,remember_me);
+                                    }
+                                    res=>res.map_err(|e|{
+                                        format!("{e:?}")
+                                    })
+                                }
                             };
-                            Message::LoginComplete(res,atris_client,login_select)
+                            Message::LoginComplete(saved(res),atris_client,login_select)
                         },|a|a)
                     },
                     Message::LoginSelector(b)=>{
@@ -320,10 +537,12 @@ impl Application for Atris {
             },
             Self::CreatingClient => {
                 match message {
-                    Message::ClientCreated(c)=>{
-                        *self = match c {
-                            Ok(atris_client)=>Self::Login { error_message:None, atris_client, username: "".into(), password: "".into(),login_select:LoginMode::LoginUser },
-                            Err(_)=>Self::ErrorCreatingClient
+                    Message::ClientCreated(c,resumed)=>{
+                        let resumed = resumed.and_then(|profile|Some((Session::of(&profile)?,profile)));
+                        *self = match (c,resumed) {
+                            (Ok(atris_client),Some((session,profile)))=>Self::home(atris_client, &profile, session),
+                            (Ok(atris_client),None)=>Self::login_page(atris_client, None),
+                            (Err(_),_)=>Self::ErrorCreatingClient
                         }
                     },
                     _=>unreachable!(),
@@ -337,33 +556,74 @@ impl Application for Atris {
                 match message {
                     Message::LoginComplete(result,atris_client,login_select)=>{
                         dbg!("Login complete!");
-                        match result {
-                            Ok(session)=>{
-                                *self = Self::Home { atris_client,session: Session(session.session_id),room_id:"".into(),other_user:"".into() }
+                        match result.map(|profile|(Session::of(&profile),profile)) {
+                            Ok((Some(session),profile))=>{
+                                *self = Self::home(atris_client, &profile, session)
                             },
+                            Ok((None,_))=>unreachable!("the session was just saved"),
                             Err(error_message)=>{
-                                *self = Self::Login { atris_client, username: "".into(), password: "".into(), error_message:Some(error_message), login_select }
+                                *self = Self::login_page(atris_client, Some(error_message));
+                                if let Self::Login { login_select: select, .. } = self {
+                                    *select = login_select;
+                                }
                             }
                         }
                         Command::none()
                     }
+                    Message::SecondFactorRequired(atris_client,username,password,passphrase,remember_me)=>{
+                        let (profiles, _) = profile::saved();
+                        *self = Self::Login { atris_client, username, password, passphrase, remember_me, profiles, error_message:None, login_select:LoginMode::LoginUser, second_factor:Some("".into()) };
+                        Command::none()
+                    }
                     _=>unreachable!()
                 }
             }
-            Self::MessageWaitingPage { room_id, other_user } => {
+            Self::MessageWaitingPage { room_id, other_user, history } => {
                 match message {
-                    Message::MessageChannelReceived(message_channel)=>{
-                        *self = Self::MessagePage { room_id:*room_id, messages: Default::default(), current_message: Default::default(), message_channel };
+                    Message::MessageChannelReceived(message_channel,files)=>{
+                        let other_user = other_user.clone().unwrap_or_else(||"The other user".into());
+                        let log = history.as_ref().map(|history|history.conversation(&History::room(*room_id)));
+                        let (log, connection_status) = match log {
+                            Some(Ok(log)) => (Some(log), None),
+                            Some(Err(e)) => (None, Some(e.to_string())),
+                            None => (None, None),
+                        };
+                        let earlier = log.as_ref().map(|log|log.page(None, HISTORY_PAGE).to_vec()).unwrap_or_default();
+                        let earliest = earlier.first().map(|entry|entry.id);
+                        let messages = earlier.into_iter().map(AtrisMessage::Earlier).collect();
+                        *self = Self::MessagePage { room_id:*room_id, other_user, other_typing: false, other_presence: None, messages, log, earliest, statuses: Default::default(), current_message: Default::default(), message_channel: message_channel.clone(), channel_state: ChannelState::Connected, connection_status, files, transfers: Default::default() };
+                        // Tell the other end which client this is
+                        Command::perform(async move {
+                            let _ = message_channel.lock().await.send(ChatMessage::hello(concat!("atris gui ", env!("CARGO_PKG_VERSION")))).await;
+                            Message::Nop
+                        }, |a|a)
                     }
                     Message::RoomWaitingFailed(msg)=>{
                         *self = Self::MesageWaitingFailed(msg);
+                        Command::none()
                     }
                     _=>unreachable!()
                 }
-                Command::none()
             }
-            Self::MessagePage { room_id, messages, current_message,message_channel,.. } => {
+            Self::MessagePage { messages, log, earliest, statuses, current_message,message_channel,channel_state,connection_status,files,transfers,other_typing,other_presence,.. } => {
+                // Recording in the history fails rarely, and the chat goes on without it
+                let mut record = |recorded: Result<(), HistoryError>| {
+                    if let Err(e) = recorded {
+                        *connection_status = Some(format!("Could not save the message history: {e}"));
+                    }
+                };
                 match message {
+                    Message::ChannelStateChanged(state)=>{
+                        *channel_state = state;
+                        Command::none()
+                    }
+                    Message::ConnectionEvent(event)=>{
+                        match event {
+                            ConnectionEvent::Closed(_) | ConnectionEvent::Error(_) => *connection_status = Some(event.to_string()),
+                            _ => {}
+                        }
+                        Command::none()
+                    }
                     Message::SendMessage => {
                         let message_channel = message_channel.clone();
                         let current_message = std::mem::take(current_message);
@@ -372,93 +632,168 @@ impl Application for Atris {
                             let mut lock = message_channel.lock().await;
                             println!("Got lock to send {current_message:?}");                            
                             // lock.send(current_message.clone()).await;
-                            lock.send(AtrisMessageData::Text(current_message.clone())).await;
+                            let result = lock.send(ChatMessage::text(current_message.clone())).await;
+                            lock.signal(Presence::StoppedTyping);
                             drop(lock);
-                            current_message
-                        }, Message::MessageSent)
+                            match result {
+                                Ok(id) => Message::MessageSent(id, current_message),
+                                Err(e) => Message::MessageSendFailed(current_message, e.to_string()),
+                            }
+                        }, |a|a)
                     },
                     Message::UpdateCurrentMessage(m) => {
+                        let signal = match m.is_empty() {
+                            true => Presence::StoppedTyping,
+                            false => Presence::Typing,
+                        };
                         *current_message = m;
+                        signal_presence(message_channel.clone(), signal)
+                    }
+                    Message::PresenceChanged(presence)=>{
+                        match presence {
+                            Presence::Typing => *other_typing = true,
+                            Presence::StoppedTyping => *other_typing = false,
+                            Presence::Active => *other_presence = None,
+                            Presence::Idle | Presence::Left => {
+                                *other_typing = false;
+                                *other_presence = Some(presence);
+                            }
+                        }
                         Command::none()
                     }
-                    Message::ReceiveMessage(m)=>{
-                        match m {
-                            AtrisMessageData::Text(m)=>{
+                    Message::WindowFocused(focused)=>{
+                        signal_presence(message_channel.clone(), if focused { Presence::Active } else { Presence::Idle })
+                    }
+                    Message::ReceiveMessage(id, m)=>{
+                        match m.body {
+                            MessageBody::Text(_) | MessageBody::File { .. } => {
+                                let m = m.body.to_string();
+                                if let Some(log) = log {
+                                    record(log.record_received(m.as_str()).map(drop));
+                                }
                                 messages.push(AtrisMessage::Received(m));
+                                // Whatever they were typing has been sent
+                                *other_typing = false;
                             }
-                            AtrisMessageData::File { data, name }=>{
-                                let filepath = native_dialog::FileDialog::new()
-                                    .set_location(&format!("~/Documents/"))
-                                    .set_filename(&name)
-                                    // .add_filter("All files", &[".*",""])
-
-                                    .show_save_single_file();
-                                if let Ok(Some(path)) = filepath {
-                                    std::fs::write(path, data);
-                                };
-
-
-                                // println!("Got file: {}",&name);
-                                // let Some(mut original_path) = dirs::home_dir() else {
-                                //     return Command::none();
-                                // };
-                                // original_path.push("Downloads");
-                                // original_path.push(&name);
-                                // dbg!(&data,&name);
-                                // let res = if original_path.exists() {
-                                //     let ext = original_path.extension().unwrap_or_default();
-                                //     let prefix = original_path.with_extension("");
-                                //     let path = &(1..).find_map(|number|{
-                                //         let new_path = prefix.with_file_name(format!("{} ({number})",prefix.file_name().and_then(OsStr::to_str).unwrap_or(""))).with_extension(ext);
-                                //         if !new_path.exists() {
-                                //             Some(new_path)
-                                //         }else {
-                                //             None
-                                //         }
-                                //     }).unwrap();
-                                //     std::fs::write(path, data)
-                                // }else {
-                                //     std::fs::write(original_path, data)
-                                // };
-                                // dbg!(&res);
-
-                                // res
-
-                                // file.txt
-                                // file(1).txt
-                                // file(2).txt
-                            }
-                            _=>unreachable!()
+                            MessageBody::Control(Control::Hello { client }) => *connection_status = Some(format!("They are using {client}")),
+                            MessageBody::Unsupported { kind } => *connection_status = Some(format!("They sent a kind of message ({kind}) this version cannot show, updating may help")),
                         }
-                        Command::none()
+                        // The message is on screen as soon as it arrives
+                        let message_channel = message_channel.clone();
+                        Command::perform(async move {
+                            let _ = message_channel.lock().await.mark_read(id).await;
+                            Message::Nop
+                        }, |a|a)
                     }
                     Message::ReceiveMessageFailed=>{
                         Command::none()
                     }
-                    Message::MessageSent(s)=>{
-                        messages.push(AtrisMessage::Sent(s));
+                    Message::MessageSent(id, s)=>{
+                        if let Some(log) = log {
+                            record(log.record_sent(id, s.as_str()).map(drop));
+                        }
+                        messages.push(AtrisMessage::Sent(id, s));
+                        statuses.entry(id).or_insert(MessageStatus::Pending);
+                        Command::none()
+                    }
+                    Message::MessageStatusChanged(update)=>{
+                        if let Some(log) = log {
+                            record(log.update_status(update));
+                        }
+                        let status = statuses.entry(update.id).or_insert(update.status);
+                        *status = update.status.max(*status);
+                        Command::none()
+                    }
+                    Message::MessageSendFailed(s,e)=>{
+                        if let Some(log) = log {
+                            record(log.record_failed(s.as_str()).map(drop));
+                        }
+                        *connection_status = Some(format!("Could not send {s:?}: {e}"));
+                        Command::none()
+                    }
+                    Message::LoadEarlier=>{
+                        if let (Some(log), Some(before)) = (log, *earliest) {
+                            let earlier = log.page(Some(before), HISTORY_PAGE);
+                            *earliest = earlier.first().map(|entry|entry.id).or(*earliest);
+                            messages.splice(0..0, earlier.iter().cloned().map(AtrisMessage::Earlier));
+                        }
                         Command::none()
                     }
                     Message::ActualSendFile(path)=>{
-                        let message_channel = message_channel.clone();
+                        let files = files.clone();
                         Command::perform(async move {
-                            let bytes = match std::fs::read(&path) {
-                                Ok(bytes)=>bytes,
-                                _ => return Message::Nop
-                            };
-                            let filename = match path.as_path().file_name().and_then(OsStr::to_str) {
-                                Some(f)=>f,
-                                None=>return Message::Nop
-                            };
-                            let mut lock = message_channel.lock().await;
-                            if lock.send(AtrisMessageData::File{
-                                data:bytes,
-                                name:filename.into()
-                            }).await.is_err() {
-                                return Message::Nop
+                            let name = path.file_name().map(|name|name.to_string_lossy().into_owned()).unwrap_or_default();
+                            let size = std::fs::metadata(&path).map(|metadata|metadata.len()).unwrap_or_default();
+                            files.send_file(&path).await.map(|id|(id,name,size)).map_err(|e|e.to_string())
+                        }, Message::FileOffered)
+                    }
+                    Message::FileOffered(result)=>{
+                        match result {
+                            Ok((id,name,size))=>{
+                                transfers.push(Transfer { id, name: name.clone(), status: "Waiting for them to accept".into(), active: true });
+                                // Show the offer among the messages too, on both ends
+                                let body = MessageBody::File { name, size };
+                                let message_channel = message_channel.clone();
+                                return Command::perform(async move {
+                                    let text = body.to_string();
+                                    match message_channel.lock().await.send(ChatMessage::new(body)).await {
+                                        Ok(id) => Message::MessageSent(id, text),
+                                        Err(e) => Message::MessageSendFailed(text, e.to_string()),
+                                    }
+                                }, |a|a);
+                            }
+                            Err(e)=>*connection_status = Some(format!("Could not send the file: {e}")),
+                        }
+                        Command::none()
+                    }
+                    Message::TransferEvent(event)=>{
+                        if let TransferEvent::Offered { id, name, size } = &event {
+                            transfers.push(Transfer { id: *id, name: name.clone(), status: format!("Offered, {size} bytes"), active: true });
+                            let filepath = native_dialog::FileDialog::new()
+                                .set_location("~/Documents/")
+                                .set_filename(name)
+                                .show_save_single_file();
+                            let files = files.clone();
+                            let id = *id;
+                            return Command::perform(async move {
+                                match filepath {
+                                    Ok(Some(path))=>{
+                                        let _ = files.accept(id, path).await;
+                                    }
+                                    _ => {
+                                        let _ = files.reject(id);
+                                    }
+                                }
+                                Message::Nop
+                            }, |a|a);
+                        }
+                        let id = match &event {
+                            TransferEvent::Offered { id, .. }
+                            | TransferEvent::Accepted { id }
+                            | TransferEvent::Rejected { id }
+                            | TransferEvent::Progress { id, .. }
+                            | TransferEvent::Completed { id, .. }
+                            | TransferEvent::Failed { id, .. }
+                            | TransferEvent::Cancelled { id } => *id,
+                        };
+                        if let Some(transfer) = transfers.iter_mut().find(|transfer|transfer.id == id) {
+                            let (status, active) = match event {
+                                TransferEvent::Offered { .. } | TransferEvent::Accepted { .. } => ("Starting".into(), true),
+                                TransferEvent::Rejected { .. } => ("Rejected".into(), false),
+                                TransferEvent::Progress { bytes, size, .. } => (format!("{bytes}/{size} bytes"), true),
+                                TransferEvent::Completed { path: Some(path), .. } => (format!("Saved to {}", path.display()), false),
+                                TransferEvent::Completed { path: None, .. } => ("Sent".into(), false),
+                                TransferEvent::Failed { reason, .. } => (format!("Failed: {reason}"), false),
+                                TransferEvent::Cancelled { .. } => ("Cancelled".into(), false),
                             };
-                            Message::MessageSent(format!("File {filename}"))
-                        }, |a|a)
+                            transfer.status = status;
+                            transfer.active = active;
+                        }
+                        Command::none()
+                    }
+                    Message::CancelTransfer(id)=>{
+                        let _ = files.cancel(id);
+                        Command::none()
                     }
 
                     Message::SendFile => {
@@ -496,9 +831,11 @@ impl Application for Atris {
 
     fn view(&self) -> Element<'_, Self::Message, iced::Renderer<Self::Theme>> {
         match &self {
-            Self::Login { username, password, error_message, login_select,.. } => {
+            Self::Login { username, password, passphrase, remember_me, profiles, error_message, login_select, second_factor,.. } => {
                 let username_input:Element<_> = text_input("Username", username, Message::UpdateUsername).into();
                 let password_input:Element<_> = text_input("Password", password, Message::UpdatePassword).into();
+                let passphrase_input:Element<_> = text_input("Passphrase for this profile on this machine", passphrase, Message::UpdatePassphrase).password().into();
+                let remember_input:Element<_> = checkbox("Remember me", *remember_me, Message::RememberMe).into();
                 
                 let create_selector: Element<_> = radio("Create new account", LoginMode::CreateUser, Some(*login_select), Message::LoginSelector).into();
                 let login_selector: Element<_> = radio("Login", LoginMode::LoginUser, Some(*login_select), Message::LoginSelector).into();
@@ -512,10 +849,20 @@ impl Application for Atris {
 
                 let submit_button = button("Submit").on_press(Message::SubmitUserInfo);
                 
-                let mut inputs = Column::with_children(vec![
-                    username_input,
-                    password_input
-                ]);
+                let mut inputs = Column::new();
+                if !profiles.is_empty() {
+                    let selected = profiles.contains(username).then(||username.clone());
+                    inputs=inputs.push(pick_list(profiles.as_slice(), selected, Message::PickProfile).placeholder("Switch profile"));
+                }
+                inputs=inputs
+                    .push(username_input)
+                    .push(password_input)
+                    .push(passphrase_input)
+                    .push(remember_input);
+                if let Some(second_factor)=second_factor{
+                    inputs=inputs.push(text("Enter the code from your authenticator app, or a recovery code"));
+                    inputs=inputs.push(text_input("Two-factor code", second_factor, Message::UpdateSecondFactor));
+                }
                 if let Some(error_message)=error_message{
                     inputs=inputs.push(text(error_message))
                 }
@@ -545,9 +892,22 @@ impl Application for Atris {
                     .align_items(Alignment::Center)
                     .into()
             },
-            Self::Home {other_user,room_id,.. } => {
+            Self::Home {username,other_user,room_id,search,search_results,export_passphrase,export_status,.. } => {
+                let mut results = Column::new().spacing(5);
+                match search_results {
+                    Some(hits) if hits.is_empty() => results = results.push(text("No messages found")),
+                    Some(hits) => for hit in hits {
+                        results = results.push(text(format!("{}: {}", hit.conversation, hit.entry)));
+                    },
+                    None => {}
+                }
                 Column::with_children(vec![
-                    text_input("Enter a username",&other_user,Message::UpdateOtherUser).into(),
+                    Row::with_children(vec![
+                        text(format!("Logged in as {username}")).into(),
+                        button("Switch profile").on_press(Message::SwitchProfile).into(),
+                        button("Log out").on_press(Message::LogOut).into(),
+                    ]).spacing(10).align_items(Alignment::Center).into(),
+                    text_input("Enter a username",other_user,Message::UpdateOtherUser).into(),
                     button(text({
                         if other_user.is_empty() {
                             "Create a room".into()
@@ -555,8 +915,19 @@ impl Application for Atris {
                             format!("Create a room with {}",other_user)
                         }
                     })).on_press(Message::CreateRoom).into(),
-                    text_input("Enter a room id",&room_id,Message::UpdateRoomId).into(),
-                    button("Join room").on_press(Message::JoinRoom).into(),
+                    text_input("Enter a room id",room_id,Message::UpdateRoomId).into(),
+                    Row::with_children(vec![
+                        button("Join room").on_press(Message::JoinRoom).into(),
+                        button("Export room").on_press(Message::ExportRoom).into(),
+                        button("Import conversation").on_press(Message::ImportConversation).into(),
+                    ]).spacing(10).into(),
+                    text_input("Passphrase for exports (optional)",export_passphrase,Message::UpdateExportPassphrase).password().into(),
+                    text(export_status.as_deref().unwrap_or_default()).into(),
+                    Row::with_children(vec![
+                        text_input("Search your messages",search,Message::UpdateSearch).on_submit(Message::Search).into(),
+                        button("Search").on_press(Message::Search).into(),
+                    ]).spacing(10).into(),
+                    results.into(),
                 ])
                     .spacing(10)
                     .padding(10)
@@ -583,18 +954,55 @@ impl Application for Atris {
                     .into()
             },
 
-            Self::MessagePage { room_id,messages,current_message,.. } => {
+            Self::MessagePage { room_id,other_user,other_typing,other_presence,messages,log,earliest,statuses,current_message,channel_state,connection_status,transfers,.. } => {
                 let mut header = vec![
                     text(format!("Room {}",room_id)).into(),
-                    text("Messages: ").into(),
                 ];
+                match channel_state {
+                    ChannelState::Reconnecting => header.push(text("Reconnecting... Messages will be sent once the connection is back").into()),
+                    ChannelState::Lost => header.push(text("Connection lost").into()),
+                    _ => {}
+                }
+                if let Some(status) = connection_status {
+                    header.push(text(status).into());
+                }
+                if let Some(presence) = other_presence {
+                    header.push(text(format!("{other_user} {presence}")).into());
+                }
+                header.push(text("Messages: ").into());
+                if earliest.is_some_and(|earliest|earliest > 0) && log.is_some() {
+                    header.push(button("Load earlier messages").on_press(Message::LoadEarlier).into());
+                }
 
                 header.extend(messages.iter().map(|m|{
                     match m {
                         AtrisMessage::Received(r)=>text(format!("Rec: {r}")).horizontal_alignment(Horizontal::Left),
-                        AtrisMessage::Sent(s)=>text(format!("Sent: {s}")).horizontal_alignment(Horizontal::Right),
+                        AtrisMessage::Earlier(entry)=>{
+                            let alignment = match entry.direction {
+                                Direction::Sent => Horizontal::Right,
+                                Direction::Received => Horizontal::Left,
+                            };
+                            text(entry.to_string()).horizontal_alignment(alignment)
+                        }
+                        AtrisMessage::Sent(id, s)=>{
+                            let status = statuses.get(id).copied().unwrap_or(MessageStatus::Pending);
+                            text(format!("Sent: {s} ({status})")).horizontal_alignment(Horizontal::Right)
+                        }
                     }.width(Length::Fill).into()
                 }));
+                header.extend(transfers.iter().map(|transfer|{
+                    let mut row = Row::with_children(vec![
+                        text(format!("File {}: {}",transfer.name,transfer.status)).into(),
+                    ]).spacing(10);
+                    if transfer.active {
+                        row = row.push(button("Cancel").on_press(Message::CancelTransfer(transfer.id)));
+                    }
+                    row.into()
+                }));
+
+                if *other_typing {
+                    header.push(text(format!("{other_user} {}", Presence::Typing)).into());
+                }
 
                 let input = text_input("Enter a message", current_message, Message::UpdateCurrentMessage).into();
                 let send = button("Send").on_press(Message::SendMessage).into();
@@ -604,7 +1012,7 @@ impl Application for Atris {
                     send_file,
                     input,
                     send,
-                ]).into();
+                ]);
 
                 Column::with_children(header)
                     .spacing(10)
@@ -633,7 +1041,7 @@ impl Application for Atris {
                     .align_items(Alignment::Center)
                     .into()
         },
-            Atris::MessageWaitingPage { room_id, other_user } => {
+            Atris::MessageWaitingPage { room_id, other_user, .. } => {
                 Column::with_children(vec![
                     text(format!("Waiting to join {room_id} with {:?}",other_user)).into(),
                 ])
@@ -646,6 +1054,14 @@ impl Application for Atris {
     }
 }
 
+/// Signal the user's presence to the other end, without waiting for it to be sent
+fn signal_presence(message_channel: Arc<Mutex<AtrisChannel<ChatMessage>>>, presence: Presence) -> Command<Message> {
+    Command::perform(async move {
+        message_channel.lock().await.signal(presence);
+        Message::Nop
+    }, |a|a)
+}
+
 pub fn main() -> iced::Result {
     let mut settings = Settings::default();
     settings.window.size = (500,500);
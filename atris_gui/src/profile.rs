@@ -0,0 +1,86 @@
+//! Unlocking, saving and switching the profiles the GUI logs in with, shared with the CLI
+use std::path::PathBuf;
+
+use atris_client_lib::atris_common::authenticate_user::AuthenticateUserResponse;
+use atris_client_lib::history::History;
+use atris_client_lib::profile::{Profiles, SavedSession, UnlockedProfile};
+
+/// The saved profiles' usernames, and the current one's, for the login page
+pub fn saved() -> (Vec<String>, Option<String>) {
+    match Profiles::open() {
+        Ok(profiles) => (profiles.list().unwrap_or_default(), profiles.current().ok().flatten()),
+        Err(_) => (Vec::new(), None),
+    }
+}
+
+/// The server the profile for `username` is on, if it has one and it is not the default
+pub fn server(username: &str) -> Option<String> {
+    Profiles::open().ok()?.find(username).ok()??.server
+}
+
+/// The current profile, if it is remembered and still logged in, to skip the login page
+pub fn resume() -> Option<UnlockedProfile> {
+    let profiles = Profiles::open().ok()?;
+    let profile = profiles.unlock_remembered(&profiles.current().ok()??).ok()??;
+    profile.session().is_some().then_some(profile)
+}
+
+/// Unlock the profile for `username` with `passphrase`, or with its remembered key if no passphrase is given,
+/// making a new profile locked with `passphrase` if there is none
+pub fn unlock(username: &str, passphrase: &str) -> Result<UnlockedProfile, String> {
+    let profiles = Profiles::open().map_err(|e| e.to_string())?;
+    let unlocked = match profiles.find(username).map_err(|e| e.to_string())? {
+        Some(profile) if passphrase.is_empty() => match profiles.remembered(username).map_err(|e| e.to_string())? {
+            Some(key) => profile.unlock_with(key),
+            None => return Err(format!("Enter the passphrase for {username}")),
+        },
+        Some(profile) => profile.unlock(passphrase),
+        None if passphrase.is_empty() => return Err("Choose a passphrase to lock the new profile with".into()),
+        None => UnlockedProfile::create(username, None, passphrase),
+    };
+    unlocked.map_err(|e| e.to_string())
+}
+
+/// Save the session from logging in to the profile, and make it the current one, remembering its key if asked
+pub fn save_login(
+    mut profile: UnlockedProfile,
+    auth: AuthenticateUserResponse,
+    remember_me: bool,
+) -> Result<UnlockedProfile, String> {
+    let profiles = Profiles::open().map_err(|e| e.to_string())?;
+    profile
+        .set_session(Some(SavedSession {
+            session_id: auth.session_id,
+            ice_servers: auth.ice_servers,
+        }))
+        .map_err(|e| e.to_string())?;
+    profiles.save(profile.profile()).map_err(|e| e.to_string())?;
+    profiles
+        .set_current(Some(profile.username()))
+        .map_err(|e| e.to_string())?;
+    let remembered = match remember_me {
+        true => profiles.remember(&profile),
+        false => profiles.forget(profile.username()),
+    };
+    remembered.map_err(|e| e.to_string())?;
+    Ok(profile)
+}
+
+/// Where the profile for `username` saves files, if it says
+pub fn download_dir(username: &str) -> Option<PathBuf> {
+    Profiles::open().ok()?.find(username).ok()??.preferences.download_dir
+}
+
+/// The messages the profile sent and received, if its history can be opened
+pub fn history(profile: &UnlockedProfile) -> Option<History> {
+    Profiles::open().ok()?.history(profile).ok()
+}
+
+/// Forget the session and the remembered key of the profile for `username`, keeping the rest of it
+pub fn log_out(username: &str) -> Result<(), String> {
+    let profiles = Profiles::open().map_err(|e| e.to_string())?;
+    let mut profile = profiles.load(username).map_err(|e| e.to_string())?;
+    profile.log_out();
+    profiles.save(&profile).map_err(|e| e.to_string())?;
+    profiles.forget(username).map_err(|e| e.to_string())
+}
@@ -0,0 +1,98 @@
+//! A TURN relay (which also answers STUN binding requests) for Atris clients that cannot reach each other directly.
+//!
+//! Clients authenticate with the short-lived credentials the auth server hands out at login, see
+//! [`atris_common::turn`].
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use atris_common::turn::{credential_for, expiry_of};
+use tokio::net::UdpSocket;
+use turn::auth::{generate_auth_key, AuthHandler};
+use turn::relay::relay_range::RelayAddressGeneratorRanges;
+use turn::server::config::{ConnConfig, ServerConfig};
+use turn::server::Server;
+use turn::Error;
+use webrtc_util::vnet::net::Net;
+
+/// The realm used when none is configured
+pub const DEFAULT_REALM: &str = "atris";
+/// The standard STUN/TURN port
+pub const DEFAULT_PORT: u16 = 3478;
+/// The lowest port relayed connections are allocated on by default
+pub const DEFAULT_MIN_RELAY_PORT: u16 = 49152;
+/// The highest port relayed connections are allocated on by default
+pub const DEFAULT_MAX_RELAY_PORT: u16 = 65535;
+
+/// How the relay listens and allocates relayed connections
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    /// The address the relay listens on, and that relayed connections are bound to
+    pub listen_address: IpAddr,
+    /// The port clients reach the relay on, or 0 to pick any free port
+    pub port: u16,
+    /// The realm clients authenticate in
+    pub realm: String,
+    /// The address clients are told to send relayed traffic to, usually the public address of the host
+    pub relay_address: IpAddr,
+    /// The lowest port relayed connections are allocated on
+    pub min_relay_port: u16,
+    /// The highest port relayed connections are allocated on
+    pub max_relay_port: u16,
+    /// The secret shared with the auth server
+    pub shared_secret: String,
+}
+
+/// Accepts any username whose embedded expiry time has not passed, with the credential derived from the shared secret
+pub struct SharedSecretAuthHandler {
+    shared_secret: String,
+}
+impl SharedSecretAuthHandler {
+    pub fn new(shared_secret: String) -> Self {
+        Self { shared_secret }
+    }
+}
+impl AuthHandler for SharedSecretAuthHandler {
+    fn auth_handle(&self, username: &str, realm: &str, _src_addr: SocketAddr) -> Result<Vec<u8>, Error> {
+        let expiry = expiry_of(username).ok_or_else(|| Error::Other(format!("Malformed username {username}")))?;
+        if Duration::from_secs(expiry) < SystemTime::now().duration_since(UNIX_EPOCH)? {
+            return Err(Error::Other(format!("Expired username {username}")));
+        }
+
+        let credential = credential_for(&self.shared_secret, username)
+            .ok_or_else(|| Error::Other("Invalid shared secret".into()))?;
+        Ok(generate_auth_key(username, realm, &credential))
+    }
+}
+
+/// Start relaying, returning the running server and the address it is listening on
+pub async fn start(config: RelayConfig) -> Result<(Server, SocketAddr), Error> {
+    if config.min_relay_port > config.max_relay_port {
+        return Err(Error::Other(format!(
+            "The relay port range {}-{} is empty",
+            config.min_relay_port, config.max_relay_port
+        )));
+    }
+    let conn = Arc::new(UdpSocket::bind((config.listen_address, config.port)).await?);
+    let local_addr = conn.local_addr()?;
+
+    let server = Server::new(ServerConfig {
+        conn_configs: vec![ConnConfig {
+            conn,
+            relay_addr_generator: Box::new(RelayAddressGeneratorRanges {
+                relay_address: config.relay_address,
+                min_port: config.min_relay_port,
+                max_port: config.max_relay_port,
+                max_retries: 0,
+                address: config.listen_address.to_string(),
+                net: Arc::new(Net::new(None)),
+            }),
+        }],
+        realm: config.realm,
+        auth_handler: Arc::new(SharedSecretAuthHandler::new(config.shared_secret)),
+        // Use the default lifetime of channel bindings
+        channel_bind_timeout: Duration::from_secs(0),
+    })
+    .await?;
+    Ok((server, local_addr))
+}
@@ -0,0 +1,62 @@
+use std::net::IpAddr;
+
+use atris_relay::{
+    RelayConfig, DEFAULT_MAX_RELAY_PORT, DEFAULT_MIN_RELAY_PORT, DEFAULT_PORT, DEFAULT_REALM,
+};
+use clap::{arg, command, value_parser};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Process the arguments with clap
+    let args = command!()
+        .arg(
+            arg!(--"relay-address" <IP> "The public address clients send relayed traffic to")
+                .value_parser(value_parser!(IpAddr)),
+        )
+        .arg(
+            arg!(--secret <SECRET> "The secret shared with the auth server")
+                .env("ATRIS_TURN_SECRET"),
+        )
+        .arg(
+            arg!(--"listen-address" <IP> "The address to listen on")
+                .value_parser(value_parser!(IpAddr))
+                .default_value("0.0.0.0"),
+        )
+        .arg(
+            arg!(--port <PORT> "The port to listen on [default: 3478]")
+                .value_parser(value_parser!(u16)),
+        )
+        .arg(
+            arg!(--realm <REALM> "The realm clients authenticate in")
+                .default_value(DEFAULT_REALM),
+        )
+        .arg(
+            arg!(--"min-relay-port" <PORT> "The lowest port to allocate relayed connections on [default: 49152]")
+                .value_parser(value_parser!(u16)),
+        )
+        .arg(
+            arg!(--"max-relay-port" <PORT> "The highest port to allocate relayed connections on [default: 65535]")
+                .value_parser(value_parser!(u16)),
+        )
+        .get_matches();
+
+    let config = RelayConfig {
+        listen_address: *args.get_one("listen-address").ok_or("No listen address")?,
+        port: args.get_one("port").copied().unwrap_or(DEFAULT_PORT),
+        realm: args.get_one::<String>("realm").ok_or("No realm")?.clone(),
+        relay_address: *args.get_one("relay-address").ok_or("--relay-address is required")?,
+        min_relay_port: args.get_one("min-relay-port").copied().unwrap_or(DEFAULT_MIN_RELAY_PORT),
+        max_relay_port: args.get_one("max-relay-port").copied().unwrap_or(DEFAULT_MAX_RELAY_PORT),
+        shared_secret: args
+            .get_one::<String>("secret")
+            .ok_or("--secret or ATRIS_TURN_SECRET is required")?
+            .clone(),
+    };
+
+    let (server, local_addr) = atris_relay::start(config).await?;
+    println!("Relaying on {local_addr}, waiting for Ctrl-C...");
+    tokio::signal::ctrl_c().await?;
+    println!("Closing the relay");
+    server.close().await?;
+    Ok(())
+}
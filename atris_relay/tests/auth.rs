@@ -0,0 +1,70 @@
+//! Which credentials the relay accepts, compared with those the auth server issues
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use atris_common::IceServer;
+use atris_relay::{RelayConfig, SharedSecretAuthHandler, DEFAULT_REALM};
+use turn::auth::{generate_auth_key, AuthHandler};
+
+const SHARED_SECRET: &str = "auth-test-secret";
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("after the epoch")
+        .as_secs()
+}
+
+/// Credentials from the auth server, issued at `unix_time`
+fn issued(secret: &str, unix_time: u64) -> IceServer {
+    atris_server::turn::credentials_at(vec!["turn:localhost".into()], secret, "auth-test", unix_time, 60)
+        .expect("valid secret")
+}
+
+/// Whether the relay derives the same key as a client holding `ice_server` does
+fn accepted(ice_server: &IceServer) -> bool {
+    let source = SocketAddr::from((Ipv4Addr::LOCALHOST, 1234));
+    let client_key = generate_auth_key(&ice_server.username, DEFAULT_REALM, &ice_server.credential);
+    SharedSecretAuthHandler::new(SHARED_SECRET.into())
+        .auth_handle(&ice_server.username, DEFAULT_REALM, source)
+        .is_ok_and(|relay_key| relay_key == client_key)
+}
+
+#[test]
+fn issued_credentials_are_accepted() {
+    assert!(accepted(&issued(SHARED_SECRET, now())));
+}
+
+#[test]
+fn credentials_issued_with_another_secret_are_refused() {
+    assert!(!accepted(&issued("another-secret", now())));
+}
+
+#[test]
+fn expired_usernames_are_refused() {
+    assert!(!accepted(&issued(SHARED_SECRET, now() - 61)));
+}
+
+#[test]
+fn malformed_usernames_are_refused() {
+    let mut ice_server = issued(SHARED_SECRET, now());
+    ice_server.username = "auth-test".into();
+    assert!(!accepted(&ice_server));
+}
+
+#[tokio::test]
+async fn empty_relay_port_ranges_are_refused() {
+    let localhost = Ipv4Addr::LOCALHOST.into();
+    let started = atris_relay::start(RelayConfig {
+        listen_address: localhost,
+        port: 0,
+        realm: DEFAULT_REALM.into(),
+        relay_address: localhost,
+        min_relay_port: 50001,
+        max_relay_port: 50000,
+        shared_secret: SHARED_SECRET.into(),
+    })
+    .await;
+    assert!(started.is_err());
+}
@@ -0,0 +1,92 @@
+//! A relay-only connection which survives the relay dropping its allocations
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use atris_client_lib::atris_common::CipherKey;
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::trickle::LocalSignaller;
+use atris_client_lib::comms::{AtrisChannel, AtrisConnection, ChannelState};
+use atris_common::turn::credential_for;
+use atris_relay::{RelayConfig, DEFAULT_REALM};
+use tokio::sync::watch;
+use tokio::time::timeout;
+
+const SHARED_SECRET: &str = "reconnect-test-secret";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Wait until the watched state is `expected`
+async fn wait_for(state: &mut watch::Receiver<ChannelState>, expected: ChannelState) -> anyhow::Result<()> {
+    while *state.borrow_and_update() != expected {
+        state.changed().await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn reconnects_and_resends_after_relay_drops_allocations() -> anyhow::Result<()> {
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let (relay, relay_address) = atris_relay::start(RelayConfig {
+        listen_address: localhost,
+        port: 0,
+        realm: DEFAULT_REALM.into(),
+        relay_address: localhost,
+        min_relay_port: 51000,
+        max_relay_port: 51999,
+        shared_secret: SHARED_SECRET.into(),
+    })
+    .await?;
+
+    let expiry = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + 600;
+    let turn_username = format!("{expiry}:reconnect-test");
+    let credential = credential_for(SHARED_SECRET, &turn_username).expect("valid secret");
+    let turn_url = format!("turn:{relay_address}?transport=udp");
+    let relay_only_connection = || {
+        AtrisConnection::builder()
+            .turn_server(&turn_url, &turn_username, &credential)
+            .relay_only(true)
+            .build()
+    };
+
+    let mut initiator = AtrisInitiator::new(relay_only_connection().await?).await?;
+    let mut responder = AtrisResponder::with_connection(relay_only_connection().await?);
+    let (initiator_signaller, responder_signaller) = LocalSignaller::pair();
+    let initiator_trickle = initiator.ice_trickle().expect("fresh initiator");
+    let responder_trickle = responder.ice_trickle().expect("fresh responder");
+    tokio::spawn(initiator_trickle.run(initiator_signaller));
+    tokio::spawn(responder_trickle.run(responder_signaller));
+
+    let (answer, responder_parts) = responder
+        .into_channel_parts_with::<String>(&initiator.encoded_local_description()?)
+        .await?;
+    let initiator_parts = initiator.into_channel_parts_with::<String>(&answer).await?;
+    let responder_parts = timeout(CONNECT_TIMEOUT, responder_parts)
+        .await?
+        .expect("responder data channel");
+
+    let room_key = CipherKey::generate();
+    let mut initiator_channel = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder_channel = AtrisChannel::new(responder_parts, room_key.as_cipher());
+    let mut initiator_state = initiator_channel.state_receiver();
+    timeout(CONNECT_TIMEOUT, wait_for(&mut initiator_state, ChannelState::Connected)).await??;
+
+    // Take the only path between the two ends away
+    relay.delete_allocations_by_username(turn_username.clone()).await?;
+    timeout(RECONNECT_TIMEOUT, wait_for(&mut initiator_state, ChannelState::Reconnecting)).await??;
+
+    // Sent while the connection is down, so they can only arrive once it is back
+    for i in 0..3 {
+        initiator_channel.send(format!("during the outage {i}")).await?;
+    }
+
+    timeout(RECONNECT_TIMEOUT, wait_for(&mut initiator_state, ChannelState::Reconnected)).await??;
+    for i in 0..3 {
+        let received = timeout(CONNECT_TIMEOUT, responder_channel.receive()).await?;
+        assert_eq!(received.expect("open channel").ok(), Some(format!("during the outage {i}")));
+    }
+
+    relay.close().await?;
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! Two local connections which are only allowed to reach each other through the relay
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use atris_client_lib::atris_common::{signal_room::SignalMessage, CipherKey};
+use atris_client_lib::comms::initiator::AtrisInitiator;
+use atris_client_lib::comms::responder::AtrisResponder;
+use atris_client_lib::comms::trickle::{LocalSignaller, Signaller};
+use atris_client_lib::comms::{AtrisChannel, AtrisConnection};
+use atris_relay::{RelayConfig, DEFAULT_REALM};
+use tokio::time::timeout;
+
+const SHARED_SECRET: &str = "relay-test-secret";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Remembers every candidate the wrapped signaller sends
+struct RecordingSignaller {
+    inner: LocalSignaller,
+    sent_candidates: Arc<Mutex<Vec<String>>>,
+}
+impl RecordingSignaller {
+    fn new(inner: LocalSignaller) -> Self {
+        Self {
+            inner,
+            sent_candidates: Default::default(),
+        }
+    }
+}
+#[async_trait::async_trait]
+impl Signaller for RecordingSignaller {
+    async fn exchange(&mut self, outgoing: Vec<SignalMessage>) -> anyhow::Result<Vec<SignalMessage>> {
+        if let Ok(mut sent_candidates) = self.sent_candidates.lock() {
+            sent_candidates.extend(outgoing.iter().filter_map(|signal| match signal {
+                SignalMessage::Candidate(candidate) => Some(candidate.clone()),
+                _ => None,
+            }));
+        }
+        self.inner.exchange(outgoing).await
+    }
+}
+
+#[tokio::test]
+async fn connects_through_relay_only() -> anyhow::Result<()> {
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let (relay, relay_address) = atris_relay::start(RelayConfig {
+        listen_address: localhost,
+        port: 0,
+        realm: DEFAULT_REALM.into(),
+        relay_address: localhost,
+        min_relay_port: 50000,
+        max_relay_port: 50999,
+        shared_secret: SHARED_SECRET.into(),
+    })
+    .await?;
+
+    // Credentials issued by the auth server itself
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let turn_url = format!("turn:{relay_address}?transport=udp");
+    let turn_server =
+        atris_server::turn::credentials_at(vec![turn_url], SHARED_SECRET, "relay-test", now, 60).expect("valid secret");
+    let relay_only_connection = || {
+        AtrisConnection::builder()
+            .ice_server(turn_server.clone())
+            .relay_only(true)
+            .build()
+    };
+
+    let mut initiator = AtrisInitiator::new(relay_only_connection().await?).await?;
+    let mut responder = AtrisResponder::with_connection(relay_only_connection().await?);
+
+    let (initiator_signaller, responder_signaller) = LocalSignaller::pair();
+    let initiator_signaller = RecordingSignaller::new(initiator_signaller);
+    let responder_signaller = RecordingSignaller::new(responder_signaller);
+    let sent_candidates = [
+        Arc::clone(&initiator_signaller.sent_candidates),
+        Arc::clone(&responder_signaller.sent_candidates),
+    ];
+    tokio::spawn(
+        initiator
+            .ice_trickle()
+            .expect("fresh initiator")
+            .run(initiator_signaller),
+    );
+    tokio::spawn(
+        responder
+            .ice_trickle()
+            .expect("fresh responder")
+            .run(responder_signaller),
+    );
+
+    let (answer, responder_parts) = responder
+        .into_channel_parts_with::<String>(&initiator.encoded_local_description()?)
+        .await?;
+    let initiator_parts = initiator.into_channel_parts_with::<String>(&answer).await?;
+    let responder_parts = timeout(CONNECT_TIMEOUT, responder_parts)
+        .await?
+        .expect("responder data channel");
+
+    let room_key = CipherKey::generate();
+    let mut initiator_channel = AtrisChannel::new(initiator_parts, room_key.as_cipher());
+    let mut responder_channel = AtrisChannel::new(responder_parts, room_key.as_cipher());
+
+    initiator_channel.send("through the relay".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, responder_channel.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("through the relay"));
+
+    responder_channel.send("and back".into()).await?;
+    let received = timeout(CONNECT_TIMEOUT, initiator_channel.receive()).await?;
+    assert_eq!(received.expect("open channel").ok().as_deref(), Some("and back"));
+
+    // Only relayed candidates may ever be offered to the other end
+    for sent_candidates in sent_candidates {
+        let sent_candidates = sent_candidates.lock().expect("unpoisoned");
+        assert!(!sent_candidates.is_empty());
+        assert!(sent_candidates.iter().all(|c| c.contains("typ relay")), "{sent_candidates:?}");
+    }
+
+    relay.close().await?;
+    Ok(())
+}
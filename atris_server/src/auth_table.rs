@@ -1,6 +1,8 @@
 use atris_common::{
     authenticate_user::AuthenticateUserError,
+    confirm_totp::ConfirmTotpError,
     create_user::{CreateUserError, CreateUserResponse},
+    enroll_totp::EnrollTotpError,
     REGION,
 };
 use aws_config::meta::region::RegionProviderChain;
@@ -15,19 +17,51 @@ pub struct User {
     pub username: String,
     /// The salted and hashed digest of the user's password
     pub password_hash: String,
+    /// The base32 TOTP secret, which is only in use once `totp_enabled` is set
+    pub totp_secret: Option<String>,
+    /// Whether logging in requires a second factor
+    pub totp_enabled: bool,
+    /// The time step of the last TOTP code used, as codes of it and earlier steps are refused
+    pub totp_last_step: Option<u64>,
+    /// The salted and hashed digests of the unused recovery codes
+    pub recovery_code_hashes: Vec<String>,
 }
 impl User {
     fn new(username: String, password_hash: String) -> Self {
         Self {
             username,
             password_hash,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: None,
+            recovery_code_hashes: Vec::new(),
         }
     }
 
     fn from_map(map: &HashMap<String, AttributeValue>) -> Option<Self> {
         let username = map.get(USERNAME_KEY)?.as_s().ok()?;
         let password = map.get(PASSWORD_KEY)?.as_s().ok()?;
-        Some(Self::new(username.clone(), password.clone()))
+        let mut user = Self::new(username.clone(), password.clone());
+        // The second factor fields are absent until the user enrolls
+        user.totp_secret = map
+            .get(TOTP_SECRET_KEY)
+            .and_then(|v| v.as_s().ok())
+            .cloned();
+        user.totp_enabled = map
+            .get(TOTP_ENABLED_KEY)
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(false);
+        user.totp_last_step = map
+            .get(TOTP_LAST_STEP_KEY)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse().ok());
+        user.recovery_code_hashes = map
+            .get(RECOVERY_CODES_KEY)
+            .and_then(|v| v.as_ss().ok())
+            .cloned()
+            .unwrap_or_default();
+        Some(user)
     }
 }
 
@@ -84,16 +118,146 @@ impl AtrisAuthDBClient {
             .key(USERNAME_KEY, AttributeValue::S(username.clone()))
             .attributes_to_get(USERNAME_KEY) //get the relevant fields
             .attributes_to_get(PASSWORD_KEY)
+            .attributes_to_get(TOTP_SECRET_KEY)
+            .attributes_to_get(TOTP_ENABLED_KEY)
+            .attributes_to_get(TOTP_LAST_STEP_KEY)
+            .attributes_to_get(RECOVERY_CODES_KEY)
             .send()
             .await
             .map_err(|_| AuthenticateUserError::DatabaseRead)?; //convert SdkError to AuthenticateUserError
-        return Ok(db_request.item().and_then(User::from_map));
+        Ok(db_request.item().and_then(User::from_map))
+    }
+
+    /// Stores a pending TOTP secret for the user, replacing any previous pending secret
+    pub async fn set_totp_secret(
+        &self,
+        username: String,
+        secret: String,
+    ) -> Result<(), EnrollTotpError> {
+        let db_request = self
+            .client
+            .update_item()
+            .table_name(TABLE_NAME)
+            .key(USERNAME_KEY, AttributeValue::S(username))
+            .expression_attribute_values(":secret", AttributeValue::S(secret))
+            .expression_attribute_values(":enabled", AttributeValue::Bool(true))
+            // Never overwrite the secret of a user who already confirmed enrollment
+            .condition_expression(format!(
+                "attribute_exists({USERNAME_KEY}) AND (attribute_not_exists({TOTP_ENABLED_KEY}) OR {TOTP_ENABLED_KEY} <> :enabled)"
+            ))
+            .update_expression(format!("SET {TOTP_SECRET_KEY} = :secret"));
+
+        db_request.send().await.map(|_| {}).map_err(|e| {
+            if let SdkError::ServiceError { err, .. } = &e {
+                if err.is_conditional_check_failed_exception() {
+                    return EnrollTotpError::AlreadyEnabled;
+                }
+            }
+            dbg!(e);
+            EnrollTotpError::DatabaseWriteError
+        })
+    }
+
+    /// Turns on two-factor authentication for the user, storing the hashed recovery codes.
+    /// Fails unless `secret` is still the pending secret, which the confirmation code was checked against at `step`.
+    pub async fn enable_totp(
+        &self,
+        username: String,
+        secret: String,
+        step: u64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<(), ConfirmTotpError> {
+        let db_request = self
+            .client
+            .update_item()
+            .table_name(TABLE_NAME)
+            .key(USERNAME_KEY, AttributeValue::S(username))
+            .expression_attribute_values(":secret", AttributeValue::S(secret))
+            .expression_attribute_values(":enabled", AttributeValue::Bool(true))
+            .expression_attribute_values(":step", AttributeValue::N(step.to_string()))
+            .expression_attribute_values(":codes", AttributeValue::Ss(recovery_code_hashes))
+            .condition_expression(format!(
+                "{TOTP_SECRET_KEY} = :secret AND (attribute_not_exists({TOTP_ENABLED_KEY}) OR {TOTP_ENABLED_KEY} <> :enabled)"
+            ))
+            .update_expression(format!(
+                "SET {TOTP_ENABLED_KEY} = :enabled, {TOTP_LAST_STEP_KEY} = :step, {RECOVERY_CODES_KEY} = :codes"
+            ));
+
+        db_request.send().await.map(|_| {}).map_err(|e| {
+            if let SdkError::ServiceError { err, .. } = &e {
+                // Enrollment was restarted with another secret, or confirmed, since the code was checked
+                if err.is_conditional_check_failed_exception() {
+                    return ConfirmTotpError::WrongCode;
+                }
+            }
+            dbg!(e);
+            ConfirmTotpError::DatabaseWriteError
+        })
+    }
+
+    /// Records that the TOTP code of `step` was used, failing if a code of it or a later step already was
+    pub async fn use_totp_step(
+        &self,
+        username: String,
+        step: u64,
+    ) -> Result<(), AuthenticateUserError> {
+        let db_request = self
+            .client
+            .update_item()
+            .table_name(TABLE_NAME)
+            .key(USERNAME_KEY, AttributeValue::S(username))
+            .expression_attribute_values(":step", AttributeValue::N(step.to_string()))
+            .condition_expression(format!(
+                "attribute_not_exists({TOTP_LAST_STEP_KEY}) OR {TOTP_LAST_STEP_KEY} < :step"
+            ))
+            .update_expression(format!("SET {TOTP_LAST_STEP_KEY} = :step"));
+
+        db_request.send().await.map(|_| {}).map_err(|e| {
+            if let SdkError::ServiceError { err, .. } = &e {
+                if err.is_conditional_check_failed_exception() {
+                    return AuthenticateUserError::WrongSecondFactor;
+                }
+            }
+            dbg!(e);
+            AuthenticateUserError::DatabaseWrite
+        })
+    }
+
+    /// Removes a recovery code once it has been used, failing if it was already used
+    pub async fn consume_recovery_code(
+        &self,
+        username: String,
+        recovery_code_hash: String,
+    ) -> Result<(), AuthenticateUserError> {
+        let db_request = self
+            .client
+            .update_item()
+            .table_name(TABLE_NAME)
+            .key(USERNAME_KEY, AttributeValue::S(username))
+            .expression_attribute_values(":hash", AttributeValue::S(recovery_code_hash.clone()))
+            .expression_attribute_values(":hashes", AttributeValue::Ss(vec![recovery_code_hash]))
+            .condition_expression(format!("contains({RECOVERY_CODES_KEY}, :hash)"))
+            .update_expression(format!("DELETE {RECOVERY_CODES_KEY} :hashes"));
+
+        db_request.send().await.map(|_| {}).map_err(|e| {
+            if let SdkError::ServiceError { err, .. } = &e {
+                if err.is_conditional_check_failed_exception() {
+                    return AuthenticateUserError::WrongSecondFactor;
+                }
+            }
+            dbg!(e);
+            AuthenticateUserError::DatabaseWrite
+        })
     }
 }
 pub enum GetUserError {}
 
-pub const USERNAME_KEY: &'static str = "username";
-pub const PASSWORD_KEY: &'static str = "hashed_salted_password";
-pub const SALT_KEY: &'static str = "salt";
+pub const USERNAME_KEY: &str = "username";
+pub const PASSWORD_KEY: &str = "hashed_salted_password";
+pub const SALT_KEY: &str = "salt";
+pub const TOTP_SECRET_KEY: &str = "totp_secret";
+pub const TOTP_ENABLED_KEY: &str = "totp_enabled";
+pub const TOTP_LAST_STEP_KEY: &str = "totp_last_step";
+pub const RECOVERY_CODES_KEY: &str = "hashed_salted_recovery_codes";
 
-pub const TABLE_NAME: &'static str = "atris_auth";
+pub const TABLE_NAME: &str = "atris_auth";
@@ -5,21 +5,20 @@ use atris_server::{
     auth_table::AtrisAuthDBClient,
     run_lambda_http,
     session_table::{AtrisSessionDBClient, CreateSessionError},
+    totp, turn,
 };
 
 run_lambda_http!(
     |request:Request<AuthenticateUserRequest>|->Result<AuthenticateUserResponse, AuthenticateUserError> {
 
     let (_,request) = request.into_parts();
-    dbg!(0);
 
     // Retrieve user from database
     let auth_client = AtrisAuthDBClient::new().await;
-    let user = dbg!(auth_client
+    let user = auth_client
         .get_user(request.username.clone())
         .await? //return any database errors
-        .ok_or(AuthenticateUserError::UnknownUsername(request.username.clone())))?;  //return user doesn't exist error
-    dbg!(1);
+        .ok_or(AuthenticateUserError::UnknownUsername(request.username.clone()))?;  //return user doesn't exist error
 
     // Confirm password, return any errors
     password_hash::PasswordHash::new(&user.password_hash)
@@ -27,15 +26,31 @@ run_lambda_http!(
         .verify_password(&[&Argon2::default()], request.password_attempt)
         .map_err(|_| AuthenticateUserError::WrongPassword)?;    //check for incorrect password
 
-    dbg!(2);
+    // If the user has two-factor authentication, they also need a TOTP code or a recovery code
+    if user.totp_enabled {
+        let code = request.second_factor.as_deref().ok_or(AuthenticateUserError::SecondFactorRequired)?;
+        let step = user.totp_secret.as_deref().and_then(|secret| totp::verify(secret, code, user.totp_last_step));
+        if let Some(step) = step {
+            // A code is only good once, even within its window
+            auth_client.use_totp_step(user.username.clone(), step).await?;
+        } else {
+            // Recovery codes are stored hashed like passwords, and each can only be used once
+            let used_hash = user.recovery_code_hashes.iter().find(|hash| {
+                password_hash::PasswordHash::new(hash)
+                    .and_then(|hash| hash.verify_password(&[&Argon2::default()], code.trim()))
+                    .is_ok()
+            }).ok_or(AuthenticateUserError::WrongSecondFactor)?;
+            auth_client.consume_recovery_code(user.username.clone(), used_hash.clone()).await?;
+        }
+    }
 
     // If no errors, then user has been authenticated, create session
     let session_client = AtrisSessionDBClient::new().await;
     let session_id = CipherKey::generate();
+    let ice_servers = turn::ice_servers_for(&request.username);
     session_client.create_session(session_id.clone(), request.username, request.initiator).await.map_err(|e|match e {
-        CreateSessionError::DuplicateSession(_) => todo!(),
+        CreateSessionError::DuplicateSession(_) => AuthenticateUserError::DuplicateSession,
         CreateSessionError::DatabaseWriteError => AuthenticateUserError::DatabaseWrite,
     })?;
-    dbg!(3);
-    Ok(AuthenticateUserResponse{session_id})
+    Ok(AuthenticateUserResponse{session_id, ice_servers})
 });
@@ -0,0 +1,54 @@
+use argon2::{Argon2, PasswordHasher};
+use atris_common::confirm_totp::*;
+use atris_server::{
+    auth_table::AtrisAuthDBClient, run_lambda_http, session_table::AtrisSessionDBClient, totp,
+};
+
+use password_hash::SaltString;
+
+run_lambda_http!(
+    |request: Request<ConfirmTotpRequest>| -> Result<ConfirmTotpResponse, ConfirmTotpError> {
+        let (_, request) = request.into_parts();
+
+        // Find the user this session belongs to
+        let session_table = AtrisSessionDBClient::new().await;
+        let session = session_table
+            .get_session(request.session_id.clone())
+            .await
+            .ok()
+            .and_then(|a| a)
+            .ok_or(ConfirmTotpError::InvalidSessionId(request.session_id.clone()))?;
+
+        let auth_client = AtrisAuthDBClient::new().await;
+        let user = auth_client
+            .get_user(session.username)
+            .await
+            .map_err(|_| ConfirmTotpError::DatabaseReadError)?
+            .ok_or(ConfirmTotpError::InvalidSessionId(request.session_id))?;
+        if user.totp_enabled {
+            return Err(ConfirmTotpError::AlreadyEnabled);
+        }
+
+        // Make sure the authenticator app actually produces matching codes before requiring them
+        let secret = user.totp_secret.ok_or(ConfirmTotpError::NotEnrolled)?;
+        let step = totp::verify(&secret, &request.code, None).ok_or(ConfirmTotpError::WrongCode)?;
+
+        // Recovery codes are salted and hashed exactly like passwords
+        let recovery_codes = totp::generate_recovery_codes();
+        let recovery_code_hashes = recovery_codes
+            .iter()
+            .map(|code| {
+                let salt = SaltString::generate(rand::rngs::OsRng);
+                Argon2::default()
+                    .hash_password(code.as_bytes(), salt.as_str())
+                    .map(|hash| hash.to_string())
+                    .map_err(|_| ConfirmTotpError::HashError)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        auth_client
+            .enable_totp(user.username, secret, step, recovery_code_hashes)
+            .await?;
+
+        Ok(ConfirmTotpResponse { recovery_codes })
+    }
+);
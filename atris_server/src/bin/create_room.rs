@@ -1,7 +1,4 @@
-use atris_common::{
-    cipher::KeyInit,
-    create_room::{CreateRoomError, CreateRoomRequest, CreateRoomResponse},
-};
+use atris_common::create_room::{CreateRoomError, CreateRoomRequest, CreateRoomResponse};
 use atris_server::{
     room_table::AtrisRoomDBClient, run_lambda_http, session_table::AtrisSessionDBClient,
 };
@@ -30,7 +27,7 @@ run_lambda_http!(
             other_session.ok_or(CreateRoomError::NoSessionForUser(request.other_user_name))?;
         let room_id = loop {
             let potential_id: u16 = rand::random();
-            let Err(CreateRoomError::DuplicateRoomId(_)) = room_table.create_room(potential_id, requester_session.username.clone()).await else {
+            let Err(CreateRoomError::DuplicateRoomId(_)) = room_table.create_room(potential_id, requester_session.username.clone(), other_session.username.clone()).await else {
                 break potential_id;
             };
         };
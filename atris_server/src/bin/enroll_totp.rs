@@ -0,0 +1,30 @@
+use atris_common::enroll_totp::*;
+use atris_server::{
+    auth_table::AtrisAuthDBClient, run_lambda_http, session_table::AtrisSessionDBClient, totp,
+};
+
+run_lambda_http!(
+    |request: Request<EnrollTotpRequest>| -> Result<EnrollTotpResponse, EnrollTotpError> {
+        let (_, request) = request.into_parts();
+
+        // Find the user this session belongs to
+        let session_table = AtrisSessionDBClient::new().await;
+        let session = session_table
+            .get_session(request.session_id.clone())
+            .await
+            .ok()
+            .and_then(|a| a)
+            .ok_or(EnrollTotpError::InvalidSessionId(request.session_id))?;
+
+        // Store a pending secret, which is not required at login until it is confirmed
+        let auth_client = AtrisAuthDBClient::new().await;
+        let secret = totp::generate_secret();
+        auth_client
+            .set_totp_secret(session.username.clone(), secret.clone())
+            .await?;
+
+        Ok(EnrollTotpResponse {
+            provisioning_uri: totp::provisioning_uri(&session.username, &secret),
+        })
+    }
+);
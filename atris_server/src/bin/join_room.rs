@@ -16,11 +16,13 @@ run_lambda_http!(
             .ok()
             .and_then(|a| a);
 
-        let _requester_session =
+        let requester_session =
             requester_session.ok_or(JoinRoomError::InvalidSessionId(request.session_id.clone()))?;
 
         let room_table = AtrisRoomDBClient::new().await;
-        let room = room_table.get_room(request.room_id).await?;
+        let room = room_table
+            .get_room(request.room_id, &requester_session.username)
+            .await?;
         Ok(JoinRoomResponse {
             room_data: room.room_data,
         })
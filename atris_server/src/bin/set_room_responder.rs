@@ -19,7 +19,7 @@ run_lambda_http!(
         let requester_session = session_table.get_session(request.session_id.clone()).await.ok().and_then(|a|a);
         let other_session = session_table.get_session_for_username(request.other_user_name.clone()).await.ok().and_then(|a|a);
         let requester_session = requester_session.ok_or(SetRoomResponderError::InvalidSessionId(request.session_id.clone()))?;
-        let other_session = other_session.ok_or(SetRoomResponderError::NoSessionForUser(request.other_user_name))?;
+        let other_session = other_session.ok_or(SetRoomResponderError::NoSessionForUser(request.other_user_name.clone()))?;
 
         let room_table = AtrisRoomDBClient::new().await;
 
@@ -34,7 +34,7 @@ run_lambda_http!(
             symmetric_key: room_symmetric_key.clone()
         };
         let room_data = Encrypted::encrypt(&room_data, &mut cipher).map_err(|_|SetRoomResponderError::EncryptionError)?;
-        room_table.update_room_data(request.room_id,requester_session.username,room_data).await?;
+        room_table.update_room_data(request.room_id,requester_session.username,request.other_user_name,room_data).await?;
 
         Ok(SetRoomResponderResponse { room_symmetric_key })
     }
@@ -0,0 +1,40 @@
+use atris_common::signal_room::*;
+
+use atris_server::{
+    room_table::AtrisRoomDBClient, run_lambda_http, session_table::AtrisSessionDBClient,
+};
+
+run_lambda_http!(
+    |request: Request<SignalRoomRequest>| -> Result<SignalRoomResponse, SignalRoomError> {
+        let (_, request) = request.into_parts();
+
+        let session_table = AtrisSessionDBClient::new().await;
+        let requester_session = session_table
+            .get_session(request.session_id.clone())
+            .await
+            .ok()
+            .and_then(|a| a);
+        let requester_session = requester_session.ok_or(SignalRoomError::InvalidSessionId(
+            request.session_id.clone(),
+        ))?;
+
+        // Publish the requester's messages, and hand back whatever the other end published that they have not seen.
+        // Only the room's two users can signal in it, each as their own end.
+        let room_table = AtrisRoomDBClient::new().await;
+        let (sender, other_queue) = room_table
+            .push_signals(
+                request.room_id,
+                requester_session.username,
+                request.role,
+                request.messages,
+            )
+            .await?;
+        Ok(SignalRoomResponse {
+            messages: other_queue
+                .into_iter()
+                .skip(request.received as usize)
+                .collect(),
+            sender,
+        })
+    }
+);
@@ -1,6 +1,8 @@
 pub mod auth_table;
 pub mod room_table;
 pub mod session_table;
+pub mod totp;
+pub mod turn;
 
 // pub struct AtrisRequest<R>{
 //     pub payload: R,
@@ -1,7 +1,12 @@
-use atris_common::{set_room_responder::SetRoomResponderError, Encrypted, REGION};
+use atris_common::{
+    set_room_responder::SetRoomResponderError,
+    signal_room::{SignalMessage, SignalRole, SignalRoomError, MAX_SIGNALS},
+    Encrypted, REGION,
+};
 use aws_config::meta::region::RegionProviderChain;
 use aws_sdk_dynamodb::{
-    model::AttributeValue,
+    error::GetItemError,
+    model::{AttributeValue, ReturnValue},
     types::{Blob, SdkError},
 };
 
@@ -14,6 +19,8 @@ pub struct Room {
     pub room_id: u16,
     /// The room's creator
     pub creator_user_name: String,
+    /// The user the room was created for, missing from rooms created before it was recorded
+    pub invited_user_name: Option<String>,
     /// The salted and hashed digest of the user's password
     pub room_data: Encrypted<RoomData>,
 }
@@ -22,11 +29,13 @@ impl Room {
         let room_id = map.get(ROOM_ID_KEY)?.as_n().ok()?.parse().ok()?;
         let room_data_slice = map.get(ROOM_DATA_KEY)?.as_b().ok()?.as_ref();
         let room_creator = map.get(ROOM_CREATOR_KEY)?.as_s().ok()?;
+        let room_invited = map.get(ROOM_INVITED_KEY).and_then(|v| v.as_s().ok());
         let room_data = bincode::deserialize(room_data_slice).ok()?;
         Some(Self {
             room_id,
             room_data,
             creator_user_name: room_creator.clone(),
+            invited_user_name: room_invited.cloned(),
         })
     }
 }
@@ -46,14 +55,20 @@ impl AtrisRoomDBClient {
             client: Client::new(&config),
         }
     }
-    pub async fn create_room(&self, room_id: u16, creator: String) -> Result<(), CreateRoomError> {
+    pub async fn create_room(
+        &self,
+        room_id: u16,
+        creator: String,
+        invited: String,
+    ) -> Result<(), CreateRoomError> {
         let db_request = self
             .client
             .put_item()
             .condition_expression(format!("attribute_not_exists({})", ROOM_ID_KEY))
             .table_name(TABLE_NAME)
             .item(ROOM_ID_KEY, AttributeValue::N(room_id.to_string()))
-            .item(ROOM_CREATOR_KEY, AttributeValue::S(creator));
+            .item(ROOM_CREATOR_KEY, AttributeValue::S(creator))
+            .item(ROOM_INVITED_KEY, AttributeValue::S(invited));
         db_request.send().await.map_err(|e| {
             if let SdkError::ServiceError { err, .. } = &e {
                 if err.is_conditional_check_failed_exception() {
@@ -66,11 +81,12 @@ impl AtrisRoomDBClient {
         Ok(())
     }
 
-    /// Update a room
+    /// Answer a room, which only its creator can do for the user it was created for
     pub async fn update_room_data(
         &self,
         room_id: u16,
         updater: String,
+        invited: String,
         room_data: Encrypted<RoomData>,
     ) -> Result<(), SetRoomResponderError> {
         let room_data =
@@ -81,25 +97,38 @@ impl AtrisRoomDBClient {
             .update_item()
             .key(ROOM_ID_KEY, AttributeValue::N(room_id.to_string()))
             .expression_attribute_values(":updater", AttributeValue::S(updater.clone()))
+            .expression_attribute_values(":invited", AttributeValue::S(invited.clone()))
             .expression_attribute_values(":room_data", AttributeValue::B(Blob::new(room_data)))
-            .condition_expression(format!("{ROOM_CREATOR_KEY} = :updater"))
+            .condition_expression(format!(
+                "{ROOM_CREATOR_KEY} = :updater AND {ROOM_INVITED_KEY} = :invited"
+            ))
             .table_name(TABLE_NAME)
             .update_expression(format!("SET {ROOM_DATA_KEY}= :room_data"));
         // .attribute_updates(ROOM_CREATOR_KEY, AttributeValueUpdate::builder().set_action(Some(AttributeAction::Put)).set_value(Some(AttributeValue::S(room.creator_user_name))).build());
 
         // Send the request to the database
-        db_request.send().await.map(|_| {}).map_err(|err| {
-            if let SdkError::ServiceError { err, .. } = err {
-                if err.is_conditional_check_failed_exception() {
-                    return SetRoomResponderError::NotRoomCreator(updater);
+        match db_request.send().await {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                // Find out which of the conditions failed
+                let item = self
+                    .get_room_item(room_id)
+                    .await
+                    .map_err(|_| SetRoomResponderError::DatabaseWriteError)?
+                    .ok_or(SetRoomResponderError::InvalidRoomId(room_id))?;
+                if !has_member(&item, ROOM_CREATOR_KEY, &updater) {
+                    return Err(SetRoomResponderError::NotRoomCreator(updater));
                 }
+                Err(SetRoomResponderError::NotInvitedUser(invited))
             }
-            SetRoomResponderError::DatabaseWriteError
-        })
+            Err(_) => Err(SetRoomResponderError::DatabaseWriteError),
+        }
     }
 
-    /// Retrieves the user of the specified username
-    pub async fn get_room(&self, room_id: u16) -> Result<Room, JoinRoomError> {
+    /// Retrieves the room of the specified id for one of its two users
+    pub async fn get_room(&self, room_id: u16, username: &str) -> Result<Room, JoinRoomError> {
         let db_request = self
             .client
             .get_item()
@@ -107,19 +136,166 @@ impl AtrisRoomDBClient {
             .key(ROOM_ID_KEY, AttributeValue::N(room_id.to_string()))
             .attributes_to_get(ROOM_ID_KEY) //get the relevant fields
             .attributes_to_get(ROOM_CREATOR_KEY)
+            .attributes_to_get(ROOM_INVITED_KEY)
             .attributes_to_get(ROOM_DATA_KEY)
             .send()
             .await
             .map_err(|_| JoinRoomError::DatabaseReadError)?; //convert SdkError to GetRoomError
-        db_request
+        let item = db_request
             .item()
-            .ok_or(JoinRoomError::NonexistentRoomId(room_id))
-            .and_then(|m| Room::from_map(m).ok_or(JoinRoomError::IncompleteRoom))
+            .ok_or(JoinRoomError::NonexistentRoomId(room_id))?;
+        // Checked before the room is answered, so others cannot wait in it either
+        if !has_member(item, ROOM_CREATOR_KEY, username)
+            && !has_member(item, ROOM_INVITED_KEY, username)
+        {
+            return Err(JoinRoomError::NotInRoom(room_id));
+        }
+        Room::from_map(item).ok_or(JoinRoomError::IncompleteRoom)
+    }
+
+    /// Appends signaling messages to the queue of one end of the room, and returns the whole queue of the other end
+    /// with the user who published it.
+    /// Only `username` may publish or read as `role`: the room's creator as the responder, and the user it was
+    /// created for as the initiator.
+    pub async fn push_signals(
+        &self,
+        room_id: u16,
+        username: String,
+        role: SignalRole,
+        messages: Vec<SignalMessage>,
+    ) -> Result<(Option<String>, Vec<SignalMessage>), SignalRoomError> {
+        if messages.len() > MAX_SIGNALS {
+            return Err(SignalRoomError::TooManySignals);
+        }
+        let member_key = signal_member_key(role);
+        let queue_key = signal_queue_key(role);
+        let other_queue_key = signal_queue_key(role.other());
+        let item = if messages.is_empty() {
+            // Nothing to publish, so just read the other end's queue
+            let item = self.get_signals(room_id).await?;
+            if !has_member(&item, member_key, &username) {
+                return Err(SignalRoomError::NotInRoom(room_id));
+            }
+            item
+        } else {
+            let room_left = MAX_SIGNALS - messages.len();
+            let messages = messages
+                .iter()
+                .map(|m| serde_json::to_string(m).map(AttributeValue::S))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| SignalRoomError::SerializationError)?;
+            let result = self
+                .client
+                .update_item()
+                .table_name(TABLE_NAME)
+                .key(ROOM_ID_KEY, AttributeValue::N(room_id.to_string()))
+                .expression_attribute_values(":messages", AttributeValue::L(messages))
+                .expression_attribute_values(":empty", AttributeValue::L(vec![]))
+                .expression_attribute_values(":user", AttributeValue::S(username.clone()))
+                .expression_attribute_values(":room_left", AttributeValue::N(room_left.to_string()))
+                .condition_expression(format!(
+                    "attribute_exists({ROOM_ID_KEY}) AND {member_key} = :user AND (attribute_not_exists({queue_key}) OR size({queue_key}) <= :room_left)"
+                ))
+                .update_expression(format!(
+                    "SET {queue_key} = list_append(if_not_exists({queue_key}, :empty), :messages)"
+                ))
+                .return_values(ReturnValue::AllNew)
+                .send()
+                .await;
+            match result {
+                Ok(output) => output.attributes().cloned().unwrap_or_default(),
+                Err(SdkError::ServiceError { err, .. })
+                    if err.is_conditional_check_failed_exception() =>
+                {
+                    // Find out which of the conditions failed
+                    let item = self.get_signals(room_id).await?;
+                    if !has_member(&item, member_key, &username) {
+                        return Err(SignalRoomError::NotInRoom(room_id));
+                    }
+                    return Err(SignalRoomError::TooManySignals);
+                }
+                Err(e) => {
+                    dbg!(e);
+                    return Err(SignalRoomError::DatabaseWriteError);
+                }
+            }
+        };
+        let sender = item
+            .get(signal_member_key(role.other()))
+            .and_then(|v| v.as_s().ok())
+            .cloned();
+        // The other end may not have published anything yet
+        let Some(queue) = item.get(other_queue_key) else {
+            return Ok((sender, Vec::new()));
+        };
+        let queue = queue
+            .as_l()
+            .map_err(|_| SignalRoomError::DatabaseReadError)?
+            .iter()
+            .map(|m| {
+                let json = m.as_s().map_err(|_| SignalRoomError::DatabaseReadError)?;
+                serde_json::from_str(json).map_err(|_| SignalRoomError::SerializationError)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((sender, queue))
+    }
+
+    /// The members and signaling queues of a room
+    async fn get_signals(
+        &self,
+        room_id: u16,
+    ) -> Result<HashMap<String, AttributeValue>, SignalRoomError> {
+        self.get_room_item(room_id)
+            .await
+            .map_err(|_| SignalRoomError::DatabaseReadError)?
+            .ok_or(SignalRoomError::NonexistentRoomId(room_id))
+    }
+
+    /// Every attribute of a room, or `None` if there is no such room
+    async fn get_room_item(
+        &self,
+        room_id: u16,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, SdkError<GetItemError>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(TABLE_NAME)
+            .key(ROOM_ID_KEY, AttributeValue::N(room_id.to_string()))
+            .send()
+            .await?;
+        Ok(output.item().cloned())
+    }
+}
+
+/// Whether the room's attribute `key` names the user `username`
+fn has_member(item: &HashMap<String, AttributeValue>, key: &str, username: &str) -> bool {
+    item.get(key)
+        .and_then(|v| v.as_s().ok())
+        .map(String::as_str)
+        == Some(username)
+}
+
+/// The attribute holding the user who may signal as the given end of the room
+fn signal_member_key(role: SignalRole) -> &'static str {
+    match role {
+        SignalRole::Initiator => ROOM_INVITED_KEY,
+        SignalRole::Responder => ROOM_CREATOR_KEY,
+    }
+}
+
+/// The attribute holding the signaling queue of the given end of the room
+fn signal_queue_key(role: SignalRole) -> &'static str {
+    match role {
+        SignalRole::Initiator => INITIATOR_SIGNALS_KEY,
+        SignalRole::Responder => RESPONDER_SIGNALS_KEY,
     }
 }
 
-pub const ROOM_ID_KEY: &'static str = "room_id";
-pub const ROOM_CREATOR_KEY: &'static str = "room_creator";
-pub const ROOM_DATA_KEY: &'static str = "room_data";
+pub const ROOM_ID_KEY: &str = "room_id";
+pub const ROOM_CREATOR_KEY: &str = "room_creator";
+pub const ROOM_DATA_KEY: &str = "room_data";
+pub const ROOM_INVITED_KEY: &str = "room_invited";
+pub const INITIATOR_SIGNALS_KEY: &str = "initiator_signals";
+pub const RESPONDER_SIGNALS_KEY: &str = "responder_signals";
 
-pub const TABLE_NAME: &'static str = "atris_rooms";
+pub const TABLE_NAME: &str = "atris_rooms";
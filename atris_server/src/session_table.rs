@@ -106,7 +106,7 @@ impl AtrisSessionDBClient {
                 dbg!(e);
                 AuthenticateSessionError::DatabaseRead
             })?; //convert SdkError to AuthenticateSessionError
-        return Ok(db_request.item().and_then(Session::from_map));
+        Ok(db_request.item().and_then(Session::from_map))
     }
 
     /// Retrieves a session of the specified username
@@ -129,7 +129,7 @@ impl AtrisSessionDBClient {
             })?; //convert SdkError to AuthenticateSessionError
         Ok(db_request
             .items()
-            .and_then(|s| s.get(0))
+            .and_then(|s| s.first())
             .and_then(Session::from_map))
     }
 }
@@ -200,8 +200,8 @@ pub struct CreateSessionResponse; // TODO: See if anything else needs to be retu
 
 pub enum GetSessionError {}
 
-pub const SESSION_ID_KEY: &'static str = "session_id";
-pub const USERNAME_KEY: &'static str = "username";
-pub const INITIATOR_KEY: &'static str = "initiator";
+pub const SESSION_ID_KEY: &str = "session_id";
+pub const USERNAME_KEY: &str = "username";
+pub const INITIATOR_KEY: &str = "initiator";
 
-pub const TABLE_NAME: &'static str = "atris_session";
+pub const TABLE_NAME: &str = "atris_session";
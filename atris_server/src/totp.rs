@@ -0,0 +1,91 @@
+//! Time-based one-time passwords as described in RFC 6238, using the same defaults as common
+//! authenticator apps (HMAC-SHA1, 6 digits, 30 second steps)
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use sha1::Sha1;
+
+/// The issuer shown in authenticator apps
+pub const ISSUER: &str = "Atris";
+/// The number of seconds each code is valid for
+pub const STEP_SECONDS: u64 = 30;
+/// The number of digits in each code
+pub const DIGITS: u32 = 6;
+/// How many steps before and after the current one are still accepted, to allow for clock drift
+pub const ALLOWED_DRIFT_STEPS: u64 = 1;
+/// The number of recovery codes issued when two-factor authentication is enabled
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Generate a new random secret, encoded in base32 as authenticator apps expect
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 20];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+/// The `otpauth://` URI an authenticator app needs to start producing codes for this secret
+pub fn provisioning_uri(username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{ISSUER}:{username}?secret={secret}&issuer={ISSUER}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}"
+    )
+}
+
+/// Compute the code for the given secret at the given step
+fn code_at(secret: &[u8], step: u64) -> Option<u32> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).ok()?;
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation, see RFC 4226 section 5.3
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(digest[offset..offset + 4].try_into().ok()?) & 0x7fff_ffff;
+    Some(binary % 10u32.pow(DIGITS))
+}
+
+/// Check the code against the secret at the current time, see [`verify_at`]
+pub fn verify(secret: &str, code: &str, last_used_step: Option<u64>) -> Option<u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    verify_at(secret, code, now, last_used_step)
+}
+
+/// Check the code against the secret at the given unix time, returning the step it belongs to if it matches.
+/// Codes of `last_used_step` and before are refused, so each code can only be used once; the step returned has to
+/// be stored as the new last used step.
+pub fn verify_at(
+    secret: &str,
+    code: &str,
+    unix_time: u64,
+    last_used_step: Option<u64>,
+) -> Option<u64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim().parse::<u32>().ok()?;
+    let current_step = unix_time / STEP_SECONDS;
+    let first_step = match last_used_step {
+        Some(last_used_step) => current_step
+            .saturating_sub(ALLOWED_DRIFT_STEPS)
+            .max(last_used_step + 1),
+        None => current_step.saturating_sub(ALLOWED_DRIFT_STEPS),
+    };
+    (first_step..=current_step + ALLOWED_DRIFT_STEPS)
+        .find(|step| code_at(&secret, *step) == Some(code))
+}
+
+/// Generate a fresh set of recovery codes, formatted as `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+    let mut rng = rand::rngs::OsRng;
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (0..10)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+//! Issues short-lived TURN credentials using a secret shared with the relay, see [`atris_common::turn`]
+//!
+//! The relay is configured through environment variables on the lambda:
+//!  - `ATRIS_TURN_URLS`: comma separated `turn:`/`turns:` urls of the relay
+//!  - `ATRIS_TURN_SECRET`: the secret shared with the relay
+//!  - `ATRIS_TURN_TTL_SECONDS`: how long issued credentials stay valid, defaults to [`DEFAULT_TTL_SECONDS`]
+//!  - `ATRIS_STUN_URLS`: comma separated `stun:` urls, defaults to [`DEFAULT_STUN_URL`]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use atris_common::turn::{credential_for, username_for};
+use atris_common::IceServer;
+
+pub const TURN_URLS_VAR: &str = "ATRIS_TURN_URLS";
+pub const TURN_SECRET_VAR: &str = "ATRIS_TURN_SECRET";
+pub const TURN_TTL_VAR: &str = "ATRIS_TURN_TTL_SECONDS";
+pub const STUN_URLS_VAR: &str = "ATRIS_STUN_URLS";
+
+/// How long credentials stay valid when [`TURN_TTL_VAR`] is not set
+pub const DEFAULT_TTL_SECONDS: u64 = 60 * 60;
+/// The STUN server used when [`STUN_URLS_VAR`] is not set
+pub const DEFAULT_STUN_URL: &str = "stun:stun.l.google.com:19302";
+
+/// Split a comma separated environment variable into its non-empty entries
+fn urls_from_env(var: &str) -> Option<Vec<String>> {
+    let urls: Vec<String> = std::env::var(var)
+        .ok()?
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(String::from)
+        .collect();
+    (!urls.is_empty()).then_some(urls)
+}
+
+/// Issue credentials for the relay which expire `ttl_seconds` after `unix_time`.
+/// The username embeds the expiry time, so the relay can check it without asking the auth server.
+pub fn credentials_at(
+    urls: Vec<String>,
+    secret: &str,
+    username: &str,
+    unix_time: u64,
+    ttl_seconds: u64,
+) -> Option<IceServer> {
+    let turn_username = username_for(username, unix_time + ttl_seconds);
+    let credential = credential_for(secret, &turn_username)?;
+    Some(IceServer {
+        urls,
+        username: turn_username,
+        credential,
+    })
+}
+
+/// The ICE servers a freshly authenticated user should use, according to the environment
+pub fn ice_servers_for(username: &str) -> Vec<IceServer> {
+    let mut ice_servers = vec![IceServer {
+        urls: urls_from_env(STUN_URLS_VAR).unwrap_or_else(|| vec![DEFAULT_STUN_URL.into()]),
+        ..Default::default()
+    }];
+
+    // Deployments without their own relay only get STUN
+    if let (Some(urls), Ok(secret)) = (urls_from_env(TURN_URLS_VAR), std::env::var(TURN_SECRET_VAR)) {
+        let ttl_seconds = std::env::var(TURN_TTL_VAR)
+            .ok()
+            .and_then(|ttl| ttl.parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECONDS);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        ice_servers.extend(credentials_at(urls, &secret, username, now, ttl_seconds));
+    }
+    ice_servers
+}
@@ -0,0 +1,54 @@
+//! Tests of [`totp`] against the SHA1 test vectors of RFC 6238 Appendix B, and of refusing reused codes
+
+use atris_server::totp::{self, STEP_SECONDS};
+use data_encoding::BASE32_NOPAD;
+
+/// The SHA1 secret of the RFC's test vectors, as authenticator apps are given it
+fn rfc_secret() -> String {
+    BASE32_NOPAD.encode(b"12345678901234567890")
+}
+
+#[test]
+fn codes_match_the_rfc_test_vectors() {
+    // The RFC's codes have 8 digits, of which the last 6 are the 6 digit code
+    let vectors = [
+        (59, "94287082"),
+        (1111111109, "07081804"),
+        (1111111111, "14050471"),
+        (1234567890, "89005924"),
+        (2000000000, "69279037"),
+        (20000000000, "65353130"),
+    ];
+    for (unix_time, code) in vectors {
+        let code = &code[2..];
+        assert_eq!(
+            totp::verify_at(&rfc_secret(), code, unix_time, None),
+            Some(unix_time / STEP_SECONDS),
+            "the code at {unix_time}"
+        );
+    }
+    assert_eq!(totp::verify_at(&rfc_secret(), "287083", 59, None), None);
+    assert_eq!(totp::verify_at("not base32!", "287082", 59, None), None);
+}
+
+#[test]
+fn codes_are_accepted_within_the_drift_window_only() {
+    // The code of step 1, from the vector at 59 seconds
+    assert_eq!(
+        totp::verify_at(&rfc_secret(), "287082", 59 + STEP_SECONDS, None),
+        Some(1)
+    );
+    assert_eq!(
+        totp::verify_at(&rfc_secret(), "287082", 59 + 2 * STEP_SECONDS, None),
+        None
+    );
+}
+
+#[test]
+fn codes_cannot_be_used_twice() {
+    let step = totp::verify_at(&rfc_secret(), "287082", 59, None);
+    assert_eq!(step, Some(1));
+    // Neither within the same window, nor after a later code was used
+    assert_eq!(totp::verify_at(&rfc_secret(), "287082", 59, step), None);
+    assert_eq!(totp::verify_at(&rfc_secret(), "287082", 59, Some(2)), None);
+}
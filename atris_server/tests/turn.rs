@@ -0,0 +1,45 @@
+//! Tests of the TURN credentials [`turn`] issues, which the relay checks with [`atris_common::turn`]
+
+use atris_common::turn::{credential_for, expiry_of};
+use atris_server::turn;
+
+const SECRET: &str = "turn-test-secret";
+const URL: &str = "turn:relay.example.com:3478?transport=udp";
+
+#[test]
+fn credentials_expire_after_the_ttl() {
+    let server = turn::credentials_at(vec![URL.into()], SECRET, "alice", 1_700_000_000, 600)
+        .expect("valid secret");
+    assert_eq!(server.urls, vec![URL.to_string()]);
+    assert_eq!(server.username, "1700000600:alice");
+    assert_eq!(expiry_of(&server.username), Some(1_700_000_600));
+}
+
+#[test]
+fn credentials_are_the_hmac_of_the_username() {
+    let server = turn::credentials_at(vec![URL.into()], SECRET, "alice", 1_700_000_000, 600)
+        .expect("valid secret");
+    assert_eq!(
+        Some(server.credential.clone()),
+        credential_for(SECRET, &server.username)
+    );
+    // A known answer, so the relay and other TURN servers (such as coturn) keep agreeing on the encoding
+    assert_eq!(
+        credential_for("1234", "1334679000:alice").as_deref(),
+        Some("KYxVmZikjnyyw8wntYIzy8I+qw4=")
+    );
+
+    let other_secret = credential_for("another-secret", &server.username);
+    assert_ne!(Some(server.credential.clone()), other_secret);
+    let later = turn::credentials_at(vec![URL.into()], SECRET, "alice", 1_700_000_001, 600)
+        .expect("valid secret");
+    assert_ne!(server.credential, later.credential);
+}
+
+#[test]
+fn usernames_without_an_expiry_are_refused() {
+    assert_eq!(expiry_of("alice"), None);
+    assert_eq!(expiry_of(":alice"), None);
+    assert_eq!(expiry_of("soon:alice"), None);
+    assert_eq!(expiry_of("1700000600"), Some(1_700_000_600));
+}
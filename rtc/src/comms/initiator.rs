@@ -47,7 +47,7 @@ impl AtrisInitiator {
                 data_channel,
             })
         } else {
-            println!("");
+            println!();
             panic!();
             // Err(anyhow::Error::new())
         }
@@ -59,12 +59,12 @@ impl AtrisInitiator {
         Ok(dbg!(b64))
     }
     /// If we created an initiator, feed the responder's response here
-    pub async fn into_channel_with<T>(self, responder_string: &String) -> Result<AtrisChannel<T>>
+    pub async fn into_channel_with<T>(self, responder_string: &str) -> Result<AtrisChannel<T>>
     where
         T: Serialize + Send + Sync + 'static,
         for<'d> T: Deserialize<'d>,
     {
-        let decoded_responder_string = crate::signal::decode(responder_string.as_str())?;
+        let decoded_responder_string = crate::signal::decode(responder_string)?;
         // Convert the json input into a useful datatype
         let responder_description =
             serde_json::from_str::<RTCSessionDescription>(&decoded_responder_string)?;
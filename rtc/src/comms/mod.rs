@@ -20,7 +20,7 @@ use webrtc::interceptor::registry::Registry;
 use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 
-pub(self) type Message = String;
+type Message = String;
 
 pub mod initiator;
 pub mod responder;
@@ -119,14 +119,13 @@ where
                 arc_data_channel.id()
             );
             Box::pin(async move {
-                let mut result = Result::<usize>::Ok(0);
                 // Get the next outgoing message from the `outgoing_sender`
                 while let Some(next_message) = outgoing_receiver.recv().await {
-                    // Send the next outgoing message and record the result
+                    // Send the next outgoing message
                     if let Result::Ok(msg) = bincode::serialize::<T>(&next_message) {
                         // incoming_sender.blocking_send(&msg);
                         let bytes = Bytes::from(msg);
-                        result = arc_data_channel.send(&bytes).await.map_err(Into::into);
+                        let _ = arc_data_channel.send(&bytes).await;
                     }
                     // unimplemented!("Sending messages");
                     // result = arc_data_channel.send(next_message).await.map_err(Into::into);
@@ -143,7 +142,7 @@ where
             let incoming_sender = Arc::clone(&incoming_sender);
             Box::pin(async move {
                 if let Result::Ok(msg) = bincode::deserialize::<T>(&msg.data) {
-                    incoming_sender.send(msg).await;
+                    let _ = incoming_sender.send(msg).await;
                 };
             })
         }));
@@ -27,7 +27,7 @@ impl AtrisResponder {
     // }
 
     /// Set the initator's description
-    pub async fn open_channel_with<T>(mut self, offer_str: &String) -> Result<AtrisChannel<T>>
+    pub async fn open_channel_with<T>(mut self, offer_str: &str) -> Result<AtrisChannel<T>>
     where
         T: Serialize + Send + Sync + 'static,
         for<'d> T: Deserialize<'d>,
@@ -39,7 +39,7 @@ impl AtrisResponder {
         let data_channel_sender = Arc::new(data_channel_sender);
 
         // Wait for the offer to be pasted
-        let decoded_offer_str = crate::signal::decode(offer_str.as_str())?;
+        let decoded_offer_str = crate::signal::decode(offer_str)?;
         let offer = serde_json::from_str::<RTCSessionDescription>(&decoded_offer_str)?;
 
         // Set the remote SessionDescription
@@ -50,7 +50,7 @@ impl AtrisResponder {
             dbg!("Oh me, me, I have a data channel!");
             let data_channel_sender = Arc::clone(&data_channel_sender);
             Box::pin(async move {
-                data_channel_sender.send(data_channel).await;
+                let _ = data_channel_sender.send(data_channel).await;
             })
         }));
 
@@ -91,19 +91,19 @@ fn read_file(target: &str) -> Vec<u8> {
 
 fn compress_g(inp: Vec<u8>) -> Vec<u8> {
     let mut encryptor = GzEncoder::new(Vec::new(), Compression::default());
-    encryptor.write_all(inp.as_slice());
+    encryptor.write_all(inp.as_slice()).unwrap();
     encryptor.finish().unwrap()
 }
 
 fn compress_z(inp: Vec<u8>) -> Vec<u8> {
     let mut encryptor = ZlibEncoder::new(Vec::new(), Compression::default());
-    encryptor.write_all(inp.as_slice());
+    encryptor.write_all(inp.as_slice()).unwrap();
     encryptor.finish().unwrap()
 }
 
 pub fn compress_d(inp: Vec<u8>) -> Vec<u8> {
     let mut encryptor = DeflateEncoder::new(Vec::new(), Compression::default());
-    encryptor.write_all(inp.as_slice());
+    encryptor.write_all(inp.as_slice()).unwrap();
     encryptor.finish().unwrap()
 }
 
@@ -111,7 +111,7 @@ fn decompress(inp: Vec<u8>) -> Vec<u8> {
     let mut decryptor = GzDecoder::new(inp.as_slice());
     let mut ret: Vec<u8> = Vec::new();
     decryptor.read_to_end(&mut ret).unwrap();
-    return ret;
+    ret
 }
 
 fn first() {
@@ -119,9 +119,9 @@ fn first() {
 
     // compression
     let mut e = GzEncoder::new(Vec::new(), Compression::default());
-    e.write_all(b"foo");
-    e.write_all(b"bar");
-    e.write_all(test_str.as_bytes());
+    e.write_all(b"foo").unwrap();
+    e.write_all(b"bar").unwrap();
+    e.write_all(test_str.as_bytes()).unwrap();
     let compressed_bytes = e.finish().unwrap();
 
     // decompression
@@ -1,3 +1,6 @@
+// Experimental scratch binary; several helpers are kept around for reference
+#![allow(dead_code)]
+
 mod compress;
 mod initiator;
 mod responder;
@@ -54,7 +57,7 @@ fn main() {
         ))
         .get_matches(); // run clap, can be omitted to save layout to a variable
 
-    let options = vec![
+    let options = [
         "initiator",
         "responder",
         "compress",